@@ -12,15 +12,25 @@ use std::ffi::{OsStr, OsString};
 use std::path::Path;
 use native_dialog::{FileDialog, MessageDialog, MessageType};
 
-use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
+use iced::futures::channel::mpsc;
+use iced::futures::StreamExt;
+use iced_native::subscription;
 
-use crate::usbdm::usb_interface::{UsbInterface, find_usbdm_as, find_usbdm,};
+use crate::usbdm::usb_interface::{UsbInterface, UsbdmProbe, find_usbdm_as, find_usbdm, list_usbdm, open_preferred_usbdm, preferred_probe_serial};
+use crate::preferences::{Preferences};
 use crate::usbdm::settings::{TargetVddSelect};
 use crate::usbdm::feedback::{PowerStatus};
-use crate::usbdm::programmer::{Programmer};
+use crate::usbdm::hotplug::{HotplugWatcher, HotplugEvent};
 use crate::dsc_target::target_factory::{TargetProgramming, TargetDsc, TargetSelector, MemorySegment, TargetYaml};
 use crate::dsc_target::test_programming::*;
+use crate::dsc_target::job_worker::{JobWorker, JobHandle, Job, JobKind, JobResult, WorkerEvent};
+use crate::dsc_target::speed_search::{SpeedSearch};
+use crate::gdb::server::{DEFAULT_PORT};
+use crate::symbols::symbol_table::{SymbolTable};
+use crate::dsc_target::live_watch::{WatchItem, WatchTable, WatchSettings, WatchFormat};
+use crate::dsc_target::profiler::{Profile, ProfileSettings};
+use crate::dsc_target::core_dump::{CoreDump};
 use crate::gui::{self, main_window};
 use crate::gui::modal_notification::{nofiy_user_model, error_notify_model, about_card, connection_image_modal, progress_bar_modal, erase_write_confirm_modal};
 use crate::gui::hexbuffer_widget::{TableContents};
@@ -28,6 +38,9 @@ use crate::file_buffer::hex_file::{load_buffer_from_file, save_buffer_to_file, F
 use crate::errors::{Error};
 use crate::utils::*;
 
+/// console text kept in app, older output is dropped
+const CONSOLE_TEXT_LIMIT : usize = 0x10000;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum UsbdmAppStatus {
     
@@ -49,10 +62,105 @@ pub enum TargetStatus {
     EndProgramming,
 
 }
-// just for test! In work verstion need implement async trait in programmer + subscribe method on iced app side
-pub async fn handle_progress(val : f32) -> f32
+/// USBDM needs a moment after plug-in before it can be opened (enumeration, driver bind)
+const REPLUG_SETTLE_TIME : u64 = 500;
+
+/// `replug_settle` - waits `REPLUG_SETTLE_TIME` on its own thread, executor stays free for other commands
+pub async fn replug_settle()
+{
+    let (sender, receiver) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(REPLUG_SETTLE_TIME));
+        sender.send(()).ok();
+    });
+    receiver.await.ok();
+}
+
+enum HotplugState {
+    Starting,
+    Watching(HotplugWatcher, mpsc::UnboundedReceiver<HotplugEvent>),
+    Stopped,
+}
+
+/// `hotplug_subscription` - USBDM plug/unplug as `Message::Hotplug`, watcher thread lives as long as subscription
+pub fn hotplug_subscription() -> Subscription<Message>
 {
-    val
+    struct Hotplug;
+
+    subscription::unfold(std::any::TypeId::of::<Hotplug>(), HotplugState::Starting, |state| async move {
+        match state
+        {
+            HotplugState::Starting =>
+            {
+                let (sender, receiver) = mpsc::unbounded();
+                match HotplugWatcher::start(move |event| { sender.unbounded_send(event).ok(); })
+                {
+                    Ok(watcher) => (None, HotplugState::Watching(watcher, receiver)),
+                    Err(_e) =>
+                    {
+                        println!("Hotplug watcher not started: {:?}", _e);
+                        (None, HotplugState::Stopped)
+                    }
+                }
+            }
+            HotplugState::Watching(watcher, mut receiver) =>
+            {
+                match receiver.next().await
+                {
+                    Some(event) => (Some(Message::Hotplug(event)), HotplugState::Watching(watcher, receiver)),
+                    None        => (None, HotplugState::Stopped),
+                }
+            }
+            HotplugState::Stopped => iced::futures::future::pending().await,
+        }
+    })
+}
+
+
+enum WorkerState {
+    Starting,
+    Running(JobWorker, mpsc::UnboundedReceiver<WorkerEvent>),
+    Stopped,
+}
+
+/// `worker_subscription` - starts job worker, gives its handle as `Message::WorkerReady`, then streams `Message::Worker` events
+pub fn worker_subscription() -> Subscription<Message>
+{
+    struct Worker;
+
+    subscription::unfold(std::any::TypeId::of::<Worker>(), WorkerState::Starting, |state| async move {
+        match state
+        {
+            WorkerState::Starting =>
+            {
+                let (sender, receiver) = mpsc::unbounded();
+                let database = TargetYaml::init_target_db().expect("Err on Yaml Database!");
+                match TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database.clone())
+                {
+                    Ok(target) =>
+                    {
+                        let worker = JobWorker::start(target, database, None, move |event| { sender.unbounded_send(event).ok(); });
+                        let handle = worker.handle();
+                        (Some(Message::WorkerReady(handle)), WorkerState::Running(worker, receiver))
+                    }
+                    Err(_e) =>
+                    {
+                        println!("Job worker not started: {:?}", _e);
+                        (None, WorkerState::Stopped)
+                    }
+                }
+            }
+            WorkerState::Running(worker, mut receiver) =>
+            {
+                match receiver.next().await
+                {
+                    Some(event) => (Some(Message::Worker(event)), WorkerState::Running(worker, receiver)),
+                    None        => (None, WorkerState::Stopped),
+                }
+            }
+            WorkerState::Stopped => iced::futures::future::pending().await,
+        }
+    })
 }
 
 
@@ -64,10 +172,12 @@ pub enum Message {
     TargetProgramminEnd,
     ColorChange(Color),  
     ThemeChange(bool), 
+    DisassemblyView(bool),
     TestBufferDoubleClick,
     OpenGithub,
     OpenFile,
     SaveFile,
+    LoadSymbols,
 
 
     OkButtonPressed,
@@ -81,16 +191,51 @@ pub enum Message {
     ConnectionImageOpen(bool),
     Connect,
     Disconnect,
+    RefreshProbes,
+    ProbeSelect(String),
+    Hotplug(HotplugEvent),
+    AutoReconnectToggle(bool),
+    PipelinedTransfersToggle(bool),
+    RecordSessionToggle(bool),
     PowerSelect(TargetVddSelect),
     PowerToggle,
     ReadTarget,
-    ReadTargetProgress(f32),
     WriteTarget,
-    WriteTargetProgress(f32),
     VerifyTarget,
-    VerifyTargetProgress(f32),
     EraseTarget,
-    EraseTargetProgress(f32),
+    TuneJtagClock,
+    ScanJtagChain,
+    BoardTest,
+    PlaySvf,
+    GdbServer,
+    OpenConsole,
+    CloseConsole,
+    ConsoleInput(String),
+    ConsoleSend,
+    OpenWatch,
+    CloseWatch,
+    WatchInput(String),
+    WatchAdd,
+    WatchRemove(usize),
+    WatchFormatSelect(usize, WatchFormat),
+    WatchInterval(u32),
+    WatchHaltBudget(u32),
+    WatchStart,
+    WatchStop,
+    OpenProfiler,
+    CloseProfiler,
+    ProfileSamples(u32),
+    ProfileInterval(u32),
+    ProfileStart,
+    ProfileStop,
+    ProfileSave,
+    SaveCoreDump,
+    OpenCoreDump,
+    CloseCoreDump,
+    DumpRegionSelect(usize),
+    WorkerReady(JobHandle),
+    Worker(WorkerEvent),
+    CancelJob,
     TestFeedback,
     
 }
@@ -100,8 +245,14 @@ pub struct App {
     pub    target             : TargetDsc,
            target_database    : TargetYaml,
 
-           programmer         : Option<Programmer>,
-           programmer2        : Option<Arc<RwLock<Programmer>>>,
+           worker             : Option<JobHandle>,
+    pub    selected_target    : TargetSelector,
+    pub    probes             : Vec<UsbdmProbe>,
+    pub    preferred_probe    : Option<String>,
+    pub    auto_reconnect     : bool,
+    pub    pipelined_transfers: bool,
+           probe_lost         : bool,
+    pub    record_session     : bool,
 
    // pub    buffer             : HexBuffer,
            buffer_path        : String,
@@ -120,11 +271,31 @@ pub struct App {
     pub    error_status       : Option<Error>,
     pub    theme              : iced::Theme,
     pub    dark_mode          : bool,
+    pub    disassembly_view   : bool,
+    /// text console of `Job::Console`, `console_sender` is `Some` while job runs
+    pub    console_open       : bool,
+    pub    console_text       : String,
+    pub    console_input      : String,
+    pub    console_sender     : Option<std::sync::mpsc::Sender<String>>,
+    /// live watch of `Job::LiveWatch`, items are edited only while it is not running
+    pub    watch_open         : bool,
+    pub    watch_input        : String,
+    pub    watch              : WatchTable,
+    pub    watch_settings     : WatchSettings,
+    pub    watch_running      : bool,
+    /// PC profile of `Job::Profile`, last one kept for export until next start
+    pub    profiler_open      : bool,
+    pub    profile_settings   : ProfileSettings,
+    pub    profile            : Option<Profile>,
+    pub    profile_running    : bool,
+    /// core dump shown in viewer, captured by `Job::CoreDump` or opened from file with no programmer
+    pub    dump_open          : bool,
+    pub    dump               : Option<CoreDump>,
+    pub    dump_region        : usize,
+    /// symbols of ELF loaded alongside image, empty until loaded
+    pub    symbols            : std::sync::Arc<SymbolTable>,
     pub    progress_bar_value : f32,
     pub    title              : String,
-    
-           progr_buff         : Vec<Vec<u8>>,   // for debug
-           progr_address     : u32,            // for debug
 
 
 }
@@ -200,6 +371,62 @@ impl App
         }
     }
 
+    /// Open the file dialog to select CodeWarrior ELF output of loaded image
+    fn symbols_file_dialog() -> Result<Option<String>, OsString> {
+        let path = FileDialog::new()
+            .add_filter(".elf", &["elf"])
+            .show_open_single_file()
+            .unwrap();
+
+        match path {
+            Some(path) => path.into_os_string().into_string().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Open the file dialog to select BSDL description or nets of board test, SVF file
+    fn board_file_dialog(name: &str, extensions: &[&str]) -> Result<Option<String>, OsString> {
+        let path = FileDialog::new()
+            .add_filter(name, extensions)
+            .show_open_single_file()
+            .unwrap();
+
+        match path {
+            Some(path) => path.into_os_string().into_string().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Open the file dialog to export profile, format follows extension
+    fn profile_file_dialog() -> Result<Option<String>, OsString> {
+        let path = FileDialog::new()
+            .add_filter(".csv", &["csv"])
+            .add_filter(".folded", &["folded"])
+            .show_save_single_file()
+            .unwrap();
+
+        match path {
+            Some(path) => path.into_os_string().into_string().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Open the file dialog to select core dump, `save` for new one
+    fn dump_file_dialog(save: bool) -> Result<Option<String>, OsString> {
+        let dialog = FileDialog::new()
+            .add_filter(".dscdump", &["dscdump"]);
+        let path = match save {
+            true  => dialog.show_save_single_file(),
+            false => dialog.show_open_single_file(),
+        }
+        .unwrap();
+
+        match path {
+            Some(path) => path.into_os_string().into_string().map(Some),
+            None => Ok(None),
+        }
+    }
+
       fn save_file_dialog() -> Result<Option<String>, OsString> {
         
        let path  =  FileDialog::new();
@@ -248,100 +475,206 @@ impl App
             .unwrap();
     }
 
-  fn check_connection_programmer(&mut self)
+  /// `submit_job` - pass job to worker thread, error modal if worker is not running
+  ///
+  /// Job refused while session (console, live watch, gdb) runs leaves target connected as it was. Gives `false` if not submitted.
+  fn submit_job(&mut self, job: Job) -> bool
   {
-    let mut prog =  self.programmer.as_mut().expect("Try to Connect to Opt:None Target!");
-
-    let mut check_connect_usb =  prog.refresh_feedback();
-    ok_or_show_error(self, check_connect_usb.clone());
-    match check_connect_usb
+    let submitted = match self.worker.as_ref()
     {
-        Ok(_) =>
-        {
-            self.status = UsbdmAppStatus::Connected;
-
-        }
-
-
-        Err(_) =>
-        {
-            self.title =  "usbdm_mc56f_rs ".to_string() + &"not connected ".to_string();
-            self.status = UsbdmAppStatus::NotConnected;
-            self.target_status = TargetStatus::NotConnected;
-
-        }
-    }
-           
- }
-
- 
-
- fn check_connection_target(&mut self)
- {
-    
-    self.check_connection_programmer();
-
-    if(self.status == UsbdmAppStatus::NotConnected)
+      Some(worker) => worker.submit(job),
+      None         => Err(Error::InternalError("Job worker not started".to_string())),
+    };
+    match submitted
     {
-        return;
+      Ok(()) => true,
+      Err(Error::SessionRunning(kind)) =>
+      {
+        self.programming_end();
+        show_error(self, Error::SessionRunning(kind));
+        false
+      }
+      Err(_e) =>
+      {
+        self.show_p_progress = false;
+        show_error(self, _e);
+        false
+      }
     }
+  }
 
-    let mut dsc =  Box::new(&mut self.target);// self.target.as_mut().expect("Try to Connect to Opt:None Target!");
-    let prog = self.programmer.as_mut().expect("Try to Connect to Opt:None Programmer!");
+  /// `programming_end` - close progress and return target to connected state after read/write/verify/erase
+  fn programming_end(&mut self)
+  {
+    self.show_p_progress = false;
+    self.target_status   = TargetStatus::Connected;
+  }
 
-    let mut check_connect_dsc =  dsc.connect(self.selected_power, prog);
-    ok_or_show_error(self, check_connect_dsc.clone());
-    match check_connect_dsc
+  fn handle_worker_event(&mut self, event: WorkerEvent) -> iced::Command<Message>
+  {
+    match event
     {
+      WorkerEvent::Opened { name, version } =>
+      {
+        self.status = UsbdmAppStatus::Connected;
+        self.title =  "usbdm_mc56f_rs ".to_string() + &"connected ".to_string() + &name + &version;
+      }
 
-        Ok(_) =>
+      WorkerEvent::Closed | WorkerEvent::ProbeLost =>
+      {
+        self.target.disconnect();
+        self.status             = UsbdmAppStatus::NotConnected;
+        self.target_status      = TargetStatus::NotConnected;
+        self.power_status       = PowerStatus::PowerOff;
+        self.show_p_progress    = false;
+        self.show_confirmation  = false;
+        self.title =  "usbdm_mc56f_rs ".to_string() + &"not connected ".to_string();
+        if let WorkerEvent::ProbeLost = event
         {
-            self.target_status = TargetStatus::Connected;
-
+          println!("USBDM unplugged, programmer dropped");
+          self.probe_lost = true;
+          notify_user(self, "USBDM was unplugged, connection closed".to_string(), "USBDM disconnected".to_string());
         }
+      }
 
+      WorkerEvent::Progress(value) =>
+      {
+        self.progress_bar_value = value;
+      }
 
-        Err(_) =>
-        {
-            self.target_status = TargetStatus::NotConnected;
-            println!("check_connection_target error");
+      WorkerEvent::Log(line) =>
+      {
+        println!("{}", line);
+      }
 
+      WorkerEvent::Console(text) =>
+      {
+        self.console_text.push_str(&text);
+        // keep tail of long sessions
+        if self.console_text.len() > CONSOLE_TEXT_LIMIT
+        {
+          let cut = self.console_text.len() - CONSOLE_TEXT_LIMIT;
+          let cut = (cut..self.console_text.len()).find(|index| self.console_text.is_char_boundary(*index)).unwrap_or(0);
+          self.console_text.drain(..cut);
         }
-    }
-
- }
+      }
 
- 
- fn check_power_state(&mut self)
- {
-
-    self.check_connection_programmer();
-
-    if(self.status == UsbdmAppStatus::NotConnected)
-    {
-        return;
-    }
+      WorkerEvent::Watch(sample) =>
+      {
+        self.watch.update(&sample);
+      }
 
-    let mut prog =  self.programmer.as_mut().expect("Try to Connect to Opt:None Target!");
-    let mut power_state =  prog.get_power_state();
-    match power_state
-    {
+      WorkerEvent::Power(power_status) =>
+      {
+        self.power_status = power_status;
+      }
 
-        Ok(power_state) =>
+      WorkerEvent::Finished(kind, result) =>
+      {
+        match (kind, result)
         {
-            self.power_status = power_state;
-
+          (JobKind::Connect, _) =>
+          {
+            self.target_status = TargetStatus::Connected;
+          }
+          (JobKind::Read, JobResult::Buffer(buffer)) =>
+          {
+            self.target.memory_buffer = buffer;
+            self.programming_end();
+          }
+          (JobKind::Write, _) =>
+          {
+            self.programming_end();
+          }
+          (JobKind::Verify, _) =>
+          {
+            self.programming_end();
+            notify_user(self, "Verification successfully completed".to_string(), "Verify Target End".to_string());
+          }
+          (JobKind::Erase, _) =>
+          {
+            println!("target erase ok!");
+            self.programming_end();
+            notify_user(self, "Erase successfully completed".to_string(), "Erase Target End".to_string());
+          }
+          (JobKind::SpeedSearch, JobResult::SpeedSearch(report)) =>
+          {
+            self.programming_end();
+            notify_user(self, report.to_string(), "JTAG clock".to_string());
+          }
+          (JobKind::ScanChain, JobResult::Chain(chain)) =>
+          {
+            let mut message = chain.to_string();
+            if chain.taps.len() > 1
+            {
+              message += "DSC is not alone on chain: programming and debug need it alone, boundary scan pads other TAPs";
+            }
+            notify_user(self, message, "JTAG chain".to_string());
+          }
+          (JobKind::BoardTest, JobResult::BoardTest(report)) =>
+          {
+            notify_user(self, report.to_string(), "Board test".to_string());
+          }
+          (JobKind::PlaySvf, JobResult::Svf(report)) =>
+          {
+            notify_user(self, report.to_string(), "SVF player".to_string());
+          }
+          (JobKind::Console, _) =>
+          {
+            self.console_sender = None;
+          }
+          (JobKind::LiveWatch, _) =>
+          {
+            self.watch_running = false;
+          }
+          (JobKind::Profile, JobResult::Profile(profile)) =>
+          {
+            self.profile_running = false;
+            self.profile = Some(profile);
+          }
+          (JobKind::CoreDump, JobResult::CoreDump(dump)) =>
+          {
+            self.programming_end();
+            self.dump        = Some(dump);
+            self.dump_region = 0;
+            self.dump_open   = true;
+          }
+          (JobKind::GdbServer, _) =>
+          {
+            self.programming_end();
+            notify_user(self, "gdb session ended, target left as gdb left it".to_string(), "GDB server".to_string());
+          }
+          _ => {}
         }
+      }
 
-
-        Err(err) =>
+      WorkerEvent::Failed(kind, _e) =>
+      {
+        println!("{:?} error", kind);
+        match kind
         {
-            println!("check_power_state error");
-            show_error(  self, err);
-            return;
-
+          JobKind::Connect => self.target_status = TargetStatus::NotConnected,
+          JobKind::Console => self.console_sender = None,
+          JobKind::LiveWatch => self.watch_running = false,
+          JobKind::Profile => self.profile_running = false,
+          JobKind::Read | JobKind::Write | JobKind::Verify | JobKind::Erase | JobKind::SpeedSearch | JobKind::GdbServer | JobKind::CoreDump => self.programming_end(),
+          _ => {}
         }
-    }         
+        show_error(self, _e);
+      }
+
+      WorkerEvent::Cancelled(kind) =>
+      {
+        self.programming_end();
+        let message = match kind
+        {
+          JobKind::Write | JobKind::Erase => format!("{:?} cancelled, target memory may be partially done", kind),
+          _                               => format!("{:?} cancelled", kind),
+        };
+        notify_user(self, message, "Cancelled".to_string());
+      }
+    }
+    iced::Command::none()
   }
 }
 
@@ -367,6 +700,24 @@ impl Application for App {
          
                 theme,
                 dark_mode          : false,  
+                disassembly_view   : false,
+                console_open       : false,
+                console_text       : String::new(),
+                console_input      : String::new(),
+                console_sender     : None,
+                watch_open         : false,
+                watch_input        : String::new(),
+                watch              : WatchTable::default(),
+                watch_settings     : WatchSettings::default(),
+                watch_running      : false,
+                profiler_open      : false,
+                profile_settings   : ProfileSettings::default(),
+                profile            : None,
+                profile_running    : false,
+                dump_open          : false,
+                dump               : None,
+                dump_region        : 0,
+                symbols            : Default::default(),
               //  buffer             : HexBuffer::default(),
                 buffer_path        : "".to_string(),
                 notify_title       : "".to_string(),
@@ -381,15 +732,19 @@ impl Application for App {
                 selected_power     : TargetVddSelect::Vdd3V3,
                 target             : TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database.clone()).expect("Target Builder Fault!"),
                 target_database    : database,
-                programmer         : None,
-                programmer2        : None,
+                worker             : None,
+                selected_target    : TargetSelector::Mc56f8035,
+                probes             : Vec::new(),
+                preferred_probe    : preferred_probe_serial(),
+                auto_reconnect     : true,
+                pipelined_transfers: Preferences::load().pipelined_transfers,
+                probe_lost         : false,
+                record_session     : false,
                 status             : UsbdmAppStatus::NotConnected,
                 target_status      : TargetStatus::NotConnected,
                 power_status       : PowerStatus::PowerOff,
                 progress_bar_value : 0.0,
                 title              : "usbdm_mc56f_rs ".to_string() + &"not connected ".to_string(),
-                progr_buff         : vec![vec![0;0]],
-                progr_address     : 0,
 
             },
             iced::Command::none(),
@@ -405,19 +760,22 @@ impl Application for App {
     }
 
     fn update(&mut self, message: Self::Message) -> iced::Command<Self::Message> {
+
         match message {
 
-            Message::TargetSelect(target) => 
+            Message::TargetSelect(selector) => 
             {
                                  
              /* TargetSelect for new target programming interface, with abstract factory */
-             let selected = TargetDsc::target_from_selector(target, self.target_database.clone());
+             let selected = TargetDsc::target_from_selector(selector, self.target_database.clone());
              match selected {
 
                 Ok(mut target) => 
                 { 
                     self.target = target;
+                    self.selected_target = selector;
                     self.target_status = TargetStatus::NotConnected;
+                    self.submit_job(Job::SelectTarget(selector));
                     return iced::Command::none();
                 }
                 Err(_e) => 
@@ -470,6 +828,10 @@ impl Application for App {
                     })
                 }
             }
+
+            Message::DisassemblyView(b) => {
+                self.disassembly_view = b;
+            }
     
          
 
@@ -523,6 +885,25 @@ impl Application for App {
 
             }
 
+            Message::LoadSymbols =>
+            {
+              let path = match App::symbols_file_dialog() {
+                Ok(Some(path)) => path,
+                Ok(None)       => return iced::Command::none(),
+                Err(e)         => {
+                  App::display_alert(&self, "usbdm_mc56f_rs", &format!("Error while selecting file!\n{:?}", e), MessageType::Error);
+                  return iced::Command::none();
+                }
+              };
+              match SymbolTable::load(&path) {
+                Ok(symbols) => {
+                  notify_user(self, format!("{}\n{}", path, symbols), "Symbols loaded".to_string());
+                  self.symbols = std::sync::Arc::new(symbols);
+                }
+                Err(e) => show_error(self, e),
+              }
+            }
+
             Message::SaveFile => 
             {
 
@@ -593,13 +974,14 @@ impl Application for App {
                     {
                     self.progress_bar_value = 0.0;
                     self.show_p_progress = true;
-                    return iced::Command::perform(handle_progress( 0.0), Message::WriteTargetProgress);
+                    let buffer = self.target.memory_buffer.clone();
+                    self.submit_job(Job::Write { power: self.selected_power, buffer });
                     }
                     TargetStatus::InProgrammingErase => 
                     {
                     self.progress_bar_value = 0.0;
                     self.show_p_progress = true;
-                    return iced::Command::perform(handle_progress( 0.0), Message::EraseTargetProgress);
+                    self.submit_job(Job::Erase { power: self.selected_power });
                     }
                     _ =>
                     {
@@ -617,147 +999,130 @@ impl Application for App {
             
             Message::Connect => 
             {  
+              // worker opens USBDM if not opened yet, inits it for target and connects target
+              println!("Try claim usb & configure descriptors");
+              self.submit_job(Job::Connect { power: self.selected_power, record_session: self.record_session });
+            } 
 
-             match self.status
-             {
+            Message::WorkerReady(handle) =>
+            {
+              self.worker = Some(handle);
+              // worker starts with default target, sync it with selected one
+              self.submit_job(Job::SelectTarget(self.selected_target));
+            }
+
+            Message::Worker(event) =>
+            {
+              return self.handle_worker_event(event);
+            }
+
+            Message::CancelJob =>
+            {
+              if let Some(worker) = self.worker.as_ref()
+              {
+                worker.cancel();
+              }
+            }
 
-                UsbdmAppStatus::NotConnected =>
+            Message::RefreshProbes =>
+            {
+              // opening probe in use fails, so list only when we don't hold one
+              if self.status == UsbdmAppStatus::Connected
+              {
+                return iced::Command::none();
+              }
+
+              match list_usbdm()
+              {
+                Ok(probes) =>
                 {
-                    // check usb low level - two type errors : can't find VID and second cannot configure descriptor (bad drivers or hw error on usb port)
-                    let check_connect = find_usbdm();
-                    
-                    match check_connect
-                    {
-                       Ok(check_connect) =>
-                       {
-   
-                       println!("Try claim usb & configure descriptors");
-                       let usb_int = UsbInterface::new(check_connect);
-        
-                       match usb_int
-                       {
-                        Ok(usb_int) =>
-                        {   
-                            // init programmer, here we can get errors on get version, settings feedback etc.
-
-                            let programmer = Programmer::new(usb_int);     
-                            match programmer
-                            {
-                                Ok(programmer) =>
-                                {
-                                
-                                  self.programmer = Some(programmer);
-                                  let handle =  self.programmer.as_mut().expect("");
-                                  self.title =  "usbdm_mc56f_rs ".to_string() + &"connected ".to_string() + &handle.name.clone() +  &handle.get_string_version().clone();
-                                
-                                }
-
-                                Err(_e) =>
-
-                                {
-                                show_error(  self, _e);
-                                return iced::Command::none();
-
-                                }
-                            }
-                        }
-                        Err(_e) =>
-                        {
-                            show_error(  self, _e);
-                            return iced::Command::none();
-
-                        }
-                       }
-                       self.target_status = TargetStatus::NotConnected;
-
-                       let prog = self.programmer.as_mut().expect("Try to Connect to Opt:None Programmer!");
-
-                       let init = self.target.init(prog);
-
-                       match init
-                       {
-                           Ok(_) =>
-                           {
-                            self.check_connection_target();
-                            self.check_power_state();
-                           }
-
-                           Err(_e) =>
-
-                           {
-                           show_error(  self, _e);
-                           println!("Error on init programmer for Target!");
-                           return iced::Command::none();
-
-                           }
-                       }
-
-              
-                    
-                    }
-                    Err(_e) =>
-                    {
-                       show_error(  self, _e);
-                       self.status = UsbdmAppStatus::NotConnected;
-                       return iced::Command::none();
-                 
-                    }
-                  }
+                  self.probes = probes;
                 }
+                Err(_e) =>
+                {
+                  show_error(self, _e);
+                }
+              }
+            }
 
+            Message::ProbeSelect(serial_number) =>
+            {
+              if serial_number.is_empty()
+              {
+                return iced::Command::none();
+              }
+              self.preferred_probe = Some(serial_number.clone());
+              if let Err(_e) = Preferences::set_preferred_probe(&serial_number)
+              {
+                show_error(self, _e);
+              }
+            }
 
+            Message::Hotplug(HotplugEvent::Left(port)) =>
+            {
+              self.probes.retain(|probe| !(probe.bus == port.bus && probe.address == port.address));
 
-                UsbdmAppStatus::Connected => 
-                {  
-
-                    self.check_connection_target(); 
-                    self.check_power_state();
+              // worker knows port of its programmer, answers with WorkerEvent::ProbeLost if it was ours
+              if self.status == UsbdmAppStatus::Connected
+              {
+                self.submit_job(Job::PortLeft(port));
+              }
+            }
 
-                }
-    
-                _ => 
+            Message::Hotplug(HotplugEvent::Arrived(_port)) =>
+            {
+              if self.probe_lost && self.status == UsbdmAppStatus::NotConnected
+              {
+                if self.auto_reconnect
                 {
-                    return iced::Command::none(); // we should never be here!!
-                } 
+                  // Connect opens preferred probe and runs init_usbdm_for_mc56f through target init
+                  self.probe_lost  = false;
+                  self.show_notify = false;
+                  return iced::Command::perform(replug_settle(), |_| Message::Connect);
+                }
+                notify_user(self, "USBDM plugged back, press Connect to init it again".to_string(), "USBDM connected".to_string());
               }
-            } 
+            }
+
+            Message::AutoReconnectToggle(auto_reconnect) =>
+            {
+              self.auto_reconnect = auto_reconnect;
+            }
+
+            Message::PipelinedTransfersToggle(pipelined_transfers) =>
+            {
+              // takes effect on next connect, programmer gets transfer mode from preferences
+              self.pipelined_transfers = pipelined_transfers;
+              if let Err(_e) = Preferences::set_pipelined_transfers(pipelined_transfers)
+              {
+                show_error(self, _e);
+              }
+            }
+
+            Message::RecordSessionToggle(record_session) =>
+            {
+              // takes effect on next connect, session file is opened together with USBDM
+              self.record_session = record_session;
+            }
 
             Message::Disconnect => 
             {    
-             
-        
                 println!("Try disconnect and drop");
-                self.target.disconnect();
-                self.status  = UsbdmAppStatus::NotConnected; 
-                
+                self.submit_job(Job::Disconnect);
             } 
 
             Message::PowerToggle => 
             {    
-
-            self.check_power_state();
-
                match self.power_status
                {
-
                  PowerStatus::PowerOn =>
                  {
-                    let dsc =  Box::new(&mut self.target);
-                    let prog = self.programmer.as_mut().expect("Try to Connect to Opt:None Programmer!");
-                    dsc.power(TargetVddSelect::VddOff, prog);
-                    self.check_power_state();
-
+                    self.submit_job(Job::Power(TargetVddSelect::VddOff));
                  }
 
                  PowerStatus::PowerOff =>
-
                  {
-    
-                    let dsc =  Box::new(&mut self.target);
-                    let prog = self.programmer.as_mut().expect("Try to Connect to Opt:None Programmer!");
-                    dsc.power(self.selected_power, prog);
-                    self.check_power_state();
-
+                    self.submit_job(Job::Power(self.selected_power));
                  }
               }
             }   
@@ -772,281 +1137,326 @@ impl Application for App {
 
             Message::ReadTarget  => 
             {
-
-             self.progr_buff.clear();
-
              self.show_p_progress = true;
-
              self.target_status = TargetStatus::InProgrammingRead;
-  
              self.progress_bar_value = 0.0;
-  
-             let dsc = Box::new(&mut self.target);
-  
-             let programm_range = self.target.programm_range().expect("Get mem range err App"); 
-      
-             self.progr_address = programm_range.start as u32;
+             self.submit_job(Job::Read { power: self.selected_power });
+            }
 
-             return iced::Command::perform(handle_progress( self.progress_bar_value), Message::ReadTargetProgress);
-              
+            Message::WriteTarget  => 
+            {
+              self.target_status = TargetStatus::InProgrammingWrite;
+              self.show_confirmation = true;
+              self.progress_bar_value = 0.0;
+              return iced::Command::none();
             }
 
-            Message::ReadTargetProgress(x)  => 
+            Message::VerifyTarget  => 
             {
+              self.show_p_progress = true;
+              self.target_status = TargetStatus::InProgrammingVerify;
+              self.progress_bar_value = 0.0;
+              let buffer = self.target.memory_buffer.clone();
+              self.submit_job(Job::Verify { power: self.selected_power, buffer });
+            }
 
-              self.target_status = TargetStatus::InProgrammingRead;
-              let dsc = Box::new(&mut self.target);
-              let prog = self.programmer.as_mut().expect("Try to Connect to Opt:None Programmer!");
+            Message::TuneJtagClock  =>
+            {
+              self.show_p_progress = true;
+              self.progress_bar_value = 0.0;
+              self.submit_job(Job::SpeedSearch(SpeedSearch::default()));
+            }
 
-              let programm_range = dsc.programm_range().expect("Get mem range err App");
-              let start_address = programm_range.start;
-              let end_address =  (programm_range.end + 1) as usize;
-  
-                
-                if end_address > self.progr_address as usize {
+            Message::ScanJtagChain  =>
+            {
+              self.submit_job(Job::ScanChain { power: self.selected_power });
+            }
 
-                  let read = dsc.read_target(self.selected_power, self.progr_address, prog);
-                  match read
-                  {
-                    Ok(read) => 
-                    {
-                     let read_len = (read.len() / 2) as u32;
-                     self.progr_buff.push(read);
-                     self.progress_bar_value = (((self.progr_address as f32 - start_address as f32) / (end_address as f32 - start_address as f32)) * 100.00) as f32;
-                     self.progr_address += read_len; 
-                     return iced::Command::perform(handle_progress( self.progress_bar_value), Message::ReadTargetProgress);
-                    }    
+            Message::BoardTest  =>
+            {
+              let bsdl = App::board_file_dialog(".bsdl", &["bsd", "bsdl"]);
+              let nets = match bsdl
+              {
+                Ok(Some(_)) => App::board_file_dialog(".yaml nets", &["yaml", "yml"]),
+                _           => Ok(None),
+              };
+              if let (Ok(Some(bsdl)), Ok(Some(nets))) = (bsdl, nets)
+              {
+                self.submit_job(Job::BoardTest { bsdl, nets, power: self.selected_power });
+              }
+            }
 
-                    Err(_e) =>
-                    {
-                    self.show_p_progress = false;
-                    show_error(self, _e);
-                    println!("ReadTarget Error!");
-                    self.check_power_state();
-                    return iced::Command::none();
-                    }
-                  }
-                }
-                
-            dbg!("Read Target End!");
-            dsc.memory_buffer.upload_from_target(self.progr_buff.clone());
-            self.show_p_progress = false;
-            self.check_power_state();
-            self.target_status = TargetStatus::Connected;
-            return iced::Command::none();
+            Message::PlaySvf  =>
+            {
+              if let Ok(Some(path)) = App::board_file_dialog(".svf .xsvf", &["svf", "xsvf"])
+              {
+                self.submit_job(Job::PlaySvf { path, power: self.selected_power });
+              }
+            }
 
+            Message::GdbServer  =>
+            {
+              // progress bar stays with Cancel button while gdb session runs
+              self.show_p_progress = true;
+              self.progress_bar_value = 0.0;
+              self.submit_job(Job::GdbServer { port: DEFAULT_PORT, power: self.selected_power, symbols: self.symbols.clone() });
             }
 
-            Message::WriteTarget  => 
+            Message::OpenConsole  =>
             {
+              // console job keeps worker busy, gui stays usable for typing
+              let (sender, input) = std::sync::mpsc::channel();
+              if self.submit_job(Job::Console { input })
+              {
+                self.console_open   = true;
+                self.console_sender = Some(sender);
+              }
+            }
 
-              self.target_status = TargetStatus::InProgrammingWrite;
-              
-              self.show_confirmation = true;
-  
-              self.progress_bar_value = 0.0;
-  
-              let dsc = Box::new(&mut self.target);
-  
-              let programm_range = self.target.programm_range().expect("Get mem range err App"); 
-      
-              self.progr_address = programm_range.start as u32;
-              dbg!(&self.progr_address);
+            Message::CloseConsole  =>
+            {
+              self.console_open = false;
+              if self.console_sender.take().is_some()
+              {
+                if let Some(worker) = self.worker.as_ref()
+                {
+                  worker.cancel();
+                }
+              }
+            }
 
-              return iced::Command::none();
+            Message::ConsoleInput(text)  =>
+            {
+              self.console_input = text;
             }
 
-            Message::WriteTargetProgress(x)  => 
+            Message::ConsoleSend  =>
             {
-              
-              self.show_p_progress = true;
-              self.target_status = TargetStatus::InProgrammingWrite;
-              let dsc = Box::new(&mut self.target);
-              let programm_range = dsc.programm_range().expect("Get mem range err App");
-              let start_address = programm_range.start;
-              let prog = self.programmer.as_mut().expect("Try to Connect to Opt:None Programmer!");
+              let line = std::mem::take(&mut self.console_input) + "\n";
+              if let Some(sender) = self.console_sender.as_ref()
+              {
+                sender.send(line).ok();
+              }
+            }
 
-              let block_size : usize = 0x500;
-              let current_adress = self.progr_address as usize;
-              let last_address: usize = (programm_range.end + 1) as usize;
+            Message::OpenWatch  =>
+            {
+              self.watch_open = true;
+            }
 
-                
-                if last_address > current_adress {
+            Message::CloseWatch  =>
+            {
+              self.watch_open = false;
+              if self.watch_running
+              {
+                if let Some(worker) = self.worker.as_ref()
+                {
+                  worker.cancel();
+                }
+              }
+            }
 
-                  let to_write: Vec<u8> = dsc.memory_buffer.download_target_block(self.progr_address as usize, block_size).unwrap(); 
-                  let real_size = ((to_write.len() / 2) as u32);
-                  let write_time_start = Instant::now();
-                  let write_target = dsc.write_target(self.selected_power,  self.progr_address, to_write, prog);
-                  match write_target
+            Message::WatchInput(text)  =>
+            {
+              self.watch_input = text;
+            }
+
+            Message::WatchAdd  =>
+            {
+              if !self.watch_running
+              {
+                match WatchItem::new(&self.watch_input, &self.symbols)
+                {
+                  Ok(item) =>
                   {
-                    Ok(write_target) => 
-                    {
-                    self.progress_bar_value = (((self.progr_address as f32 - start_address as f32) / (last_address as f32 - start_address as f32)) * 100.00) as f32;
-                    self.progr_address += real_size; 
-    
-                    let write_duration = write_time_start.elapsed();
-                    let write_throughput = ((real_size * 2) as f32) / write_duration.as_secs_f32();
-                    println!("write speed : {:>8.2} bytes/s,", write_throughput);
-                    return iced::Command::perform(handle_progress( self.progress_bar_value), Message::WriteTargetProgress);
-                    }
-                    Err(_e) =>
-                    {
-                    self.show_p_progress = false;
-                    show_error(self, _e);
-                    println!("WriteTarget error");
-                    self.check_power_state();
-                    return iced::Command::none();
-                    }
+                    self.watch.add(item);
+                    self.watch_input.clear();
                   }
+                  Err(e) => show_error(self, e),
                 }
-                
-            dbg!("Write Target End!");
-            self.show_p_progress = false;
-            dsc.power(TargetVddSelect::VddOff, prog);
-            self.check_power_state();
-            self.target_status = TargetStatus::Connected;
-            return iced::Command::none();
-                
+              }
             }
 
-            Message::VerifyTarget  => 
+            Message::WatchRemove(index)  =>
             {
+              if !self.watch_running
+              {
+                self.watch.remove(index);
+              }
+            }
 
-              self.show_p_progress = true;
-
-              self.target_status = TargetStatus::InProgrammingVerify;
-  
-              self.progress_bar_value = 0.0;
-  
-              let dsc = Box::new(&mut self.target);
-  
-              let programm_range = self.target.programm_range().expect("Get mem range err App"); 
-      
-              self.progr_address = programm_range.start as u32;
+            Message::WatchFormatSelect(index, format)  =>
+            {
+              // running job reads item words of format it was started with
+              if !self.watch_running
+              {
+                self.watch.set_format(index, format);
+              }
+            }
 
-              return iced::Command::perform(handle_progress( self.progress_bar_value), Message::VerifyTargetProgress);
+            Message::WatchInterval(milliseconds)  =>
+            {
+              self.watch_settings.interval = Duration::from_millis(milliseconds as u64);
             }
 
-            Message::VerifyTargetProgress(x)  => 
+            Message::WatchHaltBudget(milliseconds)  =>
             {
+              self.watch_settings.halt_budget = Duration::from_millis(milliseconds as u64);
+            }
 
-              self.target_status = TargetStatus::InProgrammingVerify;
-              let dsc = Box::new(&mut self.target);
-              let prog = self.programmer.as_mut().expect("Try to Connect to Opt:None Programmer!");
+            Message::WatchStart  =>
+            {
+              if !self.watch_running && !self.watch.items.is_empty()
+              {
+                self.watch.clear();
+                self.watch_running = self.submit_job(Job::LiveWatch { items: self.watch.items.clone(), settings: self.watch_settings });
+              }
+            }
 
-              let programm_range = dsc.programm_range().expect("Get mem range err App");
-              let start_address = programm_range.start;
-              let end_address =  (programm_range.end + 1) as usize;
-                
-                if end_address > self.progr_address as usize {
+            Message::WatchStop  =>
+            {
+              // sampler stops with core running
+              if self.watch_running
+              {
+                if let Some(worker) = self.worker.as_ref()
+                {
+                  worker.cancel();
+                }
+              }
+            }
 
-                  let verify = dsc.verify_target(self.selected_power, self.progr_address, prog);
-                  match verify
-                  {
-                    Ok(verify) => 
-                    {
-                     let block_len  = (verify / 2) as u32;
-                     self.progress_bar_value = (((self.progr_address as f32 - start_address as f32) / (end_address as f32 - start_address as f32)) * 100.00) as f32;
-                     self.progr_address += block_len; 
-                     return iced::Command::perform(handle_progress( self.progress_bar_value), Message::VerifyTargetProgress);
-                    }    
+            Message::OpenProfiler  =>
+            {
+              self.profiler_open = true;
+            }
 
-                    Err(_e) =>
-                    {
-                    self.show_p_progress = false;
-                    show_error(self, _e);
-                    println!("Verify_Target Error!");
-                    self.check_power_state();
-                    return iced::Command::none();
-                    }
-                  }
+            Message::CloseProfiler  =>
+            {
+              self.profiler_open = false;
+              if self.profile_running
+              {
+                if let Some(worker) = self.worker.as_ref()
+                {
+                  worker.cancel();
                 }
-                
-            self.show_p_progress = false;
-            self.check_power_state();
-            self.target_status = TargetStatus::Connected;
-            notify_user(self, "Verification successfully completed".to_string(), "Verify Target End".to_string());
-            return iced::Command::none();
-
+              }
             }
 
-            Message::EraseTarget  => 
+            Message::ProfileSamples(samples)  =>
             {
-            
-            self.target_status = TargetStatus::InProgrammingErase;
-            self.show_confirmation = true;
-            return iced::Command::none();
+              self.profile_settings.samples = samples as usize;
+            }
 
-      
+            Message::ProfileInterval(milliseconds)  =>
+            {
+              self.profile_settings.interval = Duration::from_millis(milliseconds as u64);
             }
 
-            
-            Message::EraseTargetProgress(x)  => 
+            Message::ProfileStart  =>
             {
-              
-              self.show_p_progress = true;
-              self.target_status = TargetStatus::InProgrammingErase;
-              self.progress_bar_value = 0.0;
-              
-              let dsc = Box::new(&mut self.target);
-            
-              let erase_target = dsc.erase_target(self.selected_power, self.programmer.as_mut().expect("Try to Connect to Opt:None Target!"));
+              if !self.profile_running
+              {
+                self.profile = None;
+                self.profile_running = self.submit_job(Job::Profile(self.profile_settings));
+              }
+            }
 
-              match erase_target
+            Message::ProfileStop  =>
+            {
+              // profiler ends with samples taken so far and core running
+              if self.profile_running
               {
-                Ok(_) => 
+                if let Some(worker) = self.worker.as_ref()
                 {
-                self.check_power_state();
-                println!("target erase ok!");
-    
+                  worker.cancel();
                 }
-                Err(_e) =>
-                {
-                self.show_p_progress = false;
-                show_error(self, _e);
-                self.check_power_state();
-                println!("erase_target error");
-                return iced::Command::none();
-               }
               }
-
-              self.show_p_progress = false;
-              notify_user(self, "Erase successfully completed".to_string(), "Erase Target End".to_string());
-              self.check_power_state();
-              self.target_status = TargetStatus::Connected;
-              return iced::Command::none();
             }
 
+            Message::ProfileSave  =>
+            {
+              let path = match App::profile_file_dialog() {
+                Ok(Some(path)) => path,
+                Ok(None)       => return iced::Command::none(),
+                Err(e)         => {
+                  App::display_alert(&self, "usbdm_mc56f_rs", &format!("Error while save file!\n{:?}", e), MessageType::Error);
+                  return iced::Command::none();
+                }
+              };
+              if let Some(profile) = self.profile.as_ref()
+              {
+                if let Err(e) = profile.save(&path, &self.symbols)
+                {
+                  show_error(self, e);
+                }
+              }
+            }
 
-            Message::TestFeedback =>
+            Message::SaveCoreDump  =>
             {
-                
-                println!("TestFeedback");
+              let path = match App::dump_file_dialog(true) {
+                Ok(Some(path)) => path,
+                Ok(None)       => return iced::Command::none(),
+                Err(e)         => {
+                  App::display_alert(&self, "usbdm_mc56f_rs", &format!("Error while save file!\n{:?}", e), MessageType::Error);
+                  return iced::Command::none();
+                }
+              };
+              // core dump halts a running core and leaves it halted for inspection
+              self.show_p_progress = true;
+              self.progress_bar_value = 0.0;
+              self.submit_job(Job::CoreDump { path });
+            }
 
+            Message::OpenCoreDump  =>
+            {
+              let path = match App::dump_file_dialog(false) {
+                Ok(Some(path)) => path,
+                Ok(None)       => return iced::Command::none(),
+                Err(e)         => {
+                  App::display_alert(&self, "usbdm_mc56f_rs", &format!("Error while selecting file!\n{:?}", e), MessageType::Error);
+                  return iced::Command::none();
+                }
+              };
+              match CoreDump::load(&path) {
+                Ok(dump) => {
+                  self.dump        = Some(dump);
+                  self.dump_region = 0;
+                  self.dump_open   = true;
+                }
+                Err(e) => show_error(self, e),
+              }
+            }
 
-                let mut dsc =  Box::new(&mut self.target);
-                
-                let prog = self.programmer.as_mut().expect("Try to Connect to Opt:None Programmer!");
+            Message::CloseCoreDump  =>
+            {
+              self.dump_open = false;
+            }
 
+            Message::DumpRegionSelect(index)  =>
+            {
+              self.dump_region = index;
+            }
 
-                let test_debug = dsc.test_get_speed_routine(self.selected_power, prog);
+            Message::EraseTarget  => 
+            {
+            
+            self.target_status = TargetStatus::InProgrammingErase;
+            self.show_confirmation = true;
+            return iced::Command::none();
 
-                match test_debug
-                {
-                  Ok(_) => 
-                  {
-                  self.check_power_state();
-                  println!("test_get_speed_routine ok!");
       
-                  }
-                  Err(_e) =>
-                  {
-                  show_error(self, _e);
-                  self.check_power_state();
-                  println!("test_get_speed_routine error");
-                  return iced::Command::none();
-                 }
-                }
+            }
+
+            Message::TestFeedback =>
+            {
+                
+                println!("TestFeedback");
+                let power = self.selected_power;
+                self.submit_job(Job::Custom("test_get_speed_routine".to_string(), Box::new(move |dsc, prog, _context| {
+                    dsc.test_get_speed_routine(power, prog)?;
+                    Ok(JobResult::Done)
+                })));
             } 
 
             Message::TargetProgramminEnd =>
@@ -1066,6 +1476,10 @@ impl Application for App {
         iced::Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        Subscription::batch(vec![hotplug_subscription(), worker_subscription()])
+    }
+
 /* 
     fn subscription(&self) -> Subscription<Message> {
         match self.target_status {
@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use crate::errors::{Error};
+
+/// `PortDirection` - mode of BSDL `port` entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortDirection { In, Out, InOut, Buffer, Linkage }
+
+/// `BsdlPort` - one pin of logical port, `bit_vector` ports are expanded as `NAME(i)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BsdlPort {
+    pub name      : String,
+    pub direction : PortDirection,
+}
+
+/// `CellFunction` - function of boundary register cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellFunction { Input, Output2, Output3, Control, ControlR, Internal, Clock, Bidir, ObserveOnly }
+
+impl CellFunction {
+    /// cell drives pin in EXTEST
+    pub fn drives(&self) -> bool {
+        matches!(self, CellFunction::Output2 | CellFunction::Output3 | CellFunction::Bidir)
+    }
+
+    /// cell captures pin
+    pub fn observes(&self) -> bool {
+        matches!(self, CellFunction::Input | CellFunction::Clock | CellFunction::Bidir | CellFunction::ObserveOnly)
+    }
+}
+
+/// `BoundaryCell` - entry of `BOUNDARY_REGISTER`: `num (cell, port, function, safe [, ccell, disval, rslt])`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundaryCell {
+    pub number    : usize,
+    pub cell_type : String,
+    /// `None` for `*` (control and internal cells)
+    pub port      : Option<String>,
+    pub function  : CellFunction,
+    /// `None` for `X`
+    pub safe      : Option<bool>,
+    /// control cell and value which disables this output
+    pub control   : Option<(usize, bool)>,
+}
+
+/// `Bsdl` - what boundary scan needs from BSDL description of device
+///
+/// Subset of IEEE 1149.1 BSDL: entity name, `port` list, pin map of selected package, instruction length,
+/// opcodes and capture, IDCODE register and boundary register. Other attributes are kept as text in `attributes`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Bsdl {
+    pub entity             : String,
+    pub package            : String,
+    pub ports              : Vec<BsdlPort>,
+    /// port name -> package pin
+    pub pin_map            : HashMap<String, String>,
+    pub instruction_length : u8,
+    /// instruction name -> opcodes (first one is used)
+    pub instructions       : HashMap<String, Vec<u32>>,
+    /// IDCODE value and mask of fixed bits (`X` bits are 0 in mask)
+    pub id_code            : Option<(u32, u32)>,
+    pub boundary_length    : usize,
+    /// indexed by cell number, cell 0 is nearest TDO
+    pub cells              : Vec<BoundaryCell>,
+    pub attributes         : HashMap<String, String>,
+}
+
+fn parse_error(message: String) -> Error {
+    Error::FileParserError(format!("BSDL: {}", message))
+}
+
+/// remove `--` comments
+fn strip_comments(text: &str) -> String {
+    text.lines().map(|line| {
+        let mut in_string = false;
+        let mut end = line.len();
+        let bytes = line.as_bytes();
+        for i in 0..bytes.len() {
+            match bytes[i] {
+                b'"' => in_string = !in_string,
+                b'-' if !in_string && bytes.get(i + 1) == Some(&b'-') => { end = i; break },
+                _ => {},
+            }
+        }
+        &line[..end]
+    }).collect::<Vec<&str>>().join("\n")
+}
+
+/// split in statements on `;` outside of parentheses and strings
+fn statements(text: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    for c in text.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth -= 1,
+            ';' if !in_string && depth == 0 => {
+                statements.push(current.trim().to_string());
+                current.clear();
+                continue
+            },
+            _ => {},
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        statements.push(current.trim().to_string());
+    }
+    statements
+}
+
+/// `"abc" & "def"` -> `abcdef`, numbers and names as they are
+fn value_text(value: &str) -> String {
+    let value = value.trim();
+    if !value.starts_with('"') {
+        return value.to_string()
+    }
+    value.split('&').map(|part| part.trim().trim_matches('"')).collect()
+}
+
+/// position of `word` surrounded by whitespace
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    text.match_indices(word).map(|(position, _)| position).find(|&position| {
+        let before = text[..position].chars().next_back().is_some_and(char::is_whitespace);
+        let after = text[position + word.len()..].chars().next().is_some_and(char::is_whitespace);
+        before && after
+    })
+}
+
+/// text of outer `( ... )`
+fn parenthesised(text: &str) -> Option<&str> {
+    let start = text.find('(')?;
+    let end = text.rfind(')')?;
+    (end > start).then(|| &text[start + 1..end])
+}
+
+/// split on `,` outside of parentheses
+fn split_top(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue
+            },
+            _ => {},
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+fn parse_ports(body: &str) -> Result<Vec<BsdlPort>, Error> {
+    let mut ports = Vec::new();
+    for entry in body.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (names, kind) = entry.split_once(':').ok_or_else(|| parse_error(format!("port entry `{}`", entry)))?;
+        let kind = kind.trim().to_lowercase();
+        let mut words = kind.split_whitespace();
+        let direction = match words.next() {
+            Some("in")      => PortDirection::In,
+            Some("out")     => PortDirection::Out,
+            Some("inout")   => PortDirection::InOut,
+            Some("buffer")  => PortDirection::Buffer,
+            Some("linkage") => PortDirection::Linkage,
+            other => return Err(parse_error(format!("port direction {:?} in `{}`", other, entry))),
+        };
+        let range = if kind.contains("bit_vector") {
+            let range = parenthesised(&kind).ok_or_else(|| parse_error(format!("bit_vector range in `{}`", entry)))?;
+            let bounds: Vec<&str> = range.split_whitespace().collect();
+            match bounds.as_slice() {
+                [from, "to" | "downto", to] => {
+                    let (from, to) = (from.parse::<usize>(), to.parse::<usize>());
+                    match (from, to) {
+                        (Ok(from), Ok(to)) => Some((from.min(to), from.max(to))),
+                        _ => return Err(parse_error(format!("bit_vector range in `{}`", entry))),
+                    }
+                },
+                _ => return Err(parse_error(format!("bit_vector range in `{}`", entry))),
+            }
+        } else {
+            None
+        };
+        for name in names.split(',').map(str::trim) {
+            match range {
+                Some((from, to)) => ports.extend((from..=to).map(|i| BsdlPort { name: format!("{}({})", name, i), direction })),
+                None => ports.push(BsdlPort { name: name.to_string(), direction }),
+            }
+        }
+    }
+    Ok(ports)
+}
+
+fn parse_pin_map(text: &str) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    for entry in split_top(text) {
+        if let Some((port, pins)) = entry.split_once(':') {
+            let port = port.trim();
+            let pins = pins.trim();
+            match parenthesised(pins) {
+                // vector port: pins in order of index
+                Some(list) => for (i, pin) in list.split(',').enumerate() {
+                    map.insert(format!("{}({})", port, i), pin.trim().to_string());
+                },
+                None => { map.insert(port.to_string(), pins.to_string()); },
+            }
+        }
+    }
+    map
+}
+
+fn parse_bits(text: &str) -> Result<(u32, u32), Error> {
+    let mut value = 0u32;
+    let mut mask = 0u32;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        value <<= 1;
+        mask <<= 1;
+        match c {
+            '0' => mask |= 1,
+            '1' => { value |= 1; mask |= 1 },
+            'x' | 'X' => {},
+            _ => return Err(parse_error(format!("bit pattern `{}`", text))),
+        }
+    }
+    Ok((value, mask))
+}
+
+fn parse_cell(entry: &str) -> Result<BoundaryCell, Error> {
+    let (number, fields) = entry.split_once('(').ok_or_else(|| parse_error(format!("boundary cell `{}`", entry)))?;
+    let number = number.trim().parse::<usize>().map_err(|_| parse_error(format!("cell number in `{}`", entry)))?;
+    let fields: Vec<String> = fields.trim_end_matches(')').split(',').map(|field| field.trim().to_string()).collect();
+    if fields.len() < 4 {
+        return Err(parse_error(format!("boundary cell `{}` has {} fields", entry, fields.len())))
+    }
+    let function = match fields[2].to_lowercase().as_str() {
+        "input"        => CellFunction::Input,
+        "output2"      => CellFunction::Output2,
+        "output3"      => CellFunction::Output3,
+        "control"      => CellFunction::Control,
+        "controlr"     => CellFunction::ControlR,
+        "internal"     => CellFunction::Internal,
+        "clock"        => CellFunction::Clock,
+        "bidir"        => CellFunction::Bidir,
+        "observe_only" => CellFunction::ObserveOnly,
+        other => return Err(parse_error(format!("cell function `{}` in `{}`", other, entry))),
+    };
+    let bit = |text: &str| match text { "0" => Some(false), "1" => Some(true), _ => None };
+    let control = if fields.len() >= 6 {
+        let cell = fields[4].parse::<usize>().map_err(|_| parse_error(format!("control cell in `{}`", entry)))?;
+        let disable = bit(&fields[5]).ok_or_else(|| parse_error(format!("disable value in `{}`", entry)))?;
+        Some((cell, disable))
+    } else {
+        None
+    };
+    Ok(BoundaryCell {
+        number,
+        cell_type : fields[0].clone(),
+        port      : (fields[1] != "*").then(|| fields[1].clone()),
+        function,
+        safe      : bit(&fields[3]),
+        control,
+    })
+}
+
+impl Bsdl {
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let text = fs::read_to_string(path).map_err(|e| parse_error(format!("{}: {}", path, e)))?;
+        Self::parse(&text)
+    }
+
+    /// `parse` - BSDL text, package from `PHYSICAL_PIN_MAP` generic
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut bsdl = Bsdl::default();
+        let mut constants: HashMap<String, String> = HashMap::new();
+
+        for statement in statements(&strip_comments(text)) {
+            let lower = statement.to_lowercase();
+            let keyword = lower.split_whitespace().next().unwrap_or("");
+            match keyword {
+                "entity" => {
+                    bsdl.entity = statement.split_whitespace().nth(1).unwrap_or("").to_string();
+                    // generic and port may follow in the same statement up to first `;`
+                    if let Some(position) = lower.find("generic") {
+                        bsdl.package = Self::generic_package(&statement[position..]);
+                    }
+                },
+                "generic" => bsdl.package = Self::generic_package(&statement),
+                "port" => {
+                    let body = parenthesised(&statement).ok_or_else(|| parse_error("port list".to_string()))?;
+                    bsdl.ports = parse_ports(body)?;
+                },
+                "constant" => {
+                    // constant NAME : PIN_MAP_STRING := "...";
+                    let name = statement.split_whitespace().nth(1).unwrap_or("").trim_end_matches(':').to_string();
+                    if let Some((_, value)) = statement.split_once(":=") {
+                        constants.insert(name, value_text(value));
+                    }
+                },
+                "attribute" => {
+                    // attribute NAME of TARGET : KIND is VALUE;
+                    let name = statement.split_whitespace().nth(1).unwrap_or("").to_uppercase();
+                    if let Some(position) = find_word(&lower, "is") {
+                        bsdl.attributes.insert(name, value_text(&statement[position + 2..]));
+                    }
+                },
+                _ => {},
+            }
+        }
+        if bsdl.entity.is_empty() {
+            return Err(parse_error("no entity".to_string()))
+        }
+
+        let attribute = |name: &str| bsdl.attributes.get(name).cloned().ok_or_else(|| parse_error(format!("no {} attribute", name)));
+        bsdl.instruction_length = attribute("INSTRUCTION_LENGTH")?.parse().map_err(|_| parse_error("INSTRUCTION_LENGTH".to_string()))?;
+        bsdl.boundary_length = attribute("BOUNDARY_LENGTH")?.parse().map_err(|_| parse_error("BOUNDARY_LENGTH".to_string()))?;
+
+        for entry in split_top(&attribute("INSTRUCTION_OPCODE")?) {
+            let (name, opcodes) = entry.split_once('(').ok_or_else(|| parse_error(format!("instruction `{}`", entry)))?;
+            let opcodes = opcodes.trim_end_matches(')').split(',')
+                .map(|opcode| parse_bits(opcode).map(|(value, _)| value))
+                .collect::<Result<Vec<u32>, Error>>()?;
+            bsdl.instructions.insert(name.trim().to_uppercase(), opcodes);
+        }
+        if let Some(id) = bsdl.attributes.get("IDCODE_REGISTER") {
+            bsdl.id_code = Some(parse_bits(id)?);
+        }
+
+        let mut cells = split_top(&attribute("BOUNDARY_REGISTER")?).iter().map(|entry| parse_cell(entry)).collect::<Result<Vec<BoundaryCell>, Error>>()?;
+        cells.sort_by_key(|cell| cell.number);
+        if cells.len() != bsdl.boundary_length || cells.iter().enumerate().any(|(i, cell)| cell.number != i) {
+            return Err(parse_error(format!("BOUNDARY_REGISTER has {} cells numbered not 0..{}", cells.len(), bsdl.boundary_length)))
+        }
+        if let Some(cell) = cells.iter().find(|cell| cell.control.is_some_and(|(control, _)| control >= bsdl.boundary_length)) {
+            return Err(parse_error(format!("cell {} controlled by missing cell", cell.number)))
+        }
+        bsdl.cells = cells;
+
+        if let Some(map) = constants.get(&bsdl.package) {
+            bsdl.pin_map = parse_pin_map(map);
+        }
+        Ok(bsdl)
+    }
+
+    fn generic_package(text: &str) -> String {
+        text.split_once(":=").map(|(_, value)| value_text(value.trim().trim_end_matches(')'))).unwrap_or_default()
+    }
+
+    /// `opcode` - first opcode of instruction, e.g. "EXTEST", "SAMPLE"
+    pub fn opcode(&self, instruction: &str) -> Option<u32> {
+        self.instructions.get(&instruction.to_uppercase()).and_then(|opcodes| opcodes.first().copied())
+    }
+
+    /// `port_of` - port name of port or package pin
+    pub fn port_of(&self, pin: &str) -> Option<String> {
+        if let Some(port) = self.ports.iter().find(|port| port.name.eq_ignore_ascii_case(pin)) {
+            return Some(port.name.clone())
+        }
+        self.pin_map.iter().find(|(_, package_pin)| package_pin.as_str() == pin).map(|(port, _)| port.clone())
+    }
+
+    /// `output_cell` - cell driving `port`
+    pub fn output_cell(&self, port: &str) -> Option<&BoundaryCell> {
+        self.cells.iter().find(|cell| cell.port.as_deref() == Some(port) && cell.function.drives())
+    }
+
+    /// `input_cell` - cell capturing `port`
+    pub fn input_cell(&self, port: &str) -> Option<&BoundaryCell> {
+        self.cells.iter().find(|cell| cell.port.as_deref() == Some(port) && cell.function.observes())
+    }
+
+    /// `safe_values` - boundary register with safe value of every cell (0 for `X`)
+    pub fn safe_values(&self) -> Vec<bool> {
+        self.cells.iter().map(|cell| cell.safe.unwrap_or(false)).collect()
+    }
+}
+
+impl fmt::Display for Bsdl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}), IR {} bits, boundary register {} cells, {} ports",
+            self.entity, self.package, self.instruction_length, self.boundary_length, self.ports.len())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Cut down description in MC56F8006 style for tests: 4 GPIO pins with control / output / input cells each,
+    /// input only RESETB and one internal cell. Not the vendor file - load that one from NXP for real boards.
+    pub(crate) const TEST_BSDL: &str = r#"
+-- test description, 32-pin package
+entity MC56F8006_TEST is
+    generic (PHYSICAL_PIN_MAP : string := "LQFP32");
+
+    port (
+        GPIOA : inout bit_vector (0 to 3);  -- port A
+        RESETB : in bit;
+        TCK, TMS, TDI : in bit;
+        TDO : out bit;
+        VDD, VSS : linkage bit
+    );
+
+    use STD_1149_1_2001.all;
+
+    attribute COMPONENT_CONFORMANCE of MC56F8006_TEST : entity is "STD_1149_1_2001";
+    attribute PIN_MAP of MC56F8006_TEST : entity is PHYSICAL_PIN_MAP;
+
+    constant LQFP32 : PIN_MAP_STRING :=
+        "GPIOA : (1, 2, 3, 4), " &
+        "RESETB : 5, TCK : 6, TMS : 7, TDI : 8, TDO : 9, " &
+        "VDD : 10, VSS : 11";
+
+    attribute TAP_SCAN_CLOCK of TCK : signal is (20.0e6, BOTH);
+    attribute INSTRUCTION_LENGTH of MC56F8006_TEST : entity is 8;
+    attribute INSTRUCTION_OPCODE of MC56F8006_TEST : entity is
+        "EXTEST         (00000000), " &
+        "SAMPLE         (00000001), " &
+        "IDCODE         (00000010), " &
+        "BYPASS         (11111111)";
+    attribute INSTRUCTION_CAPTURE of MC56F8006_TEST : entity is "xxxxxx01";
+    attribute IDCODE_REGISTER of MC56F8006_TEST : entity is
+        "0000" & "0001111100101000" & "00000001110" & "1";
+    attribute BOUNDARY_LENGTH of MC56F8006_TEST : entity is 14;
+    attribute BOUNDARY_REGISTER of MC56F8006_TEST : entity is
+        "0  (BC_2, *,        control, 0), " &
+        "1  (BC_2, GPIOA(0), output3, X, 0, 0, Z), " &
+        "2  (BC_4, GPIOA(0), input,   X), " &
+        "3  (BC_2, *,        control, 0), " &
+        "4  (BC_2, GPIOA(1), output3, X, 3, 0, Z), " &
+        "5  (BC_4, GPIOA(1), input,   X), " &
+        "6  (BC_2, *,        control, 0), " &
+        "7  (BC_2, GPIOA(2), output3, X, 6, 0, Z), " &
+        "8  (BC_4, GPIOA(2), input,   X), " &
+        "9  (BC_2, *,        control, 0), " &
+        "10 (BC_2, GPIOA(3), output3, X, 9, 0, Z), " &
+        "11 (BC_4, GPIOA(3), input,   X), " &
+        "12 (BC_4, RESETB,   input,   X), " &
+        "13 (BC_2, *,        internal, 1)";
+end MC56F8006_TEST;
+"#;
+
+    #[test]
+    fn parse_description() {
+        let bsdl = Bsdl::parse(TEST_BSDL).unwrap();
+        assert_eq!(bsdl.entity, "MC56F8006_TEST");
+        assert_eq!(bsdl.package, "LQFP32");
+        assert_eq!(bsdl.instruction_length, 8);
+        assert_eq!(bsdl.opcode("extest"), Some(0x00));
+        assert_eq!(bsdl.opcode("SAMPLE"), Some(0x01));
+        assert_eq!(bsdl.opcode("BYPASS"), Some(0xFF));
+        assert_eq!(bsdl.id_code, Some((0x01F2801D, 0xFFFF_FFFF)));
+        assert_eq!(bsdl.boundary_length, 14);
+        assert_eq!(bsdl.ports.len(), 4 + 1 + 3 + 1 + 2);
+        assert_eq!(bsdl.ports[2], BsdlPort { name: "GPIOA(2)".to_string(), direction: PortDirection::InOut });
+        assert_eq!(bsdl.pin_map["GPIOA(3)"], "4");
+        assert_eq!(bsdl.port_of("5"), Some("RESETB".to_string()));
+        assert_eq!(bsdl.output_cell("GPIOA(1)").unwrap(), &BoundaryCell {
+            number: 4, cell_type: "BC_2".to_string(), port: Some("GPIOA(1)".to_string()),
+            function: CellFunction::Output3, safe: None, control: Some((3, false)) });
+        assert_eq!(bsdl.input_cell("RESETB").unwrap().number, 12);
+        assert!(bsdl.output_cell("RESETB").is_none());
+        assert!(bsdl.safe_values()[13]);
+    }
+
+    #[test]
+    fn broken_description_reported() {
+        let missing = TEST_BSDL.replace("attribute BOUNDARY_LENGTH of MC56F8006_TEST : entity is 14;", "");
+        assert!(matches!(Bsdl::parse(&missing), Err(Error::FileParserError(_))));
+        let short = TEST_BSDL.replace("entity is 14;", "entity is 15;");
+        assert!(matches!(Bsdl::parse(&short), Err(Error::FileParserError(_))));
+    }
+}
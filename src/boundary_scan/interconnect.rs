@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use serde::{Deserialize};
+use crate::errors::{Error};
+use crate::usbdm::Programmer;
+use crate::usbdm::jtag_chain::{ChainPosition};
+use crate::boundary_scan::bsdl::Bsdl;
+use crate::boundary_scan::scan::BoundaryScan;
+
+/// `Net` - board net, pins as port names or package pins of scanned device
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Net {
+    pub name : String,
+    pub pins : Vec<String>,
+}
+
+/// `InterconnectFault` - what receivers of nets have read
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterconnectFault {
+    /// receiver reads 1 all the time (pulled up, not connected to driver)
+    Open { net: String, pin: String },
+    /// nets read the same wrong pattern, they are joined
+    Short { nets: Vec<String> },
+    /// receiver reads neither own net nor other one (stuck at 0, driver fighting)
+    Wrong { net: String, pin: String },
+}
+
+impl fmt::Display for InterconnectFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterconnectFault::Open { net, pin }  => write!(f, "open: pin {} on net {}", pin, net),
+            InterconnectFault::Short { nets }     => write!(f, "short: nets {}", nets.join(", ")),
+            InterconnectFault::Wrong { net, pin } => write!(f, "wrong level: pin {} on net {}", pin, net),
+        }
+    }
+}
+
+/// `InterconnectReport` - result of `interconnect_test`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InterconnectReport {
+    pub vectors  : usize,
+    pub tested   : Vec<String>,
+    /// nets without pin to drive or without pin to read
+    pub untested : Vec<String>,
+    pub faults   : Vec<InterconnectFault>,
+}
+
+impl InterconnectReport {
+    pub fn passed(&self) -> bool {
+        self.faults.is_empty()
+    }
+}
+
+impl fmt::Display for InterconnectReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Interconnect test of {} nets, {} vectors: {}",
+            self.tested.len(), self.vectors, if self.passed() { "PASSED" } else { "FAILED" })?;
+        for fault in self.faults.iter() {
+            writeln!(f, "  {}", fault)?;
+        }
+        if !self.untested.is_empty() {
+            writeln!(f, "  not tested: {}", self.untested.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// net under test: driver port and receiver ports
+struct TestNet {
+    name      : String,
+    driver    : String,
+    receivers : Vec<String>,
+}
+
+/// `interconnect_test` - find opens and shorts between pins of nets
+///
+/// One pin of every net drives, other pins with input cell read. Net `i` gets code `i` as counting sequence,
+/// followed by its complement, so every net sees both levels and two shorted (wired-AND) nets read a pattern
+/// no single net drives. Other pins are high impedance. Device is left in EXTEST with outputs released.
+pub fn interconnect_test(scan: &mut BoundaryScan, prog: &Programmer, nets: &[Net]) -> Result<InterconnectReport, Error> {
+    let mut report = InterconnectReport::default();
+    let mut tested = Vec::new();
+    for net in nets {
+        let ports = net.pins.iter()
+            .map(|pin| scan.bsdl.port_of(pin).ok_or_else(|| Error::BoundaryScanError(format!("net {}: {} has no pin {}", net.name, scan.bsdl.entity, pin))))
+            .collect::<Result<Vec<String>, Error>>()?;
+        let driver = ports.iter().find(|port| scan.bsdl.output_cell(port).is_some()).cloned();
+        let receivers: Vec<String> = ports.iter()
+            .filter(|port| Some(*port) != driver.as_ref() && scan.bsdl.input_cell(port).is_some())
+            .cloned().collect();
+        match driver {
+            Some(driver) if !receivers.is_empty() => tested.push(TestNet { name: net.name.clone(), driver, receivers }),
+            _ => report.untested.push(net.name.clone()),
+        }
+    }
+    report.tested = tested.iter().map(|net| net.name.clone()).collect();
+    if tested.is_empty() {
+        return Ok(report)
+    }
+
+    let bits = (usize::BITS - (tested.len() - 1).leading_zeros()).max(1) as usize;
+    report.vectors = 2 * bits;
+    scan.release_all();
+    scan.extest(prog)?;
+
+    let mut signatures: HashMap<&str, Vec<bool>> = HashMap::new();
+    for vector in 0..report.vectors {
+        for (code, net) in tested.iter().enumerate() {
+            let level = (code >> (vector % bits)) & 1 != 0;
+            scan.set_pin(&net.driver, Some(level != (vector >= bits)))?;
+        }
+        // first scan drives, second captures
+        scan.scan(prog)?;
+        scan.scan(prog)?;
+        for net in tested.iter() {
+            for receiver in net.receivers.iter() {
+                signatures.entry(receiver).or_default().push(scan.pin_level(receiver)?);
+            }
+        }
+    }
+    scan.release_all();
+    scan.scan(prog)?;
+
+    let mut wrong: Vec<(&TestNet, &str, &Vec<bool>)> = Vec::new();
+    for (code, net) in tested.iter().enumerate() {
+        let expected: Vec<bool> = (0..report.vectors).map(|vector| ((code >> (vector % bits)) & 1 != 0) != (vector >= bits)).collect();
+        for receiver in net.receivers.iter() {
+            let signature = &signatures[receiver.as_str()];
+            if *signature == expected {
+                continue
+            }
+            if signature.iter().all(|level| *level) {
+                report.faults.push(InterconnectFault::Open { net: net.name.clone(), pin: receiver.clone() });
+            } else {
+                wrong.push((net, receiver, signature));
+            }
+        }
+    }
+    let mut reported: Vec<&Vec<bool>> = Vec::new();
+    for (net, receiver, signature) in wrong.iter() {
+        let mut joined: Vec<String> = wrong.iter().filter(|(_, _, other)| other == signature).map(|(net, _, _)| net.name.clone()).collect();
+        joined.dedup();
+        if joined.len() > 1 {
+            if !reported.contains(signature) {
+                reported.push(signature);
+                report.faults.push(InterconnectFault::Short { nets: joined });
+            }
+        } else {
+            report.faults.push(InterconnectFault::Wrong { net: net.name.clone(), pin: receiver.to_string() });
+        }
+    }
+    Ok(report)
+}
+
+/// `load_nets` - nets of YAML file, list of `name:` / `pins:` entries
+pub fn load_nets(path: &str) -> Result<Vec<Net>, Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::FileParserError(format!("{}: {}", path, e)))?;
+    serde_yaml::from_str(&text).map_err(|e| Error::FileParserError(format!("{}: {}", path, e)))
+}
+
+/// `board_test` - `interconnect_test` of device described by `bsdl`, wherever it is on JTAG chain
+///
+/// Chain is scanned and device found by IDCODE of BSDL, other TAPs are put in BYPASS. Also on error
+/// `Programmer::chain` is set back to DSC alone and TAPs are reset, so device leaves EXTEST and runs again.
+pub fn board_test(prog: &mut Programmer, bsdl: Bsdl, nets: &[Net]) -> Result<InterconnectReport, Error> {
+    let mut chain = prog.scan_chain()?;
+    let index = chain.taps.iter()
+        .position(|tap| matches!((tap.id_code, bsdl.id_code), (Some(id), Some((expected, mask))) if id & mask == expected & mask))
+        .ok_or_else(|| Error::BoundaryScanError(format!("no TAP with IDCODE of {} on JTAG chain", bsdl.entity)))?;
+    chain.taps[index].ir_length.get_or_insert(bsdl.instruction_length);
+    chain.infer_ir_lengths();
+    prog.chain = chain.position(index)?;
+
+    let mut scan = BoundaryScan::new(bsdl);
+    let report = interconnect_test(&mut scan, prog, nets);
+    prog.chain = ChainPosition::default();
+    let reset = prog.jtag_reset();
+    let report = report?;
+    reset?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary_scan::bsdl::Bsdl;
+    use crate::boundary_scan::bsdl::tests::TEST_BSDL;
+    use crate::boundary_scan::virtual_board::VirtualBoard;
+    use crate::usbdm::jtag_interpreter::{InterpreterTransport, SimpleTap, VirtualChain};
+
+    fn nets() -> Vec<Net> {
+        let net = |name: &str, pins: &[&str]| Net { name: name.to_string(), pins: pins.iter().map(|pin| pin.to_string()).collect() };
+        // by package pins and by port names
+        vec![net("LED", &["1", "2"]), net("RESET", &["GPIOA(2)", "RESETB"]), net("TP1", &["GPIOA(3)"])]
+    }
+
+    fn run(board: impl FnOnce(&mut VirtualBoard)) -> InterconnectReport {
+        let bsdl = Bsdl::parse(TEST_BSDL).unwrap();
+        let wiring = vec![
+            vec!["GPIOA(0)".to_string(), "GPIOA(1)".to_string()],
+            vec!["GPIOA(2)".to_string(), "RESETB".to_string()],
+        ];
+        let mut virtual_board = VirtualBoard::new(bsdl.clone(), wiring);
+        board(&mut virtual_board);
+        let link = InterpreterTransport::new(virtual_board);
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        let mut scan = BoundaryScan::new(bsdl);
+        interconnect_test(&mut scan, &prog, &nets()).unwrap()
+    }
+
+    #[test]
+    fn nets_file() {
+        let path = std::env::temp_dir().join("usbdm_nets_test.yaml");
+        fs::write(&path, "- name: LED\n  pins: [\"1\", \"2\"]\n- name: RESET\n  pins: [GPIOA(2), RESETB]\n- name: TP1\n  pins: [GPIOA(3)]\n").unwrap();
+        assert_eq!(load_nets(path.to_str().unwrap()).unwrap(), nets());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(load_nets("no_such_nets.yaml"), Err(Error::FileParserError(_))));
+    }
+
+    #[test]
+    fn board_test_on_shared_chain() {
+        let bsdl = Bsdl::parse(TEST_BSDL).unwrap();
+        let wiring = vec![vec!["GPIOA(0)".to_string(), "GPIOA(1)".to_string()]];
+        let mut board = VirtualBoard::new(bsdl.clone(), wiring);
+        board.opens.push("RESETB".to_string());
+        let chain = VirtualChain {
+            before : vec![SimpleTap::new(6, Some(0x0960_2093))],
+            target : board,
+            after  : vec![SimpleTap::new(5, None)],
+        };
+        let link = InterpreterTransport::new(chain);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+
+        let report = board_test(&mut prog, bsdl.clone(), &nets()).unwrap();
+        assert_eq!(report.faults, vec![InterconnectFault::Open { net: "RESET".to_string(), pin: "RESETB".to_string() }]);
+        assert_eq!(prog.chain, ChainPosition::default());
+        assert_eq!(link.with_tap(|chain| chain.target.instruction()), bsdl.opcode("IDCODE").unwrap());
+
+        let mut other = bsdl;
+        other.id_code = Some((0x1234_5001, 0xFFFF_FFFF));
+        assert!(matches!(board_test(&mut prog, other, &nets()), Err(Error::BoundaryScanError(_))));
+    }
+
+    #[test]
+    fn good_board_passes() {
+        let report = run(|_| {});
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.tested, vec!["LED".to_string(), "RESET".to_string()]);
+        assert_eq!(report.untested, vec!["TP1".to_string()]);
+        assert_eq!(report.vectors, 2);
+    }
+
+    #[test]
+    fn open_found() {
+        let report = run(|board| board.opens.push("RESETB".to_string()));
+        assert_eq!(report.faults, vec![InterconnectFault::Open { net: "RESET".to_string(), pin: "RESETB".to_string() }]);
+    }
+
+    #[test]
+    fn short_found() {
+        let report = run(|board| board.shorts.push((0, 1)));
+        assert_eq!(report.faults, vec![InterconnectFault::Short { nets: vec!["LED".to_string(), "RESET".to_string()] }]);
+        assert!(report.to_string().contains("short: nets LED, RESET"));
+    }
+}
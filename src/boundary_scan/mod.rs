@@ -0,0 +1,4 @@
+pub mod bsdl;
+pub mod scan;
+pub mod interconnect;
+pub mod virtual_board;
@@ -0,0 +1,205 @@
+use crate::errors::{Error};
+use crate::usbdm::Programmer;
+use crate::usbdm::jtag_chain::{to_bits, to_bytes};
+use crate::usbdm::jtag_sequence::{JtagOp, JtagSequence};
+use crate::boundary_scan::bsdl::{Bsdl};
+
+/// Bits per `JTAG_SHIFT_IN_OUT_DP`, byte aligned so chunks of data area and answer stay apart
+const CHUNK_BITS : usize = 248;
+
+fn scan_error(message: String) -> Error {
+    Error::BoundaryScanError(message)
+}
+
+/// `BoundaryScan` - boundary register of one device on JTAG chain
+///
+/// `outputs` is what next DR scan shifts in (starts with safe values), `captured` what last one shifted out.
+/// Scans are JTAG sequences run by `Programmer::exec_program`, so bypass bits of `Programmer::chain` are added
+/// when device shares the chain.
+///
+/// Typical use: `sample` (SAMPLE/PRELOAD also preloads `outputs`), `extest`, then `drive` / `read` pins.
+/// Pins with common control cell (whole port enabled by one cell) are enabled together.
+#[derive(Debug, Clone)]
+pub struct BoundaryScan {
+    pub bsdl     : Bsdl,
+    pub outputs  : Vec<bool>,
+    pub captured : Vec<bool>,
+    /// instruction loaded last
+    pub instruction : Option<String>,
+}
+
+impl BoundaryScan {
+
+    pub fn new(bsdl: Bsdl) -> Self {
+        let outputs = bsdl.safe_values();
+        let captured = vec![false; bsdl.boundary_length];
+        BoundaryScan { bsdl, outputs, captured, instruction: None }
+    }
+
+    /// `load_instruction` - IR scan of `name` opcode, TAP left in RUN-TEST/IDLE
+    pub fn load_instruction(&mut self, prog: &Programmer, name: &str) -> Result<(), Error> {
+        let opcode = self.bsdl.opcode(name).ok_or_else(|| scan_error(format!("{} has no {} instruction", self.bsdl.entity, name)))?;
+        let program = JtagSequence::new()
+            .op(JtagOp::MoveIrScan)
+            .op(JtagOp::SetExitIdle)
+            .shift_out(self.bsdl.instruction_length, opcode)
+            .build()?;
+        prog.exec_program(&program)?;
+        self.instruction = Some(name.to_uppercase());
+        Ok(())
+    }
+
+    /// `scan` - DR scan shifting `outputs` in and boundary register captured out to `captured`
+    pub fn scan(&mut self, prog: &Programmer) -> Result<&[bool], Error> {
+        let chunks: Vec<&[bool]> = self.outputs.chunks(CHUNK_BITS).collect();
+        let mut sequence = JtagSequence::new()
+            .op(JtagOp::MoveDrScan)
+            .op(JtagOp::SetStayShift);
+        for (index, chunk) in chunks.iter().enumerate() {
+            if index + 1 == chunks.len() {
+                sequence = sequence.op(JtagOp::SetExitIdle);
+            }
+            sequence = sequence.op(JtagOp::ShiftInOutDp(chunk.len() as u8)).data(&to_bytes(chunk));
+        }
+        let answer = prog.exec_program(&sequence.build()?)?;
+
+        let mut captured = Vec::with_capacity(self.outputs.len());
+        let mut offset = 0;
+        for chunk in chunks {
+            let bytes = chunk.len().div_ceil(8);
+            captured.extend(to_bits(&answer[offset..offset + bytes], chunk.len()));
+            offset += bytes;
+        }
+        self.captured = captured;
+        Ok(&self.captured)
+    }
+
+    /// `sample` - SAMPLE/PRELOAD: pins captured while core keeps running, `outputs` preloaded for `extest`
+    pub fn sample(&mut self, prog: &Programmer) -> Result<(), Error> {
+        let instruction = if self.bsdl.opcode("SAMPLE").is_some() { "SAMPLE" } else { "PRELOAD" };
+        self.load_instruction(prog, instruction)?;
+        self.scan(prog)?;
+        Ok(())
+    }
+
+    /// `extest` - preload `outputs` and give pins to boundary register. Device needs reset to run again
+    pub fn extest(&mut self, prog: &Programmer) -> Result<(), Error> {
+        self.sample(prog)?;
+        self.load_instruction(prog, "EXTEST")
+    }
+
+    fn port(&self, pin: &str) -> Result<String, Error> {
+        self.bsdl.port_of(pin).ok_or_else(|| scan_error(format!("{} has no pin {}", self.bsdl.entity, pin)))
+    }
+
+    /// `set_pin` - change `outputs` only: `Some(level)` drives pin, `None` makes it high impedance
+    pub fn set_pin(&mut self, pin: &str, level: Option<bool>) -> Result<(), Error> {
+        let port = self.port(pin)?;
+        let cell = self.bsdl.output_cell(&port).ok_or_else(|| scan_error(format!("pin {} can't be driven", pin)))?.clone();
+        match (level, cell.control) {
+            (Some(level), Some((control, disable))) => {
+                self.outputs[cell.number] = level;
+                self.outputs[control] = !disable;
+            },
+            (Some(level), None) => self.outputs[cell.number] = level,
+            (None, Some((control, disable))) => self.outputs[control] = disable,
+            (None, None) => return Err(scan_error(format!("pin {} has no output enable", pin))),
+        }
+        Ok(())
+    }
+
+    /// `pin_level` - level of pin in `captured`
+    pub fn pin_level(&self, pin: &str) -> Result<bool, Error> {
+        let port = self.port(pin)?;
+        let cell = self.bsdl.input_cell(&port).ok_or_else(|| scan_error(format!("pin {} can't be read", pin)))?;
+        Ok(self.captured[cell.number])
+    }
+
+    /// `drive` - `set_pin` and scan, pin changes at UPDATE-DR in EXTEST
+    pub fn drive(&mut self, prog: &Programmer, pin: &str, level: Option<bool>) -> Result<(), Error> {
+        self.require_extest()?;
+        self.set_pin(pin, level)?;
+        self.scan(prog)?;
+        Ok(())
+    }
+
+    /// `read` - scan and level of pin
+    pub fn read(&mut self, prog: &Programmer, pin: &str) -> Result<bool, Error> {
+        self.scan(prog)?;
+        self.pin_level(pin)
+    }
+
+    /// `release_all` - every output with enable back to high impedance
+    pub fn release_all(&mut self) {
+        for cell in self.bsdl.cells.iter() {
+            if let Some((control, disable)) = cell.control {
+                self.outputs[control] = disable;
+            }
+        }
+    }
+
+    fn require_extest(&self) -> Result<(), Error> {
+        match self.instruction.as_deref() {
+            Some("EXTEST") => Ok(()),
+            other => Err(scan_error(format!("pins are driven in EXTEST, loaded instruction is {:?}", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary_scan::bsdl::tests::TEST_BSDL;
+    use crate::boundary_scan::virtual_board::VirtualBoard;
+    use crate::usbdm::jtag_chain::ChainPosition;
+    use crate::usbdm::jtag_interpreter::{InterpreterTransport, SimpleTap, VirtualChain};
+
+    fn nets() -> Vec<Vec<String>> {
+        vec![vec!["GPIOA(0)".to_string(), "GPIOA(1)".to_string()], vec!["GPIOA(2)".to_string(), "RESETB".to_string()]]
+    }
+
+    #[test]
+    fn drive_and_read_pins() {
+        let bsdl = Bsdl::parse(TEST_BSDL).unwrap();
+        let link = InterpreterTransport::new(VirtualBoard::new(bsdl.clone(), nets()));
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        let mut scan = BoundaryScan::new(bsdl);
+
+        assert!(scan.drive(&prog, "GPIOA(0)", Some(false)).is_err());
+        scan.sample(&prog).unwrap();
+        // functional mode, pull-ups
+        assert!(scan.pin_level("RESETB").unwrap());
+
+        scan.extest(&prog).unwrap();
+        scan.drive(&prog, "GPIOA(0)", Some(false)).unwrap();
+        assert!(!scan.read(&prog, "2").unwrap());
+        assert!(scan.read(&prog, "GPIOA(3)").unwrap());
+        scan.drive(&prog, "GPIOA(2)", Some(false)).unwrap();
+        assert!(!scan.read(&prog, "RESETB").unwrap());
+        scan.drive(&prog, "GPIOA(2)", None).unwrap();
+        assert!(scan.read(&prog, "RESETB").unwrap());
+        assert!(scan.set_pin("RESETB", Some(true)).is_err());
+        assert!(scan.set_pin("GPIOB(0)", Some(true)).is_err());
+    }
+
+    #[test]
+    fn scan_on_shared_chain() {
+        let bsdl = Bsdl::parse(TEST_BSDL).unwrap();
+        let chain = VirtualChain {
+            before : vec![SimpleTap::new(6, Some(0x0960_2093))],
+            target : VirtualBoard::new(bsdl.clone(), nets()),
+            after  : vec![SimpleTap::new(5, None)],
+        };
+        let link = InterpreterTransport::new(chain);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        prog.chain = ChainPosition { ir_pre: 6, ir_post: 5, dr_pre: 1, dr_post: 1 };
+        let mut scan = BoundaryScan::new(bsdl);
+
+        scan.extest(&prog).unwrap();
+        assert_eq!(link.with_tap(|chain| chain.target.instruction()), 0x00);
+        scan.drive(&prog, "GPIOA(1)", Some(false)).unwrap();
+        assert!(!scan.read(&prog, "GPIOA(0)").unwrap());
+        assert!(scan.read(&prog, "GPIOA(2)").unwrap());
+        assert!(link.with_tap(|chain| chain.before[0].in_bypass() && chain.after[0].in_bypass()));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::usbdm::jtag_interpreter::{TapRegister, VirtualTap};
+use crate::boundary_scan::bsdl::{Bsdl};
+
+/// `VirtualBoard` - device described by `bsdl` soldered on board with `nets`
+///
+/// Boundary register and instructions as BSDL says, IR captures `..01`. Pins of `nets` are ports of the device,
+/// all nets have pull-ups: undriven net reads 1, driven nets are wired-AND. `opens` are ports cut from their net,
+/// `shorts` pairs of net indexes joined. In other instructions than EXTEST core drives nothing.
+#[derive(Debug, Clone)]
+pub struct VirtualBoard {
+    pub bsdl   : Bsdl,
+    pub nets   : Vec<Vec<String>>,
+    pub opens  : Vec<String>,
+    pub shorts : Vec<(usize, usize)>,
+    ir       : u32,
+    boundary : Vec<bool>,
+    captured : Vec<bool>,
+    shifted  : Vec<bool>,
+}
+
+impl VirtualBoard {
+
+    pub fn new(bsdl: Bsdl, nets: Vec<Vec<String>>) -> Self {
+        let boundary = bsdl.safe_values();
+        let mut board = VirtualBoard { bsdl, nets, opens: Vec::new(), shorts: Vec::new(), ir: 0, boundary, captured: Vec::new(), shifted: Vec::new() };
+        board.test_logic_reset();
+        board
+    }
+
+    /// `instruction` - opcode in IR
+    pub fn instruction(&self) -> u32 {
+        self.ir
+    }
+
+    fn selected(&self, name: &str) -> bool {
+        self.bsdl.opcode(name) == Some(self.ir)
+    }
+
+    fn boundary_selected(&self) -> bool {
+        self.selected("EXTEST") || self.selected("SAMPLE") || self.selected("PRELOAD")
+    }
+
+    fn driven(&self, port: &str) -> Option<bool> {
+        if !self.selected("EXTEST") {
+            return None
+        }
+        let cell = self.bsdl.output_cell(port)?;
+        match cell.control {
+            Some((control, disable)) if self.boundary[control] == disable => None,
+            _ => Some(self.boundary[cell.number]),
+        }
+    }
+
+    /// `level` - level on `port` pin
+    pub fn level(&self, port: &str) -> bool {
+        let net = self.nets.iter().position(|net| net.iter().any(|pin| pin == port));
+        let net = match net {
+            Some(net) if !self.opens.iter().any(|open| open == port) => net,
+            _ => return self.driven(port).unwrap_or(true),
+        };
+        let mut joined = vec![net];
+        loop {
+            let more: Vec<usize> = self.shorts.iter()
+                .flat_map(|(a, b)| [(*a, *b), (*b, *a)])
+                .filter(|(a, b)| joined.contains(a) && !joined.contains(b))
+                .map(|(_, b)| b)
+                .collect();
+            if more.is_empty() {
+                break
+            }
+            joined.extend(more);
+        }
+        joined.iter()
+            .flat_map(|net| self.nets[*net].iter())
+            .filter(|pin| !self.opens.contains(pin))
+            .filter_map(|pin| self.driven(pin))
+            .all(|driven| driven)
+    }
+}
+
+impl VirtualTap for VirtualBoard {
+    fn test_logic_reset(&mut self) {
+        self.ir = self.bsdl.opcode("IDCODE").or_else(|| self.bsdl.opcode("BYPASS")).unwrap_or(0);
+    }
+
+    fn capture(&mut self, register: TapRegister) {
+        self.shifted.clear();
+        self.captured = match register {
+            TapRegister::Ir => (0..self.bsdl.instruction_length).map(|bit| bit == 0).collect(),
+            TapRegister::Dr if self.selected("IDCODE") => {
+                let id = self.bsdl.id_code.map_or(0, |(id, _)| id);
+                (0..32).map(|bit| (id >> bit) & 1 != 0).collect()
+            },
+            TapRegister::Dr if self.boundary_selected() => {
+                self.bsdl.cells.iter().map(|cell| match (&cell.port, cell.function.observes()) {
+                    (Some(port), true) => self.level(port),
+                    _ => self.boundary[cell.number],
+                }).collect()
+            },
+            TapRegister::Dr => vec![false],
+        };
+    }
+
+    fn shift(&mut self, _register: TapRegister, tdi: bool) -> bool {
+        let count = self.shifted.len();
+        let length = self.captured.len();
+        let tdo = if count < length { self.captured[count] } else { self.shifted[count - length] };
+        self.shifted.push(tdi);
+        tdo
+    }
+
+    fn update(&mut self, register: TapRegister) {
+        let length = self.captured.len();
+        if self.shifted.len() < length {
+            return
+        }
+        let last = &self.shifted[self.shifted.len() - length..];
+        match register {
+            TapRegister::Ir => self.ir = last.iter().rev().fold(0, |value, bit| (value << 1) | *bit as u32),
+            TapRegister::Dr if self.boundary_selected() => self.boundary = last.to_vec(),
+            TapRegister::Dr => {},
+        }
+    }
+}
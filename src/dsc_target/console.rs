@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use super::target_factory::TargetDsc;
+use super::job_worker::JobContext;
+use crate::errors::Error;
+use crate::usbdm::programmer::Programmer;
+use crate::usbdm::jtag::{enableONCE, OnceStatus};
+use crate::usbdm::registers::{DscRegisters, OTXRXSR_RDF, OTXRXSR_TDF};
+
+/// OTXRXSR poll interval when channel is idle, words in flight are moved without waiting
+pub const CONSOLE_POLL : Duration = Duration::from_millis(10);
+
+// Console protocol, 16-bit words written by firmware to OTX1, record type in high byte
+//--------------------------------------------------------------------
+/// `0x00cc` - character `cc`
+pub const RECORD_CHAR   : u8 = 0x00;
+/// `0x01nn` - `nn` characters follow, two per word, first one in high byte
+pub const RECORD_TEXT   : u8 = 0x01;
+/// `0x02nn` - format of `nn` characters as in `RECORD_TEXT`, then argument words it needs, see `format_printf`
+pub const RECORD_PRINTF : u8 = 0x02;
+
+/// `Conversion` - one `%` of printf format
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Conversion {
+    left    : bool,
+    zero    : bool,
+    width   : usize,
+    long    : bool,
+    kind    : char,
+}
+
+impl Conversion {
+
+    /// `words` - argument words taken by conversion, `long` ones are sent low word first
+    fn words(&self) -> usize {
+        match (self.kind, self.long) {
+            ('%', _)    => 0,
+            (_, true)   => 2,
+            (_, false)  => 1,
+        }
+    }
+
+    fn format(&self, words: &[u16]) -> String {
+        let value = match self.long {
+            true  => words[0] as u32 | (words[1] as u32) << 16,
+            false => words.first().copied().unwrap_or(0) as u32,
+        };
+        let signed = match self.long {
+            true  => value as i32,
+            false => value as u16 as i16 as i32,
+        };
+        let text = match self.kind {
+            'd' | 'i'   => signed.to_string(),
+            'u'         => value.to_string(),
+            'x'         => format!("{:x}", value),
+            'X'         => format!("{:X}", value),
+            'o'         => format!("{:o}", value),
+            'c'         => char::from(value as u8).to_string(),
+            _           => return "%".to_string(),
+        };
+        let pad = self.width.saturating_sub(text.chars().count());
+        match (self.left, self.zero && self.kind != 'c') {
+            (true, _)       => text + &" ".repeat(pad),
+            (false, true)   => match text.strip_prefix('-') {
+                Some(digits) => "-".to_string() + &"0".repeat(pad) + digits,
+                None         => "0".repeat(pad) + &text,
+            },
+            (false, false)  => " ".repeat(pad) + &text,
+        }
+    }
+}
+
+/// `FormatPart` - literal text or conversion of printf format
+#[derive(Debug, Clone, PartialEq)]
+enum FormatPart {
+    Text(String),
+    Conversion(Conversion),
+}
+
+/// `parse_format` - `%[-0][width][l](d|i|u|x|X|o|c|%)`, other conversions are kept as text
+fn parse_format(format: &str) -> Vec<FormatPart> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue
+        }
+        let mut spec = String::from("%");
+        let mut conversion = Conversion { left: false, zero: false, width: 0, long: false, kind: '%' };
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => conversion.left = true,
+                '0' => conversion.zero = true,
+                _   => break,
+            }
+            spec.push(flag);
+            chars.next();
+        }
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            conversion.width = conversion.width * 10 + digit as usize;
+            spec.push(chars.next().unwrap_or('0'));
+        }
+        if chars.peek() == Some(&'l') {
+            conversion.long = true;
+            spec.push(chars.next().unwrap_or('l'));
+        }
+        match chars.next() {
+            Some(kind) if "diuxXoc%".contains(kind) => {
+                conversion.kind = kind;
+                if !text.is_empty() {
+                    parts.push(FormatPart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(FormatPart::Conversion(conversion));
+            },
+            other => {
+                text.push_str(&spec);
+                text.extend(other);
+            },
+        }
+    }
+    if !text.is_empty() {
+        parts.push(FormatPart::Text(text));
+    }
+    parts
+}
+
+/// `argument_words` - words of arguments `format` takes
+pub fn argument_words(format: &str) -> usize {
+    parse_format(format).iter().map(|part| match part {
+        FormatPart::Conversion(conversion) => conversion.words(),
+        FormatPart::Text(_)                => 0,
+    }).sum()
+}
+
+/// `format_printf` - printf `format` with 16-bit argument words, missing arguments read as 0
+pub fn format_printf(format: &str, arguments: &[u16]) -> String {
+    let mut output = String::new();
+    let mut arguments = arguments.iter().copied();
+    for part in parse_format(format) {
+        match part {
+            FormatPart::Text(text) => output.push_str(&text),
+            FormatPart::Conversion(conversion) => {
+                let words: Vec<u16> = (0..conversion.words()).map(|_| arguments.next().unwrap_or(0)).collect();
+                output.push_str(&conversion.format(&words));
+            },
+        }
+    }
+    output
+}
+
+/// `Record` - console record being received
+#[derive(Debug, Clone, PartialEq)]
+enum Record {
+    Idle,
+    Text { length: usize, bytes: Vec<u8> },
+    Format { length: usize, bytes: Vec<u8> },
+    Arguments { format: String, words: Vec<u16> },
+}
+
+/// `ConsoleDecoder` - turns OTX1 words of console protocol into text
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleDecoder {
+    record : Record,
+}
+
+impl Default for ConsoleDecoder {
+    fn default() -> Self {
+        ConsoleDecoder { record: Record::Idle }
+    }
+}
+
+impl ConsoleDecoder {
+
+    /// `push` - next word from target, gives text when it completes a record
+    pub fn push(&mut self, word: u16) -> Option<String> {
+        let [high, low] = word.to_be_bytes();
+        let record = std::mem::replace(&mut self.record, Record::Idle);
+        let (record, output) = match record {
+            Record::Idle => match high {
+                RECORD_CHAR                 => (Record::Idle, Some(char::from(low).to_string())),
+                RECORD_TEXT | RECORD_PRINTF if low == 0 => (Record::Idle, None),
+                RECORD_TEXT                 => (Record::Text { length: low as usize, bytes: Vec::new() }, None),
+                RECORD_PRINTF               => (Record::Format { length: low as usize, bytes: Vec::new() }, None),
+                _                           => (Record::Idle, Some(format!("<${:04X}>", word))),
+            },
+            Record::Text { length, mut bytes } => {
+                bytes.extend([high, low]);
+                match bytes.len() >= length {
+                    true  => (Record::Idle, Some(text_of(&bytes[..length]))),
+                    false => (Record::Text { length, bytes }, None),
+                }
+            },
+            Record::Format { length, mut bytes } => {
+                bytes.extend([high, low]);
+                match bytes.len() >= length {
+                    true  => Self::arguments(text_of(&bytes[..length]), Vec::new()),
+                    false => (Record::Format { length, bytes }, None),
+                }
+            },
+            Record::Arguments { format, mut words } => {
+                words.push(word);
+                Self::arguments(format, words)
+            },
+        };
+        self.record = record;
+        output
+    }
+
+    fn arguments(format: String, words: Vec<u16>) -> (Record, Option<String>) {
+        match words.len() >= argument_words(&format) {
+            true  => (Record::Idle, Some(format_printf(&format, &words))),
+            false => (Record::Arguments { format, words }, None),
+        }
+    }
+}
+
+fn text_of(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| char::from(*byte)).collect()
+}
+
+/// `ConsoleChannel` - host side of EOnCE data channel, firmware keeps running
+///
+/// Target output comes from OTX1 as `ConsoleDecoder` records. Host input goes one character per word
+/// (`0x00cc`) to ORX1, next one only after core has read the previous.
+#[derive(Debug, Default)]
+pub struct ConsoleChannel {
+    decoder : ConsoleDecoder,
+    input   : VecDeque<u16>,
+}
+
+impl ConsoleChannel {
+
+    /// `send` - queue `text` for target
+    pub fn send(&mut self, text: &str) {
+        self.input.extend(text.bytes().map(|byte| byte as u16));
+    }
+
+    /// `poll` - one OTXRXSR check, moves at most one word each way; `(output, moved)`
+    ///
+    /// TDF set - core wrote OTX, host reads OTX1. RDF clear - core took last word, host may write ORX1.
+    pub fn poll(&mut self, prog: &mut Programmer) -> Result<(Option<String>, bool), Error> {
+        let status = prog.dsc_read_once_reg(DscRegisters::DscRegOtxrxsr)? as u8;
+        let mut output = None;
+        let mut moved = false;
+        if status & OTXRXSR_TDF != 0 {
+            let word = prog.dsc_read_once_reg(DscRegisters::DscRegOtx1)? as u16;
+            output = self.decoder.push(word);
+            moved = true;
+        }
+        if status & OTXRXSR_RDF == 0 {
+            if let Some(word) = self.input.pop_front() {
+                prog.dsc_write_once_reg(DscRegisters::DscRegOrx1, word as u32)?;
+                moved = true;
+            }
+        }
+        Ok((output, moved))
+    }
+}
+
+impl TargetDsc {
+
+    /// `console` - runs EOnCE data channel until job is cancelled, target output goes to `context` as console text
+    ///
+    /// Core is not halted, firmware on target must run. Lines from `input` are sent to target as they arrive.
+    pub fn console(&mut self, input: &Receiver<String>, prog: &mut Programmer, context: &mut JobContext) -> Result<(), Error> {
+        // selects ONCE data register for register accesses of `poll`
+        if enableONCE(prog)? == OnceStatus::DebugMode {
+            context.log("Core is halted, console waits for firmware to run");
+        }
+        let mut channel = ConsoleChannel::default();
+        while !context.is_cancelled() {
+            loop {
+                match input.try_recv() {
+                    Ok(text)                        => channel.send(&text),
+                    Err(TryRecvError::Empty)        => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+            let (output, moved) = channel.poll(prog)?;
+            if let Some(text) = output {
+                context.console(text);
+            }
+            if !moved {
+                thread::sleep(CONSOLE_POLL);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::jtag::enableCoreTAP;
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::DscTap;
+
+    /// `text_words` - `RECORD_TEXT` / `RECORD_PRINTF` body of `text`
+    fn text_words(text: &str) -> Vec<u16> {
+        text.as_bytes().chunks(2).map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])).collect()
+    }
+
+    #[test]
+    fn printf_formats() {
+        assert_eq!(argument_words("adc=%d t=%04lx %% %s"), 3);
+        assert_eq!(format_printf("adc=%d t=%08lX%%", &[0xFFFE, 0x5678, 0x1234]), "adc=-2 t=12345678%");
+        assert_eq!(format_printf("[%5u|%-4x|%c|%03d]", &[42, 0xA, b'k' as u16, 0xFFFF]), "[   42|a   |k|-01]");
+        assert_eq!(format_printf("%s %d", &[]), "%s 0");
+    }
+
+    #[test]
+    fn decoder_records() {
+        let mut decoder = ConsoleDecoder::default();
+        let mut words = vec![0x0048, 0x0069, 0x0105];
+        words.extend(text_words(" boot"));
+        words.push(0x0209);
+        words.extend(text_words("v%u.%02u\n"));
+        words.extend([1, 7, 0x7F00]);
+        let output: Vec<String> = words.into_iter().filter_map(|word| decoder.push(word)).collect();
+        assert_eq!(output, ["H", "i", " boot", "v1.07\n", "<$7F00>"]);
+    }
+
+    #[test]
+    fn channel_moves_words_both_ways() {
+        let mut tap = DscTap::new();
+        tap.console_output.extend([0x0041, 0x0103, 0x6F6B, 0x2100]);
+        let link = InterpreterTransport::new(tap);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+
+        let mut channel = ConsoleChannel::default();
+        channel.send("go");
+        let mut output = String::new();
+        for _ in 0..6 {
+            if let (Some(text), _) = channel.poll(&mut prog).unwrap() {
+                output.push_str(&text);
+            }
+        }
+        assert_eq!(output, "Aok!");
+        assert_eq!(link.with_tap(|tap| tap.console_input.clone()), [b'g' as u32, b'o' as u32]);
+
+        // core has not taken previous word yet
+        channel.send("!");
+        link.with_tap(|tap| tap.once_registers.insert(0x06, OTXRXSR_RDF as u32));
+        assert_eq!(channel.poll(&mut prog).unwrap(), (None, false));
+        assert_eq!(link.with_tap(|tap| tap.console_input.len()), 2);
+    }
+}
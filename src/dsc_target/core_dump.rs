@@ -0,0 +1,311 @@
+use std::fmt;
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use super::target_factory::{TargetDsc, AccessType, MemorySegment};
+use super::job_worker::JobContext;
+use crate::errors::{Error};
+use crate::usbdm::programmer::Programmer;
+use crate::usbdm::jtag::{enableCoreTAP, enableONCE, read_core_id_code, read_master_id_code_DSC_JTAG_ID, OnceStatus};
+use crate::usbdm::core_registers::CoreRegisters;
+use crate::symbols::symbol_table::{SymbolTable, space_name};
+
+/// running core not halted in this time fails dump
+const DUMP_HALT_TIMEOUT : Duration = Duration::from_millis(500);
+/// words read at once, progress is reported between blocks
+const DUMP_BLOCK_WORDS  : u32 = 0x400;
+/// words on one line of `CoreDump::memory_lines`
+const DUMP_LINE_WORDS   : usize = 8;
+/// start of dump file, bincode of `CoreDump` follows
+const DUMP_MAGIC        : &[u8] = b"USBDM-DSC-CORE-DUMP-1\n";
+
+/// `RegionKind` - memory map segment region was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegionKind {
+    Ram,
+    Data,
+    Flash,
+    Peripherals,
+}
+
+/// `DumpRegion` - words of one memory map segment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpRegion {
+    pub name  : String,
+    pub kind  : RegionKind,
+    pub space : AccessType,
+    pub start : u32,
+    pub words : Vec<u16>,
+}
+
+impl DumpRegion {
+
+    /// `word` - value at word `address`, `None` outside region
+    pub fn word(&self, address: u32) -> Option<u16> {
+        let index = address.checked_sub(self.start)? as usize;
+        self.words.get(index).copied()
+    }
+
+    /// `last` - last word address of region
+    pub fn last(&self) -> u32 {
+        self.start + self.words.len().saturating_sub(1) as u32
+    }
+}
+
+impl fmt::Display for DumpRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let space = space_name(self.space);
+        write!(f, "{:<14} {}:${:04X}..{}:${:04X} {:>6} words", self.name, space, self.start, space, self.last(), self.words.len())
+    }
+}
+
+/// `CoreDump` - snapshot of halted target for post-mortem: registers, OnCE state and memory map contents
+///
+/// Saved as one file, viewed with no programmer attached.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoreDump {
+    pub target        : String,
+    pub jtag_id       : u32,
+    pub core_id       : u32,
+    pub captured_unix : u64,
+    /// OnCE mode before dump halted core
+    pub once_status   : OnceStatus,
+    /// PC when core stopped, same as in registers
+    pub halted_pc     : u32,
+    /// core values of `CoreRegisters::values`
+    pub registers     : Vec<u32>,
+    pub regions       : Vec<DumpRegion>,
+}
+
+impl CoreDump {
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = DUMP_MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, self).map_err(|e| Error::CoreDumpError(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let content = bytes.strip_prefix(DUMP_MAGIC).ok_or(Error::CoreDumpError("not a core dump file".to_string()))?;
+        let dump: CoreDump = bincode::deserialize(content).map_err(|e| Error::CoreDumpError(format!("damaged file, {}", e)))?;
+        dump.core_registers()?;
+        Ok(dump)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn core_registers(&self) -> Result<CoreRegisters, Error> {
+        CoreRegisters::from_values(&self.registers).map_err(|_e| Error::CoreDumpError(format!("{} register values in dump", self.registers.len())))
+    }
+
+    /// `word` - dumped value at `space` word `address`, `None` if it was not dumped
+    pub fn word(&self, space: AccessType, address: u32) -> Option<u16> {
+        self.regions.iter().filter(|region| region.space == space).find_map(|region| region.word(address))
+    }
+
+    /// `report` - dump header, stop address and registers, names from `symbols`
+    pub fn report(&self, symbols: &SymbolTable) -> String {
+        let mut report = self.to_string();
+        report += &format!("stopped at {}\n", symbols.address_text(AccessType::MemoryP, self.halted_pc));
+        if let Ok(registers) = self.core_registers() {
+            report += &registers.to_string();
+        }
+        report
+    }
+
+    /// `memory_lines` - hex lines of region `index`, symbols starting in line are put before it
+    pub fn memory_lines(&self, index: usize, symbols: &SymbolTable) -> Vec<String> {
+        let region = match self.regions.get(index) {
+            Some(region) => region,
+            None => return Vec::new(),
+        };
+        let space = space_name(region.space);
+        let mut lines = Vec::new();
+        for (line, words) in region.words.chunks(DUMP_LINE_WORDS).enumerate() {
+            let address = region.start + (line * DUMP_LINE_WORDS) as u32;
+            for offset in 0..words.len() as u32 {
+                for label in symbols.labels_at(region.space, address + offset) {
+                    lines.push(format!("{}: {}:${:04X}", label, space, address + offset));
+                }
+            }
+            let hex: Vec<String> = words.iter().map(|word| format!("{:04X}", word)).collect();
+            lines.push(format!("{}:${:04X}  {}", space, address, hex.join(" ")));
+        }
+        lines
+    }
+}
+
+impl fmt::Display for CoreDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}, JTAG ID ${:08X}, core ID ${:08X}", self.target, self.jtag_id, self.core_id)?;
+        let state = match self.once_status {
+            OnceStatus::DebugMode => "was halted",
+            _                     => "was running",
+        };
+        writeln!(f, "captured {}, core {} ({:?})", utc_text(self.captured_unix), state, self.once_status)?;
+        for region in &self.regions {
+            writeln!(f, "{}", region)?;
+        }
+        Ok(())
+    }
+}
+
+/// `utc_text` - `YYYY-MM-DD hh:mm:ss UTC` of unix time
+fn utc_text(unix: u64) -> String {
+    let (days, seconds) = ((unix / 86400) as i64, unix % 86400);
+    // civil date of day number, 400 year eras from 0000-03-01
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn id_code(bytes: Vec<u8>) -> Result<u32, Error> {
+    let bytes: [u8; 4] = bytes.try_into().map_err(|_e| Error::InternalError("IDCODE is not 32-bit".to_string()))?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+impl TargetDsc {
+
+    /// `core_dump` - halts core and reads registers, OnCE status and every `memory_map` segment
+    ///
+    /// JTAG ID is read through master TAP before halting, core TAP is enabled again after it.
+    /// Core is left halted for inspection. Peripheral windows are read as core reads them,
+    /// so read-to-clear status flags are cleared by the dump.
+    pub fn core_dump(&mut self, prog: &mut Programmer, context: &mut JobContext) -> Result<CoreDump, Error> {
+        let jtag_id = id_code(read_master_id_code_DSC_JTAG_ID(true, prog)?)?;
+        enableCoreTAP(prog)?;
+        let core_id = id_code(read_core_id_code(false, prog)?)?;
+        let once_status = enableONCE(prog)?;
+        let halted = self.halt(DUMP_HALT_TIMEOUT, prog)?;
+        context.log(format!("Core {}", halted));
+        let registers = CoreRegisters::read(prog)?;
+
+        let segments: Vec<(String, RegionKind, AccessType, u32, u32)> = self.memory_map.iter().map(|segment| {
+            let (name, kind, range, space) = match segment {
+                MemorySegment::Ram(ram)                => (&ram.name, RegionKind::Ram, &ram.range, ram.access_type),
+                MemorySegment::DataEeprom(data)        => (&data.name, RegionKind::Data, &data.range, data.access_type),
+                MemorySegment::FlashProgramm(flash)    => (&flash.name, RegionKind::Flash, &flash.range, flash.access_type),
+                MemorySegment::Peripherals(peripheral) => (&peripheral.name, RegionKind::Peripherals, &peripheral.range, peripheral.access_type),
+            };
+            let name = name.clone().unwrap_or_else(|| match kind {
+                RegionKind::Ram         => format!("{} RAM", space_name(space)),
+                RegionKind::Data        => format!("{} data", space_name(space)),
+                RegionKind::Flash       => "program flash".to_string(),
+                RegionKind::Peripherals => "peripherals".to_string(),
+            });
+            (name, kind, space, range.start as u32, range.end as u32)
+        }).collect();
+
+        let total: u32 = segments.iter().map(|(_, _, _, start, last)| last + 1 - start).sum();
+        let mut done = 0;
+        let mut regions = Vec::new();
+        for (name, kind, space, start, last) in segments {
+            let mut words = Vec::new();
+            let mut address = start;
+            while address <= last {
+                context.checkpoint()?;
+                let block = DUMP_BLOCK_WORDS.min(last + 1 - address);
+                let bytes = prog.dsc_read_memory(space.into(), block * 2, address)?;
+                words.extend(bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])));
+                address += block;
+                done += block;
+                context.progress(done as f32 * 100.0 / total as f32);
+            }
+            regions.push(DumpRegion { name, kind, space, start, words });
+        }
+
+        Ok(CoreDump {
+            target        : self.name.clone(),
+            jtag_id,
+            core_id,
+            captured_unix : SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0),
+            once_status,
+            halted_pc     : halted.pc,
+            registers     : registers.values(),
+            regions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::{DscTap, DscCore, VIRTUAL_MASTER_ID, VIRTUAL_CORE_ID};
+    use crate::usbdm::registers::DscRegisters;
+    use crate::usbdm::constants::memory_space_t;
+    use crate::dsc_target::target_factory::{TargetSelector, TargetYaml};
+    use crate::symbols::symbol_table::Symbol;
+
+    #[test]
+    fn dates_are_utc() {
+        assert_eq!(utc_text(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(utc_text(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(utc_text(1_760_000_000), "2025-10-09 08:53:20 UTC");
+    }
+
+    #[test]
+    fn dump_of_running_core() {
+        let mut tap = DscTap::new();
+        let mut core = DscCore::default();
+        core.set(DscRegisters::DscRegPc, 0x0123);
+        core.set(DscRegisters::DscRegR0, 0x00_0801);
+        tap.core = Some(core);
+        // X:$0801 = $BEEF, flash module PROT X:$F410 = $1234, flash P:$0010 = $E708
+        for (space, byte_address, byte) in [(memory_space_t::MS_DATA, 0x1002, 0xEF), (memory_space_t::MS_DATA, 0x1003, 0xBE),
+            (memory_space_t::MS_DATA, 0x1E820, 0x34), (memory_space_t::MS_DATA, 0x1E821, 0x12),
+            (memory_space_t::MS_PROGRAM, 0x20, 0x08), (memory_space_t::MS_PROGRAM, 0x21, 0xE7)] {
+            tap.memory.insert((space, byte_address), byte);
+        }
+        let link = InterpreterTransport::new(tap);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Tester56f8035, database).unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut sink = |_event| {};
+        let mut context = JobContext::new(&mut sink, &cancel);
+        let dump = target.core_dump(&mut prog, &mut context).unwrap();
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::DebugMode);
+
+        assert_eq!((dump.jtag_id, dump.core_id), (VIRTUAL_MASTER_ID, VIRTUAL_CORE_ID));
+        assert_eq!((dump.once_status.clone(), dump.halted_pc), (OnceStatus::ExecuteMode, 0x0123));
+        assert_eq!(dump.core_registers().unwrap().get(DscRegisters::DscRegR0), Some(0x00_0801));
+        assert_eq!(dump.regions.iter().map(|region| region.kind).collect::<Vec<RegionKind>>(),
+            [RegionKind::Ram, RegionKind::Data, RegionKind::Flash, RegionKind::Peripherals]);
+        assert_eq!(dump.regions[0].to_string(), "P RAM          P:$8000..P:$8FFF   4096 words");
+        assert_eq!(dump.word(AccessType::MemoryX, 0x0801), Some(0xBEEF));
+        assert_eq!(dump.word(AccessType::MemoryX, 0xF410), Some(0x1234));
+        assert_eq!(dump.word(AccessType::MemoryP, 0x0010), Some(0xE708));
+        assert_eq!(dump.word(AccessType::MemoryX, 0xE000), None);
+
+        let restored = CoreDump::from_bytes(&dump.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored, dump);
+        assert!(matches!(CoreDump::from_bytes(b"USBDM session"), Err(Error::CoreDumpError(_))));
+        assert!(matches!(CoreDump::from_bytes(&dump.to_bytes().unwrap()[..100]), Err(Error::CoreDumpError(_))));
+
+        let symbols = SymbolTable::new(vec![
+            Symbol { name: "Fmain".to_string(), space: AccessType::MemoryP, address: 0x0120, size: 0x10, function: true },
+            Symbol { name: "counter".to_string(), space: AccessType::MemoryX, address: 0x0801, size: 1, function: false },
+        ], Vec::new());
+        assert!(dump.report(&symbols).contains("stopped at P:$0123 <Fmain+$3>\n"));
+        let data = dump.regions.iter().position(|region| region.kind == RegionKind::Data).unwrap();
+        assert_eq!(dump.memory_lines(data, &symbols)[0x100..0x102], ["counter: X:$0801", "X:$0800  FFFF BEEF FFFF FFFF FFFF FFFF FFFF FFFF"].map(String::from));
+    }
+}
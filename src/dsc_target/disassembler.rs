@@ -0,0 +1,290 @@
+use std::fmt;
+
+use super::target_factory::TargetDsc;
+use crate::errors::Error;
+use crate::usbdm::constants::memory_space_t;
+use crate::usbdm::programmer::Programmer;
+
+/// longest instruction in the opcode table, in words
+pub const MAX_INSTRUCTION_WORDS : usize = 3;
+
+/// `Instruction` - one decoded instruction of P memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    /// word address of first word
+    pub address : u32,
+    /// opcode and extension words
+    pub words   : Vec<u16>,
+    /// assembler text, `dc.w $xxxx` for a word not found in the opcode table
+    pub text    : String,
+}
+
+impl Instruction {
+
+    /// `size` - instruction length in words, address of next instruction is `address + size`
+    pub fn size(&self) -> u32 {
+        self.words.len() as u32
+    }
+
+    /// `is_known` - `false` for a word shown as `dc.w`
+    pub fn is_known(&self) -> bool {
+        !self.text.starts_with("dc.w")
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(|word| format!("{:04X}", word)).collect();
+        write!(f, "P:${:06X}  {:<14} {}", self.address, words.join(" "), self.text)
+    }
+}
+
+/// register field (bits 11..7 of second word) of `move.w reg,X:>>otx1` / `move.l reg,X:>>otx`,
+/// codes as used by `TARGET_READ_REG_SEQUENCE` in `registers.rs`
+const OTX_REGISTERS : [(u16, &str); 16] = [
+    (0x00, "A1"), (0x02, "B1"), (0x08, "X0"), (0x09, "A2"), (0x0A, "Y0"), (0x0B, "B2"), (0x0D, "A0"), (0x0E, "Y1"),
+    (0x0F, "B0"), (0x10, "R0"), (0x12, "R1"), (0x14, "R2"), (0x16, "R3"), (0x18, "R4"), (0x1A, "R5"), (0x1C, "N"),
+];
+
+/// destination field (bits 11..7) of one-word register move with mnemonic used for it,
+/// codes as used by register access sequences in `registers.rs`
+const MOVE_DESTINATIONS : [(u16, &str, &str); 16] = [
+    (0x01, "A1", "move.w"),  (0x03, "B1", "move.w"),  (0x08, "X0", "move.w"),   (0x09, "A2", "move.w"),
+    (0x0A, "Y0", "move.w"),  (0x0B, "B2", "move.w"),  (0x0D, "A0", "move.w"),   (0x0E, "Y1", "move.w"),
+    (0x0F, "B0", "move.w"),  (0x13, "N3", "moveu.w"), (0x15, "M01", "moveu.w"), (0x18, "R4", "moveu.w"),
+    (0x19, "OMR", "moveu.w"),(0x1B, "SR", "moveu.w"), (0x1D, "LC", "moveu.w"),  (0x1F, "LA", "moveu.w"),
+];
+
+/// source field (bits 4..0) of one-word register move, codes as used by register access sequences in `registers.rs`
+const MOVE_SOURCES : [(u16, &str); 7] = [
+    (0x0C, "R4"), (0x19, "N3"), (0x1A, "M01"), (0x1C, "OMR"), (0x1D, "SR"), (0x1E, "LC"), (0x1F, "LA"),
+];
+
+/// one-word instructions without operand fields
+const FIXED : [(u16, &str); 16] = [
+    (0xE700, "nop"),
+    (0xE706, "swap    shadows"),
+    (0xE716, "move.l  PC,R4"),
+    (0xE717, "move.l  R4,PC"),
+    (0x7C20, "tfr     C,A"),
+    (0x7C30, "tfr     D,A"),
+    (0x7D00, "tfr     A,C"),
+    (0x7D80, "tfr     A,D"),
+    (0x81A0, "tfra    R4,R0"),
+    (0x81A1, "tfra    R4,R1"),
+    (0x81A2, "tfra    R4,R2"),
+    (0x81A3, "tfra    R4,R3"),
+    (0x81A9, "tfra    R4,R5"),
+    (0x81AA, "tfra    R4,N"),
+    (0x81AB, "tfra    R4,SP"),
+    (0x81BC, "tfra    SP,R4"),
+];
+
+/// `Opcode` - instruction form, `decode` is called with `words` words when `first & mask == pattern`
+struct Opcode {
+    mask    : u16,
+    pattern : u16,
+    words   : usize,
+    decode  : fn(&[u16]) -> Option<String>,
+}
+
+/// Opcode table, checked in order.
+///
+/// Holds only the forms and operand codes of the core register access sequences in `registers.rs`.
+/// This is not a 56800E disassembler: any other word, parallel moves included, comes out as `dc.w`.
+const OPCODES : [Opcode; 6] = [
+    Opcode { mask: 0xFFFF, pattern: 0xE77F, words: 3, decode: move_word_to_otx1 },
+    Opcode { mask: 0xFFFF, pattern: 0xE37F, words: 3, decode: move_long_to_otx },
+    Opcode { mask: 0xFFFF, pattern: 0xE41C, words: 3, decode: move_long_immediate },
+    Opcode { mask: 0xFFFF, pattern: 0x874C, words: 2, decode: move_word_immediate },
+    Opcode { mask: 0x0000, pattern: 0x0000, words: 1, decode: fixed },
+    Opcode { mask: 0xF060, pattern: 0x8000, words: 1, decode: register_move },
+];
+
+fn operands(mnemonic: &str, operands: &str) -> String {
+    format!("{:<8}{}", mnemonic, operands)
+}
+
+fn otx_register(word: u16) -> Option<&'static str> {
+    let code = (word >> 7) & 0x1F;
+    OTX_REGISTERS.iter().find(|(number, _)| *number == code).map(|(_, name)| *name)
+}
+
+fn fixed(words: &[u16]) -> Option<String> {
+    FIXED.iter().find(|(word, _)| *word == words[0]).map(|(_, text)| text.to_string())
+}
+
+/// `register_move` - `1000 ddddd 00 sssss`
+fn register_move(words: &[u16]) -> Option<String> {
+    let code = (words[0] >> 7) & 0x1F;
+    let (_, destination, mnemonic) = MOVE_DESTINATIONS.iter().find(|(number, _, _)| *number == code)?;
+    let code = words[0] & 0x1F;
+    let (_, source) = MOVE_SOURCES.iter().find(|(number, _)| *number == code)?;
+    Some(operands(mnemonic, &format!("{},{}", source, destination)))
+}
+
+/// `move_word_to_otx1` - `E77F`, `1101 sssss 1111100`, `FFFF`
+fn move_word_to_otx1(words: &[u16]) -> Option<String> {
+    if words[1] & 0xF07F != 0xD07C || words[2] != 0xFFFF {
+        return None
+    }
+    Some(operands("move.w", &format!("{},X:>>otx1", otx_register(words[1])?)))
+}
+
+/// `move_long_to_otx` - `E37F`, `1101 sssss 1111101`, `FFFF`
+fn move_long_to_otx(words: &[u16]) -> Option<String> {
+    if words[1] & 0xF07F != 0xD07D || words[2] != 0xFFFF {
+        return None
+    }
+    Some(operands("move.l", &format!("{},X:>>otx", otx_register(words[1])?)))
+}
+
+/// `move_long_immediate` - `E41C`, low word, high word
+fn move_long_immediate(words: &[u16]) -> Option<String> {
+    Some(operands("move.l", &format!("#${:04x}{:04x},R4", words[2], words[1])))
+}
+
+/// `move_word_immediate` - `874C`, value
+fn move_word_immediate(words: &[u16]) -> Option<String> {
+    Some(operands("moveu.w", &format!("#${:04x},R4", words[1])))
+}
+
+/// `decode` - first instruction of `words` located at P:`address`
+///
+/// Multi-word form cut off at end of `words` is not decoded, its first word is given as `dc.w`.
+pub fn decode(words: &[u16], address: u32) -> Instruction {
+    let first = words.first().copied().unwrap_or(0xFFFF);
+    for opcode in OPCODES.iter() {
+        if first & opcode.mask != opcode.pattern || words.len() < opcode.words {
+            continue
+        }
+        if let Some(text) = (opcode.decode)(&words[..opcode.words]) {
+            return Instruction { address, words: words[..opcode.words].to_vec(), text }
+        }
+    }
+    Instruction { address, words: vec![first], text: operands("dc.w", &format!("${:04x}", first)) }
+}
+
+/// `disassemble` - all instructions of `words`, first one at P:`start`
+pub fn disassemble(words: &[u16], start: u32) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < words.len() {
+        let instruction = decode(&words[offset..], start + offset as u32);
+        offset += instruction.words.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/// `words_from_bytes` - P memory words of bytes as read from target or loaded from file (low byte first)
+pub fn words_from_bytes(bytes: &[u8]) -> Vec<u16> {
+    bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect()
+}
+
+impl TargetDsc {
+
+    /// `disassemble_at` - reads P memory of halted core and decodes `count` instructions from P:`address`
+    pub fn disassemble_at(&mut self, address: u32, count: usize, prog: &mut Programmer) -> Result<Vec<Instruction>, Error> {
+        let words = count * MAX_INSTRUCTION_WORDS;
+        let bytes = prog.dsc_read_memory(memory_space_t::MS_PWORD, words as u32 * 2, address)?;
+        let mut instructions = disassemble(&words_from_bytes(&bytes), address);
+        // instructions at the end of read may be cut, there are enough whole ones before
+        instructions.truncate(count);
+        Ok(instructions)
+    }
+
+    /// `instruction_at_pc` - instruction core executes next
+    pub fn instruction_at_pc(&mut self, prog: &mut Programmer) -> Result<Instruction, Error> {
+        let pc = prog.dsc_read_pc()?;
+        Ok(self.disassemble_at(pc, 1, prog)?.remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::registers::{TARGET_READ_REG_SEQUENCE, TARGET_WRITE_REG_SEQUENCE};
+
+    /// instructions of register sequence, each is length in words followed by big endian words
+    fn sequence_words(sequence: &[u8]) -> Vec<Vec<u16>> {
+        let mut instructions = Vec::new();
+        let mut rest = sequence;
+        while let Some((&length, tail)) = rest.split_first() {
+            let (words, tail) = tail.split_at(length as usize * 2);
+            instructions.push(words.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect());
+            rest = tail;
+        }
+        instructions
+    }
+
+    #[test]
+    fn register_sequences() {
+        let texts = |words: &[u16]| -> Vec<String> { disassemble(words, 0).into_iter().map(|instruction| instruction.text).collect() };
+
+        assert_eq!(texts(&[0xE77F, 0xD6FC, 0xFFFF]), ["move.w  A0,X:>>otx1"]);
+        assert_eq!(texts(&[0xE37F, 0xDE7D, 0xFFFF]), ["move.l  N,X:>>otx"]);
+        assert_eq!(texts(&[0xE41C, 0xDBAD, 0xFFBA]), ["move.l  #$ffbadbad,R4"]);
+        assert_eq!(texts(&[0x874C, 0xABAD]), ["moveu.w #$abad,R4"]);
+        assert_eq!(texts(&[0x8C1F, 0x869A, 0x840C, 0x8D8C]), ["moveu.w LA,R4", "move.w  M01,A0", "move.w  R4,X0", "moveu.w R4,SR"]);
+        assert_eq!(texts(&[0xE706, 0x7C30, 0x81BC, 0xE700]), ["swap    shadows", "tfr     D,A", "tfra    SP,R4", "nop"]);
+
+        // every instruction of the register access sequences is in the table
+        for sequence in TARGET_READ_REG_SEQUENCE.iter().chain(TARGET_WRITE_REG_SEQUENCE.iter()) {
+            let instructions = sequence_words(sequence.sequence);
+            assert_eq!(instructions.len(), sequence.instruction_count as usize);
+            for words in instructions {
+                let decoded = decode(&words, 0);
+                assert!(decoded.is_known(), "{:04X?} not decoded", words);
+                assert_eq!(decoded.words, words);
+            }
+        }
+    }
+
+    #[test]
+    fn move_to_otx_forms() {
+        assert_eq!(decode(&[0xE77F, 0xD07C, 0xFFFF], 0).text, "move.w  A1,X:>>otx1");
+        assert_eq!(decode(&[0xE77F, 0xD7FC, 0xFFFF], 0).text, "move.w  B0,X:>>otx1");
+        assert_eq!(decode(&[0xE37F, 0xDC7D, 0xFFFF], 0).text, "move.l  R4,X:>>otx");
+        // wrong low bits of second word, unlisted register code, missing $FFFF
+        assert!(!decode(&[0xE77F, 0xD07D, 0xFFFF], 0).is_known());
+        assert!(!decode(&[0xE77F, 0xD0FC, 0xFFFF], 0).is_known());
+        assert!(!decode(&[0xE37F, 0xDC7D, 0x0000], 0).is_known());
+    }
+
+    #[test]
+    fn register_move_form() {
+        assert_eq!(decode(&[0x808C], 0).text, "move.w  R4,A1");
+        assert_eq!(decode(&[0x818C], 0).text, "move.w  R4,B1");
+        assert_eq!(decode(&[0x869C], 0).text, "move.w  OMR,A0");
+        assert_eq!(decode(&[0x8C1E], 0).text, "moveu.w LC,R4");
+        assert_eq!(decode(&[0x8F8C], 0).text, "moveu.w R4,LA");
+        // unlisted destination, unlisted source
+        assert!(!decode(&[0x800C], 0).is_known());
+        assert!(!decode(&[0x8080], 0).is_known());
+    }
+
+    #[test]
+    fn immediate_and_fixed_forms() {
+        assert_eq!(decode(&[0xE41C, 0x5678, 0x1234], 0).text, "move.l  #$12345678,R4");
+        assert_eq!(decode(&[0x874C, 0x0001], 0).text, "moveu.w #$0001,R4");
+        assert_eq!(decode(&[0xE717], 0).text, "move.l  R4,PC");
+        assert_eq!(decode(&[0x81A9], 0).text, "tfra    R4,R5");
+        assert_eq!(decode(&[0x7D80], 0).text, "tfr     A,D");
+        assert!(!decode(&[0xE701], 0).is_known());
+    }
+
+    #[test]
+    fn unknown_and_cut_words() {
+        let instructions = disassemble(&[0xE700, 0x1234, 0xE77F, 0xD47C], 0x0200);
+        let lines: Vec<String> = instructions.iter().map(|instruction| instruction.to_string()).collect();
+        assert_eq!(lines, [
+            "P:$000200  E700           nop",
+            "P:$000201  1234           dc.w    $1234",
+            "P:$000202  E77F           dc.w    $e77f",
+            "P:$000203  D47C           dc.w    $d47c",
+        ]);
+        assert_eq!(decode(&[0xE77F, 0xD47C, 0xFFFF, 0xE700], 0).size(), 3);
+        assert_eq!(words_from_bytes(&[0x00, 0xE7, 0x06, 0xE7, 0xFF]), [0xE700, 0xE706]);
+    }
+}
@@ -2,6 +2,9 @@ use super::*;
 
 use crate::usbdm::registers::*;
 
+/// time given to flash routine to reach `debughlt`, then it is halted by debug request
+const ROUTINE_TIMEOUT: time::Duration = time::Duration::from_millis(600);
+
 #[derive(Debug)]
 pub struct FlashRoutine {
     dsc_family: DscFamily,
@@ -152,19 +155,11 @@ impl FlashRoutine {
     fn dsc_routine_go (&mut self, prog: &mut Programmer) -> Result<(), Error> {
         prog.dsc_write_pc(self.routine.code_entry)?;
 
-        //set OCR_PWU and clear OCR_ISC_SINGLE_STEP
-        let once_ctrl_reg = prog.dsc_read_once_reg(DscRegisters::DscRegOcr)?;
-        prog.dsc_write_once_reg(DscRegisters::DscRegOcr, ((once_ctrl_reg | OCR_PWU as u32) & !OCR_ISC_SINGLE_STEP as u32));
-
+        prog.dsc_set_instruction_step(None)?;
         prog.dsc_target_go()?;
 
-        let mut timeout = 30;
-        loop {
-            thread::sleep(time::Duration::from_millis(20));
-            let once_status = enableONCE(&prog)?;
-            if once_status == OnceStatus::DebugMode {break}
-            timeout -= 1;
-            if timeout <= 0 { println!("Routine halt failed!!! Timeout used"); break}
+        if !prog.dsc_wait_debug_mode(ROUTINE_TIMEOUT)? {
+            println!("Routine halt failed!!! Timeout used");
         }
 
         prog.dsc_target_halt()?;
@@ -0,0 +1,710 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use crate::errors::{Error};
+use crate::usbdm::programmer::{Programmer};
+use crate::usbdm::settings::{BdmSettings, TargetVddSelect};
+use crate::usbdm::feedback::{PowerStatus};
+use crate::usbdm::hotplug::{UsbPort};
+use crate::usbdm::jtag_chain::{JtagChain};
+use crate::boundary_scan::bsdl::{Bsdl};
+use crate::boundary_scan::interconnect::{InterconnectReport, board_test, load_nets};
+use crate::svf::{svf_file, xsvf_file};
+use crate::svf::player::{PlayReport, play};
+use crate::usbdm::session::{open_usbdm_transport};
+use crate::preferences::{Preferences};
+use crate::dsc_target::target_factory::{TargetProgramming, TargetDsc, TargetSelector, TargetYaml};
+use crate::dsc_target::memory_buffer::{MemoryBuffer};
+use crate::dsc_target::speed_search::{SpeedSearch, SpeedSearchReport};
+use crate::gdb::server::{GdbServer};
+use crate::symbols::symbol_table::{SymbolTable};
+use crate::dsc_target::live_watch::{WatchItem, WatchSettings, WatchSample};
+use crate::dsc_target::profiler::{ProfileSettings, Profile};
+use crate::dsc_target::core_dump::{CoreDump};
+
+/// Flash write block, words
+pub const WRITE_BLOCK_SIZE : usize = 0x500;
+
+/// `CustomJob` - sequence run on worker with programmer and target, e.g. test routines
+pub type CustomJob = Box<dyn FnOnce(&mut TargetDsc, &mut Programmer, &mut JobContext) -> Result<JobResult, Error> + Send>;
+
+/// `Job` - operation done by `JobWorker`, one at a time in submit order
+pub enum Job {
+    /// Open USBDM (if not opened yet), init it for DSC and connect target
+    Connect { power: TargetVddSelect, record_session: bool },
+    /// Drop programmer, USBDM powers target off on drop
+    Disconnect,
+    /// USBDM on `UsbPort` was unplugged, drop programmer if it is ours
+    PortLeft(UsbPort),
+    SelectTarget(TargetSelector),
+    Power(TargetVddSelect),
+    Read { power: TargetVddSelect },
+    Write { power: TargetVddSelect, buffer: MemoryBuffer },
+    Verify { power: TargetVddSelect, buffer: MemoryBuffer },
+    Erase { power: TargetVddSelect },
+    /// Find fastest reliable JTAG clock of connected target, remembered in preferences
+    SpeedSearch(SpeedSearch),
+    /// Find TAPs on JTAG chain (IDCODEs, IR lengths), target is connected again after scan
+    ScanChain { power: TargetVddSelect },
+    /// Interconnect test of board by boundary scan, BSDL and nets files, target is connected again after test
+    BoardTest { bsdl: String, nets: String, power: TargetVddSelect },
+    /// Play SVF or XSVF (by extension) file on JTAG chain, stops on first mismatch, target is connected again after
+    PlaySvf { path: String, power: TargetVddSelect },
+    /// Serve one gdb connection on localhost `port`, cancel stops server and halts target.
+    /// `symbols` name addresses in `monitor` commands and their output.
+    GdbServer { port: u16, power: TargetVddSelect, symbols: Arc<SymbolTable> },
+    /// Text console over EOnCE data channel of running firmware, `input` lines go to target, cancel closes it
+    Console { input: Receiver<String> },
+    /// Sample X memory `items` of running firmware by halt, read and go until cancelled
+    LiveWatch { items: Vec<WatchItem>, settings: WatchSettings },
+    /// Sample PC of running firmware by halt, read and go, cancel ends it with samples taken so far
+    Profile(ProfileSettings),
+    /// Halt core, read registers and whole memory map, save it to file at `path`
+    CoreDump { path: String },
+    Custom(String, CustomJob),
+}
+
+/// `JobKind` - job without its data, tells events of which job arrived
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JobKind {
+    Connect,
+    Disconnect,
+    PortLeft,
+    SelectTarget,
+    Power,
+    Read,
+    Write,
+    Verify,
+    Erase,
+    SpeedSearch,
+    ScanChain,
+    BoardTest,
+    PlaySvf,
+    GdbServer,
+    Console,
+    LiveWatch,
+    Profile,
+    CoreDump,
+    Custom,
+}
+
+impl JobKind {
+    /// `is_session` - job runs until cancelled (closed by user), not by itself
+    pub fn is_session(self) -> bool {
+        matches!(self, JobKind::GdbServer | JobKind::Console | JobKind::LiveWatch)
+    }
+}
+
+impl Job {
+    pub fn kind(&self) -> JobKind {
+        match self {
+            Job::Connect { .. }     => JobKind::Connect,
+            Job::Disconnect         => JobKind::Disconnect,
+            Job::PortLeft(_)        => JobKind::PortLeft,
+            Job::SelectTarget(_)    => JobKind::SelectTarget,
+            Job::Power(_)           => JobKind::Power,
+            Job::Read { .. }        => JobKind::Read,
+            Job::Write { .. }       => JobKind::Write,
+            Job::Verify { .. }      => JobKind::Verify,
+            Job::Erase { .. }       => JobKind::Erase,
+            Job::SpeedSearch(_)     => JobKind::SpeedSearch,
+            Job::ScanChain { .. }   => JobKind::ScanChain,
+            Job::BoardTest { .. }   => JobKind::BoardTest,
+            Job::PlaySvf { .. }     => JobKind::PlaySvf,
+            Job::GdbServer { .. }   => JobKind::GdbServer,
+            Job::Console { .. }     => JobKind::Console,
+            Job::LiveWatch { .. }   => JobKind::LiveWatch,
+            Job::Profile(_)         => JobKind::Profile,
+            Job::CoreDump { .. }    => JobKind::CoreDump,
+            Job::Custom(_, _)       => JobKind::Custom,
+        }
+    }
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Job::Custom(name, _) => write!(f, "Custom({})", name),
+            _                    => write!(f, "{:?}", self.kind()),
+        }
+    }
+}
+
+/// `JobResult` - what successful job gives back
+#[derive(Debug, Clone)]
+pub enum JobResult {
+    Done,
+    /// Target memory read into buffer
+    Buffer(MemoryBuffer),
+    SpeedSearch(SpeedSearchReport),
+    /// TAPs found by `Job::ScanChain`
+    Chain(JtagChain),
+    /// Opens and shorts found by `Job::BoardTest`
+    BoardTest(InterconnectReport),
+    /// Scans and mismatches of `Job::PlaySvf`
+    Svf(PlayReport),
+    /// PC histogram of `Job::Profile`
+    Profile(Profile),
+    /// Snapshot saved by `Job::CoreDump`
+    CoreDump(CoreDump),
+}
+
+/// `WorkerEvent` - streamed from worker thread to gui
+#[derive(Debug, Clone)]
+pub enum WorkerEvent {
+    /// USBDM opened and initialized
+    Opened { name: String, version: String },
+    /// Programmer dropped on `Job::Disconnect`
+    Closed,
+    /// Our USBDM was unplugged, programmer dropped
+    ProbeLost,
+    /// Percent of running job
+    Progress(f32),
+    Log(String),
+    /// Text firmware printed on `Job::Console`
+    Console(String),
+    /// Values read by `Job::LiveWatch`
+    Watch(WatchSample),
+    /// Target power state, refreshed after every job on opened programmer
+    Power(PowerStatus),
+    Finished(JobKind, JobResult),
+    Failed(JobKind, Error),
+    /// Job stopped between blocks on `JobHandle::cancel`
+    Cancelled(JobKind),
+}
+
+enum Request {
+    /// job with its own cancel token
+    Run(Job, Arc<AtomicBool>),
+    Shutdown,
+}
+
+/// `Outstanding` - submitted job not finished yet
+#[derive(Debug)]
+struct Outstanding {
+    kind   : JobKind,
+    cancel : Arc<AtomicBool>,
+}
+
+/// `JobHandle` - submits jobs to worker and cancels them, cheap to clone into gui messages
+#[derive(Debug, Clone)]
+pub struct JobHandle {
+    requests : Sender<Request>,
+    jobs     : Arc<Mutex<Vec<Outstanding>>>,
+}
+
+impl JobHandle {
+
+    /// `submit` - queue job with new cancel token
+    ///
+    /// While session job (`JobKind::is_session`) runs nothing else would start, so other jobs are refused,
+    /// except `PortLeft` which only drops programmer after session ended.
+    pub fn submit(&self, job: Job) -> Result<(), Error> {
+        let kind = job.kind();
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(session) = jobs.iter().find(|outstanding| outstanding.kind.is_session()) {
+            if kind != JobKind::PortLeft {
+                return Err(Error::SessionRunning(format!("{:?}", session.kind)))
+            }
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.requests.send(Request::Run(job, cancel.clone()))
+            .map_err(|_e| Error::InternalError("Job worker stopped".to_string()))?;
+        jobs.push(Outstanding { kind, cancel });
+        Ok(())
+    }
+
+    /// `cancel` - running and queued jobs stop at next block boundary, jobs submitted later are not affected
+    pub fn cancel(&self) {
+        for outstanding in self.jobs.lock().unwrap().iter() {
+            outstanding.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn finished(&self, cancel: &Arc<AtomicBool>) {
+        self.jobs.lock().unwrap().retain(|outstanding| !Arc::ptr_eq(&outstanding.cancel, cancel));
+    }
+}
+
+/// `JobContext` - given to job, reports progress and logs, checks cancel
+pub struct JobContext<'a> {
+    sink   : &'a mut dyn FnMut(WorkerEvent),
+    cancel : &'a AtomicBool,
+}
+
+impl<'a> JobContext<'a> {
+
+    pub fn new(sink: &'a mut dyn FnMut(WorkerEvent), cancel: &'a AtomicBool) -> Self {
+        JobContext { sink, cancel }
+    }
+
+    pub fn progress(&mut self, percent: f32) {
+        (self.sink)(WorkerEvent::Progress(percent));
+    }
+
+    pub fn log(&mut self, line: impl Into<String>) {
+        (self.sink)(WorkerEvent::Log(line.into()));
+    }
+
+    pub fn console(&mut self, text: impl Into<String>) {
+        (self.sink)(WorkerEvent::Console(text.into()));
+    }
+
+    pub fn watch(&mut self, sample: WatchSample) {
+        (self.sink)(WorkerEvent::Watch(sample));
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// `checkpoint` - call between blocks, `Err(Error::JobCancelled)` if cancel was requested
+    pub fn checkpoint(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            return Err(Error::JobCancelled)
+        }
+        Ok(())
+    }
+}
+
+/// `JobWorker` - thread owning `Programmer` and `TargetDsc`, runs jobs and streams `WorkerEvent` to `sink`
+///
+/// Gui never touches USB itself, so it stays responsive during long flash operations.
+/// Thread is stopped on drop: running job is cancelled, programmer dropped on worker thread.
+pub struct JobWorker {
+    handle : JobHandle,
+    thread : Option<JoinHandle<()>>,
+}
+
+impl JobWorker {
+
+    pub fn start<F>(target: TargetDsc, database: TargetYaml, programmer: Option<Programmer>, sink: F) -> Self
+    where F: FnMut(WorkerEvent) + Send + 'static
+    {
+        let (requests, receiver) = mpsc::channel();
+        let handle = JobHandle { requests, jobs: Arc::new(Mutex::new(Vec::new())) };
+        let jobs = handle.clone();
+
+        let thread = thread::spawn(move || {
+            let mut state = WorkerState { target, database, programmer, sink };
+            for request in receiver {
+                match request {
+                    Request::Run(job, cancel) => {
+                        state.run(job, &cancel);
+                        jobs.finished(&cancel);
+                    }
+                    Request::Shutdown => break,
+                }
+            }
+        });
+        JobWorker { handle, thread: Some(thread) }
+    }
+
+    pub fn handle(&self) -> JobHandle {
+        self.handle.clone()
+    }
+}
+
+impl Drop for JobWorker {
+    fn drop(&mut self) {
+        self.handle.cancel();
+        self.handle.requests.send(Request::Shutdown).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+struct WorkerState<F> {
+    target     : TargetDsc,
+    database   : TargetYaml,
+    programmer : Option<Programmer>,
+    sink       : F,
+}
+
+/// `percent` - position of `address` in `start..end`
+fn percent(start: u32, address: u32, end: u32) -> f32 {
+    ((address as f32 - start as f32) / (end as f32 - start as f32)) * 100.00
+}
+
+impl<F: FnMut(WorkerEvent)> WorkerState<F> {
+
+    fn run(&mut self, job: Job, cancel: &AtomicBool) {
+        let kind = job.kind();
+
+        let result = self.execute(job, cancel);
+        match result {
+            Ok(result)                  => (self.sink)(WorkerEvent::Finished(kind, result)),
+            Err(Error::JobCancelled)    => (self.sink)(WorkerEvent::Cancelled(kind)),
+            Err(e)                      => (self.sink)(WorkerEvent::Failed(kind, e)),
+        }
+
+        // as check_power_state after every operation in gui
+        if kind == JobKind::SelectTarget {
+            return
+        }
+        if let Some(prog) = self.programmer.as_mut() {
+            match prog.get_power_state() {
+                Ok(power)   => (self.sink)(WorkerEvent::Power(power)),
+                Err(_e)     => (self.sink)(WorkerEvent::Log(format!("Power state not read: {:?}", _e))),
+            }
+        }
+    }
+
+    fn execute(&mut self, job: Job, cancel: &AtomicBool) -> Result<JobResult, Error> {
+        match job {
+            Job::Connect { power, record_session } => self.connect(power, record_session),
+            Job::Disconnect => {
+                self.programmer = None;
+                (self.sink)(WorkerEvent::Closed);
+                Ok(JobResult::Done)
+            }
+            Job::PortLeft(port) => {
+                if self.programmer.as_ref().and_then(|prog| prog.usb_port()) == Some(port) {
+                    // device is gone, nothing to release on target side - just drop handle
+                    self.programmer = None;
+                    (self.sink)(WorkerEvent::ProbeLost);
+                }
+                Ok(JobResult::Done)
+            }
+            Job::SelectTarget(selector) => {
+                self.target = TargetDsc::target_from_selector(selector, self.database.clone())?;
+                if let Some(prog) = self.programmer.as_mut() {
+                    let khz = Preferences::load().jtag_speed(&self.target.name).unwrap_or(BdmSettings::default().interface_frequency);
+                    prog.set_interface_frequency(khz)?;
+                }
+                Ok(JobResult::Done)
+            }
+            Job::Power(power) => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                self.target.power(power, prog)?;
+                Ok(JobResult::Done)
+            }
+            Job::SpeedSearch(search) => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                let report = self.target.speed_search(prog, &search, &mut context)?;
+                Preferences::set_jtag_speed(&report.target, report.selected)?;
+                Ok(JobResult::SpeedSearch(report))
+            }
+            Job::ScanChain { power } => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                let chain = prog.scan_chain()?;
+                context.log(chain.to_string());
+                // scan leaves TAPs reset, DSC core TAP has to be enabled again
+                if let Err(e) = self.target.connect(power, prog) {
+                    context.log(format!("Target not connected after chain scan: {:?}", e));
+                }
+                Ok(JobResult::Chain(chain))
+            }
+            Job::BoardTest { bsdl, nets, power } => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                let bsdl = Bsdl::load(&bsdl)?;
+                let nets = load_nets(&nets)?;
+                let report = board_test(prog, bsdl, &nets)?;
+                context.log(report.to_string());
+                if let Err(e) = self.target.connect(power, prog) {
+                    context.log(format!("Target not connected after board test: {:?}", e));
+                }
+                Ok(JobResult::BoardTest(report))
+            }
+            Job::PlaySvf { path, power } => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                let program = match path.to_lowercase().ends_with(".xsvf") {
+                    true  => xsvf_file::load(&path)?,
+                    false => svf_file::load(&path)?,
+                };
+                let report = play(prog, &program, true)?;
+                context.log(report.to_string());
+                prog.jtag_reset()?;
+                if let Err(e) = self.target.connect(power, prog) {
+                    context.log(format!("Target not connected after SVF: {:?}", e));
+                }
+                Ok(JobResult::Svf(report))
+            }
+            Job::GdbServer { port, power, symbols } => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                let server = GdbServer::bind(port)?;
+                context.log(format!("GDB server listening on localhost:{}", port));
+                server.serve(&mut self.target, prog, power, &symbols, &mut context)?;
+                Ok(JobResult::Done)
+            }
+            Job::Console { input } => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                self.target.console(&input, prog, &mut context)?;
+                Ok(JobResult::Done)
+            }
+            Job::LiveWatch { items, settings } => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                self.target.live_watch(&items, &settings, prog, &mut context)?;
+                Ok(JobResult::Done)
+            }
+            Job::Profile(settings) => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                let profile = self.target.profile(&settings, prog, &mut context)?;
+                Ok(JobResult::Profile(profile))
+            }
+            Job::CoreDump { path } => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                let dump = self.target.core_dump(prog, &mut context)?;
+                dump.save(&path)?;
+                context.log(format!("Core dump saved to {}", path));
+                Ok(JobResult::CoreDump(dump))
+            }
+            Job::Custom(name, custom) => {
+                let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+                let mut context = JobContext { sink: &mut self.sink, cancel };
+                context.log(format!("Run {}", name));
+                custom(&mut self.target, prog, &mut context)
+            }
+            Job::Read { power }           => self.read(power, cancel),
+            Job::Write { power, buffer }  => self.write(power, buffer, cancel),
+            Job::Verify { power, buffer } => self.verify(power, buffer, cancel),
+            Job::Erase { power }          => self.erase(power, cancel),
+        }
+    }
+
+    fn connect(&mut self, power: TargetVddSelect, record_session: bool) -> Result<JobResult, Error> {
+        if self.programmer.is_none() {
+            // check usb low level - two type errors : can't find VID and second cannot configure descriptor (bad drivers or hw error on usb port)
+            // with several USBDM on the bus preferred (remembered) probe is opened
+            let usb_int = open_usbdm_transport(record_session)?;
+            // init programmer, here we can get errors on get version, settings feedback etc.
+            let mut programmer = Programmer::new(usb_int)?;
+            let preferences = Preferences::load();
+            // pipelined memory transfers only when user opted in
+            programmer.transfer = preferences.transfer_config();
+            // clock found by speed search for this target, set by init
+            if let Some(khz) = preferences.jtag_speed(&self.target.name) {
+                programmer.settings.interface_frequency = khz;
+            }
+            (self.sink)(WorkerEvent::Opened { name: programmer.name.clone(), version: programmer.get_string_version() });
+            let prog = self.programmer.insert(programmer);
+            self.target.init(prog)?;
+        }
+        let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+        prog.refresh_feedback()?;
+        self.target.connect(power, prog)?;
+        Ok(JobResult::Done)
+    }
+
+    fn read(&mut self, power: TargetVddSelect, cancel: &AtomicBool) -> Result<JobResult, Error> {
+        let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+        let mut context = JobContext { sink: &mut self.sink, cancel };
+
+        let programm_range = self.target.programm_range()?;
+        let start_address = programm_range.start as u32;
+        let end_address = (programm_range.end + 1) as u32;
+
+        let mut address = start_address;
+        let mut blocks = Vec::new();
+        while address < end_address {
+            context.checkpoint()?;
+            let read = self.target.read_target(power, address, prog)?;
+            let read_len = (read.len() / 2) as u32;
+            if read_len == 0 {
+                return Err(Error::InternalError(format!("Empty read at {:#06X}", address)))
+            }
+            blocks.push(read);
+            address += read_len;
+            context.progress(percent(start_address, address, end_address));
+        }
+
+        self.target.memory_buffer.upload_from_target(blocks)?;
+        Ok(JobResult::Buffer(self.target.memory_buffer.clone()))
+    }
+
+    fn write(&mut self, power: TargetVddSelect, buffer: MemoryBuffer, cancel: &AtomicBool) -> Result<JobResult, Error> {
+        let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+        let mut context = JobContext { sink: &mut self.sink, cancel };
+        self.target.memory_buffer = buffer;
+
+        let programm_range = self.target.programm_range()?;
+        let start_address = programm_range.start as u32;
+        let end_address = (programm_range.end + 1) as u32;
+
+        let mut address = start_address;
+        while address < end_address {
+            context.checkpoint()?;
+            let to_write = self.target.memory_buffer.download_target_block(address as usize, WRITE_BLOCK_SIZE)?;
+            let real_size = (to_write.len() / 2) as u32;
+            if real_size == 0 {
+                break;
+            }
+            let write_time_start = Instant::now();
+            self.target.write_target(power, address, to_write, prog)?;
+            let write_throughput = ((real_size * 2) as f32) / write_time_start.elapsed().as_secs_f32();
+            context.log(format!("write speed : {:>8.2} bytes/s,", write_throughput));
+            address += real_size;
+            context.progress(percent(start_address, address, end_address));
+        }
+
+        self.target.power(TargetVddSelect::VddOff, prog)?;
+        Ok(JobResult::Done)
+    }
+
+    fn verify(&mut self, power: TargetVddSelect, buffer: MemoryBuffer, cancel: &AtomicBool) -> Result<JobResult, Error> {
+        let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+        let mut context = JobContext { sink: &mut self.sink, cancel };
+        self.target.memory_buffer = buffer;
+
+        let programm_range = self.target.programm_range()?;
+        let start_address = programm_range.start as u32;
+        let end_address = (programm_range.end + 1) as u32;
+
+        let mut address = start_address;
+        while address < end_address {
+            context.checkpoint()?;
+            let block_len = (self.target.verify_target(power, address, prog)? / 2) as u32;
+            if block_len == 0 {
+                break;
+            }
+            address += block_len;
+            context.progress(percent(start_address, address, end_address));
+        }
+        Ok(JobResult::Done)
+    }
+
+    fn erase(&mut self, power: TargetVddSelect, cancel: &AtomicBool) -> Result<JobResult, Error> {
+        let prog = self.programmer.as_mut().ok_or(Error::LostConnection)?;
+        let mut context = JobContext { sink: &mut self.sink, cancel };
+
+        // mass erase is one command, it can be cancelled only before start
+        context.checkpoint()?;
+        self.target.erase_target(power, prog)?;
+        context.progress(100.0);
+        Ok(JobResult::Done)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::usbdm::transport::ScriptedTransport;
+
+    fn scripted_worker() -> (ScriptedTransport, JobWorker, Receiver<WorkerEvent>) {
+        let database = TargetYaml::init_target_db().unwrap();
+        let target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database.clone()).unwrap();
+        let script = ScriptedTransport::new();
+        let prog = Programmer::from_transport(Box::new(script.clone()));
+        let (sender, events) = mpsc::channel();
+        let worker = JobWorker::start(target, database, Some(prog), move |event| { sender.send(event).ok(); });
+        (script, worker, events)
+    }
+
+    fn until_done(events: &Receiver<WorkerEvent>) -> Vec<WorkerEvent> {
+        let mut received = Vec::new();
+        loop {
+            let event = events.recv_timeout(Duration::from_secs(5)).expect("worker event");
+            let done = matches!(event, WorkerEvent::Finished(_, _) | WorkerEvent::Failed(_, _) | WorkerEvent::Cancelled(_));
+            received.push(event);
+            if done {
+                return received
+            }
+        }
+    }
+
+    #[test]
+    fn custom_job_streams_events() {
+        let (script, worker, events) = scripted_worker();
+        script.answer([vec![0x00], vec![0x5A; 4]].concat());
+
+        worker.handle().submit(Job::Custom("read x".to_string(), Box::new(|_target, prog, context| {
+            let read = prog.dsc_read_memory(crate::usbdm::constants::memory_space_t::MS_XWORD, 4, 0x8000)?;
+            context.progress(100.0);
+            context.log(format!("{:02X?}", read));
+            Ok(JobResult::Done)
+        }))).unwrap();
+
+        let received = until_done(&events);
+        assert!(matches!(received[0], WorkerEvent::Log(_)));
+        assert!(matches!(received[1], WorkerEvent::Progress(p) if p == 100.0));
+        assert!(matches!(&received[2], WorkerEvent::Log(line) if line.contains("5A, 5A")));
+        assert!(matches!(received[3], WorkerEvent::Finished(JobKind::Custom, JobResult::Done)));
+        assert!(script.is_finished());
+    }
+
+    #[test]
+    fn cancel_between_blocks() {
+        let (_script, worker, events) = scripted_worker();
+        let handle = worker.handle();
+
+        handle.submit(Job::Custom("blocks".to_string(), Box::new(|_target, _prog, context| {
+            for block in 0..1000 {
+                context.checkpoint()?;
+                context.progress(block as f32 / 10.0);
+                thread::sleep(Duration::from_millis(2));
+            }
+            Ok(JobResult::Done)
+        }))).unwrap();
+
+        assert!(matches!(events.recv_timeout(Duration::from_secs(5)), Ok(WorkerEvent::Log(_))));
+        assert!(matches!(events.recv_timeout(Duration::from_secs(5)), Ok(WorkerEvent::Progress(_))));
+        handle.cancel();
+        assert!(matches!(until_done(&events).last(), Some(WorkerEvent::Cancelled(JobKind::Custom))));
+    }
+
+    #[test]
+    fn later_submit_keeps_cancel() {
+        let (_script, worker, events) = scripted_worker();
+        let handle = worker.handle();
+
+        handle.submit(Job::Custom("blocks".to_string(), Box::new(|_target, _prog, context| {
+            for _block in 0..1000 {
+                context.checkpoint()?;
+                thread::sleep(Duration::from_millis(2));
+            }
+            Ok(JobResult::Done)
+        }))).unwrap();
+        assert!(matches!(events.recv_timeout(Duration::from_secs(5)), Ok(WorkerEvent::Log(_))));
+        handle.cancel();
+        handle.submit(Job::Custom("after".to_string(), Box::new(|_target, _prog, context| {
+            context.checkpoint()?;
+            Ok(JobResult::Done)
+        }))).unwrap();
+        assert!(matches!(until_done(&events).last(), Some(WorkerEvent::Cancelled(JobKind::Custom))));
+        assert!(matches!(until_done(&events).last(), Some(WorkerEvent::Finished(JobKind::Custom, JobResult::Done))));
+    }
+
+    #[test]
+    fn session_refuses_other_jobs() {
+        let (requests, queued) = mpsc::channel();
+        let handle = JobHandle { requests, jobs: Arc::new(Mutex::new(Vec::new())) };
+        let (_input_sender, input) = mpsc::channel();
+        handle.submit(Job::Console { input }).unwrap();
+        assert!(matches!(handle.submit(Job::Read { power: TargetVddSelect::Vdd3V3 }), Err(Error::SessionRunning(kind)) if kind == "Console"));
+        handle.submit(Job::PortLeft(UsbPort { bus: 1, address: 2 })).unwrap();
+
+        let cancel = match queued.recv().unwrap() {
+            Request::Run(job, cancel) => { assert_eq!(job.kind(), JobKind::Console); cancel }
+            Request::Shutdown         => panic!("shutdown queued"),
+        };
+        handle.cancel();
+        assert!(cancel.load(Ordering::Relaxed));
+        handle.finished(&cancel);
+        handle.submit(Job::Read { power: TargetVddSelect::Vdd3V3 }).unwrap();
+    }
+
+    #[test]
+    fn no_programmer_fails_job() {
+        let (_script, worker, events) = scripted_worker();
+        worker.handle().submit(Job::Disconnect).unwrap();
+        assert!(matches!(events.recv().unwrap(), WorkerEvent::Closed));
+        assert!(matches!(until_done(&events).last(), Some(WorkerEvent::Finished(JobKind::Disconnect, _))));
+
+        worker.handle().submit(Job::Read { power: TargetVddSelect::Vdd3V3 }).unwrap();
+        assert!(matches!(until_done(&events).last(), Some(WorkerEvent::Failed(JobKind::Read, Error::LostConnection))));
+    }
+}
@@ -0,0 +1,373 @@
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::target_factory::{TargetDsc, AccessType};
+use super::job_worker::JobContext;
+use crate::errors::{Error};
+use crate::usbdm::programmer::Programmer;
+use crate::usbdm::jtag::{enableONCE, OnceStatus};
+use crate::symbols::symbol_table::SymbolTable;
+use crate::symbols::dwarf::{VariableType, TypeKind};
+
+/// running core not halted in this time fails sampling
+const WATCH_HALT_TIMEOUT : Duration = Duration::from_millis(100);
+/// cancel check interval while waiting for next sample
+const WATCH_POLL         : Duration = Duration::from_millis(10);
+
+/// `WatchFormat` - how watched X memory is decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchFormat {
+    I16,
+    U16,
+    /// signed fraction of one word, `Frac16`
+    Q15,
+    /// two words, low word first
+    I32,
+}
+
+pub const WATCH_FORMATS : [WatchFormat; 4] = [WatchFormat::I16, WatchFormat::U16, WatchFormat::Q15, WatchFormat::I32];
+
+impl WatchFormat {
+
+    pub fn words(self) -> u32 {
+        match self {
+            WatchFormat::I32 => 2,
+            _                => 1,
+        }
+    }
+
+    /// `for_type` - format of DWARF type, `U16` for types without a better one
+    pub fn for_type(ty: &VariableType) -> Self {
+        match (&ty.kind, ty.size) {
+            (TypeKind::Fractional, 2)          => WatchFormat::Q15,
+            (TypeKind::Signed, 4)              => WatchFormat::I32,
+            (TypeKind::Signed | TypeKind::Enumeration, _) => WatchFormat::I16,
+            _                                  => WatchFormat::U16,
+        }
+    }
+
+    /// `value` - number of `raw` memory contents, first word in low half
+    pub fn value(self, raw: u32) -> f64 {
+        match self {
+            WatchFormat::I16 => raw as u16 as i16 as f64,
+            WatchFormat::U16 => raw as u16 as f64,
+            WatchFormat::Q15 => raw as u16 as i16 as f64 / 32768.0,
+            WatchFormat::I32 => raw as i32 as f64,
+        }
+    }
+
+    pub fn text(self, value: f64) -> String {
+        match self {
+            WatchFormat::Q15 => format!("{:.5}", value),
+            _                => format!("{}", value as i64),
+        }
+    }
+}
+
+impl fmt::Display for WatchFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            WatchFormat::I16 => "i16",
+            WatchFormat::U16 => "u16",
+            WatchFormat::Q15 => "Q15",
+            WatchFormat::I32 => "i32",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// `WatchItem` - X memory variable sampled by `TargetDsc::live_watch`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchItem {
+    /// as typed by user
+    pub name    : String,
+    pub address : u32,
+    pub format  : WatchFormat,
+}
+
+impl WatchItem {
+
+    /// `new` - item of variable name or X address `text`, format of DWARF type when `text` names variable
+    pub fn new(text: &str, symbols: &SymbolTable) -> Result<Self, Error> {
+        let text = text.trim();
+        let (space, address) = symbols.resolve(text, AccessType::MemoryX)?;
+        if space != AccessType::MemoryX {
+            return Err(Error::SymbolError(format!("{} is not in X memory", text)))
+        }
+        let format = symbols.variable(text).map_or(WatchFormat::U16, |variable| WatchFormat::for_type(&variable.ty));
+        Ok(WatchItem { name: text.to_string(), address, format })
+    }
+}
+
+/// `WatchSettings` - sampling period and longest time core is kept halted for one sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchSettings {
+    pub interval    : Duration,
+    /// items not read in this time are read on next samples, at least one is read each time
+    pub halt_budget : Duration,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        WatchSettings { interval: Duration::from_millis(100), halt_budget: Duration::from_millis(5) }
+    }
+}
+
+/// `WatchSample` - values after one halt, `None` for items not read yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchSample {
+    /// from start of sampling
+    pub time   : Duration,
+    pub values : Vec<Option<u32>>,
+    /// core halted by sampler for this long, zero when core was halted already
+    pub halted : Duration,
+}
+
+/// `WatchRow` - last value of item and range seen since sampling started
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WatchRow {
+    pub value : Option<f64>,
+    pub min   : f64,
+    pub max   : f64,
+}
+
+/// `WatchTable` - watched items with values shown in gui
+#[derive(Debug, Clone, Default)]
+pub struct WatchTable {
+    pub items   : Vec<WatchItem>,
+    pub rows    : Vec<WatchRow>,
+    pub samples : usize,
+    /// longest halt of a sample
+    pub halted  : Duration,
+}
+
+impl WatchTable {
+
+    pub fn add(&mut self, item: WatchItem) {
+        self.items.push(item);
+        self.rows.push(WatchRow::default());
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.items.len() {
+            self.items.remove(index);
+            self.rows.remove(index);
+        }
+    }
+
+    pub fn set_format(&mut self, index: usize, format: WatchFormat) {
+        if let Some(item) = self.items.get_mut(index) {
+            item.format = format;
+            self.rows[index] = WatchRow::default();
+        }
+    }
+
+    /// `clear` - forgets values, items stay
+    pub fn clear(&mut self) {
+        self.rows = vec![WatchRow::default(); self.items.len()];
+        self.samples = 0;
+        self.halted = Duration::ZERO;
+    }
+
+    pub fn update(&mut self, sample: &WatchSample) {
+        for ((item, row), raw) in self.items.iter().zip(self.rows.iter_mut()).zip(sample.values.iter()) {
+            let Some(raw) = raw else { continue };
+            let value = item.format.value(*raw);
+            *row = match row.value {
+                Some(_) => WatchRow { value: Some(value), min: row.min.min(value), max: row.max.max(value) },
+                None    => WatchRow { value: Some(value), min: value, max: value },
+            };
+        }
+        self.samples += 1;
+        self.halted = self.halted.max(sample.halted);
+    }
+
+    /// `cells` - name, address, format, value, min and max text of row
+    pub fn cells(&self, index: usize) -> [String; 6] {
+        let (item, row) = (&self.items[index], &self.rows[index]);
+        let number = |value: f64| item.format.text(value);
+        match row.value {
+            Some(value) => [item.name.clone(), format!("X:${:04X}", item.address), item.format.to_string(), number(value), number(row.min), number(row.max)],
+            None        => [item.name.clone(), format!("X:${:04X}", item.address), item.format.to_string(), "-".to_string(), "-".to_string(), "-".to_string()],
+        }
+    }
+}
+
+/// `raw_value` - words of X memory read as bytes, low byte and first word first
+fn raw_value(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |raw, byte| raw << 8 | *byte as u32)
+}
+
+impl TargetDsc {
+
+    /// `live_watch` - samples `items` every `settings.interval` until job is cancelled
+    ///
+    /// Each sample halts core, reads items for at most `settings.halt_budget` and lets core go again
+    /// on every error path (see `sample`). Cancel is taken only while core runs, so core is never left halted by sampler.
+    /// Core halted when sampling starts is read without halting and is not let go.
+    /// Sampling ends without error when core stops by itself (breakpoint, `debughlt`).
+    pub fn live_watch(&mut self, items: &[WatchItem], settings: &WatchSettings, prog: &mut Programmer, context: &mut JobContext) -> Result<(), Error> {
+        if items.is_empty() {
+            return Ok(())
+        }
+        let halted_already = enableONCE(prog)? == OnceStatus::DebugMode;
+        if halted_already {
+            context.log("Core is halted, watch reads memory without running it");
+        }
+        let start = Instant::now();
+        let mut values = vec![None; items.len()];
+        let mut next = 0;
+        while !context.is_cancelled() {
+            let sample_start = Instant::now();
+            let halted = match halted_already {
+                true  => {
+                    Self::read_items(items, &mut values, &mut next, settings.halt_budget, prog)?;
+                    Duration::ZERO
+                },
+                false => {
+                    let (halted, read) = self.sample(WATCH_HALT_TIMEOUT, prog,
+                        |prog| Self::read_items(items, &mut values, &mut next, settings.halt_budget, prog))?;
+                    if read.is_none() {
+                        context.log(format!("Core stopped by itself, {}, watch ended", halted));
+                        return Ok(())
+                    }
+                    sample_start.elapsed()
+                },
+            };
+            context.watch(WatchSample { time: start.elapsed(), values: values.clone(), halted });
+
+            let due = sample_start + settings.interval;
+            while !context.is_cancelled() {
+                let now = Instant::now();
+                if now >= due {
+                    break
+                }
+                thread::sleep(WATCH_POLL.min(due - now));
+            }
+        }
+        Ok(())
+    }
+
+    /// `read_items` - reads items from `next` on, round robin, until `budget` is spent
+    fn read_items(items: &[WatchItem], values: &mut [Option<u32>], next: &mut usize, budget: Duration, prog: &mut Programmer) -> Result<(), Error> {
+        let start = Instant::now();
+        for _ in 0..items.len() {
+            let item = &items[*next];
+            let bytes = prog.dsc_read_memory(AccessType::MemoryX.into(), item.format.words() * 2, item.address)?;
+            values[*next] = Some(raw_value(&bytes));
+            *next = (*next + 1) % items.len();
+            if start.elapsed() >= budget {
+                break
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use crate::usbdm::jtag::{enableCoreTAP, JTAG_READ_MEM};
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::{DscTap, DscCore};
+    use crate::usbdm::registers::DscRegisters;
+    use crate::usbdm::constants::memory_space_t;
+    use crate::dsc_target::target_factory::{TargetSelector, TargetYaml};
+    use crate::dsc_target::job_worker::WorkerEvent;
+    use crate::symbols::dwarf::Variable;
+
+    #[test]
+    fn decoded_values() {
+        assert_eq!(WatchFormat::I16.value(0xFFFE), -2.0);
+        assert_eq!(WatchFormat::U16.value(0xFFFE), 65534.0);
+        assert_eq!(WatchFormat::Q15.value(0xC000), -0.5);
+        assert_eq!(WatchFormat::Q15.text(WatchFormat::Q15.value(0x4000)), "0.50000");
+        assert_eq!(WatchFormat::I32.value(0xFFFF_FFFF), -1.0);
+        assert_eq!(raw_value(&[0x34, 0x12, 0x78, 0x56]), 0x5678_1234);
+
+        let gain = Variable { name: "gain".to_string(), address: 0x0802, ty: VariableType { name: "Frac16".to_string(), size: 2, kind: TypeKind::Fractional } };
+        let symbols = SymbolTable::new(Vec::new(), vec![gain]);
+        assert_eq!(WatchItem::new(" gain ", &symbols).unwrap(), WatchItem { name: "gain".to_string(), address: 0x0802, format: WatchFormat::Q15 });
+        assert_eq!(WatchItem::new("$F000", &symbols).unwrap().format, WatchFormat::U16);
+        assert!(matches!(WatchItem::new("P:$200", &symbols), Err(Error::SymbolError(_))));
+
+        let mut table = WatchTable::default();
+        table.add(WatchItem::new("gain", &symbols).unwrap());
+        table.add(WatchItem::new("$0900", &symbols).unwrap());
+        assert_eq!(table.cells(0)[3], "-");
+        table.update(&WatchSample { time: Duration::ZERO, values: vec![Some(0x2000), None], halted: Duration::from_millis(1) });
+        table.update(&WatchSample { time: Duration::ZERO, values: vec![Some(0xE000), Some(7)], halted: Duration::from_millis(2) });
+        assert_eq!(table.cells(0), ["gain", "X:$0802", "Q15", "-0.25000", "-0.25000", "0.25000"].map(String::from));
+        assert_eq!(table.cells(1)[3..], ["7", "7", "7"].map(String::from));
+        assert_eq!((table.samples, table.halted), (2, Duration::from_millis(2)));
+        table.remove(0);
+        assert_eq!(table.items.len(), table.rows.len());
+    }
+
+    #[test]
+    fn samples_running_core() {
+        let mut tap = DscTap::new();
+        let mut core = DscCore::default();
+        core.set(DscRegisters::DscRegPc, 0x0200);
+        tap.core = Some(core);
+        // X:$0800 = -3, X:$0801..2 = $00012345
+        for (address, byte) in [(0x1000, 0xFD), (0x1001, 0xFF), (0x1002, 0x45), (0x1003, 0x23), (0x1004, 0x01), (0x1005, 0x00)] {
+            tap.memory.insert((memory_space_t::MS_DATA, address), byte);
+        }
+        let link = InterpreterTransport::new(tap);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+        target.go(&mut prog).unwrap();
+
+        let items = [
+            WatchItem { name: "speed".to_string(), address: 0x0800, format: WatchFormat::I16 },
+            WatchItem { name: "position".to_string(), address: 0x0801, format: WatchFormat::I32 },
+        ];
+        let settings = WatchSettings { interval: Duration::from_millis(5), halt_budget: Duration::ZERO };
+        let cancel = AtomicBool::new(false);
+        let mut samples = Vec::new();
+        let mut sink = |event| if let WorkerEvent::Watch(sample) = event {
+            samples.push(sample);
+            if samples.len() == 3 {
+                cancel.store(true, Ordering::SeqCst);
+            }
+        };
+        let mut context = JobContext::new(&mut sink, &cancel);
+        target.live_watch(&items, &settings, &mut prog, &mut context).unwrap();
+
+        // zero budget reads one item per halt
+        assert_eq!(samples[0].values, [Some(0xFFFD), None]);
+        assert_eq!(samples[1].values, [Some(0xFFFD), Some(0x0001_2345)]);
+        assert_eq!(samples.len(), 3);
+        assert!(samples.iter().all(|sample| sample.halted > Duration::ZERO));
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+
+        // halted core is read as it is and stays halted
+        target.halt(Duration::from_millis(100), &mut prog).unwrap();
+        cancel.store(false, Ordering::SeqCst);
+        let mut samples = 0;
+        let mut sink = |event| if let WorkerEvent::Watch(sample) = event {
+            assert_eq!(sample.halted, Duration::ZERO);
+            samples += 1;
+            cancel.store(true, Ordering::SeqCst);
+        };
+        let mut context = JobContext::new(&mut sink, &cancel);
+        target.live_watch(&items, &WatchSettings::default(), &mut prog, &mut context).unwrap();
+        assert_eq!(samples, 1);
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::DebugMode);
+
+        // memory read failing after halt ends watch with core running
+        target.go(&mut prog).unwrap();
+        cancel.store(false, Ordering::SeqCst);
+        link.fail_sequence(JTAG_READ_MEM);
+        let mut sink = |_event| {};
+        let mut context = JobContext::new(&mut sink, &cancel);
+        assert!(target.live_watch(&items, &settings, &mut prog, &mut context).is_err());
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+    }
+}
@@ -1,11 +1,14 @@
 use core::ops::Range;
 use crate::errors::Error;
+use super::disassembler::{disassemble, words_from_bytes};
+use super::target_factory::AccessType;
+use crate::symbols::symbol_table::SymbolTable;
 
 
 pub const HEX_LINE_LENGHT  : usize =  0x10;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MemoryBuffer {
 
     /// A `buffer` builded for view, 
@@ -324,6 +327,20 @@ pub fn download_all_u8(&self) -> Vec<Vec<u8>> {
 
 }
 
+/// `disassemble` - listing of buffer as P memory, one line per instruction and `name:` line per symbol
+pub fn disassemble(&self, symbols: &SymbolTable) -> Vec<String> {
+
+  let words = words_from_bytes(&self.download_in_one());
+
+  let mut listing = Vec::new();
+  for instruction in disassemble(&words, self.range.start as u32) {
+    listing.extend(symbols.labels_at(AccessType::MemoryP, instruction.address).map(|name| format!("{}:", name)));
+    listing.push(instruction.to_string());
+  }
+  listing
+
+}
+
 pub fn flash_memory_size(&self) -> usize {
                                                                                        
   let flash_memory_size = dsc_buffer_size_from_range(self.range.clone(), self.cell_size);
@@ -353,6 +370,7 @@ pub const BYTES_IN_ADDRESS : usize =  0x02; // cell_size
 #[allow(arithmetic_overflow)]
 mod tests {
     use super::*;
+    use crate::symbols::symbol_table::Symbol;
 
 
     fn resize_new_start(fill_byte : u8, mut buffer : MemoryBuffer, resize_range : Range<usize>) -> u8 {
@@ -580,6 +598,22 @@ mod tests {
 
     }
 
+    #[test]
+    fn disassembly_listing() {
+
+      let mut buff = build_empty_dsc(Range { start: 0x4000, end: 0x401F });
+      buff.upload_from_bin(vec![0x00, 0xE7, 0x7F, 0xE7, 0x7C, 0xD4, 0xFF, 0xFF]).unwrap();
+      let listing = buff.disassemble(&SymbolTable::default());
+      assert_eq!(listing[0], "P:$004000  E700           nop");
+      assert_eq!(listing[1], "P:$004001  E77F D47C FFFF move.w  X0,X:>>otx1");
+      assert_eq!(listing.len(), 2 + 0x1C);
+
+      let symbols = SymbolTable::new(vec![Symbol { name: "Fsend".to_string(), space: AccessType::MemoryP, address: 0x4001, size: 3, function: true }], Vec::new());
+      let listing = buff.disassemble(&symbols);
+      assert_eq!(listing[1..3], ["Fsend:", "P:$004001  E77F D47C FFFF move.w  X0,X:>>otx1"]);
+
+    }
+
 }
 
 
@@ -3,4 +3,12 @@ pub mod target_factory;
 pub mod target_init_actions;
 pub mod target_programming;
 pub mod flash_routine;
-pub mod test_programming;
\ No newline at end of file
+pub mod test_programming;
+pub mod job_worker;
+pub mod speed_search;
+pub mod run_control;
+pub mod disassembler;
+pub mod console;
+pub mod live_watch;
+pub mod profiler;
+pub mod core_dump;
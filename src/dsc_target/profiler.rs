@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::target_factory::{TargetDsc, AccessType};
+use super::job_worker::JobContext;
+use crate::errors::{Error};
+use crate::usbdm::programmer::Programmer;
+use crate::usbdm::jtag::{enableONCE, OnceStatus};
+use crate::symbols::symbol_table::SymbolTable;
+
+/// running core not halted in this time fails profiling
+const PROFILE_HALT_TIMEOUT : Duration = Duration::from_millis(100);
+/// function of PC without symbol
+pub const NO_SYMBOL        : &str = "(no symbol)";
+
+/// `ProfileSettings` - number of PC samples and time from one sample to next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfileSettings {
+    pub samples  : usize,
+    pub interval : Duration,
+}
+
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        ProfileSettings { samples: 1000, interval: Duration::from_millis(2) }
+    }
+}
+
+/// `ExportFormat` - text export of `Profile`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// `address,symbol,samples,percent` per sampled address
+    Csv,
+    /// `function;P:$address count` lines for flamegraph tools
+    Folded,
+}
+
+impl ExportFormat {
+    /// `from_path` - format by file extension, folded for anything but `.csv`
+    pub fn from_path(path: &str) -> Self {
+        match path.to_ascii_lowercase().ends_with(".csv") {
+            true  => ExportFormat::Csv,
+            false => ExportFormat::Folded,
+        }
+    }
+}
+
+/// `Profile` - PC samples of running firmware, counted per P address
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub counts  : BTreeMap<u32, usize>,
+    pub samples : usize,
+    /// time from first to last sample
+    pub elapsed : Duration,
+}
+
+impl Profile {
+
+    pub fn add(&mut self, pc: u32) {
+        *self.counts.entry(pc).or_insert(0) += 1;
+        self.samples += 1;
+    }
+
+    fn percent(&self, count: usize) -> f64 {
+        match self.samples {
+            0 => 0.0,
+            samples => count as f64 * 100.0 / samples as f64,
+        }
+    }
+
+    /// `by_address` - sampled addresses, most samples first
+    pub fn by_address(&self) -> Vec<(u32, usize)> {
+        let mut histogram: Vec<(u32, usize)> = self.counts.iter().map(|(pc, count)| (*pc, *count)).collect();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        histogram
+    }
+
+    /// `by_function` - samples per P symbol holding address, `NO_SYMBOL` for the rest, most samples first
+    pub fn by_function(&self, symbols: &SymbolTable) -> Vec<(String, usize)> {
+        let mut functions: BTreeMap<String, usize> = BTreeMap::new();
+        for (pc, count) in self.counts.iter() {
+            *functions.entry(Self::function(symbols, *pc)).or_insert(0) += count;
+        }
+        let mut histogram: Vec<(String, usize)> = functions.into_iter().collect();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        histogram
+    }
+
+    fn function(symbols: &SymbolTable, pc: u32) -> String {
+        symbols.symbol_at(AccessType::MemoryP, pc).map_or(NO_SYMBOL.to_string(), |(symbol, _)| symbol.name.clone())
+    }
+
+    /// `export` - histogram text in `format`
+    pub fn export(&self, format: ExportFormat, symbols: &SymbolTable) -> String {
+        let mut text = String::new();
+        match format {
+            ExportFormat::Csv => {
+                text += "address,symbol,samples,percent\n";
+                for (pc, count) in self.by_address() {
+                    let symbol = symbols.describe(AccessType::MemoryP, pc).unwrap_or_default();
+                    text += &format!("0x{:06X},{},{},{:.2}\n", pc, symbol, count, self.percent(count));
+                }
+            },
+            ExportFormat::Folded => {
+                for (pc, count) in self.counts.iter() {
+                    text += &format!("{};P:${:06X} {}\n", Self::function(symbols, *pc), pc, count);
+                }
+            },
+        }
+        text
+    }
+
+    /// `save` - exports to file at `path` in format of its extension
+    pub fn save(&self, path: &str, symbols: &SymbolTable) -> Result<(), Error> {
+        fs::write(path, self.export(ExportFormat::from_path(path), symbols))?;
+        Ok(())
+    }
+
+    /// `report` - sample summary and `lines` busiest functions
+    pub fn report(&self, symbols: &SymbolTable, lines: usize) -> String {
+        let mut report = format!("{} samples in {} ms\n", self.samples, self.elapsed.as_millis());
+        for (name, count) in self.by_function(symbols).into_iter().take(lines) {
+            report += &format!("{:6.2}% {:>6}  {}\n", self.percent(count), count, name);
+        }
+        report
+    }
+}
+
+impl TargetDsc {
+
+    /// `profile` - statistical profile of running firmware by `settings.samples` halts reading PC
+    ///
+    /// Core is let go after every sample, also when it fails (see `sample`). Cancel or core stopping by itself (breakpoint, `debughlt`)
+    /// ends sampling early with samples taken so far. Core must run when profiling starts.
+    pub fn profile(&mut self, settings: &ProfileSettings, prog: &mut Programmer, context: &mut JobContext) -> Result<Profile, Error> {
+        if enableONCE(prog)? == OnceStatus::DebugMode {
+            return Err(Error::InternalError("core is halted, profiling needs running firmware".to_string()))
+        }
+        let mut profile = Profile::default();
+        let start = Instant::now();
+        while profile.samples < settings.samples && !context.is_cancelled() {
+            let sample_start = Instant::now();
+            let (halted, sampled) = self.sample(PROFILE_HALT_TIMEOUT, prog, |_| Ok(()))?;
+            if sampled.is_none() {
+                context.log(format!("Core stopped by itself, {}, profile ended", halted));
+                break
+            }
+            profile.add(halted.pc);
+            profile.elapsed = start.elapsed();
+            context.progress(profile.samples as f32 * 100.0 / settings.samples as f32);
+            thread::sleep(settings.interval.saturating_sub(sample_start.elapsed()));
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use crate::usbdm::jtag::{enableCoreTAP, JTAG_CALL_SUBA};
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::{DscTap, DscCore};
+    use crate::usbdm::registers::DscRegisters;
+    use crate::dsc_target::target_factory::{TargetSelector, TargetYaml};
+    use crate::symbols::symbol_table::Symbol;
+
+    fn symbols() -> SymbolTable {
+        let function = |name: &str, address, size| Symbol { name: name.to_string(), space: AccessType::MemoryP, address, size, function: true };
+        SymbolTable::new(vec![function("Fmain", 0x0200, 0x40), function("Fpwm_isr", 0x0300, 0x20)], Vec::new())
+    }
+
+    #[test]
+    fn histograms_and_export() {
+        let mut profile = Profile::default();
+        for pc in [0x0305, 0x0305, 0x0301, 0x0210, 0x0400] {
+            profile.add(pc);
+        }
+        let symbols = symbols();
+        assert_eq!(profile.by_address(), [(0x0305, 2), (0x0210, 1), (0x0301, 1), (0x0400, 1)]);
+        assert_eq!(profile.by_function(&symbols), [("Fpwm_isr".to_string(), 3), (NO_SYMBOL.to_string(), 1), ("Fmain".to_string(), 1)]);
+        assert_eq!(profile.export(ExportFormat::Csv, &symbols), "address,symbol,samples,percent\n\
+            0x000305,Fpwm_isr+$5,2,40.00\n0x000210,Fmain+$10,1,20.00\n0x000301,Fpwm_isr+$1,1,20.00\n0x000400,,1,20.00\n");
+        assert_eq!(profile.export(ExportFormat::Folded, &symbols), "Fmain;P:$000210 1\nFpwm_isr;P:$000301 1\nFpwm_isr;P:$000305 2\n(no symbol);P:$000400 1\n");
+        assert_eq!(profile.report(&symbols, 1), "5 samples in 0 ms\n 60.00%      3  Fpwm_isr\n");
+        assert_eq!(ExportFormat::from_path("run.CSV"), ExportFormat::Csv);
+        assert_eq!(ExportFormat::from_path("run.folded"), ExportFormat::Folded);
+    }
+
+    #[test]
+    fn samples_running_core() {
+        let mut tap = DscTap::new();
+        let mut core = DscCore::default();
+        core.set(DscRegisters::DscRegPc, 0x0200);
+        tap.core = Some(core);
+        let link = InterpreterTransport::new(tap);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut sink = |_event| {};
+        let mut context = JobContext::new(&mut sink, &cancel);
+        let settings = ProfileSettings { samples: 4, interval: Duration::ZERO };
+        assert!(matches!(target.profile(&settings, &mut prog, &mut context), Err(Error::InternalError(_))));
+
+        target.go(&mut prog).unwrap();
+        let profile = target.profile(&settings, &mut prog, &mut context).unwrap();
+        assert_eq!(profile.samples, 4);
+        assert_eq!(profile.counts.values().sum::<usize>(), 4);
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+
+        // PC read failing after halt ends profiling with core running
+        link.fail_sequence(JTAG_CALL_SUBA);
+        assert!(target.profile(&settings, &mut prog, &mut context).is_err());
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use super::target_factory::TargetDsc;
+use crate::errors::Error;
+use crate::usbdm::jtag::*;
+use crate::usbdm::programmer::Programmer;
+use crate::usbdm::registers::DscRegisters;
+use crate::usbdm::breakpoints::{Breakpoint, BreakpointUnit, OSR_BKPT};
+
+/// time for instruction steps to complete before step is reported as not finished
+pub const STEP_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// `HaltReason` - why core is in debug mode after run-control call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltReason {
+    /// instruction step count run out
+    Step,
+    /// breakpoint unit comparator triggered, `None` when it can't be told which one
+    Breakpoint(Option<usize>),
+    /// temporary breakpoint of `run_to` reached
+    RunTo,
+    /// stopped by debug request of `halt`, also after `run_to` timeout
+    DebugRequest,
+    /// held in debug mode out of reset
+    Reset,
+    /// core entered debug mode by itself, e.g. `debughlt`
+    Software,
+    /// core was already in debug mode
+    AlreadyHalted,
+}
+
+impl fmt::Display for HaltReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HaltReason::Step                      => write!(f, "step"),
+            HaltReason::Breakpoint(Some(index))   => write!(f, "breakpoint #{}", index + 1),
+            HaltReason::Breakpoint(None)          => write!(f, "breakpoint"),
+            HaltReason::RunTo                     => write!(f, "run to address"),
+            HaltReason::DebugRequest              => write!(f, "debug request"),
+            HaltReason::Reset                     => write!(f, "reset"),
+            HaltReason::Software                  => write!(f, "debughlt"),
+            HaltReason::AlreadyHalted             => write!(f, "already halted"),
+        }
+    }
+}
+
+/// `Halted` - core state after run-control call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Halted {
+    pub reason : HaltReason,
+    pub pc     : u32,
+}
+
+impl fmt::Display for Halted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "halted at P:${:06X} ({})", self.pc, self.reason)
+    }
+}
+
+impl TargetDsc {
+
+    /// `halted` - reads PC of halted core and reports it with `reason`
+    fn halted(&mut self, reason: HaltReason, prog: &mut Programmer) -> Result<Halted, Error> {
+        self.once_status = OnceStatus::DebugMode;
+        Ok(Halted { reason, pc: prog.dsc_read_pc()? })
+    }
+
+    /// `breakpoint_reason` - breakpoint halt of `unit` from OSR flag, `None` when core halted otherwise
+    fn breakpoint_reason(unit: &BreakpointUnit, prog: &mut Programmer) -> Result<Option<HaltReason>, Error> {
+        let osr = prog.dsc_read_once_reg(DscRegisters::DscRegOsr)?;
+        if osr & OSR_BKPT == 0 {
+            return Ok(None)
+        }
+        Ok(Some(HaltReason::Breakpoint(unit.hit(osr, prog.dsc_read_pc()?))))
+    }
+
+    fn require_halted(&mut self, prog: &mut Programmer) -> Result<(), Error> {
+        self.once_status = enableONCE(prog)?;
+        if self.once_status != OnceStatus::DebugMode {
+            return Err(Error::TargetNotInDebugMode)
+        }
+        Ok(())
+    }
+
+    /// `step` - runs one instruction from current PC
+    pub fn step(&mut self, prog: &mut Programmer) -> Result<Halted, Error> {
+        self.step_n(1, prog)
+    }
+
+    /// `step_n` - runs `count` instructions from current PC by OnCE instruction step counter
+    ///
+    /// Breakpoint met on the way stops stepping early and is reported instead of `HaltReason::Step`.
+    /// If steps do not complete in `STEP_TIMEOUT` (core in stop/wait) it is halted by debug request.
+    pub fn step_n(&mut self, count: u32, prog: &mut Programmer) -> Result<Halted, Error> {
+        self.require_halted(prog)?;
+        let unit = prog.dsc_read_breakpoints()?;
+        prog.dsc_set_instruction_step(Some(count))?;
+        prog.dsc_target_go()?;
+        let stopped = prog.dsc_wait_debug_mode(STEP_TIMEOUT)?;
+        if !stopped {
+            prog.dsc_target_halt()?;
+        }
+        prog.dsc_set_instruction_step(None)?;
+
+        let reason = match stopped {
+            false => HaltReason::DebugRequest,
+            true  => Self::breakpoint_reason(&unit, prog)?.unwrap_or(HaltReason::Step),
+        };
+        self.halted(reason, prog)
+    }
+
+    /// `go` - runs core freely from current PC, use `wait_halt` or `halt` to stop it
+    pub fn go(&mut self, prog: &mut Programmer) -> Result<(), Error> {
+        self.require_halted(prog)?;
+        prog.dsc_set_instruction_step(None)?;
+        prog.dsc_target_go()?;
+        self.once_status = OnceStatus::ExecuteMode;
+        Ok(())
+    }
+
+    /// `wait_halt` - waits for running core to stop by itself, `None` if still running after `timeout`
+    pub fn wait_halt(&mut self, timeout: Duration, prog: &mut Programmer) -> Result<Option<Halted>, Error> {
+        if !prog.dsc_wait_debug_mode(timeout)? {
+            self.once_status = OnceStatus::ExecuteMode;
+            return Ok(None)
+        }
+        let unit = prog.dsc_read_breakpoints()?;
+        let reason = Self::breakpoint_reason(&unit, prog)?.unwrap_or(HaltReason::Software);
+        self.halted(reason, prog).map(Some)
+    }
+
+    /// `halt` - stops core by debug request, gives up after `timeout`
+    pub fn halt(&mut self, timeout: Duration, prog: &mut Programmer) -> Result<Halted, Error> {
+        if enableONCE(prog)? == OnceStatus::DebugMode {
+            return self.halted(HaltReason::AlreadyHalted, prog)
+        }
+        prog.targetDebugRequest()?;
+        if !prog.dsc_wait_debug_mode(timeout)? {
+            self.once_status = enableONCE(prog)?;
+            return Err(Error::TargetNotConnected(format!("DSC did not halt in {} ms", timeout.as_millis())))
+        }
+        self.halted(HaltReason::DebugRequest, prog)
+    }
+
+    /// `sample` - halts running core by debug request, runs `read` on it and lets core go again
+    ///
+    /// Once debug request is sent core is let go on every path, also when halt times out or PC or `read` fails.
+    /// Core halted already (stopped by itself) stays halted, `read` is not run and `None` is given.
+    pub fn sample<T>(&mut self, timeout: Duration, prog: &mut Programmer, read: impl FnOnce(&mut Programmer) -> Result<T, Error>) -> Result<(Halted, Option<T>), Error> {
+        if enableONCE(prog)? == OnceStatus::DebugMode {
+            return Ok((self.halted(HaltReason::AlreadyHalted, prog)?, None))
+        }
+        let sampled = self.sample_halted(timeout, prog, read);
+        // late halt after timeout is let go as well
+        let resumed = prog.dsc_target_go();
+        self.once_status = OnceStatus::ExecuteMode;
+        let (halted, value) = sampled?;
+        resumed?;
+        Ok((halted, Some(value)))
+    }
+
+    fn sample_halted<T>(&mut self, timeout: Duration, prog: &mut Programmer, read: impl FnOnce(&mut Programmer) -> Result<T, Error>) -> Result<(Halted, T), Error> {
+        prog.targetDebugRequest()?;
+        if !prog.dsc_wait_debug_mode(timeout)? {
+            return Err(Error::TargetNotConnected(format!("DSC did not halt in {} ms", timeout.as_millis())))
+        }
+        let halted = self.halted(HaltReason::DebugRequest, prog)?;
+        let value = read(prog)?;
+        Ok((halted, value))
+    }
+
+    /// `reset_and_halt` - resets target with debug request held, core stops at reset vector
+    pub fn reset_and_halt(&mut self, prog: &mut Programmer) -> Result<Halted, Error> {
+        prog.target_reset_low()?;
+        thread::sleep(Duration::from_millis(prog.settings.reset_duration));
+        enableCoreTAP(prog)?;
+        prog.targetDebugRequest()?;
+        prog.target_reset_release()?;
+        thread::sleep(Duration::from_millis(prog.settings.reset_recovery_interval));
+        self.once_status = prog.dsc_target_halt()?;
+        self.halted(HaltReason::Reset, prog)
+    }
+
+    /// `run_to` - runs core until P:`address` by temporary program breakpoint
+    ///
+    /// Needs a free comparator of breakpoint unit, set breakpoints stay active and may stop core first.
+    /// Core not there after `timeout` is halted by debug request. Breakpoint unit is restored in all cases.
+    pub fn run_to(&mut self, address: u32, timeout: Duration, prog: &mut Programmer) -> Result<Halted, Error> {
+        self.require_halted(prog)?;
+        let saved = prog.dsc_read_breakpoints()?;
+        let mut unit = saved.clone();
+        unit.count = 1;
+        let temporary = unit.set(Breakpoint::program(address))?;
+        prog.dsc_write_breakpoints(&unit)?;
+
+        let result = self.run_to_breakpoint(&unit, temporary, timeout, prog);
+        let restored = prog.dsc_write_breakpoints(&saved);
+        let halted = result?;
+        restored?;
+        Ok(halted)
+    }
+
+    fn run_to_breakpoint(&mut self, unit: &BreakpointUnit, temporary: usize, timeout: Duration, prog: &mut Programmer) -> Result<Halted, Error> {
+        self.go(prog)?;
+        if !prog.dsc_wait_debug_mode(timeout)? {
+            prog.dsc_target_halt()?;
+            return self.halted(HaltReason::DebugRequest, prog)
+        }
+        let reason = match Self::breakpoint_reason(unit, prog)? {
+            Some(HaltReason::Breakpoint(Some(index))) if index == temporary => HaltReason::RunTo,
+            reason => reason.unwrap_or(HaltReason::Software),
+        };
+        self.halted(reason, prog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsc_target::target_factory::{TargetSelector, TargetYaml};
+    use crate::usbdm::breakpoints::BreakpointKind;
+    use crate::usbdm::registers::{OCR_PWU};
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::{DscTap, DscCore};
+
+    fn halted_target(pc: u32) -> (InterpreterTransport<DscTap>, Programmer, TargetDsc) {
+        let mut tap = DscTap::new();
+        let mut core = DscCore::default();
+        core.set(DscRegisters::DscRegPc, pc);
+        tap.core = Some(core);
+        let link = InterpreterTransport::new(tap);
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        let database = TargetYaml::init_target_db().unwrap();
+        let target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+        (link, prog, target)
+    }
+
+    #[test]
+    fn steps_and_halt() {
+        let (link, mut prog, mut target) = halted_target(0x0200);
+        assert_eq!(target.step(&mut prog).unwrap(), Halted { reason: HaltReason::Step, pc: 0x0201 });
+        assert_eq!(target.step_n(16, &mut prog).unwrap(), Halted { reason: HaltReason::Step, pc: 0x0211 });
+        // stepping is switched off again, debughlt still halts
+        assert_eq!(link.with_tap(|tap| tap.once_register(0x01)), OCR_PWU as u32);
+        assert!(matches!(target.step_n(0, &mut prog), Err(Error::InternalError(_))));
+
+        target.go(&mut prog).unwrap();
+        assert_eq!(target.once_status, OnceStatus::ExecuteMode);
+        assert_eq!(target.wait_halt(Duration::from_millis(30), &mut prog).unwrap(), None);
+        assert!(matches!(target.step(&mut prog), Err(Error::TargetNotInDebugMode)));
+        assert_eq!(target.halt(Duration::from_millis(100), &mut prog).unwrap(), Halted { reason: HaltReason::DebugRequest, pc: 0x0211 });
+        assert_eq!(target.halt(Duration::from_millis(100), &mut prog).unwrap().reason, HaltReason::AlreadyHalted);
+        assert_eq!(target.once_status, OnceStatus::DebugMode);
+    }
+
+    #[test]
+    fn sample_lets_core_go() {
+        let (link, mut prog, mut target) = halted_target(0x0200);
+        target.go(&mut prog).unwrap();
+        let timeout = Duration::from_millis(100);
+
+        let sampled = target.sample(timeout, &mut prog, |prog| prog.dsc_read_core_reg(DscRegisters::DscRegX0)).unwrap();
+        assert_eq!(sampled, (Halted { reason: HaltReason::DebugRequest, pc: 0x0200 }, Some(0)));
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+
+        // failure after halt: in read, in PC read
+        let failed: Result<(Halted, Option<()>), Error> = target.sample(timeout, &mut prog, |_| Err(Error::InternalError("read".to_string())));
+        assert!(matches!(failed, Err(Error::InternalError(_))));
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+        link.fail_sequence(JTAG_CALL_SUBA);
+        assert!(target.sample(timeout, &mut prog, |_| Ok(())).is_err());
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+        assert_eq!(target.once_status, OnceStatus::ExecuteMode);
+
+        // core stopped by itself is not let go
+        prog.dsc_target_halt().unwrap();
+        let sampled = target.sample(timeout, &mut prog, |_| Ok(())).unwrap();
+        assert_eq!(sampled, (Halted { reason: HaltReason::AlreadyHalted, pc: 0x0200 }, None));
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::DebugMode);
+    }
+
+    #[test]
+    fn run_to_restores_breakpoints() {
+        let (link, mut prog, mut target) = halted_target(0x0200);
+        let mut unit = BreakpointUnit::default();
+        unit.set(Breakpoint::watch(BreakpointKind::XWrite, 0x8000)).unwrap();
+        prog.dsc_write_breakpoints(&unit).unwrap();
+
+        let halted = target.run_to(0x0345, Duration::from_millis(100), &mut prog).unwrap();
+        assert_eq!(halted, Halted { reason: HaltReason::RunTo, pc: 0x0345 });
+        assert_eq!(halted.to_string(), "halted at P:$000345 (run to address)");
+        assert_eq!(prog.dsc_read_breakpoints().unwrap(), unit);
+
+        // no free comparator
+        unit.set(Breakpoint::program(0x0400)).unwrap();
+        prog.dsc_write_breakpoints(&unit).unwrap();
+        assert!(matches!(target.run_to(0x0500, Duration::from_millis(100), &mut prog), Err(Error::BreakpointError(_))));
+
+        // user breakpoint stops free run
+        link.with_tap(|tap| tap.once_registers.insert(0x03, 0));
+        target.go(&mut prog).unwrap();
+        let halted = target.wait_halt(Duration::from_millis(100), &mut prog).unwrap().unwrap();
+        assert_eq!(halted, Halted { reason: HaltReason::Breakpoint(Some(1)), pc: 0x0400 });
+    }
+}
@@ -0,0 +1,401 @@
+use std::fmt;
+use crate::errors::{Error};
+use crate::usbdm::jtag::*;
+use crate::usbdm::jtag::{OnceStatus};
+use crate::usbdm::programmer::{Programmer};
+use super::target_factory::{TargetDsc};
+use super::job_worker::{JobContext};
+
+/// JTAG clock ladder (kHz) tried by `speed_search`, USBDM rounds to what its timer can do
+pub const SPEED_STEPS_KHZ : [u64; 11] = [250, 500, 750, 1000, 1500, 2000, 3000, 4000, 6000, 8000, 12000];
+
+/// RAM words written and read back every round
+const PATTERN_BYTES : usize = 32;
+
+/// `SpeedSearch` - how hard every clock step is tested
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedSearch {
+    /// clock ladder, kHz
+    pub steps          : Vec<u64>,
+    /// stress rounds on every step, one failed round fails the step
+    pub rounds         : u32,
+    /// selected clock is at least this much below fastest passed step
+    pub margin_percent : u64,
+}
+
+impl Default for SpeedSearch {
+    fn default() -> Self {
+        SpeedSearch { steps: SPEED_STEPS_KHZ.to_vec(), rounds: 16, margin_percent: 25 }
+    }
+}
+
+/// `SpeedStep` - one clock tried, `failure` is first thing that went wrong on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedStep {
+    pub khz     : u64,
+    pub failure : Option<String>,
+}
+
+/// `SpeedSearchReport` - steps in order they were tried, `selected` is set on programmer
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedSearchReport {
+    pub target         : String,
+    pub tried          : Vec<SpeedStep>,
+    pub fastest        : u64,
+    pub selected       : u64,
+    pub margin_percent : u64,
+}
+
+impl fmt::Display for SpeedSearchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} JTAG clock: {} kHz (fastest reliable {} kHz, margin {}%)", self.target, self.selected, self.fastest, self.margin_percent)?;
+        for step in self.tried.iter() {
+            match &step.failure {
+                None          => writeln!(f, "{:>6} kHz ok", step.khz)?,
+                Some(failure) => writeln!(f, "{:>6} kHz failed: {}", step.khz, failure)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What stress rounds compare against, read at slowest clock
+struct Reference {
+    master_id    : Vec<u8>,
+    core_id      : Vec<u8>,
+    memory_space : u8,
+    ram_start    : u32,
+}
+
+/// RAM words under pattern, written back at slowest clock `khz` when search ends
+struct SavedRam {
+    memory_space : u8,
+    address      : u32,
+    bytes        : Vec<u8>,
+    khz          : u64,
+}
+
+/// `pattern` - RAM test bytes, different every round so stale answer does not pass
+fn pattern(round: u32) -> Vec<u8> {
+    (0..PATTERN_BYTES).map(|i| [0x55, 0xAA, 0x00, 0xFF][i % 4] ^ (round as u8).wrapping_mul(0x1D) ^ i as u8).collect()
+}
+
+/// `stress_round` - IDCODEs, OnCE status and RAM pattern round-trip, `Err` describes first difference
+fn stress_round(prog: &Programmer, reference: &Reference, round: u32) -> Result<(), String> {
+    let master_id = read_master_id_code_DSC_JTAG_ID(true, prog).map_err(|e| format!("master IDCODE read: {}", e))?;
+    if master_id != reference.master_id {
+        return Err(format!("master IDCODE {:02X?} expected {:02X?}", master_id, reference.master_id))
+    }
+    enableCoreTAP(prog).map_err(|e| format!("core TAP enable: {}", e))?;
+    let core_id = read_core_id_code(false, prog).map_err(|e| format!("core IDCODE read: {}", e))?;
+    if core_id != reference.core_id {
+        return Err(format!("core IDCODE {:02X?} expected {:02X?}", core_id, reference.core_id))
+    }
+    let once_status = enableONCE(prog).map_err(|e| format!("OnCE status read: {}", e))?;
+    if once_status != OnceStatus::DebugMode {
+        return Err(format!("OnCE status {:?}", once_status))
+    }
+    let written = pattern(round);
+    prog.dsc_write_memory(reference.memory_space, written.clone(), reference.ram_start).map_err(|e| format!("RAM write: {}", e))?;
+    let read = prog.dsc_read_memory(reference.memory_space, written.len() as u32, reference.ram_start).map_err(|e| format!("RAM read: {}", e))?;
+    if read != written {
+        return Err(format!("RAM pattern {:02X?} read as {:02X?}", written, read))
+    }
+    Ok(())
+}
+
+impl TargetDsc {
+
+    /// `speed_search` - find fastest JTAG clock target takes without errors, select it minus `margin_percent`
+    ///
+    /// Target must be connected and halted. Search starts at current clock and steps up while every stress round
+    /// passes (steps down if current clock already fails). After failed step target is recovered at slowest clock:
+    /// TAP reset, core TAP and debug mode again. Retries of `Programmer::retry_policy` are off during search,
+    /// a transfer that needed retry is a failure. Pattern goes to first RAM of memory map, in its memory space.
+    ///
+    /// However search ends (failure, cancel) RAM words used by pattern are restored, clock is the selected one
+    /// or, when search failed, the one programmer had before.
+    pub fn speed_search(&mut self, prog: &mut Programmer, search: &SpeedSearch, context: &mut JobContext) -> Result<SpeedSearchReport, Error> {
+        let retry_policy = prog.retry_policy;
+        let start_khz = prog.settings.interface_frequency;
+        prog.retry_policy.max_retries = 0;
+        let mut saved = None;
+        let result = self.search_steps(prog, search, context, &mut saved);
+        let restored = restore(prog, saved, result.is_err(), result.as_ref().map_or(start_khz, |report| report.selected));
+        prog.retry_policy = retry_policy;
+        match (result, restored) {
+            (Ok(report), restored) => restored.map(|_| report),
+            (Err(e), Ok(()))       => Err(e),
+            (Err(e), Err(restore_error)) => {
+                context.log(format!("RAM and JTAG clock not restored after failed search: {}", restore_error));
+                Err(e)
+            },
+        }
+    }
+
+    fn search_steps(&mut self, prog: &mut Programmer, search: &SpeedSearch, context: &mut JobContext, saved: &mut Option<SavedRam>) -> Result<SpeedSearchReport, Error> {
+        let mut steps = search.steps.clone();
+        steps.sort_unstable();
+        steps.dedup();
+        if steps.is_empty() || search.margin_percent >= 100 {
+            return Err(Error::InternalError(format!("Bad speed search {:?}", search)))
+        }
+        let start_khz = prog.settings.interface_frequency;
+
+        // reference at slowest clock
+        prog.set_interface_frequency(steps[0])?;
+        prog.jtag_reset()?;
+        let master_id = read_master_id_code_DSC_JTAG_ID(true, prog)?;
+        enableCoreTAP(prog)?;
+        let core_id = read_core_id_code(false, prog)?;
+        self.once_status = enableONCE(prog)?;
+        if self.once_status != OnceStatus::DebugMode {
+            return Err(Error::TargetNotInDebugMode)
+        }
+        let ram = self.ram_segment()?;
+        let memory_space = u8::from(ram.access_type);
+        let ram_start = ram.range.start as u32;
+        let bytes = prog.dsc_read_memory(memory_space, PATTERN_BYTES as u32, ram_start)?;
+        *saved = Some(SavedRam { memory_space, address: ram_start, bytes, khz: steps[0] });
+        let reference = Reference { master_id, core_id, memory_space, ram_start };
+        context.log(format!("{} JTAG clock search from {} kHz, {} rounds per step", self.name, start_khz, search.rounds));
+
+        let mut tried: Vec<SpeedStep> = Vec::new();
+        let mut try_step = |index: usize, prog: &mut Programmer, context: &mut JobContext| -> Result<bool, Error> {
+            context.checkpoint()?;
+            let khz = steps[index];
+            prog.set_interface_frequency(khz)?;
+            let failure = (0..search.rounds).find_map(|round| stress_round(prog, &reference, round).err());
+            context.progress(((index + 1) as f32 / steps.len() as f32) * 100.0);
+            match &failure {
+                None          => context.log(format!("{:>6} kHz ok", khz)),
+                Some(failure) => context.log(format!("{:>6} kHz failed: {}", khz, failure)),
+            }
+            let passed = failure.is_none();
+            tried.push(SpeedStep { khz, failure });
+            if !passed {
+                recover(prog, steps[0])?;
+            }
+            Ok(passed)
+        };
+
+        let start = steps.iter().rposition(|khz| *khz <= start_khz).unwrap_or(0);
+        let mut fastest = None;
+        if try_step(start, prog, context)? {
+            fastest = Some(start);
+            for index in start + 1..steps.len() {
+                if !try_step(index, prog, context)? {
+                    break
+                }
+                fastest = Some(index);
+            }
+        } else {
+            for index in (0..start).rev() {
+                if try_step(index, prog, context)? {
+                    fastest = Some(index);
+                    break
+                }
+            }
+        }
+
+        let fastest = match fastest {
+            Some(index) => steps[index],
+            None => return Err(Error::TargetNotConnected(format!("No reliable JTAG clock down to {} kHz", steps[0]))),
+        };
+        let limit = fastest * (100 - search.margin_percent) / 100;
+        let selected = steps.iter().rev().find(|khz| **khz <= limit).copied().unwrap_or(steps[0]);
+
+        prog.set_interface_frequency(selected)?;
+        if let Some(failure) = (0..search.rounds).find_map(|round| stress_round(prog, &reference, round).err()) {
+            return Err(Error::TargetNotConnected(format!("JTAG clock {} kHz failed after search: {}", selected, failure)))
+        }
+
+        Ok(SpeedSearchReport { target: self.name.clone(), tried, fastest, selected, margin_percent: search.margin_percent })
+    }
+}
+
+/// `recover` - after failed step: slower clock, TAP reset, core TAP enabled and core halted again
+fn recover(prog: &mut Programmer, khz: u64) -> Result<(), Error> {
+    prog.set_interface_frequency(khz)?;
+    prog.jtag_reset()?;
+    enableCoreTAP(prog)?;
+    if enableONCE(prog)? != OnceStatus::DebugMode {
+        prog.dsc_target_halt()?;
+    }
+    Ok(())
+}
+
+/// `restore` - RAM under pattern written back (at slowest clock after failure, target recovered first), then `khz` set
+fn restore(prog: &mut Programmer, saved: Option<SavedRam>, failed: bool, khz: u64) -> Result<(), Error> {
+    if let Some(saved) = saved {
+        if failed {
+            recover(prog, saved.khz)?;
+        }
+        prog.dsc_write_memory(saved.memory_space, saved.bytes, saved.address)?;
+    }
+    prog.set_interface_frequency(khz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use crate::usbdm::constants::{bdm_commands, memory_space_t};
+    use crate::usbdm::transport::UsbdmTransport;
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::DscTap;
+    use crate::dsc_target::target_factory::{TargetSelector, TargetYaml, MemorySegment, RamSegement, AccessType};
+    use crate::dsc_target::job_worker::WorkerEvent;
+
+    /// Virtual DSC whose answers get a flipped bit now and then above `limit_khz`
+    #[derive(Debug, Clone)]
+    struct MarginalLink {
+        link      : InterpreterTransport<DscTap>,
+        limit_khz : u64,
+        state     : Arc<Mutex<(u64, u32)>>,
+    }
+
+    impl UsbdmTransport for MarginalLink {
+        fn write(&self, data: &[u8]) -> Result<(), Error> {
+            if data.get(1) == Some(&bdm_commands::CMD_USBDM_SET_SPEED) {
+                self.state.lock().unwrap().0 = ((data[2] as u64) << 8) | data[3] as u64;
+            }
+            self.link.write(data)
+        }
+
+        fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error> {
+            let mut answer = self.link.read(rx_size)?;
+            let mut state = self.state.lock().unwrap();
+            if state.0 > self.limit_khz && answer.len() > 1 {
+                state.1 += 1;
+                if state.1 == 3 {
+                    state.1 = 0;
+                    answer[1] ^= 0x04;
+                }
+            }
+            Ok(answer)
+        }
+
+        fn control_transfer(&self, request_type: u8, request: u8, value: u16, index: u16, rx_size: usize) -> Result<Vec<u8>, Error> {
+            self.link.control_transfer(request_type, request, value, index, rx_size)
+        }
+
+        fn read_ep(&self) -> u8 {
+            self.link.read_ep()
+        }
+
+        fn write_ep(&self) -> u8 {
+            self.link.write_ep()
+        }
+
+        fn model(&self) -> String {
+            self.link.model()
+        }
+
+        fn serial_number(&self) -> String {
+            self.link.serial_number()
+        }
+    }
+
+    #[test]
+    fn fastest_reliable_clock_with_margin() {
+        let link = InterpreterTransport::new(DscTap::new());
+        let marginal = MarginalLink { link: link.clone(), limit_khz: 3000, state: Arc::new(Mutex::new((0, 0))) };
+        let mut prog = Programmer::from_transport(Box::new(marginal.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        prog.settings.interface_frequency = 1000;
+
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+        // RAM is P:$8000, X:$8000 is something else
+        link.with_tap(|tap| tap.memory.insert((memory_space_t::MS_PROGRAM, 0x1_0000), 0x42));
+        let mut events = Vec::new();
+        let mut sink = |event| events.push(event);
+        let cancel = AtomicBool::new(false);
+        let mut context = JobContext::new(&mut sink, &cancel);
+
+        let report = target.speed_search(&mut prog, &SpeedSearch::default(), &mut context).unwrap();
+        assert_eq!(report.fastest, 3000);
+        assert_eq!(report.selected, 2000);
+        assert_eq!(report.tried.iter().map(|step| step.khz).collect::<Vec<u64>>(), vec![1000, 1500, 2000, 3000, 4000]);
+        assert!(report.tried[4].failure.is_some());
+        assert_eq!(marginal.state.lock().unwrap().0, 2000);
+        assert_eq!(prog.settings.interface_frequency, 2000);
+        assert_eq!(prog.retry_policy, Default::default());
+        // RAM under pattern is given back, X space is not touched
+        assert_eq!(link.with_tap(|tap| tap.memory[&(memory_space_t::MS_PROGRAM, 0x1_0000)]), 0x42);
+        assert!(link.with_tap(|tap| tap.memory.keys().all(|(space, _)| *space == memory_space_t::MS_PROGRAM)));
+        assert!(report.to_string().contains("4000 kHz failed"));
+        assert!(events.iter().any(|event| matches!(event, WorkerEvent::Progress(_))));
+    }
+
+    #[test]
+    fn pattern_in_space_of_ram() {
+        let link = InterpreterTransport::new(DscTap::new());
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        prog.settings.interface_frequency = 1000;
+
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+        target.memory_map.retain(|segment| !matches!(segment, MemorySegment::Ram(_)));
+        target.memory_map.insert(0, MemorySegment::Ram(RamSegement { name: None, range: 0x0..0x7FF, access_type: AccessType::MemoryX }));
+        link.with_tap(|tap| tap.memory.insert((memory_space_t::MS_DATA, 0x0), 0x42));
+        let mut sink = |_event| {};
+        let cancel = AtomicBool::new(false);
+        let mut context = JobContext::new(&mut sink, &cancel);
+
+        let search = SpeedSearch { steps: vec![500, 1000], rounds: 2, margin_percent: 25 };
+        target.speed_search(&mut prog, &search, &mut context).unwrap();
+        assert_eq!(link.with_tap(|tap| tap.memory[&(memory_space_t::MS_DATA, 0x0)]), 0x42);
+        assert!(link.with_tap(|tap| tap.memory.keys().all(|(space, _)| *space == memory_space_t::MS_DATA)));
+    }
+
+    #[test]
+    fn cancelled_search_restores_ram_and_clock() {
+        let link = InterpreterTransport::new(DscTap::new());
+        let marginal = MarginalLink { link: link.clone(), limit_khz: 3000, state: Arc::new(Mutex::new((0, 0))) };
+        let mut prog = Programmer::from_transport(Box::new(marginal.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        prog.settings.interface_frequency = 1000;
+
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+        link.with_tap(|tap| tap.memory.insert((memory_space_t::MS_PROGRAM, 0x1_0000), 0x42));
+        // cancel pressed while first step is reported, pattern is in RAM by then
+        let cancel = AtomicBool::new(false);
+        let mut sink = |event| if matches!(event, WorkerEvent::Progress(_)) { cancel.store(true, Ordering::Relaxed) };
+        let mut context = JobContext::new(&mut sink, &cancel);
+
+        let result = target.speed_search(&mut prog, &SpeedSearch::default(), &mut context);
+        assert!(matches!(result, Err(Error::JobCancelled)));
+        assert_eq!(link.with_tap(|tap| tap.memory[&(memory_space_t::MS_PROGRAM, 0x1_0000)]), 0x42);
+        assert_eq!(prog.settings.interface_frequency, 1000);
+        assert_eq!(marginal.state.lock().unwrap().0, 1000);
+        assert_eq!(prog.retry_policy, Default::default());
+    }
+
+    #[test]
+    fn steps_down_from_failing_clock() {
+        let link = InterpreterTransport::new(DscTap::new());
+        let marginal = MarginalLink { link: link.clone(), limit_khz: 750, state: Arc::new(Mutex::new((0, 0))) };
+        let mut prog = Programmer::from_transport(Box::new(marginal));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        prog.settings.interface_frequency = 4000;
+
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+        let mut sink = |_event| {};
+        let cancel = AtomicBool::new(false);
+        let mut context = JobContext::new(&mut sink, &cancel);
+
+        let report = target.speed_search(&mut prog, &SpeedSearch::default(), &mut context).unwrap();
+        assert_eq!(report.tried.iter().map(|step| step.khz).collect::<Vec<u64>>(), vec![4000, 3000, 2000, 1500, 1000, 750]);
+        assert_eq!((report.fastest, report.selected), (750, 500));
+    }
+}
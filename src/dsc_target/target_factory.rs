@@ -135,6 +135,9 @@ pub enum MemorySegment {
 
     FlashProgramm(ProgrammSegment),
 
+    /// Peripheral register window, reads have side effects of core reads (status flags cleared etc.)
+    Peripherals(DataSegment),
+
     
 }
 
@@ -319,6 +322,16 @@ impl TargetDsc {
      Ok(range)
     }
 
+    /// `ram_segment` - first RAM of memory map with its memory space
+    pub fn ram_segment(&self) -> Result<&RamSegement, Error> {
+      self.memory_map.iter()
+      .find_map(|r| match r {
+        MemorySegment::Ram(r) => Some(r),
+        _ => None,
+      })
+      .ok_or_else(|| Error::InternalError("ram_range not found for DscTarget!".to_string()))
+    }
+
     pub fn programm_range(&self) -> Result<&Range<u64>, Error> {
       let prog_flash_seg = self.memory_map.iter()
       .filter_map(|r| match r {
@@ -374,9 +387,10 @@ impl TargetDsc {
 
       for segment in memory_map {
           let (r, access_seg) = match segment {
-              MemorySegment::Ram(r) => (r.range.clone(), r.access_type.clone()),
-              MemorySegment::FlashProgramm(r) => (r.range.clone(), r.access_type.clone()),
-              MemorySegment::DataEeprom(r) => (r.range.clone(), r.access_type.clone()),
+              MemorySegment::Ram(r) => (r.range.clone(), r.access_type),
+              MemorySegment::FlashProgramm(r) => (r.range.clone(), r.access_type),
+              MemorySegment::DataEeprom(r) => (r.range.clone(), r.access_type),
+              MemorySegment::Peripherals(r) => (r.range.clone(), r.access_type),
           };
           if access == access_seg && r.contains(&address) { 
             return Some(segment);}
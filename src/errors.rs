@@ -17,6 +17,7 @@ pub enum Error {
    UsbdmFWVersionUnsupported(String, String),
    UsbdmUnsuited,
    Usb(rusb::Error),
+   ProbeNotConnected(String),
    PowerStateError,
    PowerErrorInFeedback, //Target Vdd error Possible overload !
    LostConnection,
@@ -37,6 +38,18 @@ pub enum Error {
    FileFormatErr,
    FileParserError(String),
    InternalError(String),
+   UsbdmCommandFailed(String, Box<Error>),
+   UsbdmFeatureUnsupported(String, String),
+   JobCancelled,
+   JtagSequenceInvalid(String),
+   JtagChainInvalid(String),
+   BoundaryScanError(String),
+   SvfPlayerError(String),
+   BreakpointError(String),
+   GdbServerError(String),
+   SymbolError(String),
+   CoreDumpError(String),
+   SessionRunning(String),
 }
 
 pub fn get_title_message_error_modal(err : Error) -> (String, String)
@@ -63,7 +76,14 @@ pub fn get_title_message_error_modal(err : Error) -> (String, String)
          message = "Check usd driver, cable and connection.\nUsb error is:".to_string();
 
          }
-         Error::PowerStateError =>
+         Error::ProbeNotConnected(serial_number) =>
+         {
+
+         title   = "Usbdm not found".to_string();
+         message = "Preferred probe ".to_string() + &serial_number + &" not connected.\nPlug it in or select other probe in Programmer/Probe.\n".to_string();
+
+         }
+        Error::PowerStateError =>
          {  
 
          title   = "Power Error".to_string();
@@ -125,6 +145,14 @@ pub fn get_title_message_error_modal(err : Error) -> (String, String)
           message = "Minimal version is: ".to_string() + &expected_ver + &"\nYour version :".to_string() + &current_ver + &"\nUpdate Usbdm with new firmare.\n".to_string();
 
 
+         }
+         Error::UsbdmFeatureUnsupported(feature, needed) =>
+         {
+
+          title   = "Not supported by this Usbdm".to_string();
+          message = "Feature: ".to_string() + &feature + &"\nneeds ".to_string() + &needed + &"\nUpdate Usbdm with new firmare.\n".to_string();
+
+
          }
          Error::UsbdmUnsuited =>
          {
@@ -169,6 +197,83 @@ pub fn get_title_message_error_modal(err : Error) -> (String, String)
           title   = "Target not blanked!".to_string();
           message = "Erase target before write. Failed blank check on address range : ".to_string()  +  &format!("{:#06X}", start_r) + &"...".to_string() + &format!("{:#06X}", end_r)+ &"\n".to_string();
 
+         }
+         Error::UsbdmCommandFailed(context, cause) =>
+         {
+
+          title   = "Usbdm command failed".to_string();
+          message = context + &"\nCause: ".to_string() + &format!("{:?}", cause) + &"\nCheck usb cable and connection, then connect again.\n".to_string();
+
+         }
+         Error::JobCancelled =>
+         {
+
+          title   = "Cancelled".to_string();
+          message = "Operation cancelled by user.\n".to_string();
+
+         }
+         Error::JtagSequenceInvalid(reason) =>
+         {
+
+          title   = "Internal Err".to_string();
+          message = "Invalid JTAG sequence: ".to_string() + &reason + &"\nIf occurs again, please write to me".to_string();
+
+         }
+         Error::JtagChainInvalid(reason) =>
+         {
+
+          title   = "JTAG chain".to_string();
+          message = "Can't work on this JTAG chain: ".to_string() + &reason + &"\nDSC must be alone on JTAG chain, see Programmer - Scan chain.\n".to_string();
+
+         }
+         Error::BoundaryScanError(reason) =>
+         {
+
+          title   = "Boundary scan".to_string();
+          message = "Boundary scan failed: ".to_string() + &reason + &"\n".to_string();
+
+         }
+         Error::SvfPlayerError(reason) =>
+         {
+
+          title   = "SVF player".to_string();
+          message = "Can't play SVF file: ".to_string() + &reason + &"\n".to_string();
+
+         }
+         Error::BreakpointError(reason) =>
+         {
+
+          title   = "Breakpoint".to_string();
+          message = "Breakpoint not set: ".to_string() + &reason + &"\n".to_string();
+
+         }
+         Error::GdbServerError(reason) =>
+         {
+
+          title   = "GDB server".to_string();
+          message = "GDB server stopped: ".to_string() + &reason + &"\n".to_string();
+
+         }
+         Error::SymbolError(reason) =>
+         {
+
+          title   = "Symbols".to_string();
+          message = "Can't use symbols: ".to_string() + &reason + &"\n".to_string();
+
+         }
+         Error::SessionRunning(kind) =>
+         {
+
+          title   = "Session running".to_string();
+          message = kind + &" session keeps programmer busy, close it first.\n".to_string();
+
+         }
+         Error::CoreDumpError(reason) =>
+         {
+
+          title   = "Core dump".to_string();
+          message = "Core dump failed: ".to_string() + &reason + &"\n".to_string();
+
          }
          Error::TargetVerifyError(start_r, end_r) =>
          {
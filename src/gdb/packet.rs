@@ -0,0 +1,152 @@
+use std::fmt::Write;
+
+/// Interrupt sent by gdb out of packet, e.g. Ctrl-C while target runs
+pub const INTERRUPT : u8 = 0x03;
+/// Escape of `#`, `$`, `}` and `*` in packet data, next byte is xor-ed with `ESCAPE_XOR`
+const ESCAPE        : u8 = b'}';
+const ESCAPE_XOR    : u8 = 0x20;
+
+/// `Incoming` - unit of byte stream from gdb
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Incoming {
+    /// `$data#cs` with good checksum, binary escapes removed
+    Packet(Vec<u8>),
+    /// packet with wrong checksum, gdb is asked to send it again
+    Corrupted,
+    Interrupt,
+    Ack,
+    Nack,
+}
+
+/// `checksum` - modulo 256 sum of packet data as sent (escaped)
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// `encode` - `$data#cs` with `#`, `$`, `}` and `*` escaped
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len() + 4);
+    for byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend([ESCAPE, byte ^ ESCAPE_XOR]);
+        } else {
+            escaped.push(*byte);
+        }
+    }
+    let sum = checksum(&escaped);
+    let mut packet = Vec::with_capacity(escaped.len() + 4);
+    packet.push(b'$');
+    packet.extend(escaped);
+    packet.extend(format!("#{:02x}", sum).bytes());
+    packet
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut data = data.iter();
+    while let Some(byte) = data.next() {
+        match byte {
+            &ESCAPE => if let Some(escaped) = data.next() { bytes.push(escaped ^ ESCAPE_XOR) },
+            _       => bytes.push(*byte),
+        }
+    }
+    bytes
+}
+
+/// `PacketReader` - splits bytes read from socket into `Incoming`, keeps incomplete packet for next read
+#[derive(Debug, Default)]
+pub struct PacketReader {
+    buffer : Vec<u8>,
+}
+
+impl PacketReader {
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+}
+
+impl Iterator for PacketReader {
+    type Item = Incoming;
+
+    /// `next` - first complete unit in buffer, bytes between units are dropped
+    fn next(&mut self) -> Option<Incoming> {
+        loop {
+            let first = *self.buffer.first()?;
+            let single = match first {
+                INTERRUPT => Some(Incoming::Interrupt),
+                b'+'      => Some(Incoming::Ack),
+                b'-'      => Some(Incoming::Nack),
+                b'$'      => None,
+                _         => { self.buffer.remove(0); continue },
+            };
+            if let Some(single) = single {
+                self.buffer.remove(0);
+                return Some(single)
+            }
+            let end = self.buffer.iter().position(|byte| *byte == b'#')?;
+            if self.buffer.len() < end + 3 {
+                return None
+            }
+            let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+            let data = &packet[1..end];
+            let sent = std::str::from_utf8(&packet[end + 1..]).ok().and_then(|sum| u8::from_str_radix(sum, 16).ok());
+            return Some(match sent == Some(checksum(data)) {
+                true  => Incoming::Packet(unescape(data)),
+                false => Incoming::Corrupted,
+            })
+        }
+    }
+}
+
+/// `to_hex` - two lower case digits per byte
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+        write!(hex, "{:02x}", byte).ok();
+        hex
+    })
+}
+
+/// `from_hex` - bytes of even length hex string, `None` on odd length or not hex digit
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() & 1 != 0 {
+        return None
+    }
+    (0..hex.len()).step_by(2).map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok()).collect()
+}
+
+/// `parse_number` - hex number of packet field
+pub fn parse_number(hex: &str) -> Option<u32> {
+    u32::from_str_radix(hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framing_and_escapes() {
+        assert_eq!(encode(b"OK"), b"$OK#9a".to_vec());
+        assert_eq!(encode(b"a#b"), b"$a}\x03b#43".to_vec());
+
+        let mut reader = PacketReader::default();
+        reader.push(b"+$qSupported:swbreak+#8");
+        assert_eq!(reader.next(), Some(Incoming::Ack));
+        assert_eq!(reader.next(), None);
+        reader.push(b"b\x03$m0,4#00-");
+        assert_eq!(reader.next(), Some(Incoming::Packet(b"qSupported:swbreak+".to_vec())));
+        assert_eq!(reader.next(), Some(Incoming::Interrupt));
+        assert_eq!(reader.next(), Some(Incoming::Corrupted));
+        assert_eq!(reader.next(), Some(Incoming::Nack));
+        assert_eq!(reader.next(), None);
+
+        let sent = encode(&[b'X', 0x23, 0x7D, 0x00]);
+        reader.push(&sent);
+        assert_eq!(reader.next(), Some(Incoming::Packet(vec![b'X', 0x23, 0x7D, 0x00])));
+
+        assert_eq!(to_hex(&[0x00, 0xAB]), "00ab");
+        assert_eq!(from_hex("00aB"), Some(vec![0x00, 0xAB]));
+        assert_eq!(from_hex("0"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+}
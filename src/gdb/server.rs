@@ -0,0 +1,949 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use crate::errors::Error;
+use crate::gdb::packet::*;
+use crate::usbdm::programmer::Programmer;
+use crate::usbdm::settings::TargetVddSelect;
+use crate::usbdm::registers::{DscRegisters, CORE_REGISTERS, get_register_name};
+use crate::usbdm::core_registers::CoreRegisters;
+use crate::usbdm::breakpoints::{Breakpoint, BreakpointKind, BreakpointUnit};
+use crate::usbdm::trace::TraceMode;
+use crate::symbols::symbol_table::SymbolTable;
+use crate::dsc_target::target_factory::{TargetDsc, TargetProgramming, MemorySegment, AccessType};
+use crate::dsc_target::run_control::{HaltReason, Halted};
+use crate::dsc_target::disassembler::Instruction;
+use crate::dsc_target::job_worker::JobContext;
+
+/// gdb address of X memory word 0; P memory starts at 0, both are byte addresses (DSC word address * 2)
+pub const X_SPACE       : u32 = 0x8000_0000;
+pub const DEFAULT_PORT  : u16 = 3333;
+/// packet data size told to gdb in `qSupported`
+const PACKET_SIZE       : usize = 0x1000;
+/// socket read timeout, also OnCE status poll interval while target runs
+const POLL              : Duration = Duration::from_millis(20);
+const HALT_TIMEOUT      : Duration = Duration::from_millis(500);
+/// 24-bit word address space, in bytes
+const SPACE_SIZE        : u32 = 0x0200_0000;
+/// instructions from PC shown by `monitor disas`
+const DISASSEMBLY_LINES : usize = 8;
+/// `monitor run-to` halts core by debug request after this
+const RUN_TO_TIMEOUT    : Duration = Duration::from_secs(2);
+/// words shown by `monitor read` without count, most words shown
+const READ_WORDS        : u32 = 16;
+const READ_WORDS_MAX    : u32 = 0x400;
+/// words on one line of `monitor read`
+const READ_LINE_WORDS   : usize = 8;
+
+/// `GdbServer` - gdb remote serial protocol on localhost TCP port
+///
+/// Serves one gdb connection at a time on worker thread, see `Job::GdbServer`.
+/// Register numbers are `DscRegisters` numbers of core registers, values 32-bit little endian.
+pub struct GdbServer {
+    listener : TcpListener,
+}
+
+impl GdbServer {
+
+    /// `bind` - listen on localhost `port`, 0 - any free port
+    pub fn bind(port: u16) -> Result<Self, Error> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .map_err(|e| Error::GdbServerError(format!("port {}: {}", port, e)))?;
+        Ok(GdbServer { listener })
+    }
+
+    pub fn port(&self) -> Result<u16, Error> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    /// `serve` - waits for gdb and serves it until detach, kill or closed connection
+    ///
+    /// Target is halted when gdb connects. Cancel of `context` halts target and ends session.
+    /// However session ends breakpoint unit is cleared, so breakpoints of gdb don't stop core later.
+    /// `monitor` commands take addresses as `symbols` names, see `SymbolTable::resolve`.
+    pub fn serve(&self, target: &mut TargetDsc, prog: &mut Programmer, power: TargetVddSelect, symbols: &SymbolTable, context: &mut JobContext) -> Result<(), Error> {
+        self.listener.set_nonblocking(true)?;
+        let stream = loop {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    context.log(format!("gdb connected from {}", peer));
+                    break stream
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    context.checkpoint()?;
+                    thread::sleep(POLL);
+                },
+                Err(e) => return Err(e.into()),
+            }
+        };
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL))?;
+        stream.set_nodelay(true)?;
+
+        let halted = target.halt(HALT_TIMEOUT, prog)?;
+        prog.dsc_write_breakpoints(&BreakpointUnit::default())?;
+        let mut session = Session {
+            stream,
+            reader      : PacketReader::default(),
+            target,
+            prog,
+            power,
+            registers   : None,
+            breakpoints : BreakpointUnit::default(),
+            last_stop   : String::new(),
+            last_sent   : Vec::new(),
+            no_ack      : false,
+            hwbreak     : false,
+            flash       : FlashLoad::default(),
+            trace       : None,
+            symbols,
+        };
+        session.last_stop = session.stop_reply(&halted);
+        let result = session.run(context);
+        let cleared = session.prog.dsc_write_breakpoints(&BreakpointUnit::default());
+        context.log("gdb disconnected");
+        result.and(cleared)
+    }
+}
+
+/// `gdb_address` - gdb byte address of DSC word address in `space`
+pub fn gdb_address(space: AccessType, word_address: u32) -> u32 {
+    match space {
+        AccessType::MemoryP => word_address * 2,
+        AccessType::MemoryX => X_SPACE + word_address * 2,
+    }
+}
+
+/// `dsc_address` - memory space and byte address in it of gdb address
+pub fn dsc_address(address: u32) -> (AccessType, u32) {
+    match address & X_SPACE {
+        0 => (AccessType::MemoryP, address),
+        _ => (AccessType::MemoryX, address & !X_SPACE),
+    }
+}
+
+/// flash image collected by `vFlashWrite` until `vFlashDone`
+#[derive(Debug, Default)]
+struct FlashLoad {
+    erased : bool,
+    /// contiguous blocks, P word address and data
+    blocks : Vec<(u32, Vec<u8>)>,
+}
+
+enum Received {
+    Unit(Incoming),
+    Nothing,
+    Closed,
+}
+
+enum Response {
+    Reply(Vec<u8>),
+    /// reply, then session ends
+    Last(Vec<u8>),
+    /// session ends without reply
+    Close,
+}
+
+struct Session<'a> {
+    stream      : TcpStream,
+    reader      : PacketReader,
+    target      : &'a mut TargetDsc,
+    prog        : &'a mut Programmer,
+    power       : TargetVddSelect,
+    /// snapshot of halted core, `None` after anything that may change registers
+    registers   : Option<CoreRegisters>,
+    breakpoints : BreakpointUnit,
+    last_stop   : String,
+    /// resent on `-`
+    last_sent   : Vec<u8>,
+    no_ack      : bool,
+    /// gdb takes `hwbreak` stop reason
+    hwbreak     : bool,
+    flash       : FlashLoad,
+    /// capture restarted after each `monitor trace` read out, `None` - off
+    trace       : Option<TraceMode>,
+    symbols     : &'a SymbolTable,
+}
+
+fn field(text: &str, separator: char) -> Result<(u32, &str), Error> {
+    let (number, rest) = text.split_once(separator).unwrap_or((text, ""));
+    let number = parse_number(number).ok_or_else(|| Error::GdbServerError(format!("bad number '{}'", number)))?;
+    Ok((number, rest))
+}
+
+fn bad_packet(text: &str) -> Error {
+    Error::GdbServerError(format!("bad packet '{}'", text))
+}
+
+impl<'a> Session<'a> {
+
+    fn run(&mut self, context: &mut JobContext) -> Result<(), Error> {
+        loop {
+            let incoming = match self.receive()? {
+                Received::Unit(incoming) => incoming,
+                Received::Nothing        => { context.checkpoint()?; continue },
+                Received::Closed         => return Ok(()),
+            };
+            let data = match incoming {
+                Incoming::Packet(data)  => data,
+                Incoming::Corrupted     => { self.stream.write_all(b"-")?; continue },
+                Incoming::Nack          => { let last = self.last_sent.clone(); self.stream.write_all(&last)?; continue },
+                Incoming::Ack           => continue,
+                // target is halted already
+                Incoming::Interrupt     => { let stop = self.last_stop.clone(); self.send(stop.as_bytes())?; continue },
+            };
+            if !self.no_ack {
+                self.stream.write_all(b"+")?;
+            }
+            let response = match self.command(&data, context) {
+                Ok(response)                => response,
+                Err(Error::JobCancelled)    => return Err(Error::JobCancelled),
+                Err(e) => {
+                    let shown = String::from_utf8_lossy(&data[..data.len().min(40)]).into_owned();
+                    context.log(format!("gdb '{}' failed: {:?}", shown, e));
+                    Response::Reply(b"E01".to_vec())
+                },
+            };
+            match response {
+                Response::Reply(reply)  => self.send(&reply)?,
+                Response::Last(reply)   => { self.send(&reply)?; return Ok(()) },
+                Response::Close         => return Ok(()),
+            }
+        }
+    }
+
+    /// `receive` - next unit from gdb, waits at most `POLL` for bytes
+    fn receive(&mut self) -> Result<Received, Error> {
+        if let Some(incoming) = self.reader.next() {
+            return Ok(Received::Unit(incoming))
+        }
+        let mut bytes = [0u8; PACKET_SIZE];
+        match self.stream.read(&mut bytes) {
+            Ok(0)       => Ok(Received::Closed),
+            Ok(count)   => {
+                self.reader.push(&bytes[..count]);
+                Ok(self.reader.next().map_or(Received::Nothing, Received::Unit))
+            },
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(Received::Nothing),
+            Err(e) if matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted) => Ok(Received::Closed),
+            Err(e)      => Err(e.into()),
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.last_sent = encode(data);
+        self.stream.write_all(&self.last_sent)?;
+        Ok(())
+    }
+
+    fn command(&mut self, data: &[u8], context: &mut JobContext) -> Result<Response, Error> {
+        // binary data only after address of X and vFlashWrite
+        let skip = if data.starts_with(b"vFlashWrite:") { "vFlashWrite:".len() } else { 0 };
+        let (head, binary) = match data.iter().skip(skip).position(|byte| *byte == b':') {
+            Some(colon) if data[0] == b'X' || skip > 0 => (&data[..skip + colon], &data[skip + colon + 1..]),
+            _ => (data, &[][..]),
+        };
+        let text = std::str::from_utf8(head).map_err(|_e| Error::GdbServerError("not text packet".to_string()))?;
+        let reply = |reply: &str| Ok(Response::Reply(reply.as_bytes().to_vec()));
+
+        let Some(first) = text.chars().next() else { return reply("") };
+        let rest = &text[1..];
+        match first {
+            '?' => { let stop = self.last_stop.clone(); reply(&stop) },
+            'g' => {
+                let registers = self.registers()?;
+                reply(&CORE_REGISTERS.iter().map(|reg| to_hex(&Self::gdb_value(&registers, *reg).to_le_bytes())).collect::<String>())
+            },
+            'G' => {
+                let values = from_hex(rest).filter(|values| values.len() == CORE_REGISTERS.len() * 4).ok_or_else(|| bad_packet(text))?;
+                let base = self.registers()?;
+                let mut edited = base.clone();
+                for (reg, value) in CORE_REGISTERS.iter().zip(values.chunks(4)) {
+                    let value = u32::from_le_bytes(value.try_into().unwrap());
+                    if value != Self::gdb_value(&base, *reg) {
+                        Self::set_gdb_value(&mut edited, *reg, value)?;
+                    }
+                }
+                self.write_registers(base, edited)?;
+                reply("OK")
+            },
+            'p' => {
+                let reg = Self::register(field(rest, ' ')?.0)?;
+                let registers = self.registers()?;
+                reply(&to_hex(&Self::gdb_value(&registers, reg).to_le_bytes()))
+            },
+            'P' => {
+                let (number, value) = field(rest, '=')?;
+                let reg = Self::register(number)?;
+                let value: [u8; 4] = from_hex(value).and_then(|value| value.try_into().ok()).ok_or_else(|| bad_packet(text))?;
+                let base = self.registers()?;
+                let mut edited = base.clone();
+                Self::set_gdb_value(&mut edited, reg, u32::from_le_bytes(value))?;
+                self.write_registers(base, edited)?;
+                reply("OK")
+            },
+            'm' => {
+                let (address, length) = field(rest, ',')?;
+                let length = field(length, ' ')?.0 as usize;
+                let bytes = self.read_memory(address, length.min(PACKET_SIZE / 2))?;
+                reply(&to_hex(&bytes))
+            },
+            'M' => {
+                let (address, rest) = field(rest, ',')?;
+                let (length, hex) = field(rest, ':')?;
+                let bytes = from_hex(hex).filter(|bytes| bytes.len() == length as usize).ok_or_else(|| bad_packet(text))?;
+                self.write_memory(address, &bytes)?;
+                reply("OK")
+            },
+            'X' => {
+                let (address, length) = field(rest, ',')?;
+                if binary.len() != field(length, ' ')?.0 as usize {
+                    return Err(bad_packet(text))
+                }
+                self.write_memory(address, binary)?;
+                reply("OK")
+            },
+            'c' | 's' => {
+                if !rest.is_empty() {
+                    let address = dsc_address(field(rest, ' ')?.0).1;
+                    self.prog.dsc_write_pc(address / 2)?;
+                }
+                match self.resume(first == 's', context)? {
+                    Some(halted)    => { self.last_stop = self.stop_reply(&halted); let stop = self.last_stop.clone(); reply(&stop) },
+                    None            => Ok(Response::Close),
+                }
+            },
+            'Z' | 'z' => {
+                let (kind, rest) = field(rest, ',')?;
+                let (address, length) = field(rest, ',')?;
+                let breakpoint = Self::breakpoint(kind, address, field(length, ';')?.0)?;
+                self.change_breakpoint(breakpoint, first == 'Z')?;
+                reply("OK")
+            },
+            'H' => reply("OK"),
+            'D' => {
+                self.prog.dsc_write_breakpoints(&BreakpointUnit::default())?;
+                self.target.go(self.prog)?;
+                Ok(Response::Last(b"OK".to_vec()))
+            },
+            'k' => Ok(Response::Close),
+            'q' | 'Q' => self.query(text),
+            'v' => self.v_packet(text, binary, context),
+            _   => reply(""),
+        }
+    }
+
+    fn query(&mut self, text: &str) -> Result<Response, Error> {
+        let reply = |reply: String| Ok(Response::Reply(reply.into_bytes()));
+        if let Some(features) = text.strip_prefix("qSupported") {
+            self.hwbreak = features.contains("hwbreak+");
+            return reply(format!("PacketSize={:x};qXfer:features:read+;qXfer:memory-map:read+;QStartNoAckMode+;hwbreak+", PACKET_SIZE))
+        }
+        if text == "QStartNoAckMode" {
+            // this packet is acknowledged already, gdb still acknowledges OK
+            self.no_ack = true;
+            return reply("OK".to_string())
+        }
+        if let Some(request) = text.strip_prefix("qXfer:features:read:target.xml:") {
+            return Ok(Response::Reply(Self::xfer(&target_xml(), request)?))
+        }
+        if let Some(request) = text.strip_prefix("qXfer:memory-map:read::") {
+            return Ok(Response::Reply(Self::xfer(&self.memory_map_xml()?, request)?))
+        }
+        if let Some(hex) = text.strip_prefix("qRcmd,") {
+            let command = from_hex(hex).map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string()).ok_or_else(|| bad_packet(text))?;
+            let output = match self.monitor(&command) {
+                Ok(output) => output,
+                // mistyped address or no free comparator, told in gdb
+                Err(Error::SymbolError(reason) | Error::BreakpointError(reason)) => format!("{}\n", reason),
+                Err(e) => return Err(e),
+            };
+            self.send(format!("O{}", to_hex(output.as_bytes())).as_bytes())?;
+            return reply("OK".to_string())
+        }
+        match text {
+            "qAttached"     => reply("1".to_string()),
+            "qfThreadInfo"  => reply("m1".to_string()),
+            "qsThreadInfo"  => reply("l".to_string()),
+            "qC"            => reply("QC1".to_string()),
+            _               => reply(String::new()),
+        }
+    }
+
+    fn v_packet(&mut self, text: &str, binary: &[u8], context: &mut JobContext) -> Result<Response, Error> {
+        if let Some(request) = text.strip_prefix("vFlashErase:") {
+            let (address, length) = field(request, ',')?;
+            let words = self.flash_words(address, field(length, ' ')?.0)?;
+            if !self.flash.erased {
+                context.log(format!("gdb erases flash for load at P:${:04X}", words.start));
+                self.registers = None;
+                self.target.erase_target(self.power, self.prog)?;
+                // power cycle of erase cleared breakpoint unit
+                self.breakpoints = BreakpointUnit::default();
+                self.flash.erased = true;
+            }
+            return Ok(Response::Reply(b"OK".to_vec()))
+        }
+        if let Some(request) = text.strip_prefix("vFlashWrite:") {
+            let address = field(request, ' ')?.0;
+            let words = self.flash_words(address, binary.len() as u32)?;
+            if address & 1 != 0 || binary.len() & 1 != 0 {
+                return Err(Error::GdbServerError("flash write of odd bytes".to_string()))
+            }
+            match self.flash.blocks.last_mut() {
+                Some((start, data)) if *start + data.len() as u32 / 2 == words.start => data.extend_from_slice(binary),
+                _ => self.flash.blocks.push((words.start, binary.to_vec())),
+            }
+            return Ok(Response::Reply(b"OK".to_vec()))
+        }
+        if text == "vFlashDone" {
+            let load = std::mem::take(&mut self.flash);
+            self.registers = None;
+            for (address, data) in load.blocks {
+                context.log(format!("gdb writes {} bytes at P:${:04X}", data.len(), address));
+                self.target.write_target(self.power, address, data, self.prog)?;
+            }
+            return Ok(Response::Reply(b"OK".to_vec()))
+        }
+        Ok(Response::Reply(Vec::new()))
+    }
+
+    /// `monitor` - gdb `monitor` commands, gives back text shown in gdb
+    fn monitor(&mut self, command: &str) -> Result<String, Error> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["reset"] => {
+                self.registers = None;
+                let halted = self.target.reset_and_halt(self.prog)?;
+                self.last_stop = self.stop_reply(&halted);
+                let instruction = self.target.instruction_at_pc(self.prog)?;
+                Ok(format!("{}\n{}", halted, self.listing(&[instruction])))
+            },
+            ["breakpoints"] => Ok(format!("{}\n", self.breakpoints)),
+            ["disas"] | ["disas", _] => {
+                let address = match words.get(1) {
+                    Some(text) => self.code_address(text)?,
+                    None       => self.prog.dsc_read_pc()?,
+                };
+                let instructions = self.target.disassemble_at(address, DISASSEMBLY_LINES, self.prog)?;
+                Ok(self.listing(&instructions))
+            },
+            ["break", text] => {
+                let breakpoint = Breakpoint::program(self.code_address(text)?);
+                self.change_breakpoint(breakpoint, true)?;
+                Ok(format!("{} set\n", breakpoint))
+            },
+            ["watch", text] => {
+                let (space, address) = self.symbols.resolve(text, AccessType::MemoryX)?;
+                if space != AccessType::MemoryX {
+                    return Err(Error::BreakpointError(format!("{} is not in X memory", text)))
+                }
+                // whole variable when it is named
+                let words = self.symbols.variable(text).map_or(1, |variable| variable.ty.size.div_ceil(2).max(1));
+                let watch = Self::watch(BreakpointKind::XAccess, address, words)?;
+                self.change_breakpoint(watch, true)?;
+                Ok(format!("{} set\n", watch))
+            },
+            ["clear", text] => {
+                let (space, address) = self.symbols.resolve(text, AccessType::MemoryP)?;
+                let set = self.breakpoints.list().into_iter()
+                    .find(|(_, breakpoint)| breakpoint.address == address && (breakpoint.kind == BreakpointKind::Program) == (space == AccessType::MemoryP))
+                    .map(|(_, breakpoint)| breakpoint)
+                    .ok_or_else(|| Error::BreakpointError(format!("no breakpoint at {}", self.symbols.address_text(space, address))))?;
+                self.change_breakpoint(set, false)?;
+                Ok(format!("{} cleared\n", set))
+            },
+            ["run-to", text] => {
+                let address = self.code_address(text)?;
+                self.registers = None;
+                let halted = self.target.run_to(address, RUN_TO_TIMEOUT, self.prog)?;
+                self.last_stop = self.stop_reply(&halted);
+                let instruction = self.target.instruction_at_pc(self.prog)?;
+                Ok(format!("{}\n{}", halted, self.listing(&[instruction])))
+            },
+            ["read", text] | ["read", text, _] => {
+                let (space, address) = self.symbols.resolve(text, AccessType::MemoryX)?;
+                let count = match words.get(2) {
+                    Some(count) => self.symbols.resolve(count, space)?.1.clamp(1, READ_WORDS_MAX),
+                    None        => self.symbols.variable(text).map_or(READ_WORDS, |variable| variable.ty.size.div_ceil(2).max(1)),
+                };
+                let bytes = self.prog.dsc_read_memory(space.into(), count * 2, address)?;
+                let lines = bytes.chunks(READ_LINE_WORDS * 2).enumerate().map(|(line, bytes)| {
+                    let words: Vec<String> = bytes.chunks_exact(2).map(|word| format!("{:04X}", u16::from_le_bytes([word[0], word[1]]))).collect();
+                    format!("{}  {}\n", self.symbols.address_text(space, address + (line * READ_LINE_WORDS) as u32), words.join(" "))
+                });
+                Ok(lines.collect())
+            },
+            ["symbol", text] => {
+                let (space, address) = self.symbols.resolve(text, AccessType::MemoryP)?;
+                Ok(format!("{}\n", self.symbols.address_text(space, address)))
+            },
+            ["trace", "on"] | ["trace", "calls"] => {
+                let mode = match words[1] {
+                    "on" => TraceMode::ChangeOfFlow,
+                    _    => TraceMode::CallsAndInterrupts,
+                };
+                self.prog.dsc_start_trace(mode, false)?;
+                self.trace = Some(mode);
+                Ok(format!("trace of {:?} started\n", mode))
+            },
+            ["trace", "off"] => {
+                self.prog.dsc_stop_trace()?;
+                self.trace = None;
+                Ok("trace stopped\n".to_string())
+            },
+            ["trace"] => {
+                let trace = self.prog.dsc_read_trace()?;
+                if let Some(mode) = self.trace {
+                    self.prog.dsc_start_trace(mode, false)?;
+                }
+                if trace.branches.is_empty() {
+                    return Ok(format!("{}\n", trace))
+                }
+                let symbols = self.symbols;
+                Ok(trace.listing(&|address| symbols.describe(AccessType::MemoryP, address)).iter().map(|line| format!("{}\n", line)).collect())
+            },
+            _ => Ok("monitor commands: reset, breakpoints, disas [address], break|watch|clear|run-to address, \
+                read address [words], symbol address, trace [on|calls|off]\n".to_string()),
+        }
+    }
+
+    /// `code_address` - P word address typed by user
+    fn code_address(&self, text: &str) -> Result<u32, Error> {
+        match self.symbols.resolve(text, AccessType::MemoryP)? {
+            (AccessType::MemoryP, address) => Ok(address),
+            _ => Err(Error::SymbolError(format!("{} is not in P memory", text))),
+        }
+    }
+
+    /// `listing` - instructions with `name:` line at each symbol
+    fn listing(&self, instructions: &[Instruction]) -> String {
+        let mut listing = String::new();
+        for instruction in instructions {
+            for name in self.symbols.labels_at(AccessType::MemoryP, instruction.address) {
+                listing += &format!("{}:\n", name);
+            }
+            listing += &format!("{}\n", instruction);
+        }
+        listing
+    }
+
+    /// `resume` - continue or step, `None` when gdb closed connection while target runs
+    fn resume(&mut self, step: bool, context: &mut JobContext) -> Result<Option<Halted>, Error> {
+        self.registers = None;
+        if step {
+            return self.target.step(self.prog).map(Some)
+        }
+        self.target.go(self.prog)?;
+        loop {
+            if let Some(halted) = self.target.wait_halt(Duration::ZERO, self.prog)? {
+                return Ok(Some(halted))
+            }
+            match self.receive()? {
+                Received::Unit(Incoming::Interrupt) => return self.target.halt(HALT_TIMEOUT, self.prog).map(Some),
+                Received::Closed                    => return Ok(None),
+                _ => if context.is_cancelled() {
+                    self.target.halt(HALT_TIMEOUT, self.prog)?;
+                    return Err(Error::JobCancelled)
+                },
+            }
+        }
+    }
+
+    fn stop_reply(&self, halted: &Halted) -> String {
+        let breakpoint = match halted.reason {
+            HaltReason::DebugRequest            => return "S02".to_string(),
+            HaltReason::Breakpoint(Some(index)) => self.breakpoints.comparators[index],
+            _                                   => None,
+        };
+        match breakpoint {
+            Some(breakpoint) if breakpoint.kind == BreakpointKind::Program => match self.hwbreak {
+                true  => "T05hwbreak:;".to_string(),
+                false => "S05".to_string(),
+            },
+            Some(breakpoint) => {
+                let watch = match breakpoint.kind {
+                    BreakpointKind::XRead   => "rwatch",
+                    BreakpointKind::XAccess => "awatch",
+                    _                       => "watch",
+                };
+                format!("T05{}:{:x};", watch, gdb_address(AccessType::MemoryX, breakpoint.address))
+            },
+            None => "S05".to_string(),
+        }
+    }
+
+    fn register(number: u32) -> Result<DscRegisters, Error> {
+        CORE_REGISTERS.get(number as usize).copied().ok_or_else(|| Error::GdbServerError(format!("no register {}", number)))
+    }
+
+    fn gdb_value(registers: &CoreRegisters, reg: DscRegisters) -> u32 {
+        let value = registers.get(reg).unwrap_or(0);
+        match reg {
+            DscRegisters::DscRegPc  => gdb_address(AccessType::MemoryP, value),
+            _                       => value,
+        }
+    }
+
+    fn set_gdb_value(registers: &mut CoreRegisters, reg: DscRegisters, value: u32) -> Result<(), Error> {
+        match reg {
+            DscRegisters::DscRegPc  => registers.set(reg, dsc_address(value).1 / 2),
+            _                       => registers.set(reg, value),
+        }
+    }
+
+    fn registers(&mut self) -> Result<CoreRegisters, Error> {
+        if self.registers.is_none() {
+            self.registers = Some(CoreRegisters::read(self.prog)?);
+        }
+        Ok(self.registers.clone().unwrap())
+    }
+
+    fn write_registers(&mut self, base: CoreRegisters, edited: CoreRegisters) -> Result<(), Error> {
+        self.registers = None;
+        edited.write_changes(self.prog, &base)?;
+        self.registers = Some(edited);
+        Ok(())
+    }
+
+    /// `words` - memory space, first word address and word count of gdb byte range
+    fn words(address: u32, length: usize) -> (AccessType, u32, u32) {
+        let (space, byte_address) = dsc_address(address);
+        let first = byte_address / 2;
+        let end = (byte_address + length as u32).div_ceil(2);
+        (space, first, end - first)
+    }
+
+    fn read_memory(&mut self, address: u32, length: usize) -> Result<Vec<u8>, Error> {
+        if length == 0 {
+            return Ok(Vec::new())
+        }
+        let (space, first, words) = Self::words(address, length);
+        let bytes = self.prog.dsc_read_memory(space.into(), words * 2, first)?;
+        let offset = (address % 2) as usize;
+        Ok(bytes[offset..offset + length].to_vec())
+    }
+
+    /// `write_memory` - RAM and peripherals, odd ends are read and merged
+    fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() {
+            return Ok(())
+        }
+        let (space, first, words) = Self::words(address, data.len());
+        let flash = self.target.programm_range()?;
+        if space == AccessType::MemoryP && (first as u64) <= flash.end && (first + words) as u64 > flash.start {
+            return Err(Error::GdbServerError(format!("write to flash at P:${:04X}, use load", first)))
+        }
+        let offset = (address % 2) as usize;
+        let mut bytes = match offset != 0 || data.len() & 1 != 0 {
+            true  => self.prog.dsc_read_memory(space.into(), words * 2, first)?,
+            false => vec![0; data.len()],
+        };
+        bytes[offset..offset + data.len()].copy_from_slice(data);
+        self.prog.dsc_write_memory(space.into(), bytes, first)?;
+        self.registers = None;
+        Ok(())
+    }
+
+    /// `flash_words` - P word range of gdb range, error when it is not all in program flash
+    fn flash_words(&self, address: u32, length: u32) -> Result<std::ops::Range<u32>, Error> {
+        let (space, byte_address) = dsc_address(address);
+        let range = self.target.programm_range()?;
+        let words = byte_address / 2..(byte_address + length).div_ceil(2);
+        if space != AccessType::MemoryP || (words.start as u64) < range.start || words.end as u64 > range.end + 1 {
+            return Err(Error::GdbServerError(format!("{:#X}..+{:#X} is not program flash", address, length)))
+        }
+        Ok(words)
+    }
+
+    fn breakpoint(kind: u32, address: u32, length: u32) -> Result<Breakpoint, Error> {
+        let (space, byte_address) = dsc_address(address);
+        let word = byte_address / 2;
+        match (kind, space) {
+            (0 | 1, AccessType::MemoryP) => Ok(Breakpoint::program(word)),
+            (2..=4, AccessType::MemoryX) => {
+                let kind = match kind {
+                    2 => BreakpointKind::XWrite,
+                    3 => BreakpointKind::XRead,
+                    _ => BreakpointKind::XAccess,
+                };
+                Self::watch(kind, word, Self::words(address, length.max(1) as usize).2)
+            },
+            _ => Err(Error::BreakpointError(format!("type {} at {:#X} not supported", kind, address))),
+        }
+    }
+
+    /// `watch` - watchpoint of `words` words from X:`word`
+    fn watch(kind: BreakpointKind, word: u32, words: u32) -> Result<Breakpoint, Error> {
+        let watch = Breakpoint::watch(kind, word);
+        match words {
+            1 => Ok(watch),
+            // aligned power of 2 block by address mask
+            _ if words.is_power_of_two() && word & (words - 1) == 0 => Ok(watch.with_mask(0x00FF_FFFF & !(words - 1))),
+            _ => Err(Error::BreakpointError(format!("watch of {} words at X:${:04X} can't be masked", words, word))),
+        }
+    }
+
+    fn change_breakpoint(&mut self, breakpoint: Breakpoint, insert: bool) -> Result<(), Error> {
+        let mut unit = self.breakpoints.clone();
+        if insert {
+            unit.set(breakpoint)?;
+        } else if let Some((index, _)) = unit.list().into_iter().find(|(_, set)| *set == breakpoint) {
+            unit.clear(index);
+        }
+        self.prog.dsc_write_breakpoints(&unit)?;
+        self.breakpoints = unit;
+        Ok(())
+    }
+
+    /// `xfer` - `offset,length` part of `document`, `l` prefix on last part
+    fn xfer(document: &str, request: &str) -> Result<Vec<u8>, Error> {
+        let (offset, length) = field(request, ',')?;
+        let length = field(length, ' ')?.0 as usize;
+        let bytes = document.as_bytes();
+        let start = (offset as usize).min(bytes.len());
+        let end = (start + length).min(bytes.len());
+        let mut part = vec![if end == bytes.len() { b'l' } else { b'm' }];
+        part.extend_from_slice(&bytes[start..end]);
+        Ok(part)
+    }
+
+    /// `memory_map_xml` - program flash and P RAM of target, whole X space as RAM (peripherals included)
+    fn memory_map_xml(&self) -> Result<String, Error> {
+        let mut regions = Vec::new();
+        for segment in &self.target.memory_map {
+            let (kind, range) = match segment {
+                MemorySegment::FlashProgramm(flash)                                 => ("flash", &flash.range),
+                MemorySegment::Ram(ram) if ram.access_type == AccessType::MemoryP   => ("ram", &ram.range),
+                _ => continue,
+            };
+            let start = gdb_address(AccessType::MemoryP, range.start as u32);
+            let length = (range.end + 1 - range.start) as u32 * 2;
+            regions.push(match kind {
+                // flash is erased all at once
+                "flash" => format!("  <memory type=\"flash\" start=\"{:#x}\" length=\"{:#x}\">\n    <property name=\"blocksize\">{:#x}</property>\n  </memory>", start, length, length),
+                _       => format!("  <memory type=\"ram\" start=\"{:#x}\" length=\"{:#x}\"/>", start, length),
+            });
+        }
+        regions.push(format!("  <memory type=\"ram\" start=\"{:#x}\" length=\"{:#x}\"/>", X_SPACE, SPACE_SIZE));
+        Ok(format!("<?xml version=\"1.0\"?>\n\
+            <!DOCTYPE memory-map PUBLIC \"+//IDN gnu.org//DTD GDB Memory Map V1.0//EN\" \"http://sourceware.org/gdb/gdb-memory-map.dtd\">\n\
+            <memory-map>\n{}\n</memory-map>\n", regions.join("\n")))
+    }
+}
+
+/// `target_xml` - core registers by `DscRegisters` number, all 32-bit
+fn target_xml() -> String {
+    let registers: Vec<String> = CORE_REGISTERS.iter().map(|reg| {
+        let kind = match reg {
+            DscRegisters::DscRegPc  => "code_ptr",
+            DscRegisters::DscRegSp  => "data_ptr",
+            _                       => "int",
+        };
+        format!("    <reg name=\"{}\" bitsize=\"32\" regnum=\"{}\" type=\"{}\"/>", get_register_name(*reg), *reg as u32, kind)
+    }).collect();
+    format!("<?xml version=\"1.0\"?>\n\
+        <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+        <target version=\"1.0\">\n  <feature name=\"org.usbdm.dsc56800e.core\">\n{}\n  </feature>\n</target>\n", registers.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use crate::usbdm::jtag::{enableCoreTAP, enableONCE, OnceStatus};
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::{DscTap, DscCore};
+    use crate::dsc_target::target_factory::{TargetSelector, TargetYaml};
+    use crate::symbols::symbol_table::Symbol;
+    use crate::symbols::dwarf::{Variable, VariableType, TypeKind};
+
+    /// `Client` - scripted gdb side, acknowledges every packet
+    struct Client {
+        stream : TcpStream,
+        reader : PacketReader,
+    }
+
+    impl Client {
+        fn send(&mut self, packet: &[u8]) {
+            self.stream.write_all(&encode(packet)).unwrap();
+        }
+
+        fn reply(&mut self) -> String {
+            let mut bytes = [0u8; 256];
+            loop {
+                match self.reader.next() {
+                    Some(Incoming::Packet(data)) => {
+                        self.stream.write_all(b"+").unwrap();
+                        return String::from_utf8(data).unwrap()
+                    },
+                    Some(_) => continue,
+                    None    => {
+                        let count = self.stream.read(&mut bytes).unwrap();
+                        assert!(count > 0, "server closed connection");
+                        self.reader.push(&bytes[..count]);
+                    },
+                }
+            }
+        }
+
+        fn ask(&mut self, packet: &str) -> String {
+            self.send(packet.as_bytes());
+            self.reply()
+        }
+    }
+
+    #[test]
+    fn scripted_session() {
+        let mut tap = DscTap::new();
+        let mut core = DscCore::default();
+        core.set(DscRegisters::DscRegPc, 0x0200);
+        tap.core = Some(core);
+        let link = InterpreterTransport::new(tap);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+        let counter = Variable { name: "g_counter".to_string(), address: 0x1000, ty: VariableType { name: "long".to_string(), size: 4, kind: TypeKind::Signed } };
+        let symbols = SymbolTable::new(vec![
+            Symbol { name: "Fmain".to_string(), space: AccessType::MemoryP, address: 0x0200, size: 0, function: true },
+            Symbol { name: "Fg_counter".to_string(), space: AccessType::MemoryX, address: 0x1000, size: 2, function: false },
+        ], vec![counter]);
+
+        let server = GdbServer::bind(0).unwrap();
+        let port = server.port().unwrap();
+        let client = thread::spawn(move || {
+            let mut gdb = Client { stream: TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap(), reader: PacketReader::default() };
+            assert!(gdb.ask("qSupported:multiprocess+;hwbreak+").contains("qXfer:memory-map:read+"));
+            assert_eq!(gdb.ask("QStartNoAckMode"), "OK");
+            assert_eq!(gdb.ask("?"), "S05");
+            assert!(gdb.ask("qXfer:features:read:target.xml:0,fff").contains("<reg name=\"pc\" bitsize=\"32\" regnum=\"37\" type=\"code_ptr\"/>"));
+            let map = gdb.ask("qXfer:memory-map:read::0,fff");
+            assert!(map.starts_with('l'));
+            assert!(map.contains("<memory type=\"flash\" start=\"0x0\" length=\"0x10000\">"));
+            assert_eq!(&gdb.ask("qXfer:memory-map:read::10,8")[..1], "m");
+
+            // PC in gdb is P byte address
+            assert_eq!(gdb.ask("p25"), "00040000");
+            assert_eq!(gdb.ask("P25=00060000"), "OK");
+            assert_eq!(gdb.ask("P12=00000000"), "E01");
+            assert_eq!(gdb.ask("g").len(), 38 * 8);
+
+            // odd X byte address merges with words around it
+            assert_eq!(gdb.ask("M80002001,2:abcd"), "OK");
+            assert_eq!(gdb.ask("m80002000,4"), "ffabcdff");
+            gdb.send(b"X80002004,2:\x23\x7d");
+            assert_eq!(gdb.reply(), "OK");
+            assert_eq!(gdb.ask("m80002004,2"), "237d");
+            assert_eq!(gdb.ask("M100,2:0000"), "E01");
+
+            assert_eq!(gdb.ask("Z0,800,2"), "OK");
+            assert_eq!(gdb.ask("Z2,80002000,4"), "OK");
+            assert_eq!(gdb.ask("Z0,900,2"), "E01");
+            assert_eq!(gdb.ask("c"), "T05hwbreak:;");
+            assert_eq!(gdb.ask("p25"), "00080000");
+            assert_eq!(gdb.ask("z0,800,2"), "OK");
+            assert_eq!(gdb.ask("z2,80002000,4"), "OK");
+            assert_eq!(gdb.ask("s"), "S05");
+            assert_eq!(gdb.ask("p25"), "02080000");
+
+            // runs until interrupted
+            gdb.send(b"c");
+            thread::sleep(Duration::from_millis(60));
+            gdb.stream.write_all(&[INTERRUPT]).unwrap();
+            assert_eq!(gdb.reply(), "S02");
+
+            // unwritten P memory of virtual target reads as $FFFF
+            let output = gdb.ask(&format!("qRcmd,{}", to_hex(b"disas")));
+            let listing = String::from_utf8(from_hex(&output[1..]).unwrap()).unwrap();
+            assert_eq!(listing.lines().count(), 8);
+            assert!(listing.lines().all(|line| line.starts_with("P:$") && line.ends_with("dc.w    $ffff")));
+            assert_eq!(gdb.reply(), "OK");
+
+            let monitor = |gdb: &mut Client, command: &str| {
+                let output = gdb.ask(&format!("qRcmd,{}", to_hex(command.as_bytes())));
+                assert_eq!(gdb.reply(), "OK");
+                String::from_utf8(from_hex(&output[1..]).unwrap()).unwrap()
+            };
+            assert_eq!(monitor(&mut gdb, "trace calls"), "trace of CallsAndInterrupts started\n");
+            assert_eq!(monitor(&mut gdb, "trace"), "no changes of flow captured\n");
+
+            // addresses by symbol names
+            assert_eq!(monitor(&mut gdb, "symbol $201"), "P:$0201 <Fmain+$1>\n");
+            assert_eq!(monitor(&mut gdb, "read g_counter"), "X:$1000 <Fg_counter>  ABFF FFCD\n");
+            assert_eq!(monitor(&mut gdb, "break main+$100"), "P $000300 set\n");
+            assert_eq!(monitor(&mut gdb, "watch g_counter"), "X access $001000 mask $FFFFFE set\n");
+            assert_eq!(monitor(&mut gdb, "clear g_counter"), "X access $001000 mask $FFFFFE cleared\n");
+            assert_eq!(monitor(&mut gdb, "clear P:$300"), "P $000300 cleared\n");
+            assert_eq!(monitor(&mut gdb, "break nothing"), "unknown symbol 'nothing'\n");
+            assert_eq!(monitor(&mut gdb, "break g_counter"), "g_counter is not in P memory\n");
+            assert!(monitor(&mut gdb, "run-to main+$145").starts_with("halted at P:$000345 (run to address)\nP:$000345  FFFF"));
+            assert_eq!(gdb.ask("p25"), "8a060000");
+
+            assert_eq!(gdb.ask("vFlashWrite:80000000:\x01\x02"), "E01");
+            assert_eq!(gdb.ask("vMustReplyEmpty"), "");
+            assert_eq!(gdb.ask("D"), "OK");
+        });
+
+        let mut sink = |_event| {};
+        let cancel = AtomicBool::new(false);
+        let mut context = JobContext::new(&mut sink, &cancel);
+        let served = server.serve(&mut target, &mut prog, TargetVddSelect::Vdd3V3, &symbols, &mut context);
+        client.join().unwrap();
+        served.unwrap();
+
+        assert_eq!(link.with_tap(|tap| tap.memory[&(crate::usbdm::constants::memory_space_t::MS_DATA, 0x2002)]), 0xCD);
+        // detach runs target without breakpoints
+        assert_eq!(link.with_tap(|tap| tap.once_register(0x11)), 0);
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+    }
+
+    /// `served` - session of `client` with breakpoint at P:$0400 set first, OB0CR after it and result of `serve`
+    fn served(client: impl FnOnce(&mut Client, &AtomicBool) + Send + 'static) -> (u32, Result<(), Error>) {
+        let link = InterpreterTransport::new(DscTap::new());
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        let database = TargetYaml::init_target_db().unwrap();
+        let mut target = TargetDsc::target_from_selector(TargetSelector::Mc56f8035, database).unwrap();
+
+        let server = GdbServer::bind(0).unwrap();
+        let port = server.port().unwrap();
+        let cancel = std::sync::Arc::new(AtomicBool::new(false));
+        let client_cancel = cancel.clone();
+        let client = thread::spawn(move || {
+            let mut gdb = Client { stream: TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap(), reader: PacketReader::default() };
+            assert_eq!(gdb.ask("Z0,800,2"), "OK");
+            client(&mut gdb, &client_cancel);
+        });
+        let mut sink = |_event| {};
+        let mut context = JobContext::new(&mut sink, &cancel);
+        let result = server.serve(&mut target, &mut prog, TargetVddSelect::Vdd3V3, &SymbolTable::default(), &mut context);
+        client.join().unwrap();
+        (link.with_tap(|tap| tap.once_register(0x11)), result)
+    }
+
+    #[test]
+    fn breakpoints_cleared_on_every_exit() {
+        let (ob0cr, result) = served(|gdb, _| gdb.send(b"k"));
+        assert_eq!((ob0cr, result.is_ok()), (0, true));
+
+        // gdb gone while target runs
+        let (ob0cr, result) = served(|gdb, _| {
+            gdb.send(b"c");
+            thread::sleep(Duration::from_millis(60));
+            gdb.stream.shutdown(std::net::Shutdown::Both).unwrap();
+        });
+        assert_eq!((ob0cr, result.is_ok()), (0, true));
+
+        let (ob0cr, result) = served(|_, cancel| {
+            cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+            thread::sleep(Duration::from_millis(60));
+        });
+        assert_eq!(ob0cr, 0);
+        assert!(matches!(result, Err(Error::JobCancelled)));
+    }
+}
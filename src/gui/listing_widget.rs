@@ -0,0 +1,106 @@
+use iced_native::layout::{Node, Limits};
+use iced_native::widget::Tree;
+use iced_native::{Color, Element, Length, Point, Rectangle, Size};
+use iced_native::Background;
+use iced::{alignment::Horizontal, alignment::Vertical};
+use iced_native::{
+    widget, Font,
+    layout,
+    renderer,
+    Renderer, Widget, Layout
+};
+
+use super::hexbuffer_widget::FONT_BYTES;
+
+static FONT_NAME : &str = "CourierNewPS-BoldMT";
+
+/// `ListingContents` - text lines drawn only inside viewport, for long listings like disassembly of whole flash
+pub struct ListingContents {
+    item_height : f32,
+    lines       : Vec<String>,
+}
+
+pub fn listing_contents(item_height: f32, lines: Vec<String>) -> ListingContents {
+    ListingContents { item_height, lines }
+}
+
+impl<Message> Widget<Message, iced::Renderer> for ListingContents {
+
+    fn width(&self) -> Length {
+        Length::Fill
+    }
+
+    fn height(&self) -> Length {
+        Length::Fill
+    }
+
+    fn draw(
+        &self,
+        _state: &widget::Tree,
+        renderer: &mut iced::Renderer,
+        _theme: &iced::Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor_position: Point,
+        viewport: &Rectangle,
+    ) {
+        use iced_native::text::Renderer as text_renderer;
+
+        let end_y = viewport.y + viewport.height;
+        let mut number_of_line = ((viewport.y - layout.bounds().y).max(0.0) / self.item_height) as usize;
+        let mut line_bounds = Rectangle {
+            x      : layout.bounds().x,
+            y      : (self.item_height * number_of_line as f32) + layout.bounds().y,
+            width  : layout.bounds().width,
+            height : self.item_height,
+        };
+
+        while line_bounds.y < end_y {
+            let mut background_bounds = line_bounds;
+            if line_bounds.y + line_bounds.height > end_y {
+                background_bounds.height = end_y - line_bounds.y;
+            }
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds: background_bounds,
+                    border_radius: Default::default(),
+                    border_width: 0.0,
+                    border_color: Color::WHITE,
+                }, Background::Color(Color::WHITE)
+            );
+
+            if let Some(line) = self.lines.get(number_of_line) {
+                let mut text_bounds = line_bounds;
+                text_bounds.x = viewport.x + 20.0;
+                text_bounds.y = line_bounds.center_y();
+                renderer.fill_text(
+                    iced_native::text::Text {
+                        content: line.as_str(),
+                        bounds: text_bounds,
+                        size: 15.0,
+                        color: Color::BLACK,
+                        font: Font::External { name : FONT_NAME, bytes : FONT_BYTES },
+                        horizontal_alignment: Horizontal::Left,
+                        vertical_alignment: Vertical::Center,});
+            }
+
+            line_bounds.y += line_bounds.height;
+            number_of_line += 1;
+        }
+    }
+
+    fn layout(&self, _renderer: &iced::Renderer, limits: &Limits) -> Node {
+        layout::Node::new(Size {
+            width  : limits.max().width,
+            height : self.item_height * (self.lines.len() + 1) as f32,
+        })
+    }
+}
+
+impl<'a, Message> From<ListingContents> for Element<'a, Message, iced::Renderer>
+    where Message: 'a
+{
+    fn from(listing_contents: ListingContents) -> Self {
+        Self::new(listing_contents)
+    }
+}
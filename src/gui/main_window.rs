@@ -14,9 +14,11 @@ use crate::app::{Message, App, UsbdmAppStatus, TargetStatus};
 use crate::usbdm::settings::{TargetVddSelect};
 use crate::usbdm::feedback::{PowerStatus};
 use crate::dsc_target::target_factory::{TargetSelector};
+use crate::dsc_target::live_watch::{WATCH_FORMATS};
 use super::styling::{PowerButtonStyle, ButtonStyle, EnablePowerButtonStyle};
 
 use super::hexbuffer_widget::{TableContents,table_contents };
+use super::listing_widget::{listing_contents};
 
 impl TargetVddSelect {
     pub const ALL: [TargetVddSelect; 2] = [
@@ -73,14 +75,30 @@ pub fn main_page<'a>(_app: &App) -> Column<'a, Message, iced::Renderer>
     let test_addr_line   = vec![vec!["01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F".to_string(), "test_test2".to_string(),]; 4500];
     
 
-    let table_test = table_contents(20.00, _app.target.memory_buffer.download_all_u8(), || test_buffer_double_click() );
-    
-    let test_test = scrollable(Container::new(table_test).align_y(alignment::Vertical::Center));
+    let test_test = if _app.disassembly_view {
+        let listing = listing_contents(20.00, _app.target.memory_buffer.disassemble(&_app.symbols));
+        scrollable(Container::new(listing).align_y(alignment::Vertical::Center))
+    } else {
+        let table_test = table_contents(20.00, _app.target.memory_buffer.download_all_u8(), || test_buffer_double_click() );
+        scrollable(Container::new(table_test).align_y(alignment::Vertical::Center))
+    };
     
     let body = test_test;
 
 
-    let c = col![top_bar, body];
+    let mut c = col![top_bar, body];
+    if _app.console_open {
+        c = c.push(console_panel(_app));
+    }
+    if _app.watch_open {
+        c = c.push(watch_panel(_app));
+    }
+    if _app.profiler_open {
+        c = c.push(profiler_panel(_app));
+    }
+    if _app.dump_open {
+        c = c.push(dump_panel(_app));
+    }
     
 
     c        
@@ -90,6 +108,164 @@ pub fn main_page<'a>(_app: &App) -> Column<'a, Message, iced::Renderer>
  
          
          
+}
+
+/// `console_panel` - output of `Job::Console` with input line, below buffer view
+pub fn console_panel<'a>(_app: &App) -> Column<'a, Message, iced::Renderer>
+{
+    let output = scrollable(
+        text(_app.console_text.clone()).size(15).width(Length::Fill))
+        .height(Length::Fixed(160.0));
+
+    let input = text_input("text to target", &_app.console_input, Message::ConsoleInput)
+        .on_submit(Message::ConsoleSend)
+        .padding(4);
+
+    let send = match _app.console_sender {
+        Some(_) => labeled_button("Send", Message::ConsoleSend),
+        None    => empty_labeled_button("Send"),
+    };
+
+    let controls = row![input, send.width(Length::Fixed(70.0)), labeled_button("Close", Message::CloseConsole).width(Length::Fixed(70.0))]
+        .spacing(4)
+        .align_items(alignment::Alignment::Center);
+
+    col![output, controls].spacing(4).padding([4, 8])
+}
+
+/// `watch_panel` - values of `Job::LiveWatch` items with item entry and sampling settings, below buffer view
+pub fn watch_panel<'a>(_app: &App) -> Column<'a, Message, iced::Renderer>
+{
+    let cell = |content: String| text(content).size(15).width(Length::FillPortion(2));
+
+    let header = ["name", "address", "format", "value", "min", "max"].iter()
+        .fold(Row::new(), |header, title| header.push(cell(title.to_string())))
+        .push(horizontal_space(Length::Fixed(40.0)));
+
+    let mut rows = col![header].spacing(2);
+    for index in 0.._app.watch.items.len() {
+        let [name, address, _format, value, min, max] = _app.watch.cells(index);
+        let format = pick_list(&WATCH_FORMATS[..], Some(_app.watch.items[index].format), move |format| Message::WatchFormatSelect(index, format))
+            .text_size(15)
+            .width(Length::FillPortion(2));
+        let remove = match _app.watch_running {
+            true  => empty_labeled_button("x"),
+            false => labeled_button("x", Message::WatchRemove(index)),
+        };
+        rows = rows.push(row![cell(name), cell(address), format, cell(value), cell(min), cell(max), remove.width(Length::Fixed(40.0))]
+            .align_items(alignment::Alignment::Center));
+    }
+    let table = scrollable(rows).height(Length::Fixed(160.0));
+
+    let input = text_input("variable or X address", &_app.watch_input, Message::WatchInput)
+        .on_submit(Message::WatchAdd)
+        .padding(4);
+
+    let (add, start) = match _app.watch_running {
+        true  => (empty_labeled_button("Add"), labeled_button("Stop", Message::WatchStop)),
+        false => (labeled_button("Add", Message::WatchAdd), labeled_button("Start", Message::WatchStart)),
+    };
+
+    let interval = _app.watch_settings.interval.as_millis() as u32;
+    let halt_budget = _app.watch_settings.halt_budget.as_millis() as u32;
+    let settings = row![
+        text(format!("every {} ms", interval)).size(15).width(Length::Fixed(110.0)),
+        slider(10..=1000, interval, Message::WatchInterval),
+        text(format!("halt {} ms", halt_budget)).size(15).width(Length::Fixed(90.0)),
+        slider(1..=50, halt_budget, Message::WatchHaltBudget),
+        text(format!("{} samples, longest halt {} us", _app.watch.samples, _app.watch.halted.as_micros())).size(15),
+    ]
+        .spacing(8)
+        .align_items(alignment::Alignment::Center);
+
+    let controls = row![
+        input,
+        add.width(Length::Fixed(70.0)),
+        start.width(Length::Fixed(70.0)),
+        labeled_button("Close", Message::CloseWatch).width(Length::Fixed(70.0))]
+        .spacing(4)
+        .align_items(alignment::Alignment::Center);
+
+    col![table, settings, controls].spacing(4).padding([4, 8])
+}
+
+/// `profiler_panel` - busiest functions of last `Job::Profile` with sampling settings and export, below buffer view
+pub fn profiler_panel<'a>(_app: &App) -> Column<'a, Message, iced::Renderer>
+{
+    let report = match (&_app.profile, _app.profile_running) {
+        (_, true)             => "sampling PC...".to_string(),
+        (Some(profile), _)    => profile.report(&_app.symbols, 20),
+        (None, _)             => "no profile yet, firmware must run while sampling".to_string(),
+    };
+    let output = scrollable(
+        text(report).size(15).width(Length::Fill))
+        .height(Length::Fixed(160.0));
+
+    let samples = _app.profile_settings.samples as u32;
+    let interval = _app.profile_settings.interval.as_millis() as u32;
+    let settings = row![
+        text(format!("{} samples", samples)).size(15).width(Length::Fixed(110.0)),
+        slider(100..=20000, samples, Message::ProfileSamples).step(100),
+        text(format!("every {} ms", interval)).size(15).width(Length::Fixed(110.0)),
+        slider(0..=100, interval, Message::ProfileInterval),
+    ]
+        .spacing(8)
+        .align_items(alignment::Alignment::Center);
+
+    let start = match _app.profile_running {
+        true  => labeled_button("Stop", Message::ProfileStop),
+        false => labeled_button("Start", Message::ProfileStart),
+    };
+    let save = match (&_app.profile, _app.profile_running) {
+        (Some(_), false) => labeled_button("Save", Message::ProfileSave),
+        _                => empty_labeled_button("Save"),
+    };
+
+    let controls = row![
+        horizontal_space(Length::Fill),
+        start.width(Length::Fixed(70.0)),
+        save.width(Length::Fixed(70.0)),
+        labeled_button("Close", Message::CloseProfiler).width(Length::Fixed(70.0))]
+        .spacing(4)
+        .align_items(alignment::Alignment::Center);
+
+    col![output, settings, controls].spacing(4).padding([4, 8])
+}
+
+/// `dump_panel` - opened core dump: header and registers beside memory of selected region, below buffer view
+pub fn dump_panel<'a>(_app: &App) -> Column<'a, Message, iced::Renderer>
+{
+    let dump = match _app.dump.as_ref() {
+        Some(dump) => dump,
+        None       => return col![labeled_button("Close", Message::CloseCoreDump).width(Length::Fixed(70.0))].padding([4, 8]),
+    };
+
+    let report = scrollable(
+        text(dump.report(&_app.symbols)).size(15).width(Length::Fill))
+        .height(Length::Fixed(240.0));
+
+    let memory = scrollable(
+        text(dump.memory_lines(_app.dump_region, &_app.symbols).join("\n")).size(15).width(Length::Fill))
+        .height(Length::Fixed(240.0));
+
+    let regions = dump.regions.iter().enumerate()
+        .fold(Row::new().spacing(4), |regions, (index, region)| {
+            let button = match index == _app.dump_region {
+                true  => empty_labeled_button(&region.name),
+                false => labeled_button(&region.name, Message::DumpRegionSelect(index)),
+            };
+            regions.push(button)
+        })
+        .push(horizontal_space(Length::Fill))
+        .push(labeled_button("Close", Message::CloseCoreDump).width(Length::Fixed(70.0)))
+        .align_items(alignment::Alignment::Center);
+
+    let views = row![
+        container(report).width(Length::FillPortion(1)),
+        container(memory).width(Length::FillPortion(1))]
+        .spacing(8);
+
+    col![views, regions].spacing(4).padding([4, 8])
 }
 
 pub fn test_buffer_double_click() ->  Message
@@ -248,6 +424,15 @@ pub fn programmer_button_item<'a>(label: &str, msg : Message, state : &UsbdmAppS
     
 }
 
+/// `usbdm_button_item` - action which needs opened USBDM only, target may be not connected
+pub fn usbdm_button_item<'a>(label: &str, msg : Message, state : &UsbdmAppStatus) -> MenuTree<'a, Message, iced::Renderer> {
+    match state
+    {
+        UsbdmAppStatus::Connected => MenuTree::new(labeled_button(label, msg).width(Length::Fill).height(Length::Fill)),
+        _                         => MenuTree::new(empty_labeled_button(label).width(Length::Fill).height(Length::Fill)),
+    }
+}
+
 pub fn menu_item<'a>(label: &str) -> MenuTree<'a, Message, iced::Renderer> {
     MenuTree::new(menu_button(label).width(Length::Fill).height(Length::Fill))
 }
@@ -357,10 +542,20 @@ pub fn programmer_actions_menu<'a>(_app: &App) -> MenuTree<'a, Message, iced::Re
         menu_button("Programmer"),
         vec![
             connect_button_item("Connect", Message::Connect),
+            probe_selection_menu(_app),
             programmer_button_item("Read", Message::ReadTarget, &_app.status, &_app.target_status),
             programmer_button_item("Write", Message::WriteTarget, &_app.status, &_app.target_status),
             programmer_button_item("Verify", Message::VerifyTarget, &_app.status, &_app.target_status),
             programmer_button_item("Erase", Message::EraseTarget, &_app.status, &_app.target_status),
+            programmer_button_item("Tune clock", Message::TuneJtagClock, &_app.status, &_app.target_status),
+            usbdm_button_item("Scan chain", Message::ScanJtagChain, &_app.status),
+            usbdm_button_item("Board test", Message::BoardTest, &_app.status),
+            usbdm_button_item("Play SVF", Message::PlaySvf, &_app.status),
+            programmer_button_item("GDB server", Message::GdbServer, &_app.status, &_app.target_status),
+            programmer_button_item("Console", Message::OpenConsole, &_app.status, &_app.target_status),
+            programmer_button_item("Live watch", Message::OpenWatch, &_app.status, &_app.target_status),
+            programmer_button_item("Profile", Message::OpenProfiler, &_app.status, &_app.target_status),
+            programmer_button_item("Core dump", Message::SaveCoreDump, &_app.status, &_app.target_status),
         ],
     )
     .width(110);
@@ -369,6 +564,48 @@ pub fn programmer_actions_menu<'a>(_app: &App) -> MenuTree<'a, Message, iced::Re
 }
 
 
+/// `probe_selection_menu` - connected USBDM list, selected probe is remembered in preferences
+pub fn probe_selection_menu<'a>(_app: &App) -> MenuTree<'a, Message, iced::Renderer> {
+
+    let auto_reconnect_item = MenuTree::new(
+        container(toggler(
+            Some("Reconnect when plugged back".to_string()),
+            _app.auto_reconnect,
+            Message::AutoReconnectToggle,
+        ))
+        .padding([0, 8])
+        .height(Length::Fill)
+        .align_y(alignment::Vertical::Center),
+    );
+
+    let pipelined_transfers_item = MenuTree::new(
+        container(toggler(
+            Some("Pipelined transfers (next connect)".to_string()),
+            _app.pipelined_transfers,
+            Message::PipelinedTransfersToggle,
+        ))
+        .padding([0, 8])
+        .height(Length::Fill)
+        .align_y(alignment::Vertical::Center),
+    );
+
+    let mut probe_items = vec![connect_button_item("Refresh list", Message::RefreshProbes), auto_reconnect_item, pipelined_transfers_item, dot_separator()];
+
+    for probe in _app.probes.iter() {
+        let mut label = probe.to_string();
+        if _app.preferred_probe.as_ref() == Some(&probe.serial_number) {
+            label = "* ".to_string() + &label;
+        }
+        // busy probe is shown, but without serial there is nothing to remember
+        match probe.selectable() {
+            true  => probe_items.push(connect_button_item(label.as_str(), Message::ProbeSelect(probe.serial_number.clone()))),
+            false => probe_items.push(MenuTree::new(empty_labeled_button(label.as_str()).width(Length::Fill).height(Length::Fill))),
+        }
+    }
+
+    sub_menu("Probe", Message::RefreshProbes, probe_items).width(320)
+}
+
 pub fn file_system_menu<'a>(_app: &App) -> MenuTree<'a, Message, iced::Renderer> {
 
     let root = MenuTree::with_children(
@@ -376,10 +613,12 @@ pub fn file_system_menu<'a>(_app: &App) -> MenuTree<'a, Message, iced::Renderer>
         vec![
             file_button_item("Open(s19/bin)", Message::OpenFile),
             file_button_item("Save(s19/bin)", Message::SaveFile),
+            file_button_item("Load symbols(elf)", Message::LoadSymbols),
+            file_button_item("Open core dump", Message::OpenCoreDump),
     
         ],
     )
-    .width(110);
+    .width(140);
 
     root
 }
@@ -399,6 +638,17 @@ pub fn information_menu<'a>(app: &App) -> MenuTree<'a, Message, iced::Renderer>
     );
 
 
+    let record_session_item = MenuTree::new(
+        container(toggler(
+            Some("Record USB session".to_string()),
+            app.record_session,
+            Message::RecordSessionToggle,
+        ))
+        .padding([0, 8])
+        .height(Length::Fill)
+        .align_y(alignment::Vertical::Center),
+    );
+
     let root = MenuTree::with_children(
         menu_button("Info"),
         vec![
@@ -406,6 +656,7 @@ pub fn information_menu<'a>(app: &App) -> MenuTree<'a, Message, iced::Renderer>
             dot_separator(),
             connection_image_item,
             dot_separator(),
+            record_session_item,
         ],
     );
 
@@ -455,6 +706,14 @@ pub fn view_customization_menu<'a>(app: &App) -> MenuTree<'a, Message, iced::Ren
                 )]
                 .padding([0, 8]),
             ),
+            MenuTree::new(
+                row![toggler(
+                    Some("Disassembly".into()),
+                    app.disassembly_view,
+                    Message::DisassemblyView
+                )]
+                .padding([0, 8]),
+            ),
             color_item([0.28, 0.36, 0.37]),
             color_item([0.32, 0.32, 0.4]),
             color_item([0.56, 0.55, 0.39]),
@@ -1,4 +1,5 @@
 pub mod main_window;
 pub mod styling;
 pub mod hexbuffer_widget;
-pub mod modal_notification;
\ No newline at end of file
+pub mod modal_notification;
+pub mod listing_widget;
@@ -338,7 +338,15 @@ pub fn progress_bar_modal<'a>(target_programming : bool, content: Element<'a, Me
     )   
       .foot(
         Row::new()
-            .width(Length::Shrink),
+            .spacing(10)
+            .padding(5)
+            .width(Length::Fill)
+            .push(
+                // stops between blocks, mass erase can't be stopped once started
+                Button::new(Text::new("Cancel").horizontal_alignment(Horizontal::Center))
+                    .width(Length::Fill)
+                    .on_press(Message::CancelJob),
+            ),
       )
       .on_close(Message::TargetProgramminEnd)
       .max_width(300.00)
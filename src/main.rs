@@ -10,6 +10,11 @@ mod utils;
 mod usbdm;
 mod dsc_target;
 mod file_buffer;
+mod preferences;
+mod boundary_scan;
+mod svf;
+mod gdb;
+mod symbols;
 
 use std::vec;
 use iced::window::Icon;
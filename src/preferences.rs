@@ -0,0 +1,149 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use crate::errors::{Error};
+use crate::usbdm::pipeline::TransferConfig;
+
+pub const PREFERENCES_FILE : &str = "usbdm_rs_preferences.yaml";
+/// folder of app in user config directory
+pub const CONFIG_FOLDER    : &str = "usbdm_mc56f_rs";
+
+/// `Preferences` - user choices kept between sessions
+///
+/// Stored as yaml in user config directory (`%APPDATA%`, `~/Library/Application Support`, `$XDG_CONFIG_HOME`
+/// or `~/.config`), missing or broken file gives default preferences.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Serial number of USBDM to open when several probes are connected
+    pub preferred_probe : Option<String>,
+    /// JTAG clock (kHz) found by speed search, by target name
+    pub jtag_speed_khz  : BTreeMap<String, u64>,
+    /// Memory blocks of full packet size with several commands in flight, default - 32 byte blocks one at a time
+    pub pipelined_transfers : bool,
+}
+
+impl Preferences {
+
+    /// `path` - preferences file in config directory of app, working directory if home is not known
+    pub fn path() -> PathBuf {
+        let mut path = config_dir(|name| env::var_os(name))
+            .unwrap_or_else(|| env::current_dir().expect("Current directory env err."));
+        path.push(PREFERENCES_FILE);
+        path
+    }
+
+    pub fn load() -> Self {
+        match fs::read_to_string(Self::path()) {
+            Ok(yaml) => Self::from_yaml(&yaml).unwrap_or_default(),
+            Err(_e)  => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path();
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        fs::write(path, self.to_yaml()?)?;
+        Ok(())
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(yaml).map_err(|e| Error::FileParserError(e.to_string()))
+    }
+
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        serde_yaml::to_string(self).map_err(|e| Error::InternalError(e.to_string()))
+    }
+
+    /// `set_preferred_probe` - remember probe and save at once
+    pub fn set_preferred_probe(serial_number: &str) -> Result<(), Error> {
+        if serial_number.is_empty() {
+            return Err(Error::InternalError("probe without serial number can't be preferred".to_string()))
+        }
+        let mut preferences = Self::load();
+        preferences.preferred_probe = Some(serial_number.to_string());
+        preferences.save()
+    }
+
+    /// `jtag_speed` - clock found for target before, `None` - use USBDM default
+    pub fn jtag_speed(&self, target: &str) -> Option<u64> {
+        self.jtag_speed_khz.get(target).copied()
+    }
+
+    /// `set_jtag_speed` - remember clock of target and save at once
+    pub fn set_jtag_speed(target: &str, khz: u64) -> Result<(), Error> {
+        let mut preferences = Self::load();
+        preferences.jtag_speed_khz.insert(target.to_string(), khz);
+        preferences.save()
+    }
+
+    /// `set_pipelined_transfers` - remember transfer mode and save at once, used from next connect
+    pub fn set_pipelined_transfers(enabled: bool) -> Result<(), Error> {
+        let mut preferences = Self::load();
+        preferences.pipelined_transfers = enabled;
+        preferences.save()
+    }
+
+    /// `transfer_config` - memory transfer mode chosen by user
+    pub fn transfer_config(&self) -> TransferConfig {
+        match self.pipelined_transfers {
+            true  => TransferConfig::pipelined(),
+            false => TransferConfig::legacy(),
+        }
+    }
+}
+
+/// `config_dir` - app folder in user config directory of platform, `var` gives environment variables
+fn config_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let set = |name: &str| var(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        set("APPDATA")?
+    } else if cfg!(target_os = "macos") {
+        set("HOME")?.join("Library").join("Application Support")
+    } else {
+        set("XDG_CONFIG_HOME").or_else(|| Some(set("HOME")?.join(".config")))?
+    };
+    Some(base.join(CONFIG_FOLDER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_round_trip() {
+        let preferences = Preferences {
+            preferred_probe : Some("USBDM-JMxx-0001".to_string()),
+            jtag_speed_khz  : BTreeMap::from([("Mc56f8035".to_string(), 3000)]),
+            pipelined_transfers : true,
+        };
+        let yaml = preferences.to_yaml().unwrap();
+        assert_eq!(Preferences::from_yaml(&yaml).unwrap(), preferences);
+        assert_eq!(preferences.jtag_speed("Mc56f8035"), Some(3000));
+        assert_eq!(preferences.jtag_speed("Mc56f8006"), None);
+        assert_eq!(preferences.transfer_config(), TransferConfig::pipelined());
+        assert_eq!(Preferences::default().transfer_config(), TransferConfig::legacy());
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn config_dir_of_user() {
+        let env = |vars: &'static [(&'static str, &'static str)]| move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| OsString::from(value));
+        assert_eq!(config_dir(env(&[("HOME", "/home/dsc")])), Some(PathBuf::from("/home/dsc/.config/usbdm_mc56f_rs")));
+        assert_eq!(config_dir(env(&[("HOME", "/home/dsc"), ("XDG_CONFIG_HOME", "/cfg")])), Some(PathBuf::from("/cfg/usbdm_mc56f_rs")));
+        assert_eq!(config_dir(env(&[("XDG_CONFIG_HOME", "")])), None);
+        assert!(Preferences::set_preferred_probe("").is_err());
+    }
+
+    #[test]
+    fn missing_fields_are_default() {
+        assert_eq!(Preferences::from_yaml("{}").unwrap(), Preferences::default());
+        // file of older version with hand set bypass bits
+        assert_eq!(Preferences::from_yaml("jtag_chain:\n  ir_pre: 6\n").unwrap(), Preferences::default());
+    }
+}
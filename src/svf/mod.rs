@@ -0,0 +1,3 @@
+pub mod svf_file;
+pub mod xsvf_file;
+pub mod player;
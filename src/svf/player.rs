@@ -0,0 +1,313 @@
+use std::fmt;
+use crate::errors::{Error};
+use crate::usbdm::Programmer;
+use crate::usbdm::jtag_chain::{to_bits, to_bytes};
+use crate::usbdm::jtag_interpreter::TapRegister;
+use crate::usbdm::jtag_sequence::{JtagOp, JtagSequence};
+use crate::svf::svf_file::{SvfCommand, SvfProgram, SvfState, SvfStep, bits_to_hex};
+
+/// Bits per `JTAG_SHIFT_OUT_DP` / `JTAG_SHIFT_IN_OUT_DP`
+const CHUNK_BITS   : usize = 248;
+/// Chunks per sequence, data area and answer of 7 chunks fit in one USBDM command
+const CHUNKS_PER_COMMAND : usize = 7;
+/// TCK when interface frequency is chosen by connection type, kHz
+const DEFAULT_TCK_KHZ : u64 = 1000;
+
+/// `Mismatch` - captured TDO differs from expected under mask
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub line     : usize,
+    pub register : TapRegister,
+    pub expected : Vec<bool>,
+    pub captured : Vec<bool>,
+    pub mask     : Vec<bool>,
+}
+
+/// `PlayReport` - what `play` did
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PlayReport {
+    pub steps      : usize,
+    pub scans      : usize,
+    pub mismatches : Vec<Mismatch>,
+    /// stopped on first mismatch
+    pub stopped    : bool,
+    pub binary     : bool,
+    /// things file asks which player does differently
+    pub warnings   : Vec<String>,
+}
+
+impl PlayReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for PlayReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let program = SvfProgram { steps: Vec::new(), binary: self.binary };
+        writeln!(f, "{} steps, {} scans: {}{}", self.steps, self.scans,
+            if self.passed() { "PASSED" } else { "FAILED" }, if self.stopped { " (stopped)" } else { "" })?;
+        for mismatch in self.mismatches.iter() {
+            writeln!(f, "  {}: {} TDO ({}) expected ({}) mask ({})", program.location(mismatch.line),
+                if mismatch.register == TapRegister::Ir { "SIR" } else { "SDR" },
+                bits_to_hex(&mismatch.captured), bits_to_hex(&mismatch.expected), bits_to_hex(&mismatch.mask))?;
+        }
+        for warning in self.warnings.iter() {
+            writeln!(f, "  {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+fn player_error(program: &SvfProgram, step: &SvfStep, message: String) -> Error {
+    Error::SvfPlayerError(format!("{}: {}", program.location(step.line), message))
+}
+
+/// `play` - run SVF / XSVF steps on JTAG chain
+///
+/// Steps describe whole chain (HIR/TIR/HDR/TDR), so bypass bits of `Programmer::chain` are not added.
+/// Scans are JTAG sequences run by `exec_jtag_seq`, RESET state is `jtag_reset`.
+/// USBDM moves TAP only between TEST-LOGIC-RESET, RUN-TEST/IDLE and SHIFT-DR/IR, so IDLE and RESET are the
+/// end states supported. RUNTEST and XSVF waits clock TCK in RUN-TEST/IDLE, times are turned in clocks at
+/// interface frequency, waiting in TEST-LOGIC-RESET is refused.
+pub fn play(prog: &mut Programmer, program: &SvfProgram, stop_on_mismatch: bool) -> Result<PlayReport, Error> {
+    let mut report = PlayReport { binary: program.binary, ..Default::default() };
+    let tck_khz = match prog.settings.interface_frequency { 0 => DEFAULT_TCK_KHZ, khz => khz };
+    // TAP state of chain is unknown to padding of other commands after this
+    prog.raw_scan = None;
+
+    for step in program.steps.iter() {
+        report.steps += 1;
+        match &step.command {
+            SvfCommand::Scan { register, tdi, tdo, mask, end, wait_us, retries } => {
+                check_end(program, step, *end)?;
+                let mut attempt = 0;
+                loop {
+                    report.scans += 1;
+                    let captured = scan(prog, *register, tdi, tdo.is_some() && mask.iter().any(|bit| *bit))?;
+                    run_test(prog, clocks_for(*wait_us as f64 * 1.0e-6, tck_khz))?;
+                    go_to(prog, *end)?;
+                    let Some(expected) = tdo else { break };
+                    let failed = captured.iter().zip(expected).zip(mask).any(|((captured, expected), mask)| *mask && captured != expected);
+                    if !failed {
+                        break
+                    }
+                    if attempt < *retries {
+                        attempt += 1;
+                        continue
+                    }
+                    let mismatch = Mismatch { line: step.line, register: *register, expected: expected.clone(), captured, mask: mask.clone() };
+                    report.mismatches.push(mismatch);
+                    break
+                }
+                if stop_on_mismatch && !report.passed() {
+                    report.stopped = true;
+                    break
+                }
+            },
+            SvfCommand::RunTest { state, clocks, min_time, end } => {
+                if *state != SvfState::Idle {
+                    return Err(player_error(program, step, format!("TCK can't run in state {:?}, use IDLE", state)))
+                }
+                check_end(program, step, *end)?;
+                run_test(prog, (*clocks).max(clocks_for(*min_time, tck_khz)))?;
+                go_to(prog, *end)?;
+            },
+            SvfCommand::State(path) => {
+                let last = *path.last().ok_or_else(|| player_error(program, step, "STATE without state".to_string()))?;
+                check_end(program, step, last)?;
+                go_to(prog, last)?;
+            },
+            SvfCommand::Frequency(Some(hz)) if *hz < tck_khz as f64 * 1000.0 => {
+                report.warnings.push(format!("{}: file wants TCK up to {} Hz, interface runs at {} kHz", program.location(step.line), hz, tck_khz));
+            },
+            SvfCommand::Frequency(_) => {},
+            // TRST is not on USBDM JTAG connector, TEST-LOGIC-RESET does the same to TAPs
+            SvfCommand::Trst(true) => prog.jtag_reset()?,
+            SvfCommand::Trst(false) => {},
+        }
+    }
+    Ok(report)
+}
+
+/// `clocks_for` - TCK cycles lasting at least `seconds` at `tck_khz`
+fn clocks_for(seconds: f64, tck_khz: u64) -> u32 {
+    (seconds * tck_khz as f64 * 1000.0).ceil().min(u32::MAX as f64) as u32
+}
+
+/// `run_test` - `clocks` TCK in RUN-TEST/IDLE, where scans leave TAP, NOP of sequence is one clock there
+fn run_test(prog: &Programmer, clocks: u32) -> Result<(), Error> {
+    if clocks == 0 {
+        return Ok(())
+    }
+    let program = JtagSequence::new().repeat(clocks).op(JtagOp::Nop).end_repeat().build()?;
+    prog.exec_jtag_seq(program.sequence, program.answer_length)?;
+    Ok(())
+}
+
+fn check_end(program: &SvfProgram, step: &SvfStep, state: SvfState) -> Result<(), Error> {
+    match state {
+        SvfState::Reset | SvfState::Idle => Ok(()),
+        other => Err(player_error(program, step, format!("state {:?} can't be reached by USBDM, use IDLE", other))),
+    }
+}
+
+/// scans end in RUN-TEST/IDLE, RESET goes on from there
+fn go_to(prog: &mut Programmer, state: SvfState) -> Result<(), Error> {
+    match state {
+        SvfState::Reset => prog.jtag_reset(),
+        _ => Ok(()),
+    }
+}
+
+/// `scan` - shift `tdi` from RUN-TEST/IDLE to RUN-TEST/IDLE, several sequences for long registers
+fn scan(prog: &Programmer, register: TapRegister, tdi: &[bool], capture: bool) -> Result<Vec<bool>, Error> {
+    let mut captured = Vec::with_capacity(tdi.len());
+    if tdi.is_empty() {
+        return Ok(captured)
+    }
+    let chunks: Vec<&[bool]> = tdi.chunks(CHUNK_BITS).collect();
+    let count = chunks.len();
+    for (command, group) in chunks.chunks(CHUNKS_PER_COMMAND).enumerate() {
+        let mut sequence = JtagSequence::new();
+        if command == 0 {
+            sequence = sequence.op(if register == TapRegister::Ir { JtagOp::MoveIrScan } else { JtagOp::MoveDrScan });
+        }
+        sequence = sequence.op(JtagOp::SetStayShift);
+        for (index, chunk) in group.iter().enumerate() {
+            if command * CHUNKS_PER_COMMAND + index + 1 == count {
+                sequence = sequence.op(JtagOp::SetExitIdle);
+            }
+            let bits = chunk.len() as u8;
+            sequence = sequence.op(if capture { JtagOp::ShiftInOutDp(bits) } else { JtagOp::ShiftOutDp(bits) }).data(&to_bytes(chunk));
+        }
+        let program = sequence.build()?;
+        let answer = prog.exec_jtag_seq(program.sequence, program.answer_length)?;
+        if capture {
+            let mut offset = 0;
+            for chunk in group {
+                let bytes = chunk.len().div_ceil(8);
+                captured.extend(to_bits(&answer[offset..offset + bytes], chunk.len()));
+                offset += bytes;
+            }
+        }
+    }
+    Ok(captured)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svf::{svf_file, xsvf_file};
+    use crate::svf::xsvf_file::*;
+    use crate::usbdm::jtag_interpreter::{InterpreterTransport, SimpleTap, VirtualChain};
+    use crate::usbdm::virtual_dsc::{DscTap, VIRTUAL_MASTER_ID};
+
+    const CPLD_ID : u32 = 0x0960_2093;
+
+    /// CPLD nearest TDO, DSC, TAP without IDCODE nearest TDI
+    fn board() -> (InterpreterTransport<VirtualChain<DscTap>>, Programmer) {
+        let chain = VirtualChain {
+            before : vec![SimpleTap::new(6, Some(CPLD_ID))],
+            target : DscTap::new(),
+            after  : vec![SimpleTap::new(5, None)],
+        };
+        let link = InterpreterTransport::new(chain);
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        (link, prog)
+    }
+
+    const TEST_SVF: &str = "
+        ! IDCODEs of chain after reset, then CPLD in BYPASS and DSC IDCODE
+        FREQUENCY 1E6 HZ;
+        TRST OFF;
+        ENDIR IDLE;
+        ENDDR IDLE;
+        STATE RESET IDLE;
+        SDR 65 TDI (0) TDO (001F2801D09602093);
+        SIR 19 TDI (7C0BF) TDO (04041) MASK (7FFFF);
+        RUNTEST 10 TCK;
+        SDR 34 TDI (0) TDO (003E5003A);
+        STATE RESET;
+        HDR 0;
+        TDR 33 TDI (0);
+        SDR 32 TDI (0) TDO (19602093) MASK (0FFFFFFF);
+        STATE RESET;
+        SDR 32 TDI (0) TDO (09602094);
+        STATE RESET;
+        SDR 32 TDI (0) TDO (09602093);
+    ";
+
+    #[test]
+    fn svf_plays_on_mixed_chain() {
+        let (_, mut prog) = board();
+        let program = svf_file::parse(TEST_SVF).unwrap();
+        let report = play(&mut prog, &program, false).unwrap();
+        assert_eq!(report.scans, 6);
+        assert_eq!(report.mismatches.len(), 1, "{}", report);
+        let mismatch = &report.mismatches[0];
+        assert_eq!(mismatch.line, 17);
+        // MASK kept from line 15, trailer bits not compared
+        assert!(report.to_string().contains("line 17: SDR TDO (001F2801D09602093) expected (00000000009602094) mask (0000000000FFFFFFF)"), "{}", report);
+
+        let report = play(&mut prog, &program, true).unwrap();
+        assert!(report.stopped);
+        assert_eq!(report.steps, 11);
+    }
+
+    #[test]
+    fn runtest_clocks_tck() {
+        let (link, mut prog) = board();
+        prog.settings.interface_frequency = 500;
+        let program = svf_file::parse("STATE RESET;\nRUNTEST 10 TCK;\nRUNTEST 100 TCK 1.0E-3 SEC ENDSTATE RESET;\n").unwrap();
+        let report = play(&mut prog, &program, true).unwrap();
+        assert!(report.passed(), "{}", report);
+        // 1 ms at 500 kHz is more than 100 clocks
+        assert_eq!(link.with_tap(|chain| chain.before[0].idles), 10 + 500);
+
+        let program = svf_file::parse("RUNTEST RESET 10 TCK;\n").unwrap();
+        assert!(matches!(play(&mut prog, &program, true), Err(Error::SvfPlayerError(message)) if message.starts_with("line 1")));
+    }
+
+    #[test]
+    fn long_register_split_in_commands() {
+        let (_, mut prog) = board();
+        // DR chain in BYPASS is 3 bits, 2000 bits pushed through come out 3 bits later
+        let pattern: String = (0..500).map(|digit| char::from_digit(digit % 16, 16).unwrap().to_ascii_uppercase()).collect();
+        let delayed = {
+            let bits: Vec<bool> = (0..2000).map(|bit| {
+                let digit = 499 - bit / 4;
+                (digit % 16) >> (bit % 4) & 1 != 0
+            }).collect();
+            let mut out = vec![false; 3];
+            out.extend(&bits[..1997]);
+            bits_to_hex(&out)
+        };
+        let text = format!("SIR 19 TDI (7FFFF);\nSDR 2000 TDI ({}) TDO ({});\n", pattern, delayed);
+        let report = play(&mut prog, &svf_file::parse(&text).unwrap(), true).unwrap();
+        assert!(report.passed(), "{}", report);
+    }
+
+    #[test]
+    fn xsvf_retries_and_unsupported_state() {
+        let (_, mut prog) = board();
+        let mut bytes = vec![XSTATE, 0, XSTATE, 1, XREPEAT, 3, XSDRSIZE, 0, 0, 0, 65, XTDOMASK];
+        bytes.extend([0x01u8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        bytes.push(XSDRTDO);
+        bytes.extend([0u8; 9]);
+        bytes.extend(&((VIRTUAL_MASTER_ID as u128) << 32 | CPLD_ID as u128).to_be_bytes()[7..]);
+        // expected CPLD IDCODE wrong, retried and reported
+        bytes.push(XSDRTDO);
+        bytes.extend([0u8; 9]);
+        bytes.extend(&((VIRTUAL_MASTER_ID as u128) << 32 | (CPLD_ID + 1) as u128).to_be_bytes()[7..]);
+        bytes.push(XCOMPLETE);
+        let program = xsvf_file::parse(&bytes).unwrap();
+        let report = play(&mut prog, &program, false).unwrap();
+        assert_eq!(report.scans, 1 + 4);
+        assert_eq!(report.mismatches.len(), 1);
+        assert!(report.to_string().contains("offset 0x28: SDR"), "{}", report);
+
+        let program = xsvf_file::parse(&[XENDDR, 1, XSDRSIZE, 0, 0, 0, 8, XSDR, 0]).unwrap();
+        assert!(matches!(play(&mut prog, &program, false), Err(Error::SvfPlayerError(message)) if message.starts_with("offset 0x7")));
+    }
+}
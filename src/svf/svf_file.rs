@@ -0,0 +1,351 @@
+use std::fs;
+use crate::errors::{Error};
+use crate::usbdm::jtag_interpreter::TapRegister;
+
+/// `SvfState` - TAP controller states as named in SVF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SvfState {
+    Reset, Idle,
+    DrSelect, DrCapture, DrShift, DrExit1, DrPause, DrExit2, DrUpdate,
+    IrSelect, IrCapture, IrShift, IrExit1, IrPause, IrExit2, IrUpdate,
+}
+
+impl SvfState {
+
+    pub fn from_name(name: &str) -> Option<SvfState> {
+        Some(match name.to_uppercase().as_str() {
+            "RESET"     => SvfState::Reset,
+            "IDLE"      => SvfState::Idle,
+            "DRSELECT"  => SvfState::DrSelect,
+            "DRCAPTURE" => SvfState::DrCapture,
+            "DRSHIFT"   => SvfState::DrShift,
+            "DREXIT1"   => SvfState::DrExit1,
+            "DRPAUSE"   => SvfState::DrPause,
+            "DREXIT2"   => SvfState::DrExit2,
+            "DRUPDATE"  => SvfState::DrUpdate,
+            "IRSELECT"  => SvfState::IrSelect,
+            "IRCAPTURE" => SvfState::IrCapture,
+            "IRSHIFT"   => SvfState::IrShift,
+            "IREXIT1"   => SvfState::IrExit1,
+            "IRPAUSE"   => SvfState::IrPause,
+            "IREXIT2"   => SvfState::IrExit2,
+            "IRUPDATE"  => SvfState::IrUpdate,
+            _ => return None,
+        })
+    }
+
+    /// state where TAP may stay without clocks changing it
+    pub fn is_stable(&self) -> bool {
+        matches!(self, SvfState::Reset | SvfState::Idle | SvfState::DrPause | SvfState::IrPause)
+    }
+}
+
+/// `SvfCommand` - one step for player, header / trailer and sticky values of SVF already applied
+#[derive(Debug, Clone, PartialEq)]
+pub enum SvfCommand {
+    /// Shift whole chain, bit 0 first. `tdo` compared where `mask` is set
+    Scan {
+        register : TapRegister,
+        tdi      : Vec<bool>,
+        tdo      : Option<Vec<bool>>,
+        mask     : Vec<bool>,
+        end      : SvfState,
+        /// XSVF: wait in RUN-TEST/IDLE after scan (microseconds), scan and wait repeated on mismatch `retries` times
+        wait_us  : u32,
+        retries  : u8,
+    },
+    /// Stay in `state` for `clocks` TCK and at least `min_time` seconds, then go to `end`
+    RunTest { state: SvfState, clocks: u32, min_time: f64, end: SvfState },
+    /// Move through `path`, last state is stable
+    State(Vec<SvfState>),
+    /// Highest TCK frequency of file (Hz), `None` - full speed
+    Frequency(Option<f64>),
+    /// TRST driven active
+    Trst(bool),
+}
+
+/// `SvfStep` - command and where it is in file: SVF line or XSVF byte offset
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvfStep {
+    pub line    : usize,
+    pub command : SvfCommand,
+}
+
+/// `SvfProgram` - steps of SVF or XSVF file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SvfProgram {
+    pub steps  : Vec<SvfStep>,
+    /// loaded from XSVF, `line` of steps is byte offset
+    pub binary : bool,
+}
+
+impl SvfProgram {
+    /// `location` - "line 12" / "offset 0x1C" for messages
+    pub fn location(&self, line: usize) -> String {
+        if self.binary { format!("offset {:#X}", line) } else { format!("line {}", line) }
+    }
+}
+
+/// `bits_to_hex` - bits (bit 0 first shifted) as SVF hex, first digit most significant
+pub fn bits_to_hex(bits: &[bool]) -> String {
+    let digits = bits.len().div_ceil(4).max(1);
+    (0..digits).rev().map(|digit| {
+        let value = (0..4).fold(0u32, |value, bit| value | (*bits.get(digit * 4 + bit).unwrap_or(&false) as u32) << bit);
+        char::from_digit(value, 16).unwrap().to_ascii_uppercase()
+    }).collect()
+}
+
+fn svf_error(line: usize, message: String) -> Error {
+    Error::FileParserError(format!("SVF line {}: {}", line, message))
+}
+
+/// `hex_to_bits` - SVF hex of `length` bits, last digit shifted first
+fn hex_to_bits(hex: &str, length: usize) -> Result<Vec<bool>, String> {
+    let mut bits = Vec::with_capacity(length);
+    for c in hex.chars().rev() {
+        let value = c.to_digit(16).ok_or_else(|| format!("`{}` is not hex digit", c))?;
+        bits.extend((0..4).map(|bit| (value >> bit) & 1 != 0));
+    }
+    if bits.iter().skip(length).any(|bit| *bit) {
+        return Err(format!("value ({}) longer than {} bits", hex, length))
+    }
+    bits.resize(length, false);
+    Ok(bits)
+}
+
+/// sticky values of SIR / SDR / HIR / TIR / HDR / TDR
+#[derive(Debug, Clone, Default)]
+struct ScanValues {
+    length : usize,
+    tdi    : Vec<bool>,
+    tdo    : Option<Vec<bool>>,
+    mask   : Vec<bool>,
+}
+
+impl ScanValues {
+
+    /// `update` - `LENGTH [TDI (..)] [TDO (..)] [MASK (..)] [SMASK (..)]`
+    fn update(&mut self, words: &[String]) -> Result<(), String> {
+        let length = words.first().ok_or("no length")?.parse::<usize>().map_err(|_| format!("length `{}`", words[0]))?;
+        let mut tdi = None;
+        let mut tdo = None;
+        let mut mask = None;
+        let mut rest = words[1..].iter();
+        while let Some(name) = rest.next() {
+            let value = rest.next().and_then(|value| value.strip_prefix('(')).and_then(|value| value.strip_suffix(')'))
+                .ok_or_else(|| format!("{} without (value)", name))?;
+            let bits = hex_to_bits(value, length)?;
+            match name.to_uppercase().as_str() {
+                "TDI"   => tdi = Some(bits),
+                "TDO"   => tdo = Some(bits),
+                "MASK"  => mask = Some(bits),
+                "SMASK" => {},
+                other   => return Err(format!("unknown scan parameter {}", other)),
+            }
+        }
+        if length != self.length {
+            if tdi.is_none() && length > 0 {
+                return Err(format!("length changed to {} without TDI", length))
+            }
+            self.mask = vec![true; length];
+            self.length = length;
+        }
+        if let Some(tdi) = tdi {
+            self.tdi = tdi;
+        }
+        if let Some(mask) = mask {
+            self.mask = mask;
+        }
+        self.tdo = tdo;
+        Ok(())
+    }
+}
+
+/// `statements` - `;` terminated statements with line of first word, comments (`!`, `//`) removed
+fn statements(text: &str) -> Vec<(usize, String)> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    for (number, line) in text.lines().enumerate() {
+        let line = match (line.find('!'), line.find("//")) {
+            (Some(a), Some(b)) => &line[..a.min(b)],
+            (Some(a), None) | (None, Some(a)) => &line[..a],
+            (None, None) => line,
+        };
+        for part in line.split_inclusive(';') {
+            if current.trim().is_empty() {
+                start = number + 1;
+            }
+            match part.strip_suffix(';') {
+                Some(part) => {
+                    current.push_str(part);
+                    statements.push((start, current.trim().to_string()));
+                    current.clear();
+                },
+                None => { current.push_str(part); current.push(' ') },
+            }
+        }
+    }
+    statements
+}
+
+/// `words` - split on whitespace, `( .. )` kept as one word without spaces
+fn words(statement: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_value = false;
+    for c in statement.chars() {
+        match c {
+            '(' => { if !current.is_empty() { words.push(std::mem::take(&mut current)) }; in_value = true; current.push(c) },
+            ')' => { current.push(c); in_value = false; words.push(std::mem::take(&mut current)) },
+            c if c.is_whitespace() => if !in_value && !current.is_empty() { words.push(std::mem::take(&mut current)) },
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn number(word: Option<&String>) -> Result<f64, String> {
+    let word = word.ok_or("number missing")?;
+    word.parse::<f64>().map_err(|_| format!("`{}` is not a number", word))
+}
+
+fn state(word: Option<&String>) -> Result<SvfState, String> {
+    let word = word.ok_or("state missing")?;
+    SvfState::from_name(word).ok_or_else(|| format!("unknown state {}", word))
+}
+
+/// `parse` - SVF text to steps
+pub fn parse(text: &str) -> Result<SvfProgram, Error> {
+    let mut program = SvfProgram::default();
+    let (mut hir, mut tir, mut hdr, mut tdr) = (ScanValues::default(), ScanValues::default(), ScanValues::default(), ScanValues::default());
+    let (mut sir, mut sdr) = (ScanValues::default(), ScanValues::default());
+    let (mut end_ir, mut end_dr, mut run_state, mut run_end) = (SvfState::Idle, SvfState::Idle, SvfState::Idle, SvfState::Idle);
+
+    for (line, statement) in statements(text) {
+        let words = words(&statement);
+        let Some(keyword) = words.first() else { continue };
+        let keyword = keyword.to_uppercase();
+        let args = &words[1..];
+        let command = (|| -> Result<Option<SvfCommand>, String> {
+            Ok(match keyword.as_str() {
+                "HIR" => { hir.update(args)?; None },
+                "TIR" => { tir.update(args)?; None },
+                "HDR" => { hdr.update(args)?; None },
+                "TDR" => { tdr.update(args)?; None },
+                "SIR" | "SDR" => {
+                    let (register, header, values, trailer, end) = if keyword == "SIR" {
+                        sir.update(args)?;
+                        (TapRegister::Ir, &hir, &sir, &tir, end_ir)
+                    } else {
+                        sdr.update(args)?;
+                        (TapRegister::Dr, &hdr, &sdr, &tdr, end_dr)
+                    };
+                    // header shifted first, its bits end in TAPs nearest TDO
+                    let parts = [header, values, trailer];
+                    let tdi = parts.iter().flat_map(|part| part.tdi.iter().copied()).collect();
+                    let compare = parts.iter().any(|part| part.tdo.is_some());
+                    let tdo = compare.then(|| parts.iter().flat_map(|part| part.tdo.clone().unwrap_or_else(|| vec![false; part.length])).collect());
+                    let mask = parts.iter().flat_map(|part| match part.tdo {
+                        Some(_) => part.mask.clone(),
+                        None    => vec![false; part.length],
+                    }).collect();
+                    Some(SvfCommand::Scan { register, tdi, tdo, mask, end, wait_us: 0, retries: 0 })
+                },
+                "ENDIR" => { end_ir = state(args.first())?; None },
+                "ENDDR" => { end_dr = state(args.first())?; None },
+                "STATE" => Some(SvfCommand::State(args.iter().map(|word| state(Some(word))).collect::<Result<Vec<SvfState>, String>>()?)),
+                "FREQUENCY" => Some(SvfCommand::Frequency(args.first().map(|_| number(args.first())).transpose()?)),
+                "TRST" => match args.first().map(|word| word.to_uppercase()).as_deref() {
+                    Some("ON")                           => Some(SvfCommand::Trst(true)),
+                    Some("OFF") | Some("Z") | Some("ABSENT") => Some(SvfCommand::Trst(false)),
+                    other => return Err(format!("TRST {:?}", other)),
+                },
+                "RUNTEST" => {
+                    // RUNTEST [state] [count TCK|SCK] [time SEC [MAXIMUM time SEC]] [ENDSTATE state]
+                    let mut rest = args.iter().peekable();
+                    if let Some(state) = rest.peek().and_then(|word| SvfState::from_name(word)) {
+                        run_state = state;
+                        run_end = state;
+                        rest.next();
+                    }
+                    let (mut clocks, mut min_time) = (0u32, 0.0);
+                    while let Some(word) = rest.next() {
+                        match word.to_uppercase().as_str() {
+                            "MAXIMUM"  => { number(rest.next())?; rest.next(); },
+                            "ENDSTATE" => run_end = state(rest.next())?,
+                            _ => {
+                                let value = number(Some(word))?;
+                                match rest.next().map(|unit| unit.to_uppercase()).as_deref() {
+                                    Some("TCK") => clocks = value as u32,
+                                    // system clock is not on JTAG connector
+                                    Some("SCK") => {},
+                                    Some("SEC") => min_time = value,
+                                    other => return Err(format!("RUNTEST unit {:?}", other)),
+                                }
+                            },
+                        }
+                    }
+                    Some(SvfCommand::RunTest { state: run_state, clocks, min_time, end: run_end })
+                },
+                "PIO" | "PIOMAP" => return Err(format!("{} (parallel vectors) not supported", keyword)),
+                other => return Err(format!("unknown command {}", other)),
+            })
+        })().map_err(|message| svf_error(line, message))?;
+
+        if let Some(command) = command {
+            match &command {
+                SvfCommand::State(path) if !path.last().is_some_and(|state| state.is_stable()) =>
+                    return Err(svf_error(line, "STATE must end in stable state".to_string())),
+                _ => {},
+            }
+            program.steps.push(SvfStep { line, command });
+        }
+    }
+    Ok(program)
+}
+
+pub fn load(path: &str) -> Result<SvfProgram, Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::FileParserError(format!("{}: {}", path, e)))?;
+    parse(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sticky_values_and_header() {
+        let program = parse("
+            ! comment
+            HIR 2 TDI (3);  TIR 0;
+            SIR 4 TDI (A) // trailing comment
+                TDO (1) MASK (3);
+            SIR 4 TDI (5);
+            RUNTEST 100 TCK 1.0E-3 SEC ENDSTATE RESET;
+            STATE RESET IDLE;
+        ").unwrap();
+        let bits = |value: u32, length: usize| (0..length).map(|bit| (value >> bit) & 1 != 0).collect::<Vec<bool>>();
+        assert_eq!(program.steps[0], SvfStep { line: 4, command: SvfCommand::Scan {
+            register: TapRegister::Ir, tdi: bits(0x2B, 6), tdo: Some(bits(0x04, 6)), mask: bits(0x0C, 6),
+            end: SvfState::Idle, wait_us: 0, retries: 0 } });
+        // TDI kept when length same, TDO is not
+        assert_eq!(program.steps[1].command, SvfCommand::Scan {
+            register: TapRegister::Ir, tdi: bits(0x17, 6), tdo: None, mask: vec![false; 6],
+            end: SvfState::Idle, wait_us: 0, retries: 0 });
+        assert_eq!(program.steps[2].command, SvfCommand::RunTest { state: SvfState::Idle, clocks: 100, min_time: 1.0e-3, end: SvfState::Reset });
+        assert_eq!(program.steps[3], SvfStep { line: 8, command: SvfCommand::State(vec![SvfState::Reset, SvfState::Idle]) });
+        assert_eq!(bits_to_hex(&bits(0x1F2801D, 32)), "01F2801D");
+    }
+
+    #[test]
+    fn errors_name_line() {
+        let error = parse("SIR 8 TDI (00);\nSDR 8 TDI (1FF);").unwrap_err();
+        assert!(matches!(error, Error::FileParserError(message) if message.starts_with("SVF line 2:")));
+        assert!(parse("SIR 8 TDI (00);\nSIR 6;").is_err());
+        assert!(parse("STATE DRSHIFT;").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use crate::errors::{Error};
+use crate::usbdm::jtag_chain::to_bits;
+use crate::usbdm::jtag_interpreter::TapRegister;
+use crate::svf::svf_file::{SvfCommand, SvfProgram, SvfState, SvfStep};
+
+// XSVF instructions, Xilinx XAPP503
+pub const XCOMPLETE    : u8 = 0x00;
+pub const XTDOMASK     : u8 = 0x01;
+pub const XSIR         : u8 = 0x02;
+pub const XSDR         : u8 = 0x03;
+pub const XRUNTEST     : u8 = 0x04;
+pub const XREPEAT      : u8 = 0x07;
+pub const XSDRSIZE     : u8 = 0x08;
+pub const XSDRTDO      : u8 = 0x09;
+pub const XSETSDRMASKS : u8 = 0x0A;
+pub const XSDRINC      : u8 = 0x0B;
+pub const XSDRB        : u8 = 0x0C;
+pub const XSDRC        : u8 = 0x0D;
+pub const XSDRE        : u8 = 0x0E;
+pub const XSDRTDOB     : u8 = 0x0F;
+pub const XSDRTDOC     : u8 = 0x10;
+pub const XSDRTDOE     : u8 = 0x11;
+pub const XSTATE       : u8 = 0x12;
+pub const XENDIR       : u8 = 0x13;
+pub const XENDDR       : u8 = 0x14;
+pub const XSIR2        : u8 = 0x15;
+pub const XCOMMENT     : u8 = 0x16;
+pub const XWAIT        : u8 = 0x17;
+
+/// XREPEAT before any in file
+const DEFAULT_REPEAT : u8 = 32;
+
+/// XSTATE numbering
+const XSVF_STATES : [SvfState; 16] = [
+    SvfState::Reset, SvfState::Idle,
+    SvfState::DrSelect, SvfState::DrCapture, SvfState::DrShift, SvfState::DrExit1, SvfState::DrPause, SvfState::DrExit2, SvfState::DrUpdate,
+    SvfState::IrSelect, SvfState::IrCapture, SvfState::IrShift, SvfState::IrExit1, SvfState::IrPause, SvfState::IrExit2, SvfState::IrUpdate,
+];
+
+fn xsvf_error(offset: usize, message: String) -> Error {
+    Error::FileParserError(format!("XSVF offset {:#X}: {}", offset, message))
+}
+
+struct Reader<'a> {
+    bytes    : &'a [u8],
+    position : usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.position..self.position + count).ok_or("file ends inside instruction")?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// `bits` - `count` bits, right justified, MSB byte first
+    fn bits(&mut self, count: usize) -> Result<Vec<bool>, String> {
+        Ok(to_bits(self.take(count.div_ceil(8))?, count))
+    }
+
+    fn state(&mut self) -> Result<SvfState, String> {
+        let state = self.u8()?;
+        XSVF_STATES.get(state as usize).copied().ok_or_else(|| format!("state {}", state))
+    }
+}
+
+/// `parse` - XSVF to the same steps as SVF, `line` of step is offset of instruction
+///
+/// XSDR.. scans end in XENDDR state, wait XRUNTEST there and are repeated XREPEAT times on mismatch.
+/// Scans split in parts (XSDRB/C/E, XSDRTDOB/C/E) and XSDRINC are not supported.
+pub fn parse(bytes: &[u8]) -> Result<SvfProgram, Error> {
+    let mut program = SvfProgram { steps: Vec::new(), binary: true };
+    let mut reader = Reader { bytes, position: 0 };
+    let mut sdr_size = 0usize;
+    let mut mask: Vec<bool> = Vec::new();
+    let mut expected: Vec<bool> = Vec::new();
+    let mut repeat = DEFAULT_REPEAT;
+    let mut run_test = 0u32;
+    let (mut end_ir, mut end_dr) = (SvfState::Idle, SvfState::Idle);
+
+    while reader.position < bytes.len() {
+        let offset = reader.position;
+        let command = (|| -> Result<Option<SvfCommand>, String> {
+            let opcode = reader.u8()?;
+            Ok(match opcode {
+                XCOMPLETE => {
+                    reader.position = bytes.len();
+                    None
+                },
+                XTDOMASK => { mask = reader.bits(sdr_size)?; None },
+                XSIR | XSIR2 => {
+                    let length = if opcode == XSIR { reader.u8()? as usize } else { reader.u16()? as usize };
+                    let tdi = reader.bits(length)?;
+                    Some(SvfCommand::Scan { register: TapRegister::Ir, tdi, tdo: None, mask: vec![false; length], end: end_ir, wait_us: run_test, retries: 0 })
+                },
+                XSDR | XSDRTDO => {
+                    let tdi = reader.bits(sdr_size)?;
+                    if opcode == XSDRTDO {
+                        expected = reader.bits(sdr_size)?;
+                    }
+                    if mask.len() != sdr_size || expected.len() != sdr_size {
+                        expected.resize(sdr_size, false);
+                        mask.resize(sdr_size, false);
+                    }
+                    Some(SvfCommand::Scan { register: TapRegister::Dr, tdi, tdo: Some(expected.clone()), mask: mask.clone(), end: end_dr, wait_us: run_test, retries: repeat })
+                },
+                XRUNTEST => { run_test = reader.u32()?; None },
+                XREPEAT  => { repeat = reader.u8()?; None },
+                XSDRSIZE => { sdr_size = reader.u32()? as usize; None },
+                XSTATE   => Some(SvfCommand::State(vec![reader.state()?])),
+                XENDIR | XENDDR => {
+                    let state = match reader.u8()? {
+                        0 => SvfState::Idle,
+                        1 => if opcode == XENDIR { SvfState::IrPause } else { SvfState::DrPause },
+                        other => return Err(format!("end state {}", other)),
+                    };
+                    if opcode == XENDIR { end_ir = state } else { end_dr = state }
+                    None
+                },
+                XCOMMENT => {
+                    while reader.u8()? != 0 {}
+                    None
+                },
+                XWAIT => {
+                    let state = reader.state()?;
+                    let end = reader.state()?;
+                    let time = reader.u32()?;
+                    Some(SvfCommand::RunTest { state, clocks: 0, min_time: time as f64 * 1.0e-6, end })
+                },
+                XSETSDRMASKS | XSDRINC | XSDRB | XSDRC | XSDRE | XSDRTDOB | XSDRTDOC | XSDRTDOE =>
+                    return Err(format!("instruction {:#04X} not supported", opcode)),
+                other => return Err(format!("unknown instruction {:#04X}", other)),
+            })
+        })().map_err(|message| xsvf_error(offset, message))?;
+
+        if let Some(command) = command {
+            program.steps.push(SvfStep { line: offset, command });
+        }
+    }
+    Ok(program)
+}
+
+pub fn load(path: &str) -> Result<SvfProgram, Error> {
+    let bytes = fs::read(path).map_err(|e| Error::FileParserError(format!("{}: {}", path, e)))?;
+    parse(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions_decoded() {
+        let bytes = [
+            XSTATE, 0,
+            XREPEAT, 2,
+            XRUNTEST, 0, 0, 0x03, 0xE8,
+            XSIR, 6, 0x3F,
+            XCOMMENT, b'h', b'i', 0,
+            XSDRSIZE, 0, 0, 0, 12,
+            XTDOMASK, 0x0F, 0xFF,
+            XSDRTDO, 0x01, 0x23, 0x0A, 0xBC,
+            XCOMPLETE,
+            XSIR, 1, 0,
+        ];
+        let program = parse(&bytes).unwrap();
+        assert!(program.binary);
+        assert_eq!(program.steps.len(), 3);
+        assert_eq!(program.steps[0], SvfStep { line: 0, command: SvfCommand::State(vec![SvfState::Reset]) });
+        let bits = |value: u32, length: usize| (0..length).map(|bit| (value >> bit) & 1 != 0).collect::<Vec<bool>>();
+        assert_eq!(program.steps[1].command, SvfCommand::Scan {
+            register: TapRegister::Ir, tdi: bits(0x3F, 6), tdo: None, mask: vec![false; 6], end: SvfState::Idle, wait_us: 1000, retries: 0 });
+        assert_eq!(program.steps[2], SvfStep { line: 0x18, command: SvfCommand::Scan {
+            register: TapRegister::Dr, tdi: bits(0x123, 12), tdo: Some(bits(0xABC, 12)), mask: bits(0xFFF, 12),
+            end: SvfState::Idle, wait_us: 1000, retries: 2 } });
+        assert_eq!(program.location(0x18), "offset 0x18");
+
+        let error = parse(&[XSTATE, 1, XSDRB]).unwrap_err();
+        assert!(matches!(error, Error::FileParserError(message) if message.starts_with("XSVF offset 0x2:")));
+    }
+}
@@ -0,0 +1,627 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::errors::{Error};
+use super::elf::ElfReader;
+
+// DWARF tags, attributes and forms read here
+//--------------------------------------------------------------------
+const DW_TAG_ARRAY_TYPE     : u64 = 0x01;
+const DW_TAG_CLASS_TYPE     : u64 = 0x02;
+const DW_TAG_ENUMERATION    : u64 = 0x04;
+const DW_TAG_MEMBER         : u64 = 0x0D;
+const DW_TAG_POINTER_TYPE   : u64 = 0x0F;
+const DW_TAG_REFERENCE_TYPE : u64 = 0x10;
+const DW_TAG_STRUCTURE_TYPE : u64 = 0x13;
+const DW_TAG_TYPEDEF        : u64 = 0x16;
+const DW_TAG_UNION_TYPE     : u64 = 0x17;
+const DW_TAG_SUBRANGE_TYPE  : u64 = 0x21;
+const DW_TAG_BASE_TYPE      : u64 = 0x24;
+const DW_TAG_CONST_TYPE     : u64 = 0x26;
+const DW_TAG_VARIABLE       : u64 = 0x34;
+const DW_TAG_VOLATILE_TYPE  : u64 = 0x35;
+
+const DW_AT_LOCATION        : u64 = 0x02;
+const DW_AT_NAME            : u64 = 0x03;
+const DW_AT_BYTE_SIZE       : u64 = 0x0B;
+const DW_AT_LOWER_BOUND     : u64 = 0x22;
+const DW_AT_UPPER_BOUND     : u64 = 0x2F;
+const DW_AT_ABSTRACT_ORIGIN : u64 = 0x31;
+const DW_AT_COUNT           : u64 = 0x37;
+const DW_AT_MEMBER_LOCATION : u64 = 0x38;
+const DW_AT_ENCODING        : u64 = 0x3E;
+const DW_AT_SPECIFICATION   : u64 = 0x47;
+const DW_AT_TYPE            : u64 = 0x49;
+
+const DW_ATE_BOOLEAN        : u64 = 0x02;
+const DW_ATE_FLOAT          : u64 = 0x04;
+const DW_ATE_SIGNED         : u64 = 0x05;
+const DW_ATE_SIGNED_CHAR    : u64 = 0x06;
+const DW_ATE_SIGNED_FIXED   : u64 = 0x0D;
+
+const DW_OP_ADDR            : u8  = 0x03;
+const DW_OP_PLUS_UCONST     : u8  = 0x23;
+
+/// nesting of typedefs, arrays and structures followed when type is resolved
+const MAX_TYPE_DEPTH        : usize = 8;
+
+/// `TypeKind` - how value of variable is shown
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    Signed,
+    Unsigned,
+    /// signed fixed point, `Frac16` is Q15 and `Frac32` Q31
+    Fractional,
+    Float,
+    Boolean,
+    Enumeration,
+    Pointer,
+    /// number of elements
+    Array(u32),
+    Structure(Vec<Member>),
+    /// no type information or a form not resolved
+    Void,
+}
+
+/// `VariableType` - type of variable, `size` in bytes as given by compiler
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableType {
+    pub name : String,
+    pub size : u32,
+    pub kind : TypeKind,
+}
+
+impl VariableType {
+    fn void() -> Self {
+        VariableType { name: "void".to_string(), size: 0, kind: TypeKind::Void }
+    }
+}
+
+/// `Member` - field of structure or union, `offset` in bytes from its start
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub name   : String,
+    pub offset : u32,
+    pub ty     : VariableType,
+}
+
+/// `Variable` - variable at fixed X memory address, globals and function statics
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name    : String,
+    /// address as given by `DW_OP_addr`
+    pub address : u32,
+    pub ty      : VariableType,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} at X:${:04X}", self.ty.name, self.name, self.address)
+    }
+}
+
+/// `DwarfSections` - debug sections of ELF file, `strings` may be empty
+pub struct DwarfSections<'a> {
+    pub info       : &'a [u8],
+    pub abbrev     : &'a [u8],
+    pub strings    : &'a [u8],
+    pub big_endian : bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(u64),
+    Signed(i64),
+    Text(String),
+    Block(Vec<u8>),
+    /// offset of entry in `.debug_info`
+    Reference(u64),
+}
+
+struct Abbrev {
+    tag        : u64,
+    children   : bool,
+    /// attribute, form
+    attributes : Vec<(u64, u64)>,
+}
+
+/// `Die` - debugging information entry
+struct Die {
+    tag          : u64,
+    address_size : u8,
+    attributes   : Vec<(u64, Value)>,
+    children     : Vec<usize>,
+}
+
+impl Die {
+    fn value(&self, attribute: u64) -> Option<&Value> {
+        self.attributes.iter().find(|(at, _)| *at == attribute).map(|(_, value)| value)
+    }
+
+    fn number(&self, attribute: u64) -> Option<u64> {
+        match self.value(attribute)? {
+            Value::Number(number) => Some(*number),
+            Value::Signed(number) => u64::try_from(*number).ok(),
+            _ => None,
+        }
+    }
+
+    fn text(&self, attribute: u64) -> Option<&str> {
+        match self.value(attribute)? {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn reference(&self, attribute: u64) -> Option<u64> {
+        match self.value(attribute)? {
+            Value::Reference(offset) => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+/// `Cursor` - sequential reader of DWARF section
+struct Cursor<'a> {
+    reader   : ElfReader<'a>,
+    position : usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8], big_endian: bool, position: usize) -> Self {
+        Cursor { reader: ElfReader { bytes, big_endian }, position }
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        self.position += 1;
+        self.reader.u8(self.position - 1)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        self.position += 2;
+        self.reader.u16(self.position - 2)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        self.position += 4;
+        self.reader.u32(self.position - 4)
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let (first, second) = (self.u32()? as u64, self.u32()? as u64);
+        Ok(match self.reader.big_endian {
+            true  => first << 32 | second,
+            false => second << 32 | first,
+        })
+    }
+
+    /// `sized` - unsigned number of 1, 2, 4 or 8 bytes
+    fn sized(&mut self, size: u8) -> Result<u64, Error> {
+        match size {
+            1 => Ok(self.u8()? as u64),
+            2 => Ok(self.u16()? as u64),
+            4 => Ok(self.u32()? as u64),
+            8 => self.u64(),
+            _ => Err(Error::SymbolError(format!("DWARF address size {} not supported", size))),
+        }
+    }
+
+    fn uleb(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value)
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Result<i64, Error> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1i64 << shift;
+                }
+                return Ok(value)
+            }
+        }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<Vec<u8>, Error> {
+        let bytes = self.reader.slice(self.position, length)?.to_vec();
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let text = self.reader.string(self.position)?;
+        // text is checked to end with zero
+        self.position += self.reader.bytes[self.position..].iter().position(|byte| *byte == 0).unwrap() + 1;
+        Ok(text)
+    }
+}
+
+/// `Unit` - header fields of compilation unit needed to read its entries
+struct Unit {
+    offset       : u64,
+    version      : u16,
+    address_size : u8,
+}
+
+/// `DebugInfo` - all entries of `.debug_info`
+struct DebugInfo {
+    big_endian : bool,
+    dies       : Vec<Die>,
+    offsets    : HashMap<u64, usize>,
+}
+
+fn abbreviations(sections: &DwarfSections, offset: usize) -> Result<HashMap<u64, Abbrev>, Error> {
+    let mut cursor = Cursor::new(sections.abbrev, sections.big_endian, offset);
+    let mut table = HashMap::new();
+    loop {
+        let code = cursor.uleb()?;
+        if code == 0 {
+            return Ok(table)
+        }
+        let tag = cursor.uleb()?;
+        let children = cursor.u8()? != 0;
+        let mut attributes = Vec::new();
+        loop {
+            let (attribute, form) = (cursor.uleb()?, cursor.uleb()?);
+            if attribute == 0 && form == 0 {
+                break
+            }
+            attributes.push((attribute, form));
+        }
+        table.insert(code, Abbrev { tag, children, attributes });
+    }
+}
+
+fn read_value(cursor: &mut Cursor, form: u64, unit: &Unit, sections: &DwarfSections) -> Result<Value, Error> {
+    let value = match form {
+        0x01 => Value::Number(cursor.sized(unit.address_size)?),
+        0x03 => { let length = cursor.u16()? as usize; Value::Block(cursor.bytes(length)?) },
+        0x04 => { let length = cursor.u32()? as usize; Value::Block(cursor.bytes(length)?) },
+        0x05 => Value::Number(cursor.u16()? as u64),
+        0x06 | 0x17 => Value::Number(cursor.u32()? as u64),
+        0x07 | 0x20 => Value::Number(cursor.u64()?),
+        0x08 => Value::Text(cursor.string()?),
+        0x09 | 0x18 => { let length = cursor.uleb()? as usize; Value::Block(cursor.bytes(length)?) },
+        0x0A => { let length = cursor.u8()? as usize; Value::Block(cursor.bytes(length)?) },
+        0x0B | 0x0C => Value::Number(cursor.u8()? as u64),
+        0x0D => Value::Signed(cursor.sleb()?),
+        0x0E => {
+            let offset = cursor.u32()? as usize;
+            Value::Text(ElfReader { bytes: sections.strings, big_endian: sections.big_endian }.string(offset)?)
+        },
+        0x0F => Value::Number(cursor.uleb()?),
+        // DWARF 2 gives section offsets in address size
+        0x10 => Value::Reference(match unit.version {
+            2 => cursor.sized(unit.address_size)?,
+            _ => cursor.u32()? as u64,
+        }),
+        0x11 => Value::Reference(unit.offset + cursor.u8()? as u64),
+        0x12 => Value::Reference(unit.offset + cursor.u16()? as u64),
+        0x13 => Value::Reference(unit.offset + cursor.u32()? as u64),
+        0x14 => Value::Reference(unit.offset + cursor.u64()?),
+        0x15 => Value::Reference(unit.offset + cursor.uleb()?),
+        0x16 => {
+            let form = cursor.uleb()?;
+            return read_value(cursor, form, unit, sections)
+        },
+        0x19 => Value::Number(1),
+        _ => return Err(Error::SymbolError(format!("DWARF form {:#X} not supported", form))),
+    };
+    Ok(value)
+}
+
+impl DebugInfo {
+
+    /// `parse` - entries of all compilation units, DWARF versions 2 to 4 in 32-bit format
+    fn parse(sections: &DwarfSections) -> Result<Self, Error> {
+        let mut info = DebugInfo { big_endian: sections.big_endian, dies: Vec::new(), offsets: HashMap::new() };
+        let mut cursor = Cursor::new(sections.info, sections.big_endian, 0);
+        while cursor.position < sections.info.len() {
+            let offset = cursor.position as u64;
+            let length = cursor.u32()?;
+            if length >= 0xFFFF_FFF0 {
+                return Err(Error::SymbolError("64-bit DWARF not supported".to_string()))
+            }
+            let end = cursor.position + length as usize;
+            let version = cursor.u16()?;
+            if !(2..=4).contains(&version) {
+                return Err(Error::SymbolError(format!("DWARF version {} not supported", version)))
+            }
+            let abbrev_offset = cursor.u32()? as usize;
+            let unit = Unit { offset, version, address_size: cursor.u8()? };
+            let abbrevs = abbreviations(sections, abbrev_offset)?;
+
+            let mut parents: Vec<usize> = Vec::new();
+            while cursor.position < end {
+                let die_offset = cursor.position as u64;
+                let code = cursor.uleb()?;
+                if code == 0 {
+                    parents.pop();
+                    continue
+                }
+                let abbrev = abbrevs.get(&code)
+                    .ok_or_else(|| Error::SymbolError(format!("abbreviation {} at {:#X} not defined", code, die_offset)))?;
+                let mut attributes = Vec::with_capacity(abbrev.attributes.len());
+                for (attribute, form) in abbrev.attributes.iter() {
+                    attributes.push((*attribute, read_value(&mut cursor, *form, &unit, sections)?));
+                }
+                let index = info.dies.len();
+                if let Some(parent) = parents.last() {
+                    info.dies[*parent].children.push(index);
+                }
+                info.dies.push(Die { tag: abbrev.tag, address_size: unit.address_size, attributes, children: Vec::new() });
+                info.offsets.insert(die_offset, index);
+                if abbrev.children {
+                    parents.push(index);
+                }
+            }
+            cursor.position = end;
+        }
+        Ok(info)
+    }
+
+    fn die(&self, offset: Option<u64>) -> Option<&Die> {
+        self.offsets.get(&offset?).map(|index| &self.dies[*index])
+    }
+
+    /// `declaration` - entry holding name and type of `die`, `DW_AT_specification` of definition
+    fn declaration<'d>(&'d self, die: &'d Die) -> &'d Die {
+        [DW_AT_SPECIFICATION, DW_AT_ABSTRACT_ORIGIN].iter()
+            .find_map(|attribute| self.die(die.reference(*attribute)))
+            .unwrap_or(die)
+    }
+
+    /// `type_name` - name of type at `offset` without resolving its members, for pointer targets
+    fn type_name(&self, offset: Option<u64>, depth: usize) -> String {
+        let Some(die) = self.die(offset) else { return "void".to_string() };
+        if depth > MAX_TYPE_DEPTH {
+            return "?".to_string()
+        }
+        let target = || self.type_name(die.reference(DW_AT_TYPE), depth + 1);
+        match (die.tag, die.text(DW_AT_NAME)) {
+            (DW_TAG_POINTER_TYPE | DW_TAG_REFERENCE_TYPE, _) => format!("{}*", target()),
+            (DW_TAG_CONST_TYPE, _)    => format!("const {}", target()),
+            (DW_TAG_VOLATILE_TYPE, _) => format!("volatile {}", target()),
+            (DW_TAG_ARRAY_TYPE, _)    => format!("{}[]", target()),
+            (_, Some(name))           => name.to_string(),
+            (DW_TAG_STRUCTURE_TYPE | DW_TAG_CLASS_TYPE, None) => "struct".to_string(),
+            (DW_TAG_UNION_TYPE, None) => "union".to_string(),
+            _ => "?".to_string(),
+        }
+    }
+
+    /// `element_count` - elements of array, product of its subranges
+    fn element_count(&self, array: &Die) -> u32 {
+        array.children.iter().map(|index| &self.dies[*index])
+            .filter(|die| die.tag == DW_TAG_SUBRANGE_TYPE)
+            .map(|range| match (range.number(DW_AT_COUNT), range.number(DW_AT_UPPER_BOUND)) {
+                (Some(count), _) => count as u32,
+                (None, Some(upper)) => (upper + 1).saturating_sub(range.number(DW_AT_LOWER_BOUND).unwrap_or(0)) as u32,
+                _ => 0,
+            })
+            .product()
+    }
+
+    fn member_offset(die: &Die) -> u32 {
+        match die.value(DW_AT_MEMBER_LOCATION) {
+            Some(Value::Number(offset)) => *offset as u32,
+            Some(Value::Block(expression)) if expression.first() == Some(&DW_OP_PLUS_UCONST) => {
+                Cursor::new(expression, false, 1).uleb().unwrap_or(0) as u32
+            },
+            _ => 0,
+        }
+    }
+
+    /// `variable_type` - type at `offset` with members of structures
+    fn variable_type(&self, offset: Option<u64>, depth: usize) -> VariableType {
+        let Some(die) = self.die(offset) else { return VariableType::void() };
+        if depth > MAX_TYPE_DEPTH {
+            return VariableType::void()
+        }
+        let name = die.text(DW_AT_NAME).map(str::to_string);
+        let size = die.number(DW_AT_BYTE_SIZE).unwrap_or(0) as u32;
+        let target = die.reference(DW_AT_TYPE);
+        match die.tag {
+            DW_TAG_BASE_TYPE => {
+                let kind = match die.number(DW_AT_ENCODING).unwrap_or(0) {
+                    DW_ATE_BOOLEAN                      => TypeKind::Boolean,
+                    DW_ATE_FLOAT                        => TypeKind::Float,
+                    DW_ATE_SIGNED | DW_ATE_SIGNED_CHAR  => TypeKind::Signed,
+                    DW_ATE_SIGNED_FIXED                 => TypeKind::Fractional,
+                    _                                   => TypeKind::Unsigned,
+                };
+                VariableType { name: name.unwrap_or_default(), size, kind }
+            },
+            DW_TAG_POINTER_TYPE | DW_TAG_REFERENCE_TYPE => VariableType {
+                name : self.type_name(offset, depth),
+                size : match size { 0 => die.address_size as u32, size => size },
+                kind : TypeKind::Pointer,
+            },
+            DW_TAG_TYPEDEF => {
+                let base = self.variable_type(target, depth + 1);
+                VariableType { name: name.unwrap_or(base.name), ..base }
+            },
+            DW_TAG_CONST_TYPE | DW_TAG_VOLATILE_TYPE => {
+                let base = self.variable_type(target, depth + 1);
+                VariableType { name: self.type_name(offset, depth), ..base }
+            },
+            DW_TAG_ARRAY_TYPE => {
+                let element = self.variable_type(target, depth + 1);
+                let count = self.element_count(die);
+                VariableType {
+                    name : format!("{}[{}]", element.name, count),
+                    size : match size { 0 => element.size * count, size => size },
+                    kind : TypeKind::Array(count),
+                }
+            },
+            DW_TAG_STRUCTURE_TYPE | DW_TAG_CLASS_TYPE | DW_TAG_UNION_TYPE => {
+                let members = die.children.iter().map(|index| &self.dies[*index])
+                    .filter(|member| member.tag == DW_TAG_MEMBER)
+                    .map(|member| Member {
+                        name   : member.text(DW_AT_NAME).unwrap_or("?").to_string(),
+                        offset : Self::member_offset(member),
+                        ty     : self.variable_type(member.reference(DW_AT_TYPE), depth + 1),
+                    })
+                    .collect();
+                VariableType { name: self.type_name(offset, depth), size, kind: TypeKind::Structure(members) }
+            },
+            DW_TAG_ENUMERATION => VariableType { name: name.unwrap_or_else(|| "enum".to_string()), size, kind: TypeKind::Enumeration },
+            _ => VariableType::void(),
+        }
+    }
+
+    /// `address` - address of `DW_OP_addr` location, other locations (registers, stack) are not fixed
+    fn address(&self, die: &Die) -> Option<u32> {
+        let Some(Value::Block(expression)) = die.value(DW_AT_LOCATION) else { return None };
+        if expression.len() != 1 + die.address_size as usize || expression[0] != DW_OP_ADDR {
+            return None
+        }
+        Cursor::new(expression, self.big_endian, 1).sized(die.address_size).ok().map(|address| address as u32)
+    }
+}
+
+/// `variables` - variables at fixed addresses described in `.debug_info`, in order of entries
+pub fn variables(sections: &DwarfSections) -> Result<Vec<Variable>, Error> {
+    let info = DebugInfo::parse(sections)?;
+    let variables = info.dies.iter()
+        .filter(|die| die.tag == DW_TAG_VARIABLE)
+        .filter_map(|die| {
+            let address = info.address(die)?;
+            let declaration = info.declaration(die);
+            let name = declaration.text(DW_AT_NAME)?.to_string();
+            let ty = info.variable_type(declaration.reference(DW_AT_TYPE).or(die.reference(DW_AT_TYPE)), 0);
+            Some(Variable { name, address, ty })
+        })
+        .collect();
+    Ok(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `UnitWriter` - little endian DWARF 4 unit of 4 byte addresses, entries refer only to earlier ones
+    struct UnitWriter {
+        bytes : Vec<u8>,
+    }
+
+    fn text(text: &str) -> Vec<u8> {
+        text.bytes().chain([0]).collect()
+    }
+
+    fn reference(offset: u32) -> Vec<u8> {
+        offset.to_le_bytes().to_vec()
+    }
+
+    impl UnitWriter {
+        fn new() -> Self {
+            UnitWriter { bytes: vec![0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 4] }
+        }
+
+        /// `entry` - appends entry of abbreviation `code`, gives back its offset
+        fn entry(&mut self, code: u8, fields: &[Vec<u8>]) -> u32 {
+            let offset = self.bytes.len() as u32;
+            self.bytes.push(code);
+            fields.iter().for_each(|field| self.bytes.extend(field));
+            offset
+        }
+
+        fn end_children(&mut self) {
+            self.bytes.push(0);
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            let length = self.bytes.len() as u32 - 4;
+            self.bytes[..4].copy_from_slice(&length.to_le_bytes());
+            self.bytes
+        }
+    }
+
+    /// code, tag, children, attribute and form pairs
+    const ABBREVIATIONS : [(u8, u8, u8, &[(u8, u8)]); 10] = [
+        (1, 0x11, 1, &[(0x03, 0x08)]),
+        (2, 0x24, 0, &[(0x03, 0x0E), (0x0B, 0x0B), (0x3E, 0x0B)]),
+        (3, 0x34, 0, &[(0x03, 0x08), (0x49, 0x13), (0x02, 0x18)]),
+        (4, 0x16, 0, &[(0x03, 0x08), (0x49, 0x13)]),
+        (5, 0x13, 1, &[(0x03, 0x08), (0x0B, 0x0B)]),
+        (6, 0x0D, 0, &[(0x03, 0x08), (0x49, 0x13), (0x38, 0x0A)]),
+        (7, 0x0F, 0, &[(0x49, 0x13)]),
+        (8, 0x01, 1, &[(0x49, 0x13)]),
+        (9, 0x21, 0, &[(0x2F, 0x0B)]),
+        (10, 0x34, 0, &[(0x03, 0x08), (0x3C, 0x19)]),
+    ];
+
+    fn abbrev_table() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (code, tag, children, attributes) in ABBREVIATIONS.iter() {
+            bytes.extend([*code, *tag, *children]);
+            attributes.iter().for_each(|(attribute, form)| bytes.extend([*attribute, *form]));
+            bytes.extend([0, 0]);
+        }
+        bytes.push(0);
+        bytes
+    }
+
+    fn location(address: u32) -> Vec<u8> {
+        [5, DW_OP_ADDR].into_iter().chain(address.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn variables_with_types() {
+        let strings = text("int").into_iter().chain(text("_Fract")).collect::<Vec<u8>>();
+        let mut unit = UnitWriter::new();
+        unit.entry(1, &[text("motor.c")]);
+        let int = unit.entry(2, &[reference(0), vec![2], vec![DW_ATE_SIGNED as u8]]);
+        let fract = unit.entry(2, &[reference(4), vec![2], vec![DW_ATE_SIGNED_FIXED as u8]]);
+        let frac16 = unit.entry(4, &[text("Frac16"), reference(fract)]);
+        let motor = unit.entry(5, &[text("motor"), vec![4]]);
+        unit.entry(6, &[text("speed"), reference(int), vec![0]]);
+        unit.entry(6, &[text("gain"), reference(frac16), vec![2, DW_OP_PLUS_UCONST, 2]]);
+        unit.end_children();
+        unit.entry(3, &[text("g_motor"), reference(motor), location(0x0800)]);
+        let pointer = unit.entry(7, &[reference(motor)]);
+        unit.entry(3, &[text("g_current"), reference(pointer), location(0x0804)]);
+        let array = unit.entry(8, &[reference(int)]);
+        unit.entry(9, &[vec![7]]);
+        unit.end_children();
+        unit.entry(3, &[text("samples"), reference(array), location(0x0810)]);
+        unit.entry(10, &[text("declared_only")]);
+        unit.end_children();
+
+        let info = unit.finish();
+        let abbrev = abbrev_table();
+        let variables = variables(&DwarfSections { info: &info, abbrev: &abbrev, strings: &strings, big_endian: false }).unwrap();
+        assert_eq!(variables.len(), 3);
+
+        let int_type = VariableType { name: "int".to_string(), size: 2, kind: TypeKind::Signed };
+        assert_eq!(variables[0].name, "g_motor");
+        assert_eq!(variables[0].address, 0x0800);
+        assert_eq!(variables[0].ty, VariableType { name: "motor".to_string(), size: 4, kind: TypeKind::Structure(vec![
+            Member { name: "speed".to_string(), offset: 0, ty: int_type.clone() },
+            Member { name: "gain".to_string(), offset: 2, ty: VariableType { name: "Frac16".to_string(), size: 2, kind: TypeKind::Fractional } },
+        ]) });
+        assert_eq!(variables[1].to_string(), "motor* g_current at X:$0804");
+        assert_eq!(variables[1].ty, VariableType { name: "motor*".to_string(), size: 4, kind: TypeKind::Pointer });
+        assert_eq!(variables[2].ty, VariableType { name: "int[8]".to_string(), size: 16, kind: TypeKind::Array(8) });
+
+        let mut old = info.clone();
+        old[4] = 5;
+        assert!(matches!(super::variables(&DwarfSections { info: &old, abbrev: &abbrev, strings: &strings, big_endian: false }), Err(Error::SymbolError(_))));
+    }
+}
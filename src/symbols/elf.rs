@@ -0,0 +1,307 @@
+use crate::errors::{Error};
+
+// ELF32 constants used by CodeWarrior 56800E linker output
+//--------------------------------------------------------------------
+const ELF_MAGIC       : [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS32      : u8  = 1;
+const ELFDATA2MSB     : u8  = 2;
+const HEADER_SIZE     : usize = 52;
+const SECTION_SIZE    : usize = 40;
+const SYMBOL_SIZE     : usize = 16;
+
+pub const SHT_SYMTAB  : u32 = 2;
+pub const SHT_NOBITS  : u32 = 8;
+pub const SHF_EXECINSTR : u32 = 1<<2;
+
+pub const SHN_UNDEF   : u16 = 0;
+pub const SHN_ABS     : u16 = 0xFFF1;
+
+pub const STT_OBJECT  : u8 = 1;
+pub const STT_FUNC    : u8 = 2;
+pub const STT_SECTION : u8 = 3;
+pub const STT_FILE    : u8 = 4;
+
+/// `ElfSection` - section header, `offset` and `size` locate its data in file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElfSection {
+    pub name    : String,
+    pub kind    : u32,
+    pub flags   : u32,
+    pub address : u32,
+    pub offset  : u32,
+    pub size    : u32,
+    pub link    : u32,
+}
+
+impl ElfSection {
+    /// section holds code, its symbols are in P memory
+    pub fn is_code(&self) -> bool {
+        self.flags & SHF_EXECINSTR != 0
+    }
+}
+
+/// `ElfSymbol` - entry of `.symtab`, `value` and `size` as written by linker
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElfSymbol {
+    pub name    : String,
+    pub value   : u32,
+    pub size    : u32,
+    pub kind    : u8,
+    pub section : u16,
+}
+
+/// `ElfFile` - section headers and symbols of ELF32 file of either byte order
+pub struct ElfFile<'a> {
+    bytes          : &'a [u8],
+    pub big_endian : bool,
+    pub machine    : u16,
+    pub sections   : Vec<ElfSection>,
+}
+
+/// `ElfReader` - fixed offset fields of ELF data in file byte order
+#[derive(Clone, Copy)]
+pub struct ElfReader<'a> {
+    pub bytes      : &'a [u8],
+    pub big_endian : bool,
+}
+
+impl<'a> ElfReader<'a> {
+
+    pub fn slice(&self, offset: usize, length: usize) -> Result<&'a [u8], Error> {
+        offset.checked_add(length)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or_else(|| Error::SymbolError(format!("ELF data ends before offset {:#X}", offset + length)))
+    }
+
+    pub fn u8(&self, offset: usize) -> Result<u8, Error> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    pub fn u16(&self, offset: usize) -> Result<u16, Error> {
+        let bytes: [u8; 2] = self.slice(offset, 2)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true  => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    pub fn u32(&self, offset: usize) -> Result<u32, Error> {
+        let bytes: [u8; 4] = self.slice(offset, 4)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true  => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// `string` - zero terminated string at `offset`
+    pub fn string(&self, offset: usize) -> Result<String, Error> {
+        let tail = self.bytes.get(offset..).ok_or_else(|| Error::SymbolError(format!("string offset {:#X} out of data", offset)))?;
+        let end = tail.iter().position(|byte| *byte == 0).ok_or_else(|| Error::SymbolError(format!("string at {:#X} not terminated", offset)))?;
+        Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+    }
+}
+
+impl<'a> ElfFile<'a> {
+
+    /// `parse` - ELF header and section headers of `bytes`
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE || bytes[..4] != ELF_MAGIC {
+            return Err(Error::SymbolError("not an ELF file".to_string()))
+        }
+        if bytes[4] != ELFCLASS32 {
+            return Err(Error::SymbolError(format!("ELF class {} is not 32-bit", bytes[4])))
+        }
+        let reader = ElfReader { bytes, big_endian: bytes[5] == ELFDATA2MSB };
+        let machine = reader.u16(18)?;
+        let section_offset = reader.u32(32)? as usize;
+        let section_size = reader.u16(46)? as usize;
+        let section_count = reader.u16(48)? as usize;
+        let names_index = reader.u16(50)? as usize;
+        if section_count > 0 && section_size < SECTION_SIZE {
+            return Err(Error::SymbolError(format!("section header size {} too small", section_size)))
+        }
+
+        let mut sections = Vec::with_capacity(section_count);
+        let mut name_offsets = Vec::with_capacity(section_count);
+        for index in 0..section_count {
+            let header = section_offset + index * section_size;
+            name_offsets.push(reader.u32(header)? as usize);
+            sections.push(ElfSection {
+                name    : String::new(),
+                kind    : reader.u32(header + 4)?,
+                flags   : reader.u32(header + 8)?,
+                address : reader.u32(header + 12)?,
+                offset  : reader.u32(header + 16)?,
+                size    : reader.u32(header + 20)?,
+                link    : reader.u32(header + 24)?,
+            });
+        }
+        if let Some(names) = sections.get(names_index).map(|section| section.offset as usize) {
+            for (section, offset) in sections.iter_mut().zip(name_offsets) {
+                section.name = reader.string(names + offset)?;
+            }
+        }
+        Ok(ElfFile { bytes, big_endian: reader.big_endian, machine, sections })
+    }
+
+    pub fn reader(&self) -> ElfReader<'a> {
+        ElfReader { bytes: self.bytes, big_endian: self.big_endian }
+    }
+
+    /// `section_data` - file contents of section `name`, `None` if there is no such section or it has no data
+    pub fn section_data(&self, name: &str) -> Option<&'a [u8]> {
+        let section = self.sections.iter().find(|section| section.name == name && section.kind != SHT_NOBITS)?;
+        self.reader().slice(section.offset as usize, section.size as usize).ok()
+    }
+
+    /// `symbols` - entries of all symbol tables, null entries skipped
+    pub fn symbols(&self) -> Result<Vec<ElfSymbol>, Error> {
+        let reader = self.reader();
+        let mut symbols = Vec::new();
+        for table in self.sections.iter().filter(|section| section.kind == SHT_SYMTAB) {
+            let names = self.sections.get(table.link as usize)
+                .ok_or_else(|| Error::SymbolError(format!("{} has no string table", table.name)))?;
+            let start = table.offset as usize;
+            for entry in (start..start + table.size as usize).step_by(SYMBOL_SIZE).skip(1) {
+                symbols.push(ElfSymbol {
+                    name    : reader.string(names.offset as usize + reader.u32(entry)? as usize)?,
+                    value   : reader.u32(entry + 4)?,
+                    size    : reader.u32(entry + 8)?,
+                    kind    : reader.u8(entry + 12)? & 0x0F,
+                    section : reader.u16(entry + 14)?,
+                });
+            }
+        }
+        Ok(symbols)
+    }
+}
+
+/// `ElfWriter` - minimal ELF32 of sections and symbols for tests
+#[cfg(test)]
+pub struct ElfWriter {
+    pub big_endian : bool,
+    /// name, type, flags, address, data
+    sections       : Vec<(String, u32, u32, u32, Vec<u8>)>,
+    /// name, value, size, type, section index
+    symbols        : Vec<(String, u32, u32, u8, u16)>,
+}
+
+#[cfg(test)]
+impl ElfWriter {
+
+    pub fn new(big_endian: bool) -> Self {
+        ElfWriter { big_endian, sections: Vec::new(), symbols: Vec::new() }
+    }
+
+    /// `section` - adds section, gives back its index
+    pub fn section(&mut self, name: &str, kind: u32, flags: u32, address: u32, data: Vec<u8>) -> u16 {
+        self.sections.push((name.to_string(), kind, flags, address, data));
+        self.sections.len() as u16
+    }
+
+    pub fn symbol(&mut self, name: &str, value: u32, size: u32, kind: u8, section: u16) {
+        self.symbols.push((name.to_string(), value, size, kind, section));
+    }
+
+    fn put16(&self, out: &mut Vec<u8>, value: u16) {
+        out.extend(if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    }
+
+    fn put32(&self, out: &mut Vec<u8>, value: u32) {
+        out.extend(if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; SYMBOL_SIZE];
+        for (name, value, size, kind, section) in self.symbols.iter() {
+            self.put32(&mut symtab, strtab.len() as u32);
+            self.put32(&mut symtab, *value);
+            self.put32(&mut symtab, *size);
+            symtab.extend([*kind, 0]);
+            self.put16(&mut symtab, *section);
+            strtab.extend(name.bytes().chain([0]));
+        }
+        let base = self.sections.len() as u32 + 1;
+        let mut sections = self.sections.clone();
+        sections.push((".symtab".to_string(), SHT_SYMTAB, 0, 0, symtab));
+        sections.push((".strtab".to_string(), 3, 0, 0, strtab));
+        sections.push((".shstrtab".to_string(), 3, 0, 0, Vec::new()));
+        let mut shstrtab = vec![0u8];
+        let mut name_offsets = Vec::new();
+        for (name, ..) in sections.iter() {
+            name_offsets.push(shstrtab.len() as u32);
+            shstrtab.extend(name.bytes().chain([0]));
+        }
+        sections.last_mut().unwrap().4 = shstrtab;
+
+        let mut out = Vec::new();
+        out.extend(ELF_MAGIC);
+        out.extend([ELFCLASS32, if self.big_endian { ELFDATA2MSB } else { 1 }, 1]);
+        out.resize(16, 0);
+        self.put16(&mut out, 2);
+        self.put16(&mut out, 0);
+        self.put32(&mut out, 1);
+        self.put32(&mut out, 0);
+        self.put32(&mut out, 0);
+        let shoff_at = out.len();
+        self.put32(&mut out, 0);
+        self.put32(&mut out, 0);
+        self.put16(&mut out, HEADER_SIZE as u16);
+        self.put16(&mut out, 0);
+        self.put16(&mut out, 0);
+        self.put16(&mut out, SECTION_SIZE as u16);
+        self.put16(&mut out, sections.len() as u16 + 1);
+        self.put16(&mut out, sections.len() as u16);
+
+        let mut offsets = Vec::new();
+        for (.., data) in sections.iter() {
+            offsets.push(out.len() as u32);
+            out.extend(data);
+        }
+        let shoff = out.len() as u32;
+        out[shoff_at..shoff_at + 4].copy_from_slice(&if self.big_endian { shoff.to_be_bytes() } else { shoff.to_le_bytes() });
+        out.extend([0u8; SECTION_SIZE]);
+        for (index, (_, kind, flags, address, data)) in sections.iter().enumerate() {
+            let link = if *kind == SHT_SYMTAB { base + 1 } else { 0 };
+            for value in [name_offsets[index], *kind, *flags, *address, offsets[index], data.len() as u32, link, 0, 1, 0] {
+                self.put32(&mut out, value);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_and_symbols() {
+        for big_endian in [false, true] {
+            let mut writer = ElfWriter::new(big_endian);
+            let text = writer.section(".text", 1, SHF_EXECINSTR | 2, 0x0200, vec![0x00, 0xE7]);
+            let data = writer.section(".data", 1, 2 | 1, 0x1000, vec![0; 4]);
+            writer.symbol("Fmain", 0x0200, 1, STT_FUNC, text);
+            writer.symbol("Fcounter", 0x1000, 2, STT_OBJECT, data);
+            let bytes = writer.build();
+
+            let elf = ElfFile::parse(&bytes).unwrap();
+            assert_eq!(elf.big_endian, big_endian);
+            assert_eq!(elf.sections[text as usize].name, ".text");
+            assert!(elf.sections[text as usize].is_code());
+            assert!(!elf.sections[data as usize].is_code());
+            assert_eq!(elf.section_data(".text"), Some(&[0x00, 0xE7][..]));
+            assert_eq!(elf.section_data(".debug_info"), None);
+            let symbols = elf.symbols().unwrap();
+            assert_eq!(symbols, [
+                ElfSymbol { name: "Fmain".to_string(), value: 0x0200, size: 1, kind: STT_FUNC, section: text },
+                ElfSymbol { name: "Fcounter".to_string(), value: 0x1000, size: 2, kind: STT_OBJECT, section: data },
+            ]);
+        }
+        assert!(matches!(ElfFile::parse(b"S00F000068656C6C6F202020202000003C"), Err(Error::SymbolError(_))));
+        let mut truncated = ElfWriter::new(false).build();
+        truncated.truncate(60);
+        assert!(ElfFile::parse(&truncated).is_err());
+    }
+}
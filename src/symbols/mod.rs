@@ -0,0 +1,3 @@
+pub mod elf;
+pub mod dwarf;
+pub mod symbol_table;
@@ -0,0 +1,303 @@
+use std::fmt;
+use std::fs;
+use crate::errors::{Error};
+use crate::dsc_target::target_factory::AccessType;
+use super::elf::*;
+use super::dwarf::{self, DwarfSections, Variable, TypeKind};
+
+/// highest DSC word address, 24 bits
+const ADDRESS_MASK : u32 = 0x00FF_FFFF;
+
+/// `Symbol` - named address of ELF symbol table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name     : String,
+    pub space    : AccessType,
+    pub address  : u32,
+    /// as written by linker, 0 - unknown
+    pub size     : u32,
+    pub function : bool,
+}
+
+/// `SymbolTable` - symbols and variables of CodeWarrior ELF output loaded alongside image
+///
+/// Addresses are DSC word addresses as in linker map. Symbols of code sections are in P memory,
+/// all others and DWARF variables in X memory. CodeWarrior prefixes C names with `F` (`Fmain`),
+/// lookups take names with or without it.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    /// sorted by memory space, then address
+    symbols   : Vec<Symbol>,
+    variables : Vec<Variable>,
+}
+
+/// sort order of symbols, P memory first
+fn key(space: AccessType, address: u32) -> (bool, u32) {
+    (space == AccessType::MemoryX, address)
+}
+
+/// `space_name` - `P` or `X` as in `P:$0200`
+pub fn space_name(space: AccessType) -> &'static str {
+    match space {
+        AccessType::MemoryP => "P",
+        AccessType::MemoryX => "X",
+    }
+}
+
+impl SymbolTable {
+
+    pub fn new(mut symbols: Vec<Symbol>, variables: Vec<Variable>) -> Self {
+        symbols.sort_by_key(|symbol| key(symbol.space, symbol.address));
+        SymbolTable { symbols, variables }
+    }
+
+    /// `load` - symbols of ELF file at `path`
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let bytes = fs::read(path).map_err(|e| Error::SymbolError(format!("{}: {}", path, e)))?;
+        Self::from_elf(&bytes)
+    }
+
+    /// `from_elf` - `.symtab` symbols and, if file has debug information, DWARF variables
+    pub fn from_elf(bytes: &[u8]) -> Result<Self, Error> {
+        let elf = ElfFile::parse(bytes)?;
+        let mut symbols = Vec::new();
+        for symbol in elf.symbols()? {
+            if symbol.name.is_empty() || matches!(symbol.kind, STT_SECTION | STT_FILE) || symbol.section == SHN_UNDEF {
+                continue
+            }
+            let space = match elf.sections.get(symbol.section as usize) {
+                Some(section) if section.is_code() => AccessType::MemoryP,
+                _ => AccessType::MemoryX,
+            };
+            symbols.push(Symbol { name: symbol.name, space, address: symbol.value, size: symbol.size, function: symbol.kind == STT_FUNC });
+        }
+        let variables = match (elf.section_data(".debug_info"), elf.section_data(".debug_abbrev")) {
+            (Some(info), Some(abbrev)) => dwarf::variables(&DwarfSections {
+                info,
+                abbrev,
+                strings    : elf.section_data(".debug_str").unwrap_or(&[]),
+                big_endian : elf.big_endian,
+            })?,
+            _ => Vec::new(),
+        };
+        Ok(Self::new(symbols, variables))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty() && self.variables.is_empty()
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn variables(&self) -> &[Variable] {
+        &self.variables
+    }
+
+    /// `find` - symbol of `name`, also CodeWarrior `F` prefixed one
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        let prefixed = format!("F{}", name);
+        self.symbols.iter().find(|symbol| symbol.name == name)
+            .or_else(|| self.symbols.iter().find(|symbol| symbol.name == prefixed))
+    }
+
+    /// `variable` - DWARF variable or member of structure variable by `path`, e.g. `motor.speed`
+    ///
+    /// Member offsets are in bytes, member address is start of structure plus offset in words.
+    pub fn variable(&self, path: &str) -> Option<Variable> {
+        let mut fields = path.split('.');
+        let root = fields.next()?;
+        let variable = self.variables.iter().find(|variable| variable.name == root)?;
+        let (mut address, mut ty) = (variable.address, variable.ty.clone());
+        for field in fields {
+            let TypeKind::Structure(members) = &ty.kind else { return None };
+            let member = members.iter().find(|member| member.name == field)?;
+            address += member.offset / 2;
+            ty = member.ty.clone();
+        }
+        Some(Variable { name: path.to_string(), address, ty })
+    }
+
+    /// `symbol_at` - closest symbol at or below `address` of `space` and offset of address from it
+    ///
+    /// Symbol with known size holds address only inside its size.
+    pub fn symbol_at(&self, space: AccessType, address: u32) -> Option<(&Symbol, u32)> {
+        let after = self.symbols.partition_point(|symbol| key(symbol.space, symbol.address) <= key(space, address));
+        let symbol = self.symbols[..after].last().filter(|symbol| symbol.space == space)?;
+        let offset = address - symbol.address;
+        match symbol.size {
+            0 => Some((symbol, offset)),
+            size if offset < size => Some((symbol, offset)),
+            _ => None,
+        }
+    }
+
+    /// `describe` - `name` or `name+$offset` of address, `None` without symbol there
+    pub fn describe(&self, space: AccessType, address: u32) -> Option<String> {
+        match self.symbol_at(space, address)? {
+            (symbol, 0)      => Some(symbol.name.clone()),
+            (symbol, offset) => Some(format!("{}+${:X}", symbol.name, offset)),
+        }
+    }
+
+    /// `labels_at` - names of symbols starting exactly at `address`
+    pub fn labels_at(&self, space: AccessType, address: u32) -> impl Iterator<Item = &str> {
+        let first = self.symbols.partition_point(|symbol| key(symbol.space, symbol.address) < key(space, address));
+        self.symbols[first..].iter()
+            .take_while(move |symbol| symbol.space == space && symbol.address == address)
+            .map(|symbol| symbol.name.as_str())
+    }
+
+    /// `resolve` - memory space and word address of address typed by user
+    ///
+    /// Takes `[P:|X:]term[(+|-)term...]`, term is `$hex`, `0xhex`, decimal, symbol or variable path.
+    /// Space is the given prefix, else space of first named term, else `default`.
+    pub fn resolve(&self, text: &str, default: AccessType) -> Result<(AccessType, u32), Error> {
+        let text = text.trim();
+        let (mut space, mut rest) = match text.get(..2).map(|prefix| prefix.to_ascii_uppercase()).as_deref() {
+            Some("P:") => (Some(AccessType::MemoryP), &text[2..]),
+            Some("X:") => (Some(AccessType::MemoryX), &text[2..]),
+            _          => (None, text),
+        };
+        let mut address = 0i64;
+        let mut sign = 1i64;
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let (value, term_space) = self.term(rest[..end].trim())?;
+            space = space.or(term_space);
+            address += sign * value as i64;
+            if end == rest.len() {
+                break
+            }
+            sign = if rest[end..].starts_with('+') { 1 } else { -1 };
+            rest = &rest[end + 1..];
+        }
+        if !(0..=ADDRESS_MASK as i64).contains(&address) {
+            return Err(Error::SymbolError(format!("'{}' is out of 24-bit address space", text)))
+        }
+        Ok((space.unwrap_or(default), address as u32))
+    }
+
+    fn term(&self, term: &str) -> Result<(u32, Option<AccessType>), Error> {
+        let number = |digits: &str, radix: u32| u32::from_str_radix(digits, radix)
+            .map(|value| (value, None))
+            .map_err(|_e| Error::SymbolError(format!("bad number '{}'", term)));
+        if let Some(hex) = term.strip_prefix('$').or_else(|| term.strip_prefix("0x")).or_else(|| term.strip_prefix("0X")) {
+            return number(hex, 16)
+        }
+        if term.starts_with(|c: char| c.is_ascii_digit()) {
+            return number(term, 10)
+        }
+        if term.is_empty() {
+            return Err(Error::SymbolError("address expected".to_string()))
+        }
+        if let Some(symbol) = self.find(term) {
+            return Ok((symbol.address, Some(symbol.space)))
+        }
+        match self.variable(term) {
+            Some(variable) => Ok((variable.address, Some(AccessType::MemoryX))),
+            None           => Err(Error::SymbolError(format!("unknown symbol '{}'", term))),
+        }
+    }
+
+    /// `address_text` - `P:$xxxx <name+$offset>` of address for listings
+    pub fn address_text(&self, space: AccessType, address: u32) -> String {
+        match self.describe(space, address) {
+            Some(name) => format!("{}:${:04X} <{}>", space_name(space), address, name),
+            None       => format!("{}:${:04X}", space_name(space), address),
+        }
+    }
+}
+
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let functions = self.symbols.iter().filter(|symbol| symbol.function).count();
+        write!(f, "{} symbols ({} functions), {} variables", self.symbols.len(), functions, self.variables.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbols::dwarf::{Member, VariableType};
+
+    fn symbol(name: &str, space: AccessType, address: u32, size: u32) -> Symbol {
+        Symbol { name: name.to_string(), space, address, size, function: space == AccessType::MemoryP }
+    }
+
+    fn table() -> SymbolTable {
+        let word = |name: &str, kind| VariableType { name: name.to_string(), size: 2, kind };
+        let motor = Variable { name: "g_motor".to_string(), address: 0x0800, ty: VariableType { name: "motor".to_string(), size: 4, kind: TypeKind::Structure(vec![
+            Member { name: "speed".to_string(), offset: 0, ty: word("int", TypeKind::Signed) },
+            Member { name: "gain".to_string(), offset: 2, ty: word("Frac16", TypeKind::Fractional) },
+        ]) } };
+        SymbolTable::new(vec![
+            symbol("Fisr_timer", AccessType::MemoryP, 0x0400, 0x20),
+            symbol("Fmain", AccessType::MemoryP, 0x0200, 0),
+            symbol("Fg_motor", AccessType::MemoryX, 0x0800, 2),
+        ], vec![motor])
+    }
+
+    #[test]
+    fn names_of_addresses() {
+        let symbols = table();
+        assert_eq!(symbols.describe(AccessType::MemoryP, 0x0200).as_deref(), Some("Fmain"));
+        assert_eq!(symbols.describe(AccessType::MemoryP, 0x0345).as_deref(), Some("Fmain+$145"));
+        assert_eq!(symbols.describe(AccessType::MemoryP, 0x0404).as_deref(), Some("Fisr_timer+$4"));
+        assert_eq!(symbols.describe(AccessType::MemoryP, 0x0420), None);
+        assert_eq!(symbols.describe(AccessType::MemoryP, 0x0100), None);
+        assert_eq!(symbols.describe(AccessType::MemoryX, 0x0801).as_deref(), Some("Fg_motor+$1"));
+        assert_eq!(symbols.describe(AccessType::MemoryX, 0x0200), None);
+        assert_eq!(symbols.labels_at(AccessType::MemoryP, 0x0400).collect::<Vec<_>>(), ["Fisr_timer"]);
+        assert_eq!(symbols.labels_at(AccessType::MemoryX, 0x0400).count(), 0);
+        assert_eq!(symbols.address_text(AccessType::MemoryP, 0x0401), "P:$0401 <Fisr_timer+$1>");
+        assert_eq!(symbols.to_string(), "3 symbols (2 functions), 1 variables");
+    }
+
+    #[test]
+    fn addresses_typed_by_user() {
+        let symbols = table();
+        let p = AccessType::MemoryP;
+        let x = AccessType::MemoryX;
+        assert_eq!(symbols.resolve("$1F0", p).unwrap(), (p, 0x01F0));
+        assert_eq!(symbols.resolve("x:0x1f0", p).unwrap(), (x, 0x01F0));
+        assert_eq!(symbols.resolve("16 + 4", x).unwrap(), (x, 20));
+        assert_eq!(symbols.resolve("main", x).unwrap(), (p, 0x0200));
+        assert_eq!(symbols.resolve("isr_timer+$10", x).unwrap(), (p, 0x0410));
+        assert_eq!(symbols.resolve("Fisr_timer - 1", x).unwrap(), (p, 0x03FF));
+        assert_eq!(symbols.resolve("g_motor.gain", p).unwrap(), (x, 0x0801));
+        assert_eq!(symbols.resolve("P:g_motor", x).unwrap(), (p, 0x0800));
+        assert_eq!(symbols.variable("g_motor.gain").unwrap().ty.kind, TypeKind::Fractional);
+        assert!(symbols.variable("g_motor.speed.high").is_none());
+        for bad in ["", "main+", "nothing", "$12G", "main-$1000", "$1000000"] {
+            assert!(matches!(symbols.resolve(bad, p), Err(Error::SymbolError(_))), "'{}' resolved", bad);
+        }
+    }
+
+    #[test]
+    fn symbols_from_elf() {
+        let mut writer = ElfWriter::new(true);
+        let text = writer.section(".text", 1, SHF_EXECINSTR | 2, 0x0200, vec![0; 4]);
+        let data = writer.section(".bss", SHT_NOBITS, 3, 0x0800, Vec::new());
+        writer.symbol("motor.c", 0, 0, STT_FILE, SHN_ABS);
+        writer.symbol("Fmain", 0x0200, 2, STT_FUNC, text);
+        writer.symbol("Fcounter", 0x0800, 1, STT_OBJECT, data);
+        writer.symbol("Fexternal", 0, 0, STT_FUNC, SHN_UNDEF);
+        writer.symbol("_stack_addr", 0x0F00, 0, 0, SHN_ABS);
+
+        let symbols = SymbolTable::from_elf(&writer.build()).unwrap();
+        assert_eq!(symbols.symbols(), [
+            symbol("Fmain", AccessType::MemoryP, 0x0200, 2),
+            Symbol { function: false, ..symbol("Fcounter", AccessType::MemoryX, 0x0800, 1) },
+            Symbol { function: false, ..symbol("_stack_addr", AccessType::MemoryX, 0x0F00, 0) },
+        ]);
+        assert!(symbols.variables().is_empty());
+        assert!(SymbolTable::default().is_empty());
+        assert!(matches!(SymbolTable::load("/nonexistent/firmware.elf"), Err(Error::SymbolError(_))));
+    }
+}
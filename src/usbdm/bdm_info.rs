@@ -2,7 +2,7 @@ use super::*;
 use super::jtag::*;
 use crate::usbdm::programmer::{Programmer};
 use crate::usbdm::constants::{bdm_commands};
-use crate::usbdm::usb_interface::{UsbInterface};
+use crate::usbdm::transport::{UsbdmTransport, check_usbdm_return_code};
 use crate::errors::{Error, USBDM_ErrorCode};
 use std::fmt;
 use std::time::Duration;
@@ -14,7 +14,7 @@ pub const MINIMAL_VERSION : u32 = 0x040C01;
 ///The idea is to group a huge number of USBDM structures, enumerations and settings into three abstractions.
 /// 
 /// One is BdmInfo - It includes all data information about USBDM, software and hardware versions, buffer sizes
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BdmInfo {
     pub bdm_software_version      : u32,           // Version of USBDM Firmware
     pub bdm_hardware_version      : u8,            // Version of USBDM Hardware
@@ -44,7 +44,7 @@ impl Default for BdmInfo {
     } 
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Capabilities {
     pub hcs12:       bool,  // Supports HCS12
     pub rs08:        bool,  // 12 V Flash programming supply available (RS08 support)
@@ -93,6 +93,86 @@ impl fmt::Display for BdmInfo {
 }
 
 impl BdmInfo {
+    /// `read_from` - query firmware version and capabilities, nothing else is sent to USBDM
+    ///
+    /// Safe to use on a probe we only want to list, target power is not touched
+    pub fn read_from(device: &dyn UsbdmTransport) -> Result<Self, Error> {
+        let mut info = BdmInfo::default();
+        info.read_version(device)?;
+        info.read_capabilities(device)?;
+        Ok(info)
+    }
+
+    fn read_version(&mut self, device: &dyn UsbdmTransport) -> Result<(), Error>{
+        let request_type = 64; //LIBUSB_REQUEST_TYPE_VENDOR
+        let request_type = request_type | device.read_ep();
+    
+        let request  = bdm_commands::CMD_USBDM_GET_VER; // command
+        let value    = 100;
+        let index    = 0;
+        let timeout  = Duration::from_millis(2500);
+        let rx_size  = 10;
+     
+        let version = device.control_transfer(
+            request_type,
+            request,
+            value,
+            index,
+            rx_size)?;                                    
+
+        let raw_bdm_software_version = u32::from (version[1]);
+        let calculation = ((raw_bdm_software_version&0xF0)<<12) + ((raw_bdm_software_version&0x0F)<<8);
+
+        self.bdm_software_version = calculation;
+        self.bdm_hardware_version  = version[2];
+        self.icp_software_version  = version[3];
+        self.icp_hardware_version  = version[4];
+        Ok(())
+    }
+
+    fn read_capabilities(&mut self, device: &dyn UsbdmTransport) -> Result<(), Error>{
+        let mut usb_buf = [0; 2];
+        usb_buf[0] = 2;  // lenght
+        usb_buf[1] = bdm_commands::CMD_USBDM_GET_CAPABILITIES;
+        let command = "CMD_USBDM_GET_CAPABILITIES".to_string();
+
+        let bit = 0x80;
+        let bitter = usb_buf[1] | bit;
+        usb_buf[1] = bitter;
+
+        device.write(&usb_buf)?;                          // write command
+        let answer: Vec<u8> = device.read(8)?;            //  read
+        check_usbdm_return_code(&answer)?;
+
+        if answer.len() >= 3 {
+            let capabilities: u16 = ((answer[1] as u16) << 8) | answer[2] as u16 ^ ((1<<5) | (1<<6));
+            self.capabilities.parse(capabilities);
+        }
+
+        if answer.len() >= 5 {
+            let mut buffer_size: u16 = ((answer[3] as u16) << 8) + answer[4] as u16;
+            let max_packet_size: u16 = 255;
+            if buffer_size > max_packet_size {
+                buffer_size = max_packet_size;
+            }
+            let jtag_header_size: u16 = 5;
+            self.command_buffer_size = buffer_size;
+            self.jtag_buffer_size = buffer_size - jtag_header_size;
+        }
+
+        if answer.len() >= 8 {
+            // Newer BDMs report extended software version
+            self.bdm_software_version = ((answer[5] as u32) << 16)+((answer[6] as u32) << 8)+answer[7] as u32;
+        }
+
+        // Calculate permitted read & write length in bytes
+        // Allow for JTAG header + USB header (5 bytes) & make multiple of 4
+        self.dsc_max_memory_read_size  = (self.jtag_buffer_size - JTAG_READ_MEMORY_HEADER_SIZE  - 5) & !3;
+        self.dsc_max_memory_write_size = (self.jtag_buffer_size - JTAG_WRITE_MEMORY_HEADER_SIZE - 5) & !3;
+                                        
+        Ok(())
+    }
+
     pub fn print_version(&self) {
         println!("bdm_software_version: {:#02}",  &self.bdm_software_version);
         println!("bdm_hardware_version: {:#02}",  &self.bdm_hardware_version);
@@ -128,6 +208,8 @@ impl BdmInfo {
         println!("s12z: {}",  &self.capabilities.s12z);
     }
 
+    /// `check_version` - hardware matches bootloader and firmware is not older than firmware table,
+    /// features of newer firmware are gated with `supports`/`require`
     pub fn check_version(&self) -> Result<(), Error> {
         if &self.bdm_hardware_version != &self.icp_hardware_version { 
             Err(Error::USBDM_Errors(USBDM_ErrorCode::BDM_RC_WRONG_BDM_REVISION))
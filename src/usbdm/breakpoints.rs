@@ -0,0 +1,348 @@
+use std::fmt;
+use crate::errors::{Error};
+use crate::usbdm::Programmer;
+use crate::usbdm::registers::*;
+
+// EONCE_OB0CR breakpoint unit control register details
+//--------------------------------------------------------------------
+/// comparator 1 bus select, bits 1..0, then comparator 2 bus select, bits 3..2 (`BreakpointKind` codes)
+pub const OBCR_BS1_SHIFT   : u32 = 0;
+pub const OBCR_BS2_SHIFT   : u32 = 2;
+pub const OBCR_BS_MASK     : u32 = 0x03;
+pub const OBCR_EN1         : u32 = 1<<4;   // comparator 1 enabled
+pub const OBCR_EN2         : u32 = 1<<5;   // comparator 2 enabled
+pub const OBCR_MSK2        : u32 = 1<<6;   // comparator 2 compares address bits of OB0MSK only
+pub const OBCR_CNTEN       : u32 = 1<<7;   // trigger decrements OB0CNTR, break when it passes zero
+pub const OBCR_DEBUG       : u32 = 1<<8;   // trigger enters debug mode
+
+// EONCE_OSR status register details
+//--------------------------------------------------------------------
+pub const OSR_BKPT : u32 = 1<<2;           // breakpoint unit triggered since last OSR read
+
+/// Comparators of breakpoint unit, OB0AR1 (24-bit) and OB0AR2 (with OB0MSK)
+pub const BREAKPOINT_COMPARATORS : usize = 2;
+
+/// Program and X data addresses are 24-bit
+const ADDRESS_MASK : u32 = 0x00FF_FFFF;
+
+/// `BreakpointKind` - bus watched by comparator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakpointKind {
+    /// program fetch (PAB), core halts before instruction executes
+    Program,
+    XWrite,
+    XRead,
+    /// X read or write
+    XAccess,
+}
+
+impl BreakpointKind {
+    fn code(self) -> u32 {
+        match self {
+            BreakpointKind::Program => 0,
+            BreakpointKind::XWrite  => 1,
+            BreakpointKind::XRead   => 2,
+            BreakpointKind::XAccess => 3,
+        }
+    }
+
+    fn from_code(code: u32) -> Self {
+        match code & OBCR_BS_MASK {
+            0 => BreakpointKind::Program,
+            1 => BreakpointKind::XWrite,
+            2 => BreakpointKind::XRead,
+            _ => BreakpointKind::XAccess,
+        }
+    }
+}
+
+/// `Breakpoint` - program breakpoint or X memory watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub kind    : BreakpointKind,
+    pub address : u32,
+    /// address bits compared (1 - compare), `None` - all; only comparator 2 has mask
+    pub mask    : Option<u32>,
+}
+
+impl Breakpoint {
+    pub fn program(address: u32) -> Self {
+        Breakpoint { kind: BreakpointKind::Program, address, mask: None }
+    }
+
+    pub fn watch(kind: BreakpointKind, address: u32) -> Self {
+        Breakpoint { kind, address, mask: None }
+    }
+
+    /// `with_mask` - break on every address equal to `address` in bits set in `mask`, e.g. `0xFFFFF0` - 16 words
+    pub fn with_mask(mut self, mask: u32) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// `matches` - `address` hits breakpoint
+    pub fn matches(&self, address: u32) -> bool {
+        let mask = self.mask.unwrap_or(ADDRESS_MASK) & ADDRESS_MASK;
+        address & mask == self.address & mask
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            BreakpointKind::Program => "P",
+            BreakpointKind::XWrite  => "X write",
+            BreakpointKind::XRead   => "X read",
+            BreakpointKind::XAccess => "X access",
+        };
+        write!(f, "{} ${:06X}", kind, self.address)?;
+        if let Some(mask) = self.mask {
+            write!(f, " mask ${:06X}", mask)?;
+        }
+        Ok(())
+    }
+}
+
+/// `BreakpointUnit` - EOnCE breakpoint unit 0: two comparators, either may trigger, common trigger counter
+///
+/// `count` - core halts on `count`-th trigger (1 - at first), counter is shared by both comparators.
+/// Layout of OB0CR / OSR fields is in `OBCR_*` / `OSR_*` constants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointUnit {
+    pub comparators : [Option<Breakpoint>; BREAKPOINT_COMPARATORS],
+    pub count       : u16,
+}
+
+impl Default for BreakpointUnit {
+    fn default() -> Self {
+        BreakpointUnit { comparators: [None; BREAKPOINT_COMPARATORS], count: 1 }
+    }
+}
+
+impl BreakpointUnit {
+
+    /// `set` - put breakpoint on free comparator, masked one needs comparator 2. Gives comparator index
+    pub fn set(&mut self, breakpoint: Breakpoint) -> Result<usize, Error> {
+        if breakpoint.address & !ADDRESS_MASK != 0 {
+            return Err(Error::BreakpointError(format!("address ${:X} is wider than 24 bits", breakpoint.address)))
+        }
+        if self.comparators.contains(&Some(breakpoint)) {
+            return Err(Error::BreakpointError(format!("{} is set already", breakpoint)))
+        }
+        let free = if breakpoint.mask.is_some() {
+            self.comparators[1].is_none().then_some(1)
+        } else {
+            self.comparators.iter().position(|comparator| comparator.is_none())
+        };
+        let index = free.ok_or_else(|| Error::BreakpointError(format!("no free comparator for {}, in use: {}", breakpoint, self)))?;
+        self.comparators[index] = Some(breakpoint);
+        Ok(index)
+    }
+
+    /// `clear` - free comparator, clearing free one is not an error
+    pub fn clear(&mut self, index: usize) {
+        if let Some(comparator) = self.comparators.get_mut(index) {
+            *comparator = None;
+        }
+    }
+
+    pub fn clear_all(&mut self) {
+        self.comparators = [None; BREAKPOINT_COMPARATORS];
+        self.count = 1;
+    }
+
+    /// `list` - set breakpoints with their comparator index
+    pub fn list(&self) -> Vec<(usize, Breakpoint)> {
+        self.comparators.iter().enumerate().filter_map(|(index, comparator)| comparator.map(|breakpoint| (index, breakpoint))).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comparators.iter().all(|comparator| comparator.is_none())
+    }
+
+    /// `registers` - OB0CR, OB0AR1, OB0AR2, OB0MSK, OB0CNTR values of unit
+    pub fn registers(&self) -> Result<[(DscRegisters, u32); 5], Error> {
+        if self.count == 0 {
+            return Err(Error::BreakpointError("trigger count is 0".to_string()))
+        }
+        if self.comparators[0].is_some_and(|breakpoint| breakpoint.mask.is_some()) {
+            return Err(Error::BreakpointError("comparator 1 has no mask".to_string()))
+        }
+        let mut control = 0;
+        let [first, second] = self.comparators;
+        if let Some(breakpoint) = first {
+            control |= OBCR_EN1 | (breakpoint.kind.code() << OBCR_BS1_SHIFT);
+        }
+        if let Some(breakpoint) = second {
+            control |= OBCR_EN2 | (breakpoint.kind.code() << OBCR_BS2_SHIFT);
+            if breakpoint.mask.is_some() {
+                control |= OBCR_MSK2;
+            }
+        }
+        if !self.is_empty() {
+            control |= OBCR_DEBUG;
+            if self.count > 1 {
+                control |= OBCR_CNTEN;
+            }
+        }
+        Ok([
+            (DscRegisters::DscRegOb0cr,   control),
+            (DscRegisters::DscRegOb0ar1,  first.map_or(0, |breakpoint| breakpoint.address)),
+            (DscRegisters::DscRegOb0ar2,  second.map_or(0, |breakpoint| breakpoint.address)),
+            (DscRegisters::DscRegOb0msk,  second.and_then(|breakpoint| breakpoint.mask).unwrap_or(ADDRESS_MASK)),
+            (DscRegisters::DscRegOb0Cntr, (self.count - 1) as u32),
+        ])
+    }
+
+    /// `from_registers` - unit as programmed in OB0CR, OB0AR1, OB0AR2, OB0MSK, OB0CNTR
+    pub fn from_registers(control: u32, address1: u32, address2: u32, mask: u32, counter: u32) -> Self {
+        let first = (control & OBCR_EN1 != 0).then(|| Breakpoint {
+            kind: BreakpointKind::from_code(control >> OBCR_BS1_SHIFT), address: address1, mask: None });
+        let second = (control & OBCR_EN2 != 0).then(|| Breakpoint {
+            kind: BreakpointKind::from_code(control >> OBCR_BS2_SHIFT), address: address2, mask: (control & OBCR_MSK2 != 0).then_some(mask) });
+        let count = if control & OBCR_CNTEN != 0 { counter as u16 + 1 } else { 1 };
+        BreakpointUnit { comparators: [first, second], count }
+    }
+
+    /// `hit` - comparator that halted core, from OSR and PC read after halt
+    ///
+    /// Program breakpoint halts with PC at its address. Watchpoint halts after accessing instruction, so it is
+    /// told only when no program breakpoint matches PC and it is the only watchpoint set - otherwise `None`.
+    pub fn hit(&self, osr: u32, pc: u32) -> Option<usize> {
+        if osr & OSR_BKPT == 0 {
+            return None
+        }
+        let set = self.list();
+        if let Some((index, _)) = set.iter().find(|(_, breakpoint)| breakpoint.kind == BreakpointKind::Program && breakpoint.matches(pc)) {
+            return Some(*index)
+        }
+        let watchpoints: Vec<usize> = set.iter().filter(|(_, breakpoint)| breakpoint.kind != BreakpointKind::Program).map(|(index, _)| *index).collect();
+        match watchpoints[..] {
+            [index] => Some(index),
+            _       => None,
+        }
+    }
+}
+
+impl fmt::Display for BreakpointUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let set: Vec<String> = self.list().iter().map(|(index, breakpoint)| format!("#{} {}", index + 1, breakpoint)).collect();
+        if set.is_empty() {
+            return write!(f, "none")
+        }
+        write!(f, "{}", set.join(", "))?;
+        if self.count > 1 {
+            write!(f, ", break on trigger {}", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Programmer
+{
+    /// `dsc_write_breakpoints` - program breakpoint unit 0, empty unit disables it
+    ///
+    /// `note` Assumes Core TAP is active & in RUN-TEST/IDLE, OB0CR written last so unit is armed with new addresses
+    pub fn dsc_write_breakpoints(&self, unit: &BreakpointUnit) -> Result<(), Error> {
+        let registers = unit.registers()?;
+        self.dsc_write_once_reg(DscRegisters::DscRegOb0cr, 0)?;
+        for (reg, value) in registers.iter().skip(1) {
+            self.dsc_write_once_reg(*reg, *value)?;
+        }
+        self.dsc_write_once_reg(DscRegisters::DscRegOb0cr, registers[0].1)
+    }
+
+    /// `dsc_read_breakpoints` - breakpoint unit 0 as programmed on target
+    pub fn dsc_read_breakpoints(&self) -> Result<BreakpointUnit, Error> {
+        Ok(BreakpointUnit::from_registers(
+            self.dsc_read_once_reg(DscRegisters::DscRegOb0cr)?,
+            self.dsc_read_once_reg(DscRegisters::DscRegOb0ar1)?,
+            self.dsc_read_once_reg(DscRegisters::DscRegOb0ar2)?,
+            self.dsc_read_once_reg(DscRegisters::DscRegOb0msk)?,
+            self.dsc_read_once_reg(DscRegisters::DscRegOb0Cntr)?,
+        ))
+    }
+
+    /// `dsc_breakpoint_hit` - comparator of `unit` that halted core, see `BreakpointUnit::hit`
+    ///
+    /// `note` Core must be halted, reading OSR clears its breakpoint flag
+    pub fn dsc_breakpoint_hit(&self, unit: &BreakpointUnit) -> Result<Option<usize>, Error> {
+        let osr = self.dsc_read_once_reg(DscRegisters::DscRegOsr)?;
+        let pc = self.dsc_read_pc()?;
+        Ok(unit.hit(osr, pc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::jtag::*;
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::{DscTap, DscCore};
+
+    #[test]
+    fn comparators_are_allocated() {
+        let mut unit = BreakpointUnit::default();
+        assert_eq!(unit.set(Breakpoint::watch(BreakpointKind::XWrite, 0x8000).with_mask(0xFFFFF0)).unwrap(), 1);
+        assert_eq!(unit.set(Breakpoint::program(0x0200)).unwrap(), 0);
+        assert!(matches!(unit.set(Breakpoint::program(0x0300)), Err(Error::BreakpointError(_))));
+        assert_eq!(unit.to_string(), "#1 P $000200, #2 X write $008000 mask $FFFFF0");
+
+        unit.clear(1);
+        assert!(unit.set(Breakpoint::program(0x0200)).is_err());
+        assert!(unit.set(Breakpoint::program(0x100_0000)).is_err());
+        assert_eq!(unit.set(Breakpoint::watch(BreakpointKind::XRead, 0x8010)).unwrap(), 1);
+        assert_eq!(unit.list().len(), 2);
+        // mask only on comparator 2
+        unit.clear(1);
+        unit.comparators[0] = Some(Breakpoint::program(0x0200).with_mask(0xFFFF00));
+        assert!(unit.registers().is_err());
+    }
+
+    #[test]
+    fn written_and_read_back() {
+        let link = InterpreterTransport::new(DscTap::new());
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+
+        let mut unit = BreakpointUnit { count: 5, ..Default::default() };
+        unit.set(Breakpoint::program(0x0123)).unwrap();
+        unit.set(Breakpoint::watch(BreakpointKind::XAccess, 0x8040).with_mask(0xFFFFC0)).unwrap();
+        prog.dsc_write_breakpoints(&unit).unwrap();
+
+        let control = link.with_tap(|tap| tap.once_register(0x11));
+        assert_eq!(control, OBCR_EN1 | OBCR_EN2 | (3 << OBCR_BS2_SHIFT) | OBCR_MSK2 | OBCR_CNTEN | OBCR_DEBUG);
+        assert_eq!(link.with_tap(|tap| tap.once_register(0x15)), 4);
+        assert_eq!(prog.dsc_read_breakpoints().unwrap(), unit);
+
+        prog.dsc_write_breakpoints(&BreakpointUnit::default()).unwrap();
+        assert_eq!(link.with_tap(|tap| tap.once_register(0x11)), 0);
+        assert!(prog.dsc_read_breakpoints().unwrap().is_empty());
+    }
+
+    #[test]
+    fn halt_reason() {
+        let mut tap = DscTap::new();
+        let mut core = DscCore::default();
+        core.set(DscRegisters::DscRegPc, 0x0123);
+        tap.core = Some(core);
+        let link = InterpreterTransport::new(tap);
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+
+        let mut unit = BreakpointUnit::default();
+        unit.set(Breakpoint::watch(BreakpointKind::XWrite, 0x8000)).unwrap();
+        assert_eq!(prog.dsc_breakpoint_hit(&unit).unwrap(), None);
+
+        link.with_tap(|tap| tap.once_registers.insert(0x03, OSR_BKPT));
+        assert_eq!(prog.dsc_breakpoint_hit(&unit).unwrap(), Some(0));
+        unit.set(Breakpoint::program(0x0123)).unwrap();
+        assert_eq!(prog.dsc_breakpoint_hit(&unit).unwrap(), Some(1));
+        unit.clear(1);
+        unit.set(Breakpoint::watch(BreakpointKind::XRead, 0x9000)).unwrap();
+        // two watchpoints, PC does not tell
+        assert_eq!(prog.dsc_breakpoint_hit(&unit).unwrap(), None);
+    }
+}
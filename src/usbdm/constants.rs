@@ -17,7 +17,28 @@ pub mod bdm_commands {
     pub const  CMD_USBDM_JTAG_READ               : u8 =  41;  // Read from JTAG chain
     pub const  CMD_USBDM_SET_VPP                 : u8  = 42;  // Target Vdd Off but previously set level unchanged
     pub const  CMD_USBDM_JTAG_EXECUTE_SEQUENCE   : u8  = 44;  // Execute sequence of JTAG commands
-    
+
+    /// `command_name` - constant name for command byte (bit 7 "has data" flag ignored), for logs and session files
+    pub fn command_name(command : u8) -> &'static str {
+        match command & 0x7F {
+            CMD_USBDM_GET_COMMAND_RESPONSE  => "CMD_USBDM_GET_COMMAND_RESPONSE",
+            CMD_USBDM_SET_TARGET            => "CMD_USBDM_SET_TARGET",
+            CMD_USBDM_SET_VDD               => "CMD_USBDM_SET_VDD",
+            CMD_USBDM_GET_BDM_STATUS        => "CMD_USBDM_GET_BDM_STATUS",
+            CMD_USBDM_GET_CAPABILITIES      => "CMD_USBDM_GET_CAPABILITIES",
+            CMD_USBDM_SET_OPTIONS           => "CMD_USBDM_SET_OPTIONS",
+            CMD_USBDM_CONTROL_PINS          => "CMD_USBDM_CONTROL_PINS",
+            CMD_USBDM_GET_VER               => "CMD_USBDM_GET_VER",
+            CMD_USBDM_SET_SPEED             => "CMD_USBDM_SET_SPEED",
+            CMD_USBDM_JTAG_GOTORESET        => "CMD_USBDM_JTAG_GOTORESET",
+            CMD_USBDM_JTAG_GOTOSHIFT        => "CMD_USBDM_JTAG_GOTOSHIFT",
+            CMD_USBDM_JTAG_WRITE            => "CMD_USBDM_JTAG_WRITE",
+            CMD_USBDM_JTAG_READ             => "CMD_USBDM_JTAG_READ",
+            CMD_USBDM_SET_VPP               => "CMD_USBDM_SET_VPP",
+            CMD_USBDM_JTAG_EXECUTE_SEQUENCE => "CMD_USBDM_JTAG_EXECUTE_SEQUENCE",
+            _                               => "CMD_USBDM_UNKNOWN",
+        }
+    }
 }
 
  
@@ -56,8 +77,13 @@ pub mod jtag_shift {
 
     pub const JTAG_SHIFT_DR                      : u8  = 0;     // Enter SHIFT-DR (from TEST-LOGIC-RESET or RUN-TEST/IDLE)
     pub const JTAG_SHIFT_IR                      : u8  = 1;     // Enter SHIFT-IR (from TEST-LOGIC-RESET or RUN-TEST/IDLE)
+    pub const JTAG_STAY_SHIFT                    : u8  = 0;     // Remain in SHIFT-DR or SHIFT-IR
     pub const JTAG_EXIT_IDLE                     : u8  = 1;     // Exit SHIFT-XX to RUN-TEST/IDLE
     pub const JTAG_EXIT_SHIFT_DR                 : u8  = 2;     // Exit SHIFT-XX & enter SHIFT-DR w/o crossing RUN-TEST/IDLE
+    pub const JTAG_EXIT_SHIFT_IR                 : u8  = 3;     // Exit SHIFT-XX & enter SHIFT-IR w/o crossing RUN-TEST/IDLE
+    pub const JTAG_EXIT_ACTION_MASK              : u8  = 0x03;  // Exit action part of exit byte
+    pub const JTAG_WRITE_0                       : u8  = 0x00;  // Shift in 0's when reading
+    pub const JTAG_WRITE_1                       : u8  = 0x80;  // Shift in 1's when reading (used for IR)
 
 
 }
\ No newline at end of file
@@ -0,0 +1,308 @@
+use std::fmt;
+use crate::errors::{Error};
+use crate::usbdm::Programmer;
+use crate::usbdm::registers::*;
+
+/// EOnCE registers kept in snapshot, reading them does not change EOnCE state (unlike OTX/ORX, trace buffer)
+pub const SNAPSHOT_ONCE_REGISTERS: [DscRegisters; 4] = [
+    DscRegisters::DscRegOcr, DscRegisters::DscRegOscntr, DscRegisters::DscRegOsr, DscRegisters::DscRegOtxrxsr,
+];
+
+/// Core registers used by transfer sequences of other registers: A by C, D, OMR, SR, N3, M01, SHM01, R4 by every write
+/// and by LA, LC, SP, PC reads. Read first, written back last.
+const SCRATCH_REGISTERS: [DscRegisters; 4] = [
+    DscRegisters::DscRegA0, DscRegisters::DscRegA1, DscRegisters::DscRegA2, DscRegisters::DscRegR4,
+];
+
+/// 36-bit accumulators, extension (4 bits) : MSP : LSP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accumulator {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Accumulator {
+    /// `parts` - extension, MSP, LSP
+    pub fn parts(self) -> [DscRegisters; 3] {
+        match self {
+            Accumulator::A => [DscRegisters::DscRegA2, DscRegisters::DscRegA1, DscRegisters::DscRegA0],
+            Accumulator::B => [DscRegisters::DscRegB2, DscRegisters::DscRegB1, DscRegisters::DscRegB0],
+            Accumulator::C => [DscRegisters::DscRegC2, DscRegisters::DscRegC1, DscRegisters::DscRegC0],
+            Accumulator::D => [DscRegisters::DscRegD2, DscRegisters::DscRegD1, DscRegisters::DscRegD0],
+        }
+    }
+}
+
+/// `RegisterChange` - register that differs between two snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub register : DscRegisters,
+    pub before   : u32,
+    pub after    : u32,
+}
+
+impl fmt::Display for RegisterChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = digits(self.register);
+        write!(f, "{:<7} ${:0w$X} -> ${:0w$X}", get_register_name(self.register), self.before, self.after, w = digits)
+    }
+}
+
+/// hex digits of register value
+fn digits(reg: DscRegisters) -> usize {
+    (get_register_size(reg).unwrap_or(32) as usize).div_ceil(4)
+}
+
+fn mask(reg: DscRegisters) -> u32 {
+    match get_register_size(reg).unwrap_or(32) {
+        32    => u32::MAX,
+        width => (1 << width) - 1,
+    }
+}
+
+/// `CoreRegisters` - all core registers and EOnCE status registers of halted DSC read at once
+///
+/// Values are as wide as register (`get_register_size`), index of `core` is `DscRegisters` number.
+/// Edit with `set` / `set_accumulator`, then `write_changes` writes back only what differs from snapshot read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreRegisters {
+    core : [u32; CORE_REGISTERS.len()],
+    once : [u32; SNAPSHOT_ONCE_REGISTERS.len()],
+}
+
+impl CoreRegisters {
+
+    /// `read` - snapshot of halted core, registers used as scratch by transfer sequences are restored
+    ///
+    /// `note` Assumes Core TAP is active, target in debug mode
+    pub fn read(prog: &Programmer) -> Result<Self, Error> {
+        let mut core = [0; CORE_REGISTERS.len()];
+        for reg in SCRATCH_REGISTERS.iter().chain(CORE_REGISTERS.iter().filter(|reg| !SCRATCH_REGISTERS.contains(reg))) {
+            core[*reg as usize] = prog.dsc_read_core_reg(*reg)?;
+        }
+        let mut once = [0; SNAPSHOT_ONCE_REGISTERS.len()];
+        for (index, reg) in SNAPSHOT_ONCE_REGISTERS.iter().enumerate() {
+            once[index] = prog.dsc_read_once_reg(*reg)?;
+        }
+        let registers = CoreRegisters { core, once };
+        registers.write_scratch(prog)?;
+        Ok(registers)
+    }
+
+    fn write_scratch(&self, prog: &Programmer) -> Result<(), Error> {
+        for reg in SCRATCH_REGISTERS {
+            prog.dsc_write_core_reg(reg, self.core[reg as usize])?;
+        }
+        Ok(())
+    }
+
+    /// `get` - value of core or snapshot EOnCE register, `None` for registers not in snapshot
+    pub fn get(&self, reg: DscRegisters) -> Option<u32> {
+        if let Some(index) = CORE_REGISTERS.iter().position(|core| *core == reg) {
+            return Some(self.core[index])
+        }
+        SNAPSHOT_ONCE_REGISTERS.iter().position(|once| *once == reg).map(|index| self.once[index])
+    }
+
+    /// `set` - change core register in snapshot, read-only registers and values wider than register are refused
+    pub fn set(&mut self, reg: DscRegisters, value: u32) -> Result<(), Error> {
+        if !CORE_REGISTERS.contains(&reg) {
+            return Err(Error::InternalError(format!("{} is not a core register", get_register_name(reg))))
+        }
+        if is_read_only(reg) {
+            return Err(Error::InternalError(format!("Core register {} is read only", get_register_name(reg))))
+        }
+        if value & !mask(reg) != 0 {
+            return Err(Error::InternalError(format!("${:X} too wide for {}", value, get_register_name(reg))))
+        }
+        self.core[reg as usize] = value;
+        Ok(())
+    }
+
+    /// `values` - core registers in `CORE_REGISTERS` order, then `SNAPSHOT_ONCE_REGISTERS`, e.g. to keep snapshot in file
+    pub fn values(&self) -> Vec<u32> {
+        self.core.iter().chain(self.once.iter()).copied().collect()
+    }
+
+    /// `from_values` - snapshot given back by `values`
+    pub fn from_values(values: &[u32]) -> Result<Self, Error> {
+        if values.len() != CORE_REGISTERS.len() + SNAPSHOT_ONCE_REGISTERS.len() {
+            return Err(Error::InternalError(format!("{} register values, snapshot has {}", values.len(), CORE_REGISTERS.len() + SNAPSHOT_ONCE_REGISTERS.len())))
+        }
+        let (core, once) = values.split_at(CORE_REGISTERS.len());
+        Ok(CoreRegisters { core: core.try_into().unwrap(), once: once.try_into().unwrap() })
+    }
+
+    pub fn pc(&self) -> u32 {
+        self.core[DscRegisters::DscRegPc as usize]
+    }
+
+    /// `accumulator` - 36-bit value, extension in bits 32..35
+    pub fn accumulator(&self, accumulator: Accumulator) -> u64 {
+        let [extension, msp, lsp] = accumulator.parts().map(|reg| self.core[reg as usize] as u64);
+        (extension << 32) | (msp << 16) | lsp
+    }
+
+    pub fn set_accumulator(&mut self, accumulator: Accumulator, value: u64) -> Result<(), Error> {
+        if value >> 36 != 0 {
+            return Err(Error::InternalError(format!("${:X} too wide for 36-bit {:?}", value, accumulator)))
+        }
+        let [extension, msp, lsp] = accumulator.parts();
+        self.set(extension, (value >> 32) as u32)?;
+        self.set(msp, ((value >> 16) & 0xFFFF) as u32)?;
+        self.set(lsp, (value & 0xFFFF) as u32)
+    }
+
+    /// `diff` - registers changed from `self` to `other`, core registers first, then EOnCE
+    pub fn diff(&self, other: &CoreRegisters) -> Vec<RegisterChange> {
+        CORE_REGISTERS.iter().chain(SNAPSHOT_ONCE_REGISTERS.iter())
+            .filter_map(|reg| {
+                let (before, after) = (self.get(*reg)?, other.get(*reg)?);
+                (before != after).then_some(RegisterChange { register: *reg, before, after })
+            })
+            .collect()
+    }
+
+    /// `write_changes` - write core registers that differ from `base` (snapshot read before editing)
+    ///
+    /// C and D go first (their sequences pass through A), A and R4 are written again at the end when
+    /// some other write used them. EOnCE registers are never written. Gives back changes made.
+    pub fn write_changes(&self, prog: &Programmer, base: &CoreRegisters) -> Result<Vec<RegisterChange>, Error> {
+        let changes: Vec<RegisterChange> = base.diff(self).into_iter()
+            .filter(|change| CORE_REGISTERS.contains(&change.register))
+            .collect();
+        if let Some(change) = changes.iter().find(|change| is_read_only(change.register)) {
+            return Err(Error::InternalError(format!("Core register {} is read only", get_register_name(change.register))))
+        }
+        if changes.is_empty() {
+            return Ok(changes)
+        }
+
+        let through_a = |reg: DscRegisters| Accumulator::C.parts().contains(&reg) || Accumulator::D.parts().contains(&reg);
+        let changed: Vec<DscRegisters> = changes.iter().map(|change| change.register).collect();
+        let first = changed.iter().filter(|reg| through_a(**reg));
+        let others = changed.iter().filter(|reg| !through_a(**reg) && !SCRATCH_REGISTERS.contains(reg));
+        for reg in first.chain(others) {
+            prog.dsc_write_core_reg(*reg, self.core[*reg as usize])?;
+        }
+        let a_used = changed.iter().any(|reg| through_a(*reg));
+        for reg in Accumulator::A.parts().iter().rev() {
+            if a_used || changed.contains(reg) {
+                prog.dsc_write_core_reg(*reg, self.core[*reg as usize])?;
+            }
+        }
+        // every write sequence loads R4
+        prog.dsc_write_core_reg(DscRegisters::DscRegR4, self.core[DscRegisters::DscRegR4 as usize])?;
+        Ok(changes)
+    }
+}
+
+impl fmt::Display for CoreRegisters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers: Vec<DscRegisters> = CORE_REGISTERS.iter().chain(SNAPSHOT_ONCE_REGISTERS.iter()).copied().collect();
+        for line in registers.chunks(4) {
+            let cells: Vec<String> = line.iter()
+                .map(|reg| format!("{:<7} ${:<8}", get_register_name(*reg), format!("{:0w$X}", self.get(*reg).unwrap_or(0), w = digits(*reg))))
+                .collect();
+            writeln!(f, "{}", cells.join(" ").trim_end())?;
+        }
+        for accumulator in [Accumulator::A, Accumulator::B, Accumulator::C, Accumulator::D] {
+            write!(f, "{:?} ${:09X}  ", accumulator, self.accumulator(accumulator))?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::jtag::*;
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::{DscTap, DscCore};
+
+    fn halted_core() -> (InterpreterTransport<DscTap>, Programmer, DscCore) {
+        let mut core = DscCore::default();
+        for (index, reg) in CORE_REGISTERS.iter().enumerate() {
+            core.set(*reg, 0x0011_1111 * (index as u32 + 1));
+        }
+        let mut tap = DscTap::new();
+        tap.core = Some(core);
+        let link = InterpreterTransport::new(tap);
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        link.with_tap(|tap| tap.once_registers.insert(0x03, 0x0302));
+        let core = link.with_tap(|tap| tap.core.clone().unwrap());
+        (link, prog, core)
+    }
+
+    #[test]
+    fn snapshot_is_coherent() {
+        let (link, prog, core) = halted_core();
+        let registers = CoreRegisters::read(&prog).unwrap();
+
+        for reg in CORE_REGISTERS.iter().filter(|reg| !is_read_only(**reg)) {
+            assert_eq!(registers.get(*reg), Some(core.get(*reg)), "{}", get_register_name(*reg));
+        }
+        assert_eq!(registers.get(DscRegisters::DscRegLc2), Some(0xABAD));
+        assert_eq!(registers.get(DscRegisters::DscRegOsr), Some(0x0302));
+        assert_eq!(registers.get(DscRegisters::DscRegOtx), None);
+        assert_eq!(registers.pc(), core.get(DscRegisters::DscRegPc));
+        // A and R4 used by transfer sequences are given back
+        assert_eq!(link.with_tap(|tap| tap.core.clone().unwrap()), core);
+        assert!(registers.to_string().contains("pc      $"));
+        assert_eq!(CoreRegisters::from_values(&registers.values()).unwrap(), registers);
+        assert!(CoreRegisters::from_values(&registers.values()[1..]).is_err());
+    }
+
+    #[test]
+    fn accumulators_are_36_bit() {
+        let (_link, prog, _core) = halted_core();
+        let mut registers = CoreRegisters::read(&prog).unwrap();
+        registers.set_accumulator(Accumulator::B, 0xF_8000_0001).unwrap();
+        assert_eq!(registers.get(DscRegisters::DscRegB2), Some(0xF));
+        assert_eq!(registers.get(DscRegisters::DscRegB1), Some(0x8000));
+        assert_eq!(registers.get(DscRegisters::DscRegB0), Some(0x0001));
+        assert_eq!(registers.accumulator(Accumulator::B), 0xF_8000_0001);
+        assert!(registers.set_accumulator(Accumulator::B, 0x10_0000_0000).is_err());
+    }
+
+    #[test]
+    fn only_changes_written_back() {
+        let (link, prog, core) = halted_core();
+        let base = CoreRegisters::read(&prog).unwrap();
+        let mut edited = base.clone();
+        assert!(edited.set(DscRegisters::DscRegLa2, 0).is_err());
+        assert!(edited.set(DscRegisters::DscRegLc2, 0).is_err());
+        // hardware stack has no write sequence
+        assert!(edited.set(DscRegisters::DscRegHws0, 0).is_err());
+        assert!(edited.set(DscRegisters::DscRegHws1, 0).is_err());
+        assert!(edited.set(DscRegisters::DscRegX0, 0x1_0000).is_err());
+        assert!(edited.set(DscRegisters::DscRegOcr, 0).is_err());
+        assert!(prog.dsc_write_core_reg(DscRegisters::DscRegLa2, 0).is_err());
+
+        edited.set(DscRegisters::DscRegC1, 0x1234).unwrap();
+        edited.set(DscRegisters::DscRegR0, 0x00_8000).unwrap();
+        edited.set(DscRegisters::DscRegPc, 0x0_0200).unwrap();
+        assert_eq!(base.diff(&edited).iter().map(|change| change.register).collect::<Vec<DscRegisters>>(),
+            vec![DscRegisters::DscRegC1, DscRegisters::DscRegR0, DscRegisters::DscRegPc]);
+
+        link.with_tap(|tap| tap.executed.clear());
+        let written = edited.write_changes(&prog, &base).unwrap();
+        assert_eq!(written.len(), 3);
+        assert_eq!(written[0].to_string(), format!("c1      ${:04X} -> $1234", base.get(DscRegisters::DscRegC1).unwrap()));
+
+        let mut expected = core.clone();
+        expected.set(DscRegisters::DscRegC1, 0x1234);
+        expected.set(DscRegisters::DscRegR0, 0x00_8000);
+        expected.set(DscRegisters::DscRegPc, 0x0_0200);
+        assert_eq!(link.with_tap(|tap| tap.core.clone().unwrap()), expected);
+        assert_eq!(CoreRegisters::read(&prog).unwrap(), edited);
+
+        link.with_tap(|tap| tap.executed.clear());
+        assert!(edited.write_changes(&prog, &edited).unwrap().is_empty());
+        assert!(link.with_tap(|tap| tap.executed.is_empty()));
+    }
+}
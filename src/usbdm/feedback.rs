@@ -135,8 +135,7 @@ impl Programmer
       let bitter = usb_buf[1] | bit;
       usb_buf[1] = bitter;
 
-      self.usb_device.write(&usb_buf)?;       // write command
-      let answer = self.usb_device.read(3)?;       // read status from bdm and save buffer to answer -
+      let answer = self.bdm_command(&usb_buf, 3)?;       // write command, read status from bdm and save buffer to answer -
                                                    
       let feedback_slice = [answer[1],answer[2]];      // two bytes for status feedback (in answer [1] use only 2 bits... for VPP bits)
      // println!("FeedBack is: {:02X?}", feedback_slice);
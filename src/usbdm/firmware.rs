@@ -0,0 +1,188 @@
+use crate::errors::{Error};
+use crate::usbdm::bdm_info::{BdmInfo, MINIMAL_VERSION};
+use crate::usbdm::constants::{bdm_commands};
+use crate::usbdm::jtag::*;
+
+/// `FirmwareFeature` - part of USBDM firmware this tool relies on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FirmwareFeature {
+    /// Capability bit: DSC targets
+    DscTarget,
+    /// Capability bit: JTAG targets
+    JtagTarget,
+    /// Capability bit: target Vdd control (`CMD_USBDM_SET_VDD`)
+    VddControl,
+    /// `CMD_USBDM_JTAG_EXECUTE_SEQUENCE` with shift, IF, REPEAT and variable opcodes
+    JtagSequence,
+    /// `CMD_USBDM_GET_COMMAND_RESPONSE` polling of busy commands
+    CommandResponse,
+    /// Three byte firmware version in capabilities answer
+    ExtendedVersion,
+    /// `JTAG_SUBx` / `JTAG_CALL_SUBx` / `JTAG_SAVE_SUB` - used by core register access
+    JtagSubroutines,
+    /// `JTAG_READ_MEM` / `JTAG_WRITE_MEM` - DSC memory access done by firmware
+    DscMemoryAccess,
+}
+
+impl FirmwareFeature {
+
+    /// `required_version` - firmware needed for feature, `None` for capability bits
+    ///
+    /// Sequence features are all taken as present from `MINIMAL_VERSION` (4.12.1, the firmware
+    /// the tool was developed and tested with), older firmware is refused by `check_version`
+    pub fn required_version(&self) -> Option<u32> {
+        match self {
+            FirmwareFeature::DscTarget | FirmwareFeature::JtagTarget | FirmwareFeature::VddControl => None,
+            _ => Some(MINIMAL_VERSION),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FirmwareFeature::DscTarget       => "DSC target support",
+            FirmwareFeature::JtagTarget      => "JTAG target support",
+            FirmwareFeature::VddControl      => "target Vdd control",
+            FirmwareFeature::JtagSequence    => "JTAG sequences (CMD_USBDM_JTAG_EXECUTE_SEQUENCE)",
+            FirmwareFeature::CommandResponse => "busy command polling (CMD_USBDM_GET_COMMAND_RESPONSE)",
+            FirmwareFeature::ExtendedVersion => "extended firmware version",
+            FirmwareFeature::JtagSubroutines => "JTAG sequence subroutines (core register access)",
+            FirmwareFeature::DscMemoryAccess => "DSC memory access (JTAG_READ_MEM/JTAG_WRITE_MEM)",
+        }
+    }
+}
+
+/// `command_feature` - feature needed by raw USBDM command
+pub fn command_feature(command: u8) -> Option<FirmwareFeature> {
+    match command & 0x7F {
+        bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE => Some(FirmwareFeature::JtagSequence),
+        bdm_commands::CMD_USBDM_GET_COMMAND_RESPONSE  => Some(FirmwareFeature::CommandResponse),
+        bdm_commands::CMD_USBDM_SET_VDD               => Some(FirmwareFeature::VddControl),
+        _                                             => None,
+    }
+}
+
+/// `opcode_feature` - feature needed by JTAG sequence opcode beyond plain `JtagSequence`
+pub fn opcode_feature(opcode: u8) -> Option<FirmwareFeature> {
+    match opcode {
+        JTAG_READ_MEM | JTAG_WRITE_MEM                      => Some(FirmwareFeature::DscMemoryAccess),
+        JTAG_END_SUB | JTAG_RETURN | JTAG_SAVE_SUB          => Some(FirmwareFeature::JtagSubroutines),
+        op if (JTAG_SUBA..=JTAG_CALL_SUBD).contains(&op)    => Some(FirmwareFeature::JtagSubroutines),
+        _                                                   => None,
+    }
+}
+
+/// `version_string` - 0x040C01 as "4.12.1"
+pub fn version_string(version: u32) -> String {
+    format!("{}.{}.{}", (version >> 16) & 0xFF, (version >> 8) & 0xFF, version & 0xFF)
+}
+
+impl BdmInfo {
+
+    /// `supports` - firmware version and capabilities allow `feature`
+    ///
+    /// Version 0 means firmware was not queried (`Programmer::from_transport`), nothing is gated then.
+    pub fn supports(&self, feature: FirmwareFeature) -> bool {
+        if self.bdm_software_version == 0 {
+            return true
+        }
+        match feature {
+            FirmwareFeature::DscTarget  => self.capabilities.dsc,
+            FirmwareFeature::JtagTarget => self.capabilities.jtag,
+            FirmwareFeature::VddControl => self.capabilities.vddcontrol,
+            _ => feature.required_version().map_or(true, |version| self.bdm_software_version >= version),
+        }
+    }
+
+    /// `require` - `Ok` if `feature` is supported, otherwise error naming feature and firmware needed
+    pub fn require(&self, feature: FirmwareFeature) -> Result<(), Error> {
+        if self.supports(feature) {
+            return Ok(())
+        }
+        match (feature, feature.required_version()) {
+            (FirmwareFeature::DscTarget, _) => Err(Error::UsbdmUnsuited),
+            (_, Some(version)) => Err(Error::UsbdmFeatureUnsupported(
+                feature.description().to_string(),
+                format!("firmware >= {} (probe has {})", version_string(version), version_string(self.bdm_software_version)))),
+            (_, None) => Err(Error::UsbdmFeatureUnsupported(
+                feature.description().to_string(),
+                "USBDM hardware with this capability".to_string())),
+        }
+    }
+
+    /// `supported_features` - for connection info and bug reports
+    pub fn supported_features(&self) -> Vec<FirmwareFeature> {
+        let all = [
+            FirmwareFeature::DscTarget, FirmwareFeature::JtagTarget, FirmwareFeature::VddControl,
+            FirmwareFeature::JtagSequence, FirmwareFeature::CommandResponse, FirmwareFeature::ExtendedVersion,
+            FirmwareFeature::JtagSubroutines, FirmwareFeature::DscMemoryAccess,
+        ];
+        all.into_iter()
+            .filter(|feature| self.supports(*feature))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::constants::{memory_space_t};
+    use crate::usbdm::transport::ScriptedTransport;
+    use crate::usbdm::programmer::Programmer;
+
+    fn info_with_version(version: u32) -> BdmInfo {
+        let mut info = BdmInfo { bdm_software_version: version, ..Default::default() };
+        info.capabilities.dsc = true;
+        info.capabilities.jtag = true;
+        info
+    }
+
+    #[test]
+    fn features_by_version() {
+        let old = info_with_version(0x040905);
+        assert!(!old.supports(FirmwareFeature::JtagSequence));
+        assert!(!old.supports(FirmwareFeature::JtagSubroutines));
+        assert!(!old.supports(FirmwareFeature::DscMemoryAccess));
+        assert!(!old.supports(FirmwareFeature::VddControl));
+
+        let current = info_with_version(0x040C01);
+        assert!(current.supports(FirmwareFeature::JtagSubroutines));
+        assert!(current.supports(FirmwareFeature::DscMemoryAccess));
+        assert!(BdmInfo::default().supports(FirmwareFeature::DscMemoryAccess));   // not queried
+    }
+
+    #[test]
+    fn precise_error() {
+        match info_with_version(0x040905).require(FirmwareFeature::DscMemoryAccess) {
+            Err(Error::UsbdmFeatureUnsupported(feature, needed)) => {
+                assert!(feature.contains("JTAG_READ_MEM"));
+                assert_eq!(needed, "firmware >= 4.12.1 (probe has 4.9.5)");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let mut no_dsc = info_with_version(0x040C01);
+        no_dsc.capabilities.dsc = false;
+        assert!(matches!(no_dsc.require(FirmwareFeature::DscTarget), Err(Error::UsbdmUnsuited)));
+    }
+
+    #[test]
+    fn version_check_uses_minimal_version() {
+        let mut info = info_with_version(0x030000);
+        assert!(matches!(info.check_version(), Err(Error::UsbdmFWVersionUnsupported(_, _))));
+        info.bdm_software_version = 0x040905;
+        assert!(matches!(info.check_version(), Err(Error::UsbdmFWVersionUnsupported(_, _))));
+        info.bdm_software_version = MINIMAL_VERSION;
+        assert!(info.check_version().is_ok());
+    }
+
+    #[test]
+    fn old_firmware_gated_in_programmer() {
+        let script = ScriptedTransport::new();
+        let mut prog = Programmer::from_transport(Box::new(script.clone()));
+        prog.bdm_info = info_with_version(0x040905);
+
+        let result = prog.dsc_read_memory(memory_space_t::MS_XWORD, 4, 0x8000);
+        assert!(matches!(result, Err(Error::UsbdmFeatureUnsupported(_, _))));
+        assert!(script.written().is_empty());
+        assert_eq!(opcode_feature(JTAG_CALL_SUBA), Some(FirmwareFeature::JtagSubroutines));
+    }
+}
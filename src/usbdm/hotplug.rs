@@ -0,0 +1,163 @@
+use rusb::{UsbContext};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::errors::{Error};
+use crate::usbdm::usb_interface::{match_vid, USBDM_VID};
+
+/// How often libusb event loop checks stop flag
+const EVENT_LOOP_TIMEOUT : u64 = 200;
+/// Period of device list polling when libusb has no hotplug (Windows backend)
+pub const POLLING_PERIOD : u64 = 500;
+
+/// `UsbPort` - where device sits on the bus, the same until it's unplugged
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UsbPort {
+    pub bus     : u8,
+    pub address : u8,
+}
+
+impl UsbPort {
+    pub fn of_device<T: UsbContext>(device: &rusb::Device<T>) -> Self {
+        UsbPort { bus: device.bus_number(), address: device.address() }
+    }
+}
+
+/// `HotplugEvent` - USBDM (VID 0x16D0) plugged or unplugged
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HotplugEvent {
+    Arrived(UsbPort),
+    Left(UsbPort),
+}
+
+/// `HotplugWatcher` - background thread reporting USBDM hotplug events to `sink`
+///
+/// Uses libusb hotplug callbacks when available, otherwise polls device list every `POLLING_PERIOD` ms.
+/// Devices already connected at start are not reported. Thread is stopped on drop.
+///
+/// Sink is called from watcher thread, it must not do USB transfers (libusb forbids sync API in hotplug callback),
+/// just pass event further (channel to gui subscription).
+#[derive(Debug)]
+pub struct HotplugWatcher {
+    running : Arc<AtomicBool>,
+    thread  : Option<JoinHandle<()>>,
+}
+
+struct HotplugSink<F> {
+    sink : F,
+}
+
+impl<T: UsbContext, F: FnMut(HotplugEvent) + Send> rusb::Hotplug<T> for HotplugSink<F> {
+    fn device_arrived(&mut self, device: rusb::Device<T>) {
+        (self.sink)(HotplugEvent::Arrived(UsbPort::of_device(&device)));
+    }
+
+    fn device_left(&mut self, device: rusb::Device<T>) {
+        (self.sink)(HotplugEvent::Left(UsbPort::of_device(&device)));
+    }
+}
+
+impl HotplugWatcher {
+
+    /// `start` - libusb hotplug if supported, polling fallback otherwise
+    pub fn start<F>(sink: F) -> Result<Self, Error>
+    where F: FnMut(HotplugEvent) + Send + 'static
+    {
+        if rusb::has_hotplug() {
+            Self::start_hotplug(sink)
+        } else {
+            Ok(Self::start_polling(sink, Duration::from_millis(POLLING_PERIOD)))
+        }
+    }
+
+    /// `start_hotplug` - libusb callbacks, event loop on own context
+    pub fn start_hotplug<F>(sink: F) -> Result<Self, Error>
+    where F: FnMut(HotplugEvent) + Send + 'static
+    {
+        let context = rusb::Context::new()?;
+        let registration : rusb::Registration<rusb::Context> = rusb::HotplugBuilder::new()
+            .vendor_id(USBDM_VID)
+            .enumerate(false)
+            .register(&context, Box::new(HotplugSink { sink }))?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let thread = thread::spawn(move || {
+            let _registration = registration;
+            while thread_running.load(Ordering::Relaxed) {
+                if let Err(e) = context.handle_events(Some(Duration::from_millis(EVENT_LOOP_TIMEOUT))) {
+                    println!("Hotplug event loop error {:?}", e);
+                    break;
+                }
+            }
+        });
+        Ok(HotplugWatcher { running, thread: Some(thread) })
+    }
+
+    /// `start_polling` - compare USBDM ports every `period`
+    pub fn start_polling<F>(mut sink: F, period: Duration) -> Self
+    where F: FnMut(HotplugEvent) + Send + 'static
+    {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let thread = thread::spawn(move || {
+            let mut known = usbdm_ports();
+            while thread_running.load(Ordering::Relaxed) {
+                thread::sleep(period);
+                let present = usbdm_ports();
+                for event in diff_ports(&known, &present) {
+                    sink(event);
+                }
+                known = present;
+            }
+        });
+        HotplugWatcher { running, thread: Some(thread) }
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Drop for HotplugWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// `usbdm_ports` - ports of all USBDM on the bus, empty set if device list fails
+pub fn usbdm_ports() -> BTreeSet<UsbPort> {
+    match rusb::DeviceList::new() {
+        Ok(list) => list.iter().filter(match_vid).map(|device| UsbPort::of_device(&device)).collect(),
+        Err(_e)  => BTreeSet::new(),
+    }
+}
+
+/// `diff_ports` - events turning `known` set into `present` set, left first
+pub fn diff_ports(known: &BTreeSet<UsbPort>, present: &BTreeSet<UsbPort>) -> Vec<HotplugEvent> {
+    known.difference(present).map(|port| HotplugEvent::Left(*port))
+        .chain(present.difference(known).map(|port| HotplugEvent::Arrived(*port)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polling_diff() {
+        let a = UsbPort { bus: 1, address: 4 };
+        let b = UsbPort { bus: 1, address: 7 };
+        let c = UsbPort { bus: 2, address: 3 };
+        let known : BTreeSet<UsbPort> = [a, b].into_iter().collect();
+        let present : BTreeSet<UsbPort> = [b, c].into_iter().collect();
+
+        assert_eq!(diff_ports(&known, &present), vec![HotplugEvent::Left(a), HotplugEvent::Arrived(c)]);
+        assert!(diff_ports(&present, &present).is_empty());
+    }
+}
@@ -2,6 +2,11 @@
 
 use super::*;
 use crate::errors::{Error, USBDM_ErrorCode};
+use crate::usbdm::jtag_sequence::{JtagSequence, JtagOp, JtagProgram};
+use crate::usbdm::retry::{CommandKind};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
     
 pub const JTAG_COMMAND_MASK         : u8 = 0x7<<5;
 
@@ -125,20 +130,20 @@ pub fn JTAG_IF_VARB_NEQ_32(x: u32) -> Vec<u8> { add_vu(JTAG_PUSH_32(x), JTAG_IF_
 //============================================================================================
 // The following uses a value previously set by JTAG_PUSH...
                                     // 5/8/16/32 bit operand from JTAG_PUSH...
-const JTAG_REPEAT               : u8 = 40;  // Value     Repeat a block N times
+pub const JTAG_REPEAT               : u8 = 40;  // Value     Repeat a block N times
 pub fn JTAG_REPEAT_16(x: u16)      -> Vec<u8> { add_vu(JTAG_PUSH_16(x), JTAG_REPEAT) }  // 16-bit value
 pub fn JTAG_REPEAT_32(x: u32)      -> Vec<u8> { add_vu(JTAG_PUSH_32(x), JTAG_REPEAT) }  // 32-bit value
     
 //============================================================================================
 // The following use an 8-bit operand as next byte in sequence
-const JTAG_REPEAT8: u8 = 41;
+pub const JTAG_REPEAT8              : u8 = 41;
 pub fn JTAG_REPEAT_8(x: u8)        -> Vec<u8> { add_uu(JTAG_REPEAT8, x) }   // 8-bit value
 
 //============================================================================================
 // The following push an 8/16/32-bit operand as the next 1/2/4 bytes in sequence (big-endian)
-const JTAG_PUSH8                    : u8 = 42;
-const JTAG_PUSH16                   : u8 = 43;
-const JTAG_PUSH32                   : u8 = 44;
+pub const JTAG_PUSH8                : u8 = 42;
+pub const JTAG_PUSH16               : u8 = 43;
+pub const JTAG_PUSH32               : u8 = 44;
 
 pub fn JTAG_PUSH_8 (x: u8)         -> Vec<u8> { vec![JTAG_PUSH8,  x] }                                                   // Push an 8-bit #
 pub fn JTAG_PUSH_16(x: u16)        -> Vec<u8> { vec![JTAG_PUSH16, (x>>8)  as u8, x as u8] }                              // Push a 16-bit #
@@ -230,6 +235,9 @@ pub const JTAG_CORE_COMMAND_LENGTH    : u8 = 4;
 pub const CORE_ENABLE_ONCE_COMMAND    : u8 = 0x06;
 pub const CORE_DEBUG_REQUEST_COMMAND  : u8 = 0x07;
 
+/// OnCE status polling interval of `dsc_wait_debug_mode`
+pub const DEBUG_MODE_POLL: Duration = Duration::from_millis(20);
+
 pub const JTAG_READ_MEMORY_HEADER_SIZE: u16 = 8;
 pub const JTAG_WRITE_MEMORY_HEADER_SIZE: u16 = 8;
 
@@ -254,7 +262,7 @@ fn add_vu(mut x: Vec<u8>, y: u8) -> Vec<u8> {
 
 //}
 
-#[derive(Debug, Clone,PartialEq)]
+#[derive(Debug, Clone,PartialEq, Serialize, Deserialize)]
 pub enum OnceStatus {
     
    ExecuteMode,
@@ -291,21 +299,16 @@ impl From <u8>  for OnceStatus  {
     // @note - Leaves Core TAP in RUN-TEST/IDLE
     //
     pub fn read_id_code(commandRegLength :u8, resetTAP: bool, prg:  &Programmer) -> Result<(Vec<u8>), Error> {
-        let mut sequence: Vec<u8> = Vec::new();
-        if resetTAP {
-            sequence.push(JTAG_TEST_LOGIC_RESET);
-        } else {
-            sequence.push(JTAG_NOP);
-        }
-        sequence.push(JTAG_MOVE_IR_SCAN);  // Write IDCODE command to IR
-        sequence.push(JTAG_SET_EXIT_SHIFT_DR);
-        sequence.push(JTAG_SHIFT_OUT_Q(commandRegLength)); 
-        sequence.push(JTAG_IDCODE_COMMAND);
-        sequence.push(JTAG_SET_EXIT_IDLE);  // Read IDCODE from DR
-        sequence.push(JTAG_SHIFT_IN_Q(32));
-        sequence.push(JTAG_END);
-
-        prg.exec_jtag_seq(sequence, 4)
+        let program = JtagSequence::new()
+            .op(if resetTAP { JtagOp::TestLogicReset } else { JtagOp::Nop })
+            .op(JtagOp::MoveIrScan)                          // Write IDCODE command to IR
+            .op(JtagOp::SetExitShiftDr)
+            .shift_out(commandRegLength, JTAG_IDCODE_COMMAND as u32)
+            .op(JtagOp::SetExitIdle)                         // Read IDCODE from DR
+            .shift_in(JTAG_IDCODE_LENGTH)
+            .build()?;
+
+        prg.exec_program_idempotent(&program)
     }
 
     pub fn read_master_id_code_DSC_JTAG_ID(resetTAP: bool, prg:  &Programmer) -> Result<(Vec<u8>), Error> {
@@ -322,20 +325,16 @@ impl From <u8>  for OnceStatus  {
     //  @note - It appears that the sequence must end with a EXIT_SHIFT_DR?
     //  @note Leaves Core TAP in RUN-TEST/IDLE to TLM action??
     pub fn enableCoreTAP(prg:  &Programmer) -> Result<(), Error> {
-        let mut sequence: Vec<u8> = Vec::new();
-        sequence.push(JTAG_TEST_LOGIC_RESET);               // Reset TAP
-        sequence.append(&mut JTAG_REPEAT_16(50)); // ~2.26ms
-        sequence.push(JTAG_NOP);
-        sequence.push(JTAG_END_REPEAT);
-        sequence.push(JTAG_MOVE_IR_SCAN);                   // Write TLM command to IR
-        sequence.push(JTAG_SET_EXIT_SHIFT_DR);
-        sequence.push(JTAG_SHIFT_OUT_Q(JTAG_MASTER_COMMAND_LENGTH));  
-        sequence.push(JTAG_TLM_SELECT_COMMAND);
-        sequence.push(JTAG_SET_EXIT_IDLE);                  // Select Core TAP
-        sequence.push(JTAG_SHIFT_OUT_Q(TLM_REGISTER_LENGTH)); 
-        sequence.push(TLM_SLAVE_SELECT_MASK);
-        sequence.push(JTAG_END);
-        prg.exec_jtag_seq(sequence, 0)?;
+        let program = JtagSequence::new()
+            .op(JtagOp::TestLogicReset)                      // Reset TAP
+            .repeat(50).op(JtagOp::Nop).end_repeat()         // ~2.26ms
+            .op(JtagOp::MoveIrScan)                          // Write TLM command to IR
+            .op(JtagOp::SetExitShiftDr)
+            .shift_out(JTAG_MASTER_COMMAND_LENGTH, JTAG_TLM_SELECT_COMMAND as u32)
+            .op(JtagOp::SetExitIdle)                         // Select Core TAP
+            .shift_out(TLM_REGISTER_LENGTH, TLM_SLAVE_SELECT_MASK as u32)
+            .build()?;
+        prg.exec_program(&program)?;
         Ok(())
     }
 
@@ -354,13 +353,12 @@ impl From <u8>  for OnceStatus  {
      /// ```
      /// `EOnCE` = `Enhanced On-chip emulation (unit)`
      pub fn enableONCE(prg:  &Programmer) -> Result<(OnceStatus), Error> {
-        let mut sequence: Vec<u8> = Vec::new();
-        sequence.push(JTAG_MOVE_IR_SCAN);                // Write enable EONCE command to IR
-        sequence.push(JTAG_SET_EXIT_IDLE); 
-        sequence.push(JTAG_SHIFT_IN_OUT_Q(JTAG_CORE_COMMAND_LENGTH));
-        sequence.push(CORE_ENABLE_ONCE_COMMAND);
-        sequence.push(JTAG_END);
-        let answer = prg.exec_jtag_seq(sequence, JTAG_CORE_COMMAND_LENGTH)?;
+        let program = JtagSequence::new()
+            .op(JtagOp::MoveIrScan)                          // Write enable EONCE command to IR
+            .op(JtagOp::SetExitIdle)
+            .shift_in_out(JTAG_CORE_COMMAND_LENGTH, CORE_ENABLE_ONCE_COMMAND as u32)
+            .build()?;
+        let answer = prg.exec_program_idempotent(&program)?;
         let once_byte = answer[0];
         Ok((OnceStatus::from(once_byte)))
     }
@@ -375,14 +373,13 @@ impl Programmer
     ///
     /// Private helper function use `dsc_target_halt()` instead
     pub fn targetDebugRequest(&self) -> Result<OnceStatus, Error> {
-        let mut sequence: Vec<u8> = Vec::new();
-        sequence.push(JTAG_MOVE_IR_SCAN);                // Write enable EONCE command to IR
-        sequence.push(JTAG_SET_EXIT_IDLE); 
-        sequence.push(JTAG_SHIFT_IN_OUT_Q(JTAG_CORE_COMMAND_LENGTH));
-        sequence.push(CORE_DEBUG_REQUEST_COMMAND);
-        sequence.push(JTAG_END);
-        let answer = self.exec_jtag_seq(sequence, JTAG_CORE_COMMAND_LENGTH)?;
-        let once_byte = answer[0]; // TODO need right conversion!!! from 4 byte of answer to one once byte. now empric first byte from debug
+        let program = JtagSequence::new()
+            .op(JtagOp::MoveIrScan)                          // Write debug request command to IR
+            .op(JtagOp::SetExitIdle)
+            .shift_in_out(JTAG_CORE_COMMAND_LENGTH, CORE_DEBUG_REQUEST_COMMAND as u32)
+            .build()?;
+        let answer = self.exec_program_idempotent(&program)?;
+        let once_byte = answer[0]; // 4 bits of IR capture in one byte
         Ok(OnceStatus::from(once_byte))
     }
     
@@ -426,16 +423,50 @@ impl Programmer
     /// if PWD bit not inited DSC not halt after execution (just executes two NOPs), and it will be undef. bev.! 
     pub fn dsc_target_go(&self) -> Result<(), Error> {
 
-        let mut sequence: Vec<u8> = Vec::new();
-        sequence.push(JTAG_MOVE_DR_SCAN);  // Write to ONCE (DR-CHAIN)
-        sequence.push(JTAG_SET_EXIT_IDLE);
-        sequence.push(JTAG_SHIFT_OUT_Q(ONCE_CMD_LENGTH));  // ONCE command
-        sequence.push(ONCE_CMD_EXIT|ONCE_CMD_NOREG);
-        sequence.push(JTAG_END);  // Terminate sequence
-        self.exec_jtag_seq(sequence, 0)?; // Terminate sequence
+        let program = JtagSequence::new()
+            .op(JtagOp::MoveDrScan)                          // Write to ONCE (DR-CHAIN)
+            .op(JtagOp::SetExitIdle)
+            .shift_out(ONCE_CMD_LENGTH, (ONCE_CMD_EXIT|ONCE_CMD_NOREG) as u32)  // ONCE command
+            .build()?;
+        self.exec_program(&program)?;
         Ok(())
     }
 
+    /// `dsc_set_instruction_step` - OCR instruction step control for next `dsc_target_go`
+    ///
+    /// `Some(n)` - core re-enters debug mode after n instructions (OSCNTR = n - 1), `None` - core runs freely.
+    /// OCR_PWU is set in both cases, so `debughlt` instruction halts core.
+    ///
+    /// `note` Core must be halted
+    pub fn dsc_set_instruction_step(&self, steps: Option<u32>) -> Result<(), Error> {
+        let ocr = (self.dsc_read_once_reg(DscRegisters::DscRegOcr)? | OCR_PWU as u32) & !(OCR_ISC_7 as u32);
+        match steps {
+            None => self.dsc_write_once_reg(DscRegisters::DscRegOcr, ocr),
+            Some(steps) if steps == 0 || steps > 0x0100_0000 =>
+                Err(Error::InternalError(format!("Instruction step count {} out of range", steps))),
+            Some(steps) => {
+                self.dsc_write_once_reg(DscRegisters::DscRegOscntr, steps - 1)?;
+                self.dsc_write_once_reg(DscRegisters::DscRegOcr, ocr | OCR_ISC_SINGLE_STEP as u32)
+            },
+        }
+    }
+
+    /// `dsc_wait_debug_mode` - polls OnCE status until core enters debug mode, `false` if it did not in `timeout`
+    ///
+    /// `note` Assumes Core TAP is active & in RUN-TEST/IDLE
+    pub fn dsc_wait_debug_mode(&self, timeout: Duration) -> Result<bool, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if enableONCE(self)? == OnceStatus::DebugMode {
+                return Ok(true)
+            }
+            if Instant::now() >= deadline {
+                return Ok(false)
+            }
+            thread::sleep(DEBUG_MODE_POLL.min(timeout));
+        }
+    }
+
     // Read X/P memory via ONCE & target execution
     //
     // @param memorySpace - Memory space & size of memory accesses 1/2/4 bytes
@@ -447,8 +478,8 @@ impl Programmer
     // @note If memory space size is byte size then address is DSC byte pointer address
     // @note Size is limited to dscInfo.maxMemoryReadSize
     //
-    /// Private helper function use `dsc_read_memory` instead
-    fn read_memory_block(&self, mut memory_space: u8, num_bytes: u8, address: u32) -> Result<(Vec<u8>), Error> {
+    /// Private helper function use `dsc_read_memory` instead, builds JTAG sequence of one block read
+    fn read_memory_sequence(&self, mut memory_space: u8, num_bytes: u8, address: u32) -> Result<JtagProgram, Error> {
         if (memory_space == memory_space_t::MS_PLONG) {
             // Treat as word access
             memory_space = memory_space_t::MS_PWORD;
@@ -487,19 +518,12 @@ impl Programmer
          *    +-----------------------+
          */
 
-        let mut sequence: Vec<u8> = Vec::with_capacity(JTAG_READ_MEMORY_HEADER_SIZE.into());
-        sequence.push(JTAG_READ_MEM);          // 0
-        sequence.push(JTAG_END);               // 1
-        sequence.push((address >> 24) as u8);  // 2 Address
-        sequence.push((address >> 16) as u8);  // 3
-        sequence.push((address >> 8) as u8);   // 4
-        sequence.push(address as u8);          // 5
-        sequence.push(num_bytes_adjusted);     // 6 Elements
-        sequence.push(memory_space);           // 7 Memory space
-
-        let block = self.exec_jtag_seq(sequence, num_bytes)?;
+        let program = JtagSequence::new()
+            .read_mem(memory_space, num_bytes_adjusted, address)
+            .build()?;
+        self.require_program(&program)?;
 
-        Ok(block)
+        self.chain.place(&program)
     }
 
 
@@ -518,9 +542,9 @@ impl Programmer
         let element_size: u8 = memory_space & memory_space_t::MS_SIZE;
         let mut bytes_done: u32 = 0;
         let mut current_address: u32 = address;
-        let mut output: Vec<u8> = Vec::new();
+        let mut commands: Vec<(Vec<u8>, usize)> = Vec::new();
 
-        let max_read_size: u32 = 0x20;
+        let max_read_size: u32 = self.transfer.memory_block() as u32;
 
         while (bytes_done < num_bytes) {
             let mut block_size: u32 = num_bytes - bytes_done;
@@ -528,8 +552,8 @@ impl Programmer
             if (block_size > max_read_size) {
                 block_size = max_read_size; }
             
-            let mut data = self.read_memory_block(memory_space, block_size as u8, current_address)?;
-            output.append(&mut data);
+            let program = self.read_memory_sequence(memory_space, block_size as u8, current_address)?;
+            commands.push((program.command(), program.answer_length as usize + 1));
             bytes_done += block_size;
             if element_size == memory_space_t::MS_BYTE {
                 current_address += block_size; // Byte currentAddress advanced by count of bytes written
@@ -537,7 +561,9 @@ impl Programmer
                 current_address += block_size / 2; // Address advanced by count of words written
             }
         }
-        Ok(output)
+        // blocks go pipelined if `transfer.depth` allows, answers come in order with status byte first
+        let answers = self.bdm_pipeline(&commands, CommandKind::Idempotent)?;
+        Ok(answers.into_iter().flat_map(|answer| answer.into_iter().skip(1)).collect())
     }
 
     //================================================================================
@@ -552,8 +578,8 @@ impl Programmer
     // @note If memory space size is byte size then address is DSC byte pointer address
     // @note Size is limited to dscInfo.maxMemoryWriteSize
     //
-    /// Private helper function use `dsc_write_memory` instead
-    fn write_memory_block(&self, mut memory_space: u8, mut data: Vec<u8>, address: u32) -> Result<(), Error> {
+    /// Private helper function use `dsc_write_memory` instead, builds JTAG sequence of one block write
+    fn write_memory_sequence(&self, mut memory_space: u8, mut data: Vec<u8>, address: u32) -> Result<JtagProgram, Error> {
         if (memory_space == memory_space_t::MS_PLONG) {
             // Treat as word access
             memory_space = memory_space_t::MS_PWORD;
@@ -598,19 +624,12 @@ impl Programmer
          *    +-----------------------+
          */
 
-        let mut sequence: Vec<u8> = Vec::with_capacity(JTAG_READ_MEMORY_HEADER_SIZE.into());
-        sequence.push(JTAG_WRITE_MEM);          // 0
-        sequence.push(JTAG_END);               // 1
-        sequence.push((address >> 24) as u8);  // 2 Address
-        sequence.push((address >> 16) as u8);  // 3
-        sequence.push((address >> 8) as u8);   // 4
-        sequence.push(address as u8);          // 5
-        sequence.push(num_bytes_adjusted);     // 6 Elements
-        sequence.push(memory_space);           // 7 Memory space
-        sequence.append(&mut data);
-
-        self.exec_jtag_seq(sequence, 0)?;
-        Ok(())
+        let program = JtagSequence::new()
+            .write_mem(memory_space, num_bytes_adjusted, address, &data)
+            .build()?;
+        self.require_program(&program)?;
+
+        self.chain.place(&program)
     }
 
     //================================================================================
@@ -628,7 +647,8 @@ impl Programmer
         let mut current_address: u32 = address;
         let element_size: u8 = memory_space & memory_space_t::MS_SIZE;
 
-        let max_write_size: usize = 0x20;
+        let max_write_size: usize = self.transfer.memory_block();
+        let mut commands: Vec<(Vec<u8>, usize)> = Vec::new();
 
         while (data.len() > 0) {
             let mut block_size = data.len();
@@ -636,16 +656,67 @@ impl Programmer
             if (block_size > max_write_size) {
                 block_size = max_write_size; };
 
-            self.write_memory_block(memory_space, data.drain(..block_size).collect(), current_address)?;
+            let program = self.write_memory_sequence(memory_space, data.drain(..block_size).collect(), current_address)?;
+            commands.push((program.command(), 1));
             if element_size == memory_space_t::MS_BYTE {
                 current_address += block_size as u32; // Byte currentAddress advanced by count of bytes written
             } else {
                 current_address += block_size as u32 / 2; // Address advanced by count of words written
             }
         }
-     
+        self.bdm_pipeline(&commands, CommandKind::NonIdempotent)?;
         Ok(())
     }
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::transport::ScriptedTransport;
+
+    fn scripted_programmer() -> (ScriptedTransport, Programmer) {
+        let script = ScriptedTransport::new();
+        let prog = Programmer::from_transport(Box::new(script.clone()));
+        (script, prog)
+    }
+
+    #[test]
+    fn read_core_id_code_framing() {
+        let (script, prog) = scripted_programmer();
+        let sequence = vec![
+            JTAG_TEST_LOGIC_RESET, JTAG_MOVE_IR_SCAN, JTAG_SET_EXIT_SHIFT_DR,
+            JTAG_SHIFT_OUT_Q(JTAG_CORE_COMMAND_LENGTH), JTAG_IDCODE_COMMAND,
+            JTAG_SET_EXIT_IDLE, JTAG_SHIFT_IN_Q(32), JTAG_END];
+        let mut command = vec![4 + sequence.len() as u8, bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE, 4, sequence.len() as u8];
+        command.extend(sequence);
+        script.expect(command, vec![0x00, 0x02, 0x21, 0x10, 0x04]);
+
+        assert_eq!(read_core_id_code(true, &prog).unwrap(), vec![0x02, 0x21, 0x10, 0x04]);
+        assert!(script.is_finished());
+    }
+
+    #[test]
+    fn read_memory_split_in_blocks() {
+        let (script, mut prog) = scripted_programmer();
+        prog.transfer.max_memory_block = 0x20;
+        script.answer([vec![0x00], vec![0xAA; 0x20]].concat());
+        script.answer([vec![0x00], vec![0xBB; 0x10]].concat());
+
+        let data = prog.dsc_read_memory(memory_space_t::MS_XWORD, 0x30, 0x8000).unwrap();
+        assert_eq!(data, [vec![0xAA; 0x20], vec![0xBB; 0x10]].concat());
+
+        let written = script.written();
+        assert_eq!(written.len(), 2);
+        // second block starts 0x10 words later, 8 words long
+        assert_eq!(&written[1][4..], &[JTAG_READ_MEM, JTAG_END, 0x00, 0x00, 0x80, 0x10, 0x08, memory_space_t::MS_XWORD]);
+    }
+
+    #[test]
+    fn error_status_from_usbdm() {
+        let (script, prog) = scripted_programmer();
+        script.answer(vec![0x01]);
+        assert!(matches!(enableONCE(&prog), Err(Error::USBDM_Errors(_))));
+    }
+}
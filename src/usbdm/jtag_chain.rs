@@ -0,0 +1,515 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use crate::errors::Error;
+use crate::usbdm::Programmer;
+use crate::usbdm::constants::jtag_shift::*;
+use crate::usbdm::jtag::*;
+use crate::usbdm::jtag_interpreter::TapRegister;
+use crate::usbdm::jtag_sequence::{JtagOp, JtagProgram, MAX_SEQUENCE_LENGTH, decode};
+
+/// Most TAPs `scan_chain` looks for
+pub const MAX_CHAIN_TAPS    : usize = 16;
+/// Longest IR chain (sum of all IR lengths) `scan_chain` measures
+pub const MAX_CHAIN_IR_BITS : usize = 256;
+/// Bits per `CMD_USBDM_JTAG_READ` / `CMD_USBDM_JTAG_WRITE`
+const CHUNK_BITS            : usize = 128;
+/// JEDEC manufacturer of Freescale / NXP in IDCODE bits 11..1
+pub const FREESCALE_MANUFACTURER : u16 = 0x00E;
+
+/// `ChainPosition` - bypass bits of other TAPs around our TAP on JTAG chain
+///
+/// `*_pre` bits belong to TAPs between our TAP and TDO, they are shifted first and their captured
+/// bits come out first. `*_post` bits belong to TAPs between TDI and our TAP. IR bits are written as 1's
+/// (BYPASS instruction), DR bits are 1-bit BYPASS registers of other TAPs, which IR scans put them in.
+/// Default - our TAP alone on chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainPosition {
+    pub ir_pre  : u16,
+    pub ir_post : u16,
+    pub dr_pre  : u16,
+    pub dr_post : u16,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Exit { Stay, Idle, ShiftDr, ShiftIr }
+
+impl Exit {
+    fn op(self) -> JtagOp {
+        match self {
+            Exit::Stay    => JtagOp::SetStayShift,
+            Exit::Idle    => JtagOp::SetExitIdle,
+            Exit::ShiftDr => JtagOp::SetExitShiftDr,
+            Exit::ShiftIr => JtagOp::SetExitShiftIr,
+        }
+    }
+
+    fn from_action(shift_exit: u8) -> Exit {
+        match shift_exit & JTAG_EXIT_ACTION_MASK {
+            JTAG_STAY_SHIFT    => Exit::Stay,
+            JTAG_EXIT_IDLE     => Exit::Idle,
+            JTAG_EXIT_SHIFT_DR => Exit::ShiftDr,
+            _                  => Exit::ShiftIr,
+        }
+    }
+
+    /// scan after shift with this exit: register and whether it already started
+    fn next_scan(self, register: TapRegister) -> Option<(TapRegister, bool)> {
+        match self {
+            Exit::Stay    => Some((register, true)),
+            Exit::Idle    => None,
+            Exit::ShiftDr => Some((TapRegister::Dr, false)),
+            Exit::ShiftIr => Some((TapRegister::Ir, false)),
+        }
+    }
+}
+
+fn is_shift(op: &JtagOp) -> bool {
+    matches!(op, JtagOp::ShiftOutDpVarA | JtagOp::ShiftOutVar(..) | JtagOp::ShiftInOutVar(..) | JtagOp::ShiftOutDp(_) |
+        JtagOp::ShiftInDp(_) | JtagOp::ShiftInOutDp(_) | JtagOp::ShiftInQ(_) | JtagOp::ShiftOutQ(..) | JtagOp::ShiftInOutQ(..))
+}
+
+/// `pad_ops` - `bits` of bypass value as in-line data chunks of at most 32 bits
+fn pad_ops(bits: u16, register: TapRegister) -> Vec<JtagOp> {
+    let mut ops = Vec::new();
+    let mut left = bits;
+    while left > 0 {
+        let chunk = left.min(32) as u8;
+        let value: u32 = match register {
+            TapRegister::Ir if chunk == 32 => u32::MAX,
+            TapRegister::Ir                => (1 << chunk) - 1,
+            TapRegister::Dr                => 0,
+        };
+        ops.push(JtagOp::ShiftOutQ(chunk, value.to_be_bytes()[4 - BITS_TO_BYTES(chunk) as usize..].to_vec()));
+        left -= chunk as u16;
+    }
+    ops
+}
+
+impl ChainPosition {
+
+    /// `is_alone` - no other TAPs, nothing to add
+    pub fn is_alone(&self) -> bool {
+        *self == ChainPosition::default()
+    }
+
+    /// `padding` - bits before and after our data in scan of `register`
+    pub fn padding(&self, register: TapRegister) -> (u16, u16) {
+        match register {
+            TapRegister::Ir => (self.ir_pre, self.ir_post),
+            TapRegister::Dr => (self.dr_pre, self.dr_post),
+        }
+    }
+
+    /// `place` - `program` with bypass bits shifted before first and after last shift of every scan
+    ///
+    /// Answer is unchanged, padding is shifted out only. Scan must start in the program (MOVE_xR_SCAN or exit
+    /// to SHIFT-xR), shifts inside IF/REPEAT/SUB are refused. Firmware routines (target instruction execution,
+    /// `JTAG_READ_MEM` / `JTAG_WRITE_MEM`) shift as if DSC was alone, so they are refused on shared chain.
+    pub fn place(&self, program: &JtagProgram) -> Result<JtagProgram, Error> {
+        if self.is_alone() {
+            return Ok(program.clone())
+        }
+        let refuse = |reason: String| Error::JtagChainInvalid(format!("{} (chain {})", reason, self));
+        let (ops, data) = decode(&program.sequence)?;
+        let mut placed: Vec<JtagOp> = Vec::new();
+        let mut scan: Option<(TapRegister, bool)> = None;
+        let mut exit = Exit::Idle;
+        let mut depth = 0;
+        for (offset, op) in ops {
+            match &op {
+                JtagOp::End => break,
+                JtagOp::TestLogicReset   => scan = None,
+                JtagOp::MoveDrScan       => scan = Some((TapRegister::Dr, false)),
+                JtagOp::MoveIrScan       => scan = Some((TapRegister::Ir, false)),
+                JtagOp::SetStayShift     => exit = Exit::Stay,
+                JtagOp::SetExitIdle      => exit = Exit::Idle,
+                JtagOp::SetExitShiftDr   => exit = Exit::ShiftDr,
+                JtagOp::SetExitShiftIr   => exit = Exit::ShiftIr,
+                JtagOp::If(_) | JtagOp::Repeat | JtagOp::Repeat8(_) | JtagOp::RepeatQ(_) | JtagOp::RepeatDp | JtagOp::Sub(_) => depth += 1,
+                JtagOp::EndIf | JtagOp::EndRepeat | JtagOp::EndSub => depth -= 1,
+                JtagOp::CallSub(_) | JtagOp::ReadMem | JtagOp::WriteMem =>
+                    return Err(refuse(format!("{} at {:04X} runs in USBDM firmware, which knows only DSC alone on chain", op, offset))),
+                op if is_shift(op) => {
+                    if depth > 0 {
+                        return Err(refuse(format!("{} at {:04X} is inside block, can't pad", op, offset)))
+                    }
+                    let (register, started) = scan.ok_or_else(|| refuse(format!("{} at {:04X} continues scan of previous sequence", op, offset)))?;
+                    let (pre, post) = self.padding(register);
+                    let needs_pre = !started && pre > 0;
+                    let needs_post = exit != Exit::Stay && post > 0;
+                    if needs_pre || needs_post {
+                        placed.push(JtagOp::SetStayShift);
+                    }
+                    if needs_pre {
+                        placed.extend(pad_ops(pre, register));
+                        if !needs_post && exit != Exit::Stay {
+                            placed.push(exit.op());
+                        }
+                    }
+                    placed.push(op.clone());
+                    if needs_post {
+                        let mut pad = pad_ops(post, register);
+                        let last = pad.pop();
+                        placed.extend(pad);
+                        placed.push(exit.op());
+                        placed.extend(last);
+                    }
+                    scan = exit.next_scan(register);
+                    continue
+                },
+                _ => {},
+            }
+            placed.push(op);
+        }
+        let mut sequence = Vec::new();
+        for op in placed {
+            op.encode(&mut sequence);
+        }
+        JtagOp::End.encode(&mut sequence);
+        sequence.extend(data);
+        if sequence.len() > MAX_SEQUENCE_LENGTH {
+            return Err(refuse(format!("sequence of {} bytes with padding, USBDM takes at most {}", sequence.len(), MAX_SEQUENCE_LENGTH)))
+        }
+        Ok(JtagProgram { sequence, answer_length: program.answer_length, features: program.features.clone() })
+    }
+}
+
+impl fmt::Display for ChainPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IR pre {} post {}, DR pre {} post {}", self.ir_pre, self.ir_post, self.dr_pre, self.dr_post)
+    }
+}
+
+/// `ChainTap` - one TAP found by `scan_chain`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainTap {
+    /// `None` - TAP has no IDCODE register (BYPASS after reset)
+    pub id_code   : Option<u32>,
+    /// `None` - can't tell from captured IR pattern, set it by hand
+    pub ir_length : Option<u8>,
+}
+
+impl ChainTap {
+    pub fn manufacturer(&self) -> Option<u16> {
+        self.id_code.map(|id| ((id >> 1) & 0x7FF) as u16)
+    }
+}
+
+/// `JtagChain` - TAPs on JTAG chain, index 0 is nearest to TDO
+#[derive(Debug, Clone, PartialEq)]
+pub struct JtagChain {
+    pub taps     : Vec<ChainTap>,
+    /// Sum of all IR lengths
+    pub ir_total : usize,
+}
+
+impl JtagChain {
+
+    /// `infer_ir_lengths` - when only one IR length is unknown it is what is left of `ir_total`
+    pub fn infer_ir_lengths(&mut self) {
+        let unknown: Vec<usize> = (0..self.taps.len()).filter(|i| self.taps[*i].ir_length.is_none()).collect();
+        if unknown.len() == 1 {
+            let known: usize = self.taps.iter().filter_map(|tap| tap.ir_length).map(|length| length as usize).sum();
+            if self.ir_total > known && self.ir_total - known <= u8::MAX as usize {
+                self.taps[unknown[0]].ir_length = Some((self.ir_total - known) as u8);
+            }
+        }
+    }
+
+    /// `position` - bypass bits around TAP `index`
+    pub fn position(&self, index: usize) -> Result<ChainPosition, Error> {
+        if index >= self.taps.len() {
+            return Err(Error::JtagChainInvalid(format!("no TAP {} on chain of {}", index, self.taps.len())))
+        }
+        let ir_sum = |taps: &[ChainTap]| -> Result<u16, Error> {
+            taps.iter().map(|tap| tap.ir_length.map(|length| length as u16)).sum::<Option<u16>>()
+                .ok_or_else(|| Error::JtagChainInvalid("IR length of TAP unknown, set bypass bits by hand".to_string()))
+        };
+        Ok(ChainPosition {
+            ir_pre  : ir_sum(&self.taps[..index])?,
+            ir_post : ir_sum(&self.taps[index + 1..])?,
+            dr_pre  : index as u16,
+            dr_post : (self.taps.len() - index - 1) as u16,
+        })
+    }
+
+    /// `dsc_index` - first Freescale TAP, DSC master TAP (8-bit IR) on our boards
+    pub fn dsc_index(&self) -> Option<usize> {
+        self.taps.iter().position(|tap| tap.manufacturer() == Some(FREESCALE_MANUFACTURER))
+    }
+
+    /// `dsc_position` - bypass bits around DSC, its IR taken as master TAP length if not measured
+    pub fn dsc_position(&self) -> Result<ChainPosition, Error> {
+        let index = self.dsc_index().ok_or_else(|| Error::TargetNotConnected("No Freescale TAP on JTAG chain".to_string()))?;
+        let mut chain = self.clone();
+        chain.taps[index].ir_length.get_or_insert(JTAG_MASTER_COMMAND_LENGTH);
+        chain.infer_ir_lengths();
+        chain.position(index)
+    }
+}
+
+impl fmt::Display for JtagChain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "JTAG chain: {} TAP(s), IR {} bits, TAP 0 nearest TDO", self.taps.len(), self.ir_total)?;
+        for (index, tap) in self.taps.iter().enumerate() {
+            let id = match tap.id_code {
+                Some(id) => format!("IDCODE {:08X} (manufacturer {:03X}, part {:04X}, version {})", id, (id >> 1) & 0x7FF, (id >> 12) & 0xFFFF, id >> 28),
+                None     => "no IDCODE".to_string(),
+            };
+            let ir = tap.ir_length.map_or("?".to_string(), |length| length.to_string());
+            writeln!(f, "  TAP {}: {}, IR {} bits", index, id, ir)?;
+        }
+        Ok(())
+    }
+}
+
+/// `split_ir` - IR lengths from captured IR bits (first out of TDO first)
+///
+/// Every IR captures ..01, so TAP starts where 1 is followed by 0. When that gives not exactly `count`
+/// TAPs starting at first bit, lengths are unknown.
+pub fn split_ir(captured: &[bool], count: usize) -> Vec<Option<u8>> {
+    if count == 1 && captured.len() <= u8::MAX as usize {
+        return vec![Some(captured.len() as u8)]
+    }
+    let starts: Vec<usize> = (0..captured.len().saturating_sub(1)).filter(|i| captured[*i] && !captured[*i + 1]).collect();
+    if starts.len() != count || starts.first() != Some(&0) {
+        return vec![None; count]
+    }
+    let mut ends = starts[1..].to_vec();
+    ends.push(captured.len());
+    starts.iter().zip(ends).map(|(start, end)| u8::try_from(end - start).ok()).collect()
+}
+
+pub(crate) fn to_bits(bytes: &[u8], count: usize) -> Vec<bool> {
+    (0..count).map(|bit| (bytes[bytes.len() - 1 - bit / 8] >> (bit % 8)) & 1 != 0).collect()
+}
+
+pub(crate) fn to_bytes(bits: &[bool]) -> Vec<u8> {
+    let length = bits.len().div_ceil(8);
+    let mut bytes = vec![0u8; length];
+    for (bit, value) in bits.iter().enumerate() {
+        if *value {
+            bytes[length - 1 - bit / 8] |= 1 << (bit % 8);
+        }
+    }
+    bytes
+}
+
+impl Programmer {
+
+    /// `jtag_scan` - write or read of selected register with bypass bits of `chain` around it
+    pub(crate) fn jtag_scan(&mut self, shift_exit: u8, bit_count: u8, data: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+        let exit = Exit::from_action(shift_exit);
+        let Some((register, started)) = self.raw_scan else {
+            // TAP state not known (shift selected before), nothing to pad
+            return self.jtag_shift_command(shift_exit, bit_count, data)
+        };
+        let (pre, post) = self.chain.padding(register);
+        let fill = register == TapRegister::Ir;
+        if !started && pre > 0 {
+            self.write_bits(&vec![fill; pre as usize], JTAG_STAY_SHIFT)?;
+        }
+        let needs_post = exit != Exit::Stay && post > 0;
+        let data_exit = if needs_post { (shift_exit & !JTAG_EXIT_ACTION_MASK) | JTAG_STAY_SHIFT } else { shift_exit };
+        let answer = self.jtag_shift_command(data_exit, bit_count, data)?;
+        if needs_post {
+            self.write_bits(&vec![fill; post as usize], shift_exit & JTAG_EXIT_ACTION_MASK)?;
+        }
+        self.raw_scan = exit.next_scan(register);
+        Ok(answer)
+    }
+
+    /// `write_bits` - `bits` (first shifted first) in chunks, last chunk with `shift_exit`
+    fn write_bits(&mut self, bits: &[bool], shift_exit: u8) -> Result<(), Error> {
+        let chunks: Vec<&[bool]> = bits.chunks(CHUNK_BITS).collect();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let exit = if index + 1 == chunks.len() { shift_exit } else { JTAG_STAY_SHIFT };
+            self.jtag_shift_command(exit, chunk.len() as u8, Some(&to_bytes(chunk)))?;
+        }
+        Ok(())
+    }
+
+    /// `read_bits` - `count` bits shifting in 1's, last chunk with `shift_exit`
+    fn read_bits(&mut self, count: usize, shift_exit: u8) -> Result<Vec<bool>, Error> {
+        let mut bits = Vec::with_capacity(count);
+        let mut left = count;
+        while left > 0 {
+            let chunk = left.min(CHUNK_BITS);
+            left -= chunk;
+            let exit = if left == 0 { shift_exit } else { JTAG_STAY_SHIFT };
+            let answer = self.jtag_shift_command(exit | JTAG_WRITE_1, chunk as u8, None)?;
+            bits.extend(to_bits(&answer, chunk));
+        }
+        Ok(bits)
+    }
+
+    /// `scan_chain` - find TAPs on JTAG chain, their IDCODEs and IR lengths
+    ///
+    /// 1. all TAPs in BYPASS, 0's pushed through DR, count of 0's before 1's come back is TAP count
+    /// 2. after reset DR of TAP is IDCODE (32 bits, LSB 1) or BYPASS (one 0 bit)
+    /// 3. captured IR pattern, then 0's pushed through IR, count of 0's before 1's is IR chain length
+    ///
+    /// Works with raw JTAG commands, `chain` is not used. Leaves TAPs reset, TAP in RUN-TEST/IDLE.
+    pub fn scan_chain(&mut self) -> Result<JtagChain, Error> {
+        self.raw_scan = None;
+
+        self.jtag_reset()?;
+        self.select_shift_unpadded(JTAG_SHIFT_IR)?;
+        self.write_bits(&[true; MAX_CHAIN_IR_BITS], JTAG_EXIT_IDLE)?;
+        self.select_shift_unpadded(JTAG_SHIFT_DR)?;
+        self.write_bits(&[false; MAX_CHAIN_TAPS], JTAG_STAY_SHIFT)?;
+        let flushed = self.read_bits(MAX_CHAIN_TAPS + 1, JTAG_EXIT_IDLE)?;
+        let count = match flushed.iter().position(|bit| *bit) {
+            Some(0)     => return Err(Error::TargetNotConnected("No TAP on JTAG chain (TDO stuck at 1)".to_string())),
+            Some(count) => count,
+            None        => return Err(Error::TargetNotConnected(format!("TDO stuck at 0 or more than {} TAPs on JTAG chain", MAX_CHAIN_TAPS))),
+        };
+
+        self.jtag_reset()?;
+        self.select_shift_unpadded(JTAG_SHIFT_DR)?;
+        let ids = self.read_bits(count * 32, JTAG_EXIT_IDLE)?;
+        let mut taps = Vec::new();
+        let mut position = 0;
+        for _ in 0..count {
+            if ids[position] {
+                let id = ids[position..position + 32].iter().rev().fold(0u32, |value, bit| (value << 1) | *bit as u32);
+                taps.push(ChainTap { id_code: Some(id), ir_length: None });
+                position += 32;
+            } else {
+                taps.push(ChainTap { id_code: None, ir_length: None });
+                position += 1;
+            }
+        }
+
+        self.jtag_reset()?;
+        self.select_shift_unpadded(JTAG_SHIFT_IR)?;
+        let captured = self.read_bits(MAX_CHAIN_IR_BITS, JTAG_STAY_SHIFT)?;
+        self.write_bits(&[false; MAX_CHAIN_IR_BITS], JTAG_STAY_SHIFT)?;
+        // 1's shifted in last, every TAP updates to BYPASS
+        let flushed = self.read_bits(MAX_CHAIN_IR_BITS, JTAG_EXIT_IDLE)?;
+        let ir_total = flushed.iter().position(|bit| *bit)
+            .ok_or_else(|| Error::TargetNotConnected(format!("IR chain longer than {} bits", MAX_CHAIN_IR_BITS)))?;
+        for (tap, length) in taps.iter_mut().zip(split_ir(&captured[..ir_total], count)) {
+            tap.ir_length = length;
+        }
+        self.jtag_reset()?;
+
+        let mut chain = JtagChain { taps, ir_total };
+        chain.infer_ir_lengths();
+        Ok(chain)
+    }
+
+    fn select_shift_unpadded(&mut self, shift: u8) -> Result<(), Error> {
+        self.jtag_select_shift(shift)?;
+        // scan routines shift whole chain themselves
+        self.raw_scan = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::jtag_interpreter::{InterpreterTransport, SimpleTap, VirtualChain};
+    use crate::usbdm::jtag_sequence::JtagSequence;
+    use crate::usbdm::virtual_dsc::{DscTap, VIRTUAL_MASTER_ID, VIRTUAL_CORE_ID};
+    use crate::usbdm::constants::memory_space_t;
+    use crate::usbdm::registers::*;
+
+    const CPLD_ID : u32 = 0x0960_2093;
+
+    /// CPLD nearest TDO, DSC, then TAP without IDCODE nearest TDI
+    fn board() -> (InterpreterTransport<VirtualChain<DscTap>>, Programmer) {
+        let chain = VirtualChain {
+            before : vec![SimpleTap::new(6, Some(CPLD_ID))],
+            target : DscTap::new(),
+            after  : vec![SimpleTap::new(5, None)],
+        };
+        let link = InterpreterTransport::new(chain);
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        (link, prog)
+    }
+
+    #[test]
+    fn ir_split_on_capture_pattern() {
+        let bits = |pattern: &str| pattern.chars().map(|c| c == '1').collect::<Vec<bool>>();
+        assert_eq!(split_ir(&bits("10000000"), 1), vec![Some(8)]);
+        assert_eq!(split_ir(&bits("10000010000000100"), 3), vec![Some(6), Some(8), Some(3)]);
+        // extra 10 inside capture of one TAP - unknown
+        assert_eq!(split_ir(&bits("1010001000"), 2), vec![None, None]);
+    }
+
+    #[test]
+    fn program_padded_around_scans() {
+        let position = ChainPosition { ir_pre: 6, ir_post: 5, dr_pre: 1, dr_post: 1 };
+        let program = JtagSequence::new()
+            .op(JtagOp::MoveIrScan)
+            .op(JtagOp::SetExitShiftDr)
+            .shift_out(8, 0x02)
+            .op(JtagOp::SetExitIdle)
+            .shift_in(32)
+            .build().unwrap();
+        let placed = position.place(&program).unwrap();
+        assert_eq!(placed.answer_length, program.answer_length);
+        let (ops, _) = decode(&placed.sequence).unwrap();
+        let ops: Vec<JtagOp> = ops.into_iter().map(|(_, op)| op).collect();
+        assert_eq!(ops, vec![
+            JtagOp::MoveIrScan, JtagOp::SetExitShiftDr,
+            JtagOp::SetStayShift, JtagOp::ShiftOutQ(6, vec![0x3F]), JtagOp::ShiftOutQ(8, vec![0x02]),
+            JtagOp::SetExitShiftDr, JtagOp::ShiftOutQ(5, vec![0x1F]),
+            JtagOp::SetExitIdle,
+            JtagOp::SetStayShift, JtagOp::ShiftOutQ(1, vec![0x00]), JtagOp::ShiftInQ(32),
+            JtagOp::SetExitIdle, JtagOp::ShiftOutQ(1, vec![0x00]),
+            JtagOp::End]);
+
+        assert_eq!(ChainPosition::default().place(&program).unwrap(), program);
+        let firmware = JtagSequence::new().read_mem(memory_space_t::MS_XWORD, 2, 0x8000).build().unwrap();
+        assert!(matches!(position.place(&firmware), Err(Error::JtagChainInvalid(_))));
+    }
+
+    #[test]
+    fn scan_finds_taps() {
+        let (_link, mut prog) = board();
+        let chain = prog.scan_chain().unwrap();
+        assert_eq!(chain.taps, vec![
+            ChainTap { id_code: Some(CPLD_ID), ir_length: Some(6) },
+            ChainTap { id_code: Some(VIRTUAL_MASTER_ID), ir_length: Some(8) },
+            ChainTap { id_code: None, ir_length: Some(5) },
+        ]);
+        assert_eq!(chain.ir_total, 19);
+        assert_eq!(chain.dsc_index(), Some(1));
+        assert_eq!(chain.dsc_position().unwrap(), ChainPosition { ir_pre: 6, ir_post: 5, dr_pre: 1, dr_post: 1 });
+    }
+
+    #[test]
+    fn dsc_routines_on_shared_chain() {
+        let (link, mut prog) = board();
+        prog.chain = prog.scan_chain().unwrap().dsc_position().unwrap();
+
+        assert_eq!(read_master_id_code_DSC_JTAG_ID(true, &prog).unwrap(), VIRTUAL_MASTER_ID.to_be_bytes().to_vec());
+        enableCoreTAP(&prog).unwrap();
+        assert_eq!(read_core_id_code(false, &prog).unwrap(), VIRTUAL_CORE_ID.to_be_bytes().to_vec());
+        assert_eq!(prog.dsc_target_halt().unwrap(), OnceStatus::DebugMode);
+        prog.dsc_write_once_reg(DscRegisters::DscRegOcr, 0x5A).unwrap();
+        assert_eq!(link.with_tap(|chain| chain.target.once_register(0x01)), 0x5A);
+        assert_eq!(prog.dsc_read_once_reg(DscRegisters::DscRegOcr).unwrap(), 0x5A);
+        prog.dsc_target_go().unwrap();
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+
+        // other TAPs stay in BYPASS
+        assert!(link.with_tap(|chain| chain.before[0].in_bypass() && chain.after[0].in_bypass()));
+        assert!(matches!(prog.dsc_read_memory(memory_space_t::MS_XWORD, 2, 0x8000), Err(Error::JtagChainInvalid(_))));
+    }
+
+    #[test]
+    fn raw_scan_padded() {
+        let (link, mut prog) = board();
+        prog.chain = ChainPosition { ir_pre: 6, ir_post: 5, dr_pre: 1, dr_post: 1 };
+        prog.jtag_reset().unwrap();
+        prog.jtag_select_shift(JTAG_SHIFT_IR).unwrap();
+        prog.jtag_write(JTAG_EXIT_SHIFT_DR, JTAG_MASTER_COMMAND_LENGTH, vec![JTAG_IDCODE_COMMAND]).unwrap();
+        assert_eq!(prog.jtag_read(JTAG_EXIT_IDLE | JTAG_WRITE_1, 32).unwrap(), VIRTUAL_MASTER_ID.to_be_bytes().to_vec());
+        assert!(link.with_tap(|chain| chain.before[0].in_bypass() && chain.after[0].in_bypass()));
+    }
+}
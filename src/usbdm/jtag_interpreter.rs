@@ -0,0 +1,892 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use crate::errors::{Error, USBDM_ErrorCode};
+use crate::usbdm::constants::{bdm_commands, memory_space_t, jtag_shift};
+use crate::usbdm::jtag::*;
+use crate::usbdm::jtag_sequence::{JtagOp, JtagSub, JtagCondition, JtagVar, decode};
+use crate::usbdm::transport::{UsbdmTransport};
+
+// USBDM return codes reported by sequence interpreter, as firmware does
+pub const BDM_RC_OK                    : u8 = 0;
+pub const BDM_RC_ILLEGAL_PARAMS        : u8 = 1;
+pub const BDM_RC_ILLEGAL_COMMAND       : u8 = 4;
+pub const BDM_RC_JTAG_UNMATCHED_REPEAT : u8 = 37;
+pub const BDM_RC_JTAG_UNMATCHED_RETURN : u8 = 38;
+pub const BDM_RC_JTAG_UNMATCHED_IF     : u8 = 39;
+pub const BDM_RC_JTAG_STACK_ERROR      : u8 = 40;
+pub const BDM_RC_JTAG_ILLEGAL_SEQUENCE : u8 = 41;
+pub const BDM_RC_TARGET_BUSY           : u8 = 42;
+
+/// Calls nested deeper than this are reported as `BDM_RC_JTAG_STACK_ERROR`
+const MAX_CALL_DEPTH : usize = 8;
+
+/// `TapRegister` - register between TDI and TDO while shifting
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TapRegister { Ir, Dr }
+
+/// `VirtualTap` - model of JTAG target driven by `JtagInterpreter`
+///
+/// Interpreter walks TAP state machine and calls capture / shift / update as a real TAP sees them,
+/// bits go LSB first. Firmware implemented parts of sequence language (subroutine A executing target
+/// instructions, `JTAG_READ_MEM` / `JTAG_WRITE_MEM`) are handed to the model as whole operations,
+/// models without them answer `BDM_RC_ILLEGAL_COMMAND`.
+pub trait VirtualTap: fmt::Debug + Send {
+    /// TEST-LOGIC-RESET state entered
+    fn test_logic_reset(&mut self);
+
+    /// CAPTURE-DR / CAPTURE-IR
+    fn capture(&mut self, register: TapRegister);
+
+    /// One SHIFT-DR / SHIFT-IR clock, returns TDO
+    fn shift(&mut self, register: TapRegister, tdi: bool) -> bool;
+
+    /// UPDATE-DR / UPDATE-IR
+    fn update(&mut self, register: TapRegister);
+
+    /// One clock in RUN-TEST/IDLE
+    fn idle(&mut self) {}
+
+    /// `execute` - firmware subroutine A, one target instruction (opcode and operand words as in sequence)
+    fn execute(&mut self, _instruction: &[u8]) -> Result<(), u8> {
+        Err(BDM_RC_ILLEGAL_COMMAND)
+    }
+
+    /// `read_memory` - firmware `JTAG_READ_MEM`, `elements` of size given by `memory_space`
+    fn read_memory(&mut self, _memory_space: u8, _address: u32, _elements: u8) -> Result<Vec<u8>, u8> {
+        Err(BDM_RC_ILLEGAL_COMMAND)
+    }
+
+    /// `write_memory` - firmware `JTAG_WRITE_MEM`
+    fn write_memory(&mut self, _memory_space: u8, _address: u32, _data: &[u8]) -> Result<(), u8> {
+        Err(BDM_RC_ILLEGAL_COMMAND)
+    }
+}
+
+/// `JtagFault` - sequence stopped with USBDM error `code` at instruction `offset`
+#[derive(Debug, Clone, PartialEq)]
+pub struct JtagFault {
+    pub code   : u8,
+    pub offset : usize,
+    pub reason : String,
+}
+
+impl fmt::Display for JtagFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error {} at {:04X}: {}", self.code, self.offset, self.reason)
+    }
+}
+
+impl From<JtagFault> for Error {
+    fn from(fault: JtagFault) -> Error {
+        Error::USBDM_Errors(USBDM_ErrorCode::from(fault.code))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum TapState { Reset, Idle, ShiftDr, ShiftIr }
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ShiftExit { Stay, ShiftDr, ShiftIr, Idle }
+
+/// `Flow` - how instruction list was left
+enum Flow { Finished, End, Return }
+
+struct Loop {
+    body      : usize,
+    count     : u32,
+    iteration : u32,
+}
+
+/// state of one sequence run
+struct Run {
+    data   : Vec<u8>,
+    dp     : usize,
+    answer : Vec<u8>,
+    value  : u32,
+    subs   : [Option<Vec<(usize, JtagOp)>>; 4],
+    /// last subroutine defined, target of `JTAG_SAVE_SUB`
+    defined: Option<JtagSub>,
+    depth  : usize,
+}
+
+/// `JtagInterpreter` - runs USBDM JTAG sequences on host against `VirtualTap`
+///
+/// Same language as firmware `CMD_USBDM_JTAG_EXECUTE_SEQUENCE`: variables A..D, pushed value, data pointer
+/// over data area after `JTAG_END`, REPEAT loops, IF/ELSE/END_IF, subroutines and all shift instructions.
+/// TAP state, shift exit mode, fill bit, variables and cached subroutines persist between runs as in firmware.
+/// `IF_ITER_..` compares with pass number of innermost REPEAT counted from 1.
+#[derive(Debug)]
+pub struct JtagInterpreter<T: VirtualTap> {
+    pub tap   : T,
+    state     : TapState,
+    exit      : ShiftExit,
+    fill      : bool,
+    vars      : [u32; 4],
+    cached    : [Option<Vec<(usize, JtagOp)>>; 4],
+    debug     : bool,
+}
+
+impl<T: VirtualTap> JtagInterpreter<T> {
+
+    pub fn new(tap: T) -> Self {
+        JtagInterpreter { tap, state: TapState::Reset, exit: ShiftExit::Idle, fill: true, vars: [0; 4], cached: Default::default(), debug: false }
+    }
+
+    /// `run` - execute sequence (instructions, `JTAG_END`, data area), returns answer bytes
+    pub fn run(&mut self, sequence: &[u8]) -> Result<Vec<u8>, JtagFault> {
+        let (ops, data) = decode(sequence).map_err(|e| JtagFault { code: BDM_RC_JTAG_ILLEGAL_SEQUENCE, offset: sequence.len(), reason: format!("{:?}", e) })?;
+        let mut run = Run { data, dp: 0, answer: Vec::new(), value: 0, subs: Default::default(), defined: None, depth: 0 };
+        match self.exec(&ops, &mut run)? {
+            Flow::Return => Err(JtagFault { code: BDM_RC_JTAG_UNMATCHED_RETURN, offset: 0, reason: "RETURN outside of subroutine".to_string() }),
+            _            => Ok(run.answer),
+        }
+    }
+
+    fn exec(&mut self, ops: &[(usize, JtagOp)], run: &mut Run) -> Result<Flow, JtagFault> {
+        let mut loops: Vec<Loop> = Vec::new();
+        let mut pc = 0;
+        while pc < ops.len() {
+            let (offset, op) = &ops[pc];
+            let fault = |code: u8, reason: &str| JtagFault { code, offset: *offset, reason: format!("{}: {}", op, reason) };
+            if self.debug {
+                println!("JTAG {:04X}  {}", offset, op);
+            }
+            pc += 1;
+            match op {
+                JtagOp::End                 => {
+                    if !loops.is_empty() {
+                        return Err(fault(BDM_RC_JTAG_UNMATCHED_REPEAT, "REPEAT without END_REPEAT"))
+                    }
+                    return Ok(Flow::End)
+                },
+                JtagOp::Nop                 => if self.state == TapState::Idle { self.tap.idle() },
+                JtagOp::EndSub | JtagOp::Return => return Ok(Flow::Return),
+                JtagOp::TestLogicReset      => {
+                    self.tap.test_logic_reset();
+                    self.state = TapState::Idle;
+                },
+                JtagOp::MoveDrScan          => self.move_to_shift(TapRegister::Dr),
+                JtagOp::MoveIrScan          => self.move_to_shift(TapRegister::Ir),
+                JtagOp::SetStayShift        => self.exit = ShiftExit::Stay,
+                JtagOp::SetExitShiftDr      => self.exit = ShiftExit::ShiftDr,
+                JtagOp::SetExitShiftIr      => self.exit = ShiftExit::ShiftIr,
+                JtagOp::SetExitIdle         => self.exit = ShiftExit::Idle,
+                JtagOp::SetInFill0          => self.fill = false,
+                JtagOp::SetInFill1          => self.fill = true,
+                JtagOp::DebugOn             => self.debug = true,
+                JtagOp::DebugOff            => self.debug = false,
+                JtagOp::SetBusy             => {},
+                JtagOp::SetError            => if run.value != 0 {
+                    return Err(fault(run.value as u8, "error set by sequence"))
+                },
+                JtagOp::If(condition)       => {
+                    let iteration = loops.last().map_or(0, |l| l.iteration + 1);
+                    let taken = match condition {
+                        JtagCondition::VarAEq  => self.vars[0] == run.value,
+                        JtagCondition::VarBEq  => self.vars[1] == run.value,
+                        JtagCondition::VarANeq => self.vars[0] != run.value,
+                        JtagCondition::VarBNeq => self.vars[1] != run.value,
+                        JtagCondition::IterEq  => iteration == run.value,
+                        JtagCondition::IterNeq => iteration != run.value,
+                    };
+                    if !taken {
+                        pc = skip_if(ops, pc).ok_or_else(|| fault(BDM_RC_JTAG_UNMATCHED_IF, "no END_IF"))?;
+                    }
+                },
+                JtagOp::Else                => {
+                    // end of taken branch
+                    pc = skip_if(ops, pc).ok_or_else(|| fault(BDM_RC_JTAG_UNMATCHED_IF, "no END_IF"))?;
+                },
+                JtagOp::EndIf               => {},
+                JtagOp::Repeat | JtagOp::Repeat8(_) | JtagOp::RepeatQ(_) | JtagOp::RepeatDp => {
+                    let count = match op {
+                        JtagOp::Repeat8(count) => *count as u32,
+                        JtagOp::RepeatQ(count) => *count as u32,
+                        JtagOp::RepeatDp       => self.take_data(run, 1).map_err(|reason| fault(BDM_RC_ILLEGAL_PARAMS, &reason))?[0] as u32,
+                        _                      => run.value,
+                    };
+                    if count == 0 {
+                        pc = end_of_repeat(ops, pc).ok_or_else(|| fault(BDM_RC_JTAG_UNMATCHED_REPEAT, "no END_REPEAT"))? + 1;
+                    } else {
+                        loops.push(Loop { body: pc, count, iteration: 0 });
+                    }
+                },
+                JtagOp::EndRepeat           => {
+                    let current = loops.last_mut().ok_or_else(|| fault(BDM_RC_JTAG_UNMATCHED_REPEAT, "no REPEAT"))?;
+                    current.iteration += 1;
+                    if current.iteration < current.count {
+                        pc = current.body;
+                    } else {
+                        loops.pop();
+                    }
+                },
+                JtagOp::Break | JtagOp::Continue => {
+                    if loops.is_empty() {
+                        return Err(fault(BDM_RC_JTAG_UNMATCHED_REPEAT, "outside of REPEAT"))
+                    }
+                    let end = end_of_repeat(ops, pc).ok_or_else(|| fault(BDM_RC_JTAG_UNMATCHED_REPEAT, "no END_REPEAT"))?;
+                    if *op == JtagOp::Break {
+                        loops.pop();
+                        pc = end + 1;
+                    } else {
+                        pc = end;
+                    }
+                },
+                JtagOp::Sub(sub)            => {
+                    let end = (pc..ops.len()).find(|i| ops[*i].1 == JtagOp::EndSub)
+                        .ok_or_else(|| fault(BDM_RC_JTAG_UNMATCHED_RETURN, "no END_SUB"))?;
+                    run.subs[sub_index(*sub)] = Some(ops[pc..end].to_vec());
+                    run.defined = Some(*sub);
+                    pc = end + 1;
+                },
+                JtagOp::SaveSub             => {
+                    let sub = run.defined.ok_or_else(|| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, "no subroutine defined before"))?;
+                    self.cached[sub_index(sub)] = run.subs[sub_index(sub)].clone();
+                },
+                JtagOp::CallSub(sub)        => {
+                    let body = run.subs[sub_index(*sub)].clone().or_else(|| self.cached[sub_index(*sub)].clone());
+                    match body {
+                        Some(body) => {
+                            if run.depth >= MAX_CALL_DEPTH {
+                                return Err(fault(BDM_RC_JTAG_STACK_ERROR, "calls nested too deep"))
+                            }
+                            run.depth += 1;
+                            let flow = self.exec(&body, run)?;
+                            run.depth -= 1;
+                            if let Flow::End = flow {
+                                return Ok(Flow::End)
+                            }
+                        },
+                        None if *sub == JtagSub::A => self.execute_instructions(run).map_err(|(code, reason)| fault(code, &reason))?,
+                        None => return Err(fault(BDM_RC_JTAG_UNMATCHED_RETURN, "subroutine not defined")),
+                    }
+                },
+                JtagOp::LoadVar(var)        => self.vars[var_index(*var)] = run.value,
+                JtagOp::SaveOutDp(var)      => self.vars[var_index(*var)] = run.dp as u32,
+                JtagOp::RestoreDp(var)      => run.dp = self.vars[var_index(*var)] as usize,
+                JtagOp::Push8(value)        => run.value = *value as u32,
+                JtagOp::Push16(value)       => run.value = *value as u32,
+                JtagOp::Push32(value)       => run.value = *value,
+                JtagOp::PushQ(value)        => run.value = *value as u32,
+                JtagOp::PushDp8 | JtagOp::PushDp16 | JtagOp::PushDp32 => {
+                    let size = match op { JtagOp::PushDp8 => 1, JtagOp::PushDp16 => 2, _ => 4 };
+                    let bytes = self.take_data(run, size).map_err(|reason| fault(BDM_RC_ILLEGAL_PARAMS, &reason))?;
+                    run.value = bytes.iter().fold(0, |value, byte| (value << 8) | *byte as u32);
+                },
+                JtagOp::SkipDp              => run.dp += run.value as usize,
+                JtagOp::ShiftOutDpVarA      => {
+                    let bits = self.vars[0].min(u8::MAX as u32) as u8;
+                    let out = self.take_data(run, BITS_TO_BYTES(bits) as usize).map_err(|reason| fault(BDM_RC_ILLEGAL_PARAMS, &reason))?;
+                    self.shift(bits, Some(&out)).map_err(|reason| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, &reason))?;
+                },
+                JtagOp::ShiftOutVar(var, bits) => {
+                    let out = value_bytes(self.vars[var_index(*var)], *bits);
+                    self.shift(*bits, Some(&out)).map_err(|reason| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, &reason))?;
+                },
+                JtagOp::ShiftInOutVar(var, bits) => {
+                    let out = value_bytes(self.vars[var_index(*var)], *bits);
+                    let captured = self.shift(*bits, Some(&out)).map_err(|reason| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, &reason))?;
+                    self.vars[var_index(*var)] = captured.iter().fold(0, |value, byte| (value << 8) | *byte as u32);
+                },
+                JtagOp::ShiftOutDp(bits) | JtagOp::ShiftInOutDp(bits) => {
+                    let out = self.take_data(run, BITS_TO_BYTES(*bits) as usize).map_err(|reason| fault(BDM_RC_ILLEGAL_PARAMS, &reason))?;
+                    let captured = self.shift(*bits, Some(&out)).map_err(|reason| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, &reason))?;
+                    if let JtagOp::ShiftInOutDp(_) = op {
+                        run.answer.extend(captured);
+                    }
+                },
+                JtagOp::ShiftInDp(bits)     => {
+                    let bits = match bits {
+                        0    => self.take_data(run, 1).map_err(|reason| fault(BDM_RC_ILLEGAL_PARAMS, &reason))?[0],
+                        bits => *bits,
+                    };
+                    let captured = self.shift(bits, None).map_err(|reason| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, &reason))?;
+                    run.answer.extend(captured);
+                },
+                JtagOp::ShiftInQ(bits)      => {
+                    let captured = self.shift(*bits, None).map_err(|reason| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, &reason))?;
+                    run.answer.extend(captured);
+                },
+                JtagOp::ShiftOutQ(bits, out) => {
+                    self.shift(*bits, Some(out)).map_err(|reason| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, &reason))?;
+                },
+                JtagOp::ShiftInOutQ(bits, out) => {
+                    let captured = self.shift(*bits, Some(out)).map_err(|reason| fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, &reason))?;
+                    run.answer.extend(captured);
+                },
+                JtagOp::ReadMem | JtagOp::WriteMem => {
+                    let header = self.take_data(run, 6).map_err(|reason| fault(BDM_RC_ILLEGAL_PARAMS, &reason))?;
+                    let address = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+                    let (elements, memory_space) = (header[4], header[5]);
+                    let size = (memory_space & memory_space_t::MS_SIZE) as usize * elements as usize;
+                    if *op == JtagOp::ReadMem {
+                        let bytes = self.tap.read_memory(memory_space, address, elements).map_err(|code| fault(code, "memory read failed"))?;
+                        run.answer.extend(bytes);
+                    } else {
+                        let bytes = self.take_data(run, size).map_err(|reason| fault(BDM_RC_ILLEGAL_PARAMS, &reason))?;
+                        self.tap.write_memory(memory_space, address, &bytes).map_err(|code| fault(code, "memory write failed"))?;
+                    }
+                },
+                JtagOp::ArmReadAp | JtagOp::ArmWriteAp | JtagOp::ArmWriteApI =>
+                    return Err(fault(BDM_RC_ILLEGAL_COMMAND, "ARM access port not modelled")),
+                JtagOp::Unknown(_)          => return Err(fault(BDM_RC_JTAG_ILLEGAL_SEQUENCE, "unknown opcode")),
+            }
+        }
+        if !loops.is_empty() {
+            return Err(JtagFault { code: BDM_RC_JTAG_UNMATCHED_REPEAT, offset: ops.last().map_or(0, |(offset, _)| *offset), reason: "REPEAT without END_REPEAT".to_string() })
+        }
+        Ok(Flow::Finished)
+    }
+
+    /// `execute_instructions` - firmware subroutine A: instruction count, then per instruction word count and words from data area
+    fn execute_instructions(&mut self, run: &mut Run) -> Result<(), (u8, String)> {
+        let count = self.take_data(run, 1).map_err(|reason| (BDM_RC_ILLEGAL_PARAMS, reason))?[0];
+        for _ in 0..count {
+            let words = self.take_data(run, 1).map_err(|reason| (BDM_RC_ILLEGAL_PARAMS, reason))?[0];
+            let instruction = self.take_data(run, 2 * words as usize).map_err(|reason| (BDM_RC_ILLEGAL_PARAMS, reason))?;
+            self.tap.execute(&instruction).map_err(|code| (code, "target instruction failed".to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn take_data(&self, run: &mut Run, count: usize) -> Result<Vec<u8>, String> {
+        let bytes = run.data.get(run.dp..run.dp + count)
+            .ok_or_else(|| format!("data area exhausted, {} bytes needed at {} of {}", count, run.dp, run.data.len()))?
+            .to_vec();
+        run.dp += count;
+        Ok(bytes)
+    }
+
+    /// `goto_reset` - `CMD_USBDM_JTAG_GOTORESET`, TAP reset and left in RUN-TEST/IDLE
+    pub fn goto_reset(&mut self) {
+        self.tap.test_logic_reset();
+        self.state = TapState::Idle;
+    }
+
+    /// `goto_shift` - `CMD_USBDM_JTAG_GOTOSHIFT`
+    pub fn goto_shift(&mut self, register: TapRegister) {
+        self.move_to_shift(register);
+    }
+
+    /// `raw_shift` - `CMD_USBDM_JTAG_WRITE` (`out` given) / `CMD_USBDM_JTAG_READ`, exit and fill from `shift_exit`
+    ///
+    /// Exit mode and fill of sequences are not changed.
+    pub fn raw_shift(&mut self, shift_exit: u8, bits: u8, out: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let (exit, fill) = (self.exit, self.fill);
+        self.exit = match shift_exit & jtag_shift::JTAG_EXIT_ACTION_MASK {
+            jtag_shift::JTAG_STAY_SHIFT    => ShiftExit::Stay,
+            jtag_shift::JTAG_EXIT_IDLE     => ShiftExit::Idle,
+            jtag_shift::JTAG_EXIT_SHIFT_DR => ShiftExit::ShiftDr,
+            _                              => ShiftExit::ShiftIr,
+        };
+        self.fill = shift_exit & jtag_shift::JTAG_WRITE_1 != 0;
+        let captured = self.shift(bits, out);
+        (self.exit, self.fill) = (exit, fill);
+        captured
+    }
+
+    fn move_to_shift(&mut self, register: TapRegister) {
+        match self.state {
+            TapState::ShiftDr => self.tap.update(TapRegister::Dr),
+            TapState::ShiftIr => self.tap.update(TapRegister::Ir),
+            _ => {},
+        }
+        self.tap.capture(register);
+        self.state = if register == TapRegister::Dr { TapState::ShiftDr } else { TapState::ShiftIr };
+    }
+
+    /// `shift` - `bits` through selected register, `out` MSB byte first (TDI fill when `None`), returns TDO the same way
+    fn shift(&mut self, bits: u8, out: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let register = match self.state {
+            TapState::ShiftDr => TapRegister::Dr,
+            TapState::ShiftIr => TapRegister::Ir,
+            _ => return Err("TAP not in SHIFT-DR/IR".to_string()),
+        };
+        let bytes = BITS_TO_BYTES(bits) as usize;
+        let mut captured = vec![0u8; bytes];
+        for bit in 0..bits as usize {
+            let index = bytes - 1 - bit / 8;
+            let tdi = match out {
+                Some(out) => (out[index] >> (bit % 8)) & 1 != 0,
+                None      => self.fill,
+            };
+            if self.tap.shift(register, tdi) {
+                captured[index] |= 1 << (bit % 8);
+            }
+        }
+        match self.exit {
+            ShiftExit::Stay    => {},
+            ShiftExit::Idle    => {
+                self.tap.update(register);
+                self.state = TapState::Idle;
+                self.tap.idle();
+            },
+            ShiftExit::ShiftDr => {
+                self.tap.update(register);
+                self.tap.capture(TapRegister::Dr);
+                self.state = TapState::ShiftDr;
+            },
+            ShiftExit::ShiftIr => {
+                self.tap.update(register);
+                self.tap.capture(TapRegister::Ir);
+                self.state = TapState::ShiftIr;
+            },
+        }
+        Ok(captured)
+    }
+}
+
+fn sub_index(sub: JtagSub) -> usize {
+    sub as usize
+}
+
+fn var_index(var: JtagVar) -> usize {
+    var as usize
+}
+
+fn value_bytes(value: u32, bits: u8) -> Vec<u8> {
+    let bytes = (BITS_TO_BYTES(bits) as usize).min(4);
+    value.to_be_bytes()[4 - bytes..].to_vec()
+}
+
+fn opens_repeat(op: &JtagOp) -> bool {
+    matches!(op, JtagOp::Repeat | JtagOp::Repeat8(_) | JtagOp::RepeatQ(_) | JtagOp::RepeatDp)
+}
+
+/// `skip_if` - index after ELSE or of END_IF closing IF/ELSE block containing `from`
+fn skip_if(ops: &[(usize, JtagOp)], from: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, (_, op)) in ops.iter().enumerate().skip(from) {
+        match op {
+            JtagOp::If(_) => depth += 1,
+            JtagOp::Else if depth == 0 => return Some(index + 1),
+            JtagOp::EndIf if depth == 0 => return Some(index),
+            JtagOp::EndIf => depth -= 1,
+            _ => {},
+        }
+    }
+    None
+}
+
+/// `end_of_repeat` - index of END_REPEAT closing loop containing `from`
+fn end_of_repeat(ops: &[(usize, JtagOp)], from: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, (_, op)) in ops.iter().enumerate().skip(from) {
+        match op {
+            op if opens_repeat(op) => depth += 1,
+            JtagOp::EndRepeat if depth == 0 => return Some(index),
+            JtagOp::EndRepeat => depth -= 1,
+            _ => {},
+        }
+    }
+    None
+}
+
+/// `SimpleTap` - TAP with BYPASS and optional IDCODE only, e.g. CPLD sharing chain with DSC
+///
+/// IR captures ..01, all 1's is BYPASS, 0x01 selects IDCODE when TAP has one. After reset IDCODE
+/// (or BYPASS without it) is selected, as IEEE 1149.1 wants.
+#[derive(Debug, Clone)]
+pub struct SimpleTap {
+    pub ir_length : u8,
+    pub id_code   : Option<u32>,
+    pub ir        : u32,
+    /// TCK clocks in RUN-TEST/IDLE so far
+    pub idles     : usize,
+    captured      : u64,
+    length        : usize,
+    shifted       : Vec<bool>,
+}
+
+impl SimpleTap {
+    pub fn new(ir_length: u8, id_code: Option<u32>) -> Self {
+        let mut tap = SimpleTap { ir_length, id_code, ir: 0, idles: 0, captured: 0, length: 1, shifted: Vec::new() };
+        tap.test_logic_reset();
+        tap
+    }
+
+    fn bypass_code(&self) -> u32 {
+        if self.ir_length >= 32 { u32::MAX } else { (1 << self.ir_length) - 1 }
+    }
+
+    pub fn in_bypass(&self) -> bool {
+        self.ir == self.bypass_code()
+    }
+
+    fn idcode_selected(&self) -> bool {
+        self.id_code.is_some() && self.ir == 0x01
+    }
+}
+
+impl VirtualTap for SimpleTap {
+    fn test_logic_reset(&mut self) {
+        self.ir = if self.id_code.is_some() { 0x01 } else { self.bypass_code() };
+    }
+
+    fn capture(&mut self, register: TapRegister) {
+        self.shifted.clear();
+        (self.captured, self.length) = match register {
+            TapRegister::Ir                          => (0x01, self.ir_length as usize),
+            TapRegister::Dr if self.idcode_selected() => (self.id_code.unwrap_or(0) as u64, 32),
+            TapRegister::Dr                          => (0, 1),
+        };
+    }
+
+    fn shift(&mut self, _register: TapRegister, tdi: bool) -> bool {
+        let count = self.shifted.len();
+        let tdo = if count < self.length { (self.captured >> count) & 1 != 0 } else { self.shifted[count - self.length] };
+        self.shifted.push(tdi);
+        tdo
+    }
+
+    fn update(&mut self, register: TapRegister) {
+        if register == TapRegister::Ir && self.shifted.len() >= self.length {
+            let last = &self.shifted[self.shifted.len() - self.length..];
+            self.ir = last.iter().rev().fold(0, |value, bit| (value << 1) | *bit as u32);
+        }
+    }
+
+    fn idle(&mut self) {
+        self.idles += 1;
+    }
+}
+
+/// `VirtualChain` - `target` with other TAPs on the same chain
+///
+/// `before` are TAPs between `target` and TDO (index 0 nearest TDO), `after` between TDI and `target`
+/// (last nearest TDI). Firmware hooks go to `target`.
+#[derive(Debug)]
+pub struct VirtualChain<T: VirtualTap> {
+    pub before : Vec<SimpleTap>,
+    pub target : T,
+    pub after  : Vec<SimpleTap>,
+}
+
+impl<T: VirtualTap> VirtualTap for VirtualChain<T> {
+    fn test_logic_reset(&mut self) {
+        self.before.iter_mut().for_each(|tap| tap.test_logic_reset());
+        self.target.test_logic_reset();
+        self.after.iter_mut().for_each(|tap| tap.test_logic_reset());
+    }
+
+    fn capture(&mut self, register: TapRegister) {
+        self.before.iter_mut().for_each(|tap| tap.capture(register));
+        self.target.capture(register);
+        self.after.iter_mut().for_each(|tap| tap.capture(register));
+    }
+
+    fn shift(&mut self, register: TapRegister, tdi: bool) -> bool {
+        let mut bit = tdi;
+        for tap in self.after.iter_mut().rev() {
+            bit = tap.shift(register, bit);
+        }
+        bit = self.target.shift(register, bit);
+        for tap in self.before.iter_mut().rev() {
+            bit = tap.shift(register, bit);
+        }
+        bit
+    }
+
+    fn update(&mut self, register: TapRegister) {
+        self.before.iter_mut().for_each(|tap| tap.update(register));
+        self.target.update(register);
+        self.after.iter_mut().for_each(|tap| tap.update(register));
+    }
+
+    fn idle(&mut self) {
+        self.before.iter_mut().for_each(|tap| tap.idle());
+        self.target.idle();
+        self.after.iter_mut().for_each(|tap| tap.idle());
+    }
+
+    fn execute(&mut self, instruction: &[u8]) -> Result<(), u8> {
+        self.target.execute(instruction)
+    }
+
+    fn read_memory(&mut self, memory_space: u8, address: u32, elements: u8) -> Result<Vec<u8>, u8> {
+        self.target.read_memory(memory_space, address, elements)
+    }
+
+    fn write_memory(&mut self, memory_space: u8, address: u32, data: &[u8]) -> Result<(), u8> {
+        self.target.write_memory(memory_space, address, data)
+    }
+}
+
+/// `InterpreterTransport` - USBDM whose `CMD_USBDM_JTAG_EXECUTE_SEQUENCE` runs on `JtagInterpreter`
+///
+/// Raw `CMD_USBDM_JTAG_GOTORESET/GOTOSHIFT/WRITE/READ` go to the same TAP, other commands are answered OK. Answer shorter or longer than requested by command is
+/// reported as `BDM_RC_ILLEGAL_PARAMS`, so wrong answer length of generated sequence fails the test.
+#[derive(Debug)]
+pub struct InterpreterTransport<T: VirtualTap> {
+    interpreter : Arc<Mutex<JtagInterpreter<T>>>,
+    pending     : Arc<Mutex<Vec<Vec<u8>>>>,
+    /// first opcode of sequence to fail, see `fail_sequence`
+    fail        : Arc<Mutex<Option<u8>>>,
+}
+
+impl<T: VirtualTap> Clone for InterpreterTransport<T> {
+    fn clone(&self) -> Self {
+        InterpreterTransport { interpreter: self.interpreter.clone(), pending: self.pending.clone(), fail: self.fail.clone() }
+    }
+}
+
+impl<T: VirtualTap> InterpreterTransport<T> {
+    pub fn new(tap: T) -> Self {
+        InterpreterTransport { interpreter: Arc::new(Mutex::new(JtagInterpreter::new(tap))), pending: Arc::new(Mutex::new(Vec::new())),
+            fail: Arc::new(Mutex::new(None)) }
+    }
+
+    /// `fail_sequence` - next sequence starting with `opcode` is not run and answers `BDM_RC_ILLEGAL_PARAMS`
+    pub fn fail_sequence(&self, opcode: u8) {
+        *self.fail.lock().unwrap() = Some(opcode);
+    }
+
+    /// `with_tap` - inspect or change TAP model between commands
+    pub fn with_tap<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.interpreter.lock().unwrap().tap)
+    }
+
+    fn execute(&self, command: &[u8]) -> Vec<u8> {
+        let mut fail = self.fail.lock().unwrap();
+        if command.get(1).map(|cmd| cmd & 0x7F) == Some(bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE) && fail.is_some() && command.get(4) == fail.as_ref() {
+            *fail = None;
+            return vec![BDM_RC_ILLEGAL_PARAMS]
+        }
+        drop(fail);
+        match command.get(1).map(|cmd| cmd & 0x7F) {
+            Some(bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE) => self.execute_sequence(command),
+            Some(bdm_commands::CMD_USBDM_JTAG_GOTORESET) => {
+                self.interpreter.lock().unwrap().goto_reset();
+                vec![BDM_RC_OK]
+            },
+            Some(bdm_commands::CMD_USBDM_JTAG_GOTOSHIFT) if command.len() == 3 => {
+                let register = if command[2] == jtag_shift::JTAG_SHIFT_IR { TapRegister::Ir } else { TapRegister::Dr };
+                self.interpreter.lock().unwrap().goto_shift(register);
+                vec![BDM_RC_OK]
+            },
+            Some(bdm_commands::CMD_USBDM_JTAG_WRITE) | Some(bdm_commands::CMD_USBDM_JTAG_READ) if command.len() >= 4 => {
+                let bits = command[3];
+                let out = if command[1] & 0x7F == bdm_commands::CMD_USBDM_JTAG_WRITE { Some(&command[4..]) } else { None };
+                if out.map_or(command.len() != 4, |out| out.len() != BITS_TO_BYTES(bits) as usize) {
+                    return vec![BDM_RC_ILLEGAL_PARAMS]
+                }
+                match self.interpreter.lock().unwrap().raw_shift(command[2], bits, out) {
+                    Ok(captured) if out.is_none() => [vec![BDM_RC_OK], captured].concat(),
+                    Ok(_) => vec![BDM_RC_OK],
+                    Err(reason) => {
+                        println!("JTAG raw shift: {}", reason);
+                        vec![BDM_RC_JTAG_ILLEGAL_SEQUENCE]
+                    },
+                }
+            },
+            Some(bdm_commands::CMD_USBDM_JTAG_GOTOSHIFT) | Some(bdm_commands::CMD_USBDM_JTAG_WRITE) | Some(bdm_commands::CMD_USBDM_JTAG_READ) =>
+                vec![BDM_RC_ILLEGAL_PARAMS],
+            _ => vec![BDM_RC_OK],
+        }
+    }
+
+    fn execute_sequence(&self, command: &[u8]) -> Vec<u8> {
+        if command.len() < 4 || command.len() != 4 + command[3] as usize {
+            return vec![BDM_RC_ILLEGAL_PARAMS]
+        }
+        let answer_length = command[2] as usize;
+        match self.interpreter.lock().unwrap().run(&command[4..]) {
+            Ok(answer) if answer.len() == answer_length => [vec![BDM_RC_OK], answer].concat(),
+            Ok(answer) => {
+                println!("JTAG sequence returned {} bytes, command expects {}", answer.len(), answer_length);
+                vec![BDM_RC_ILLEGAL_PARAMS]
+            },
+            Err(fault) => {
+                println!("JTAG sequence {}", fault);
+                vec![fault.code]
+            },
+        }
+    }
+}
+
+impl<T: VirtualTap + 'static> UsbdmTransport for InterpreterTransport<T> {
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        let answer = self.execute(data);
+        self.pending.lock().unwrap().push(answer);
+        Ok(())
+    }
+
+    fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() {
+            return Err(Error::Usb(rusb::Error::Timeout))
+        }
+        let mut answer = pending.remove(0);
+        answer.resize(rx_size, 0);
+        Ok(answer)
+    }
+
+    fn control_transfer(&self, _request_type: u8, _request: u8, _value: u16, _index: u16, rx_size: usize) -> Result<Vec<u8>, Error> {
+        Ok(vec![0; rx_size])
+    }
+
+    fn read_ep(&self) -> u8 {
+        0x82
+    }
+
+    fn write_ep(&self) -> u8 {
+        0x01
+    }
+
+    fn clear_halt(&self) -> Result<(), Error> {
+        self.pending.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn model(&self) -> String {
+        "USBDM virtual JTAG".to_string()
+    }
+
+    fn serial_number(&self) -> String {
+        "VIRTUAL".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::jtag_sequence::{JtagSequence};
+
+    /// `LoopbackTap` - IR and DR are plain shift registers of given lengths, records what it saw
+    #[derive(Debug, Default)]
+    struct LoopbackTap {
+        ir_length : usize,
+        dr_length : usize,
+        ir        : u64,
+        dr        : u64,
+        updates   : Vec<(TapRegister, u64)>,
+        idles     : usize,
+    }
+
+    impl VirtualTap for LoopbackTap {
+        fn test_logic_reset(&mut self) {
+            self.ir = 0;
+        }
+        fn capture(&mut self, _register: TapRegister) {}
+        fn shift(&mut self, register: TapRegister, tdi: bool) -> bool {
+            let (value, length) = match register {
+                TapRegister::Ir => (&mut self.ir, self.ir_length),
+                TapRegister::Dr => (&mut self.dr, self.dr_length),
+            };
+            let tdo = *value & 1 != 0;
+            *value = (*value >> 1) | ((tdi as u64) << (length - 1));
+            tdo
+        }
+        fn update(&mut self, register: TapRegister) {
+            let value = if register == TapRegister::Ir { self.ir } else { self.dr };
+            self.updates.push((register, value));
+        }
+        fn idle(&mut self) {
+            self.idles += 1;
+        }
+    }
+
+    fn interpreter() -> JtagInterpreter<LoopbackTap> {
+        JtagInterpreter::new(LoopbackTap { ir_length: 4, dr_length: 16, ..LoopbackTap::default() })
+    }
+
+    #[test]
+    fn shift_exit_modes() {
+        let mut jtag = interpreter();
+        jtag.tap.dr = 0xBEEF;
+        let program = JtagSequence::new()
+            .op(JtagOp::TestLogicReset)
+            .op(JtagOp::MoveIrScan)
+            .op(JtagOp::SetExitShiftDr)
+            .shift_out(4, 0x6)
+            .op(JtagOp::SetExitIdle)
+            .shift_in_out(16, 0x1234)
+            .build().unwrap();
+        let answer = jtag.run(&program.sequence).unwrap();
+        assert_eq!(answer, vec![0xBE, 0xEF]);
+        assert_eq!(jtag.tap.updates, vec![(TapRegister::Ir, 0x6), (TapRegister::Dr, 0x1234)]);
+        assert_eq!(jtag.tap.idles, 1);
+    }
+
+    #[test]
+    fn loops_conditions_and_variables() {
+        let mut jtag = interpreter();
+        // count passes in DR: pass 2 shifts 0xAA instead of 0x11
+        let program = JtagSequence::new()
+            .op(JtagOp::MoveDrScan)
+            .op(JtagOp::SetStayShift)
+            .repeat(3)
+                .if_(JtagCondition::IterEq, 2).shift_out(8, 0xAA).else_().shift_out(8, 0x11).end_if()
+            .end_repeat()
+            .op(JtagOp::SetInFill0)
+            .shift_in(16)
+            .build().unwrap();
+        assert_eq!(jtag.run(&program.sequence).unwrap(), vec![0x11, 0xAA]);
+
+        let mut jtag = interpreter();
+        let program = JtagSequence::new()
+            .op(JtagOp::TestLogicReset)
+            .push(7).op(JtagOp::LoadVar(JtagVar::A))
+            .repeat(5)
+                .if_(JtagCondition::IterEq, 3).op(JtagOp::Break).end_if()
+                .op(JtagOp::Nop)
+            .end_repeat()
+            .op(JtagOp::MoveDrScan)
+            .op(JtagOp::SetStayShift)
+            .if_(JtagCondition::VarAEq, 7).shift_in(8).else_().shift_in(16).end_if()
+            .build();
+        assert!(program.is_err());                           // branches of different length rejected by builder
+        let program = JtagSequence::new()
+            .op(JtagOp::TestLogicReset)
+            .push(7).op(JtagOp::LoadVar(JtagVar::A))
+            .repeat(5)
+                .if_(JtagCondition::IterEq, 3).op(JtagOp::Break).end_if()
+                .op(JtagOp::Nop)
+            .end_repeat()
+            .op(JtagOp::MoveDrScan)
+            .op(JtagOp::SetStayShift)
+            .if_(JtagCondition::VarANeq, 7).op(JtagOp::SetInFill1).else_().op(JtagOp::SetInFill0).end_if()
+            .shift_in(16)
+            .build().unwrap();
+        jtag.tap.dr = 0x00FF;
+        assert_eq!(jtag.run(&program.sequence).unwrap(), vec![0x00, 0xFF]);
+        assert_eq!(jtag.tap.dr, 0x0000);                     // VARA == 7, zeros shifted in
+        assert_eq!(jtag.tap.idles, 2);                       // two NOPs before BREAK
+    }
+
+    #[test]
+    fn data_pointer_and_subroutines() {
+        let mut jtag = interpreter();
+        let program = JtagSequence::new()
+            .sub(JtagSub::B)
+                .op(JtagOp::MoveDrScan).op(JtagOp::SetExitIdle).op(JtagOp::ShiftInOutDp(16))
+            .end_sub()
+            .op(JtagOp::SaveSub)
+            .call(JtagSub::B)
+            .call(JtagSub::B)
+            .data(&[0x12, 0x34, 0x56, 0x78])
+            .build().unwrap();
+        jtag.tap.dr = 0x0F0F;
+        assert_eq!(jtag.run(&program.sequence).unwrap(), vec![0x0F, 0x0F, 0x12, 0x34]);
+
+        // cached subroutine survives to next sequence
+        let program = JtagSequence::new().call(JtagSub::B).data(&[0xAB, 0xCD]).build().unwrap();
+        assert_eq!(jtag.run(&program.sequence).unwrap(), vec![0x56, 0x78]);
+        assert_eq!(jtag.tap.dr, 0xABCD);
+    }
+
+    #[test]
+    fn faults_reported_with_offset() {
+        let mut jtag = interpreter();
+        let fault = jtag.run(&[JTAG_NOP, JTAG_SHIFT_IN_Q(8), JTAG_END]).unwrap_err();
+        assert_eq!((fault.code, fault.offset), (BDM_RC_JTAG_ILLEGAL_SEQUENCE, 1));   // not in SHIFT state
+
+        let fault = jtag.run(&[JTAG_MOVE_DR_SCAN, JTAG_SHIFT_OUT_DP, 8, JTAG_END]).unwrap_err();
+        assert_eq!(fault.code, BDM_RC_ILLEGAL_PARAMS);                               // data area empty
+
+        assert_eq!(jtag.run(&[JTAG_REPEAT_Q(2), JTAG_NOP, JTAG_END]).unwrap_err().code, BDM_RC_JTAG_UNMATCHED_REPEAT);
+        assert_eq!(jtag.run(&[JTAG_PUSH_Q(5), JTAG_IF_VARA_EQ, JTAG_END]).unwrap_err().code, BDM_RC_JTAG_UNMATCHED_IF);
+        assert_eq!(jtag.run(&[JTAG_PUSH_Q(9), JTAG_SET_ERROR, JTAG_END]).unwrap_err().code, 9);
+        assert_eq!(jtag.run(&[JTAG_CALL_SUBC, JTAG_END]).unwrap_err().code, BDM_RC_JTAG_UNMATCHED_RETURN);
+    }
+}
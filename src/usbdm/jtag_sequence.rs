@@ -0,0 +1,928 @@
+use std::fmt;
+use crate::errors::{Error};
+use crate::usbdm::constants::{memory_space_t};
+use crate::usbdm::firmware::{FirmwareFeature, opcode_feature};
+use crate::usbdm::jtag::*;
+use crate::usbdm::pipeline::{MAX_COMMAND_SIZE};
+use crate::usbdm::programmer::{jtag_sequence_command};
+
+/// Longest sequence in one `CMD_USBDM_JTAG_EXECUTE_SEQUENCE`, 4 bytes of command header
+pub const MAX_SEQUENCE_LENGTH : usize = MAX_COMMAND_SIZE - 4;
+/// Longest answer of one sequence, status byte comes first
+pub const MAX_ANSWER_LENGTH   : usize = MAX_COMMAND_SIZE - 1;
+
+/// `JtagVar` - sequence variables A..D (A/B loaded from pushed value, C/D hold saved data pointer)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JtagVar { A, B, C, D }
+
+/// `JtagSub` - subroutines A..D, A is firmware implemented target instruction execute
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JtagSub { A, B, C, D }
+
+/// `JtagCondition` - test of `JTAG_IF_..` against value pushed before
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JtagCondition { VarAEq, VarBEq, VarANeq, VarBNeq, IterEq, IterNeq }
+
+impl JtagVar {
+    fn index(self) -> u8 { self as u8 }
+    fn from_index(index: u8) -> JtagVar { [JtagVar::A, JtagVar::B, JtagVar::C, JtagVar::D][(index & 3) as usize] }
+}
+
+impl JtagSub {
+    fn index(self) -> u8 { self as u8 }
+    fn from_index(index: u8) -> JtagSub { [JtagSub::A, JtagSub::B, JtagSub::C, JtagSub::D][(index & 3) as usize] }
+}
+
+/// `JtagOp` - one instruction of USBDM JTAG sequence language with its in-line operands
+///
+/// Bit counts of quick opcodes are kept as 1..=32 (encoded 32 is 0).
+#[derive(Debug, Clone, PartialEq)]
+pub enum JtagOp {
+    End,
+    Nop,
+    EndSub,
+    TestLogicReset,
+    MoveDrScan,
+    MoveIrScan,
+    SetStayShift,
+    SetExitShiftDr,
+    SetExitShiftIr,
+    SetExitIdle,
+    SetInFill0,
+    SetInFill1,
+    Else,
+    EndIf,
+    Return,
+    Break,
+    Continue,
+    EndRepeat,
+    SetError,
+    DebugOn,
+    DebugOff,
+    Sub(JtagSub),
+    CallSub(JtagSub),
+    If(JtagCondition),
+    LoadVar(JtagVar),
+    SaveOutDp(JtagVar),
+    RestoreDp(JtagVar),
+    /// Count from pushed value
+    Repeat,
+    Repeat8(u8),
+    /// Count 2..=32 in opcode
+    RepeatQ(u8),
+    /// Count from data area
+    RepeatDp,
+    Push8(u8),
+    Push16(u16),
+    Push32(u32),
+    PushQ(u8),
+    PushDp8,
+    PushDp16,
+    PushDp32,
+    SaveSub,
+    SkipDp,
+    ShiftOutDpVarA,
+    SetBusy,
+    ShiftOutVar(JtagVar, u8),
+    ShiftInOutVar(JtagVar, u8),
+    ShiftOutDp(u8),
+    /// Bit count 0 - taken from data area
+    ShiftInDp(u8),
+    ShiftInOutDp(u8),
+    ShiftInQ(u8),
+    ShiftOutQ(u8, Vec<u8>),
+    ShiftInOutQ(u8, Vec<u8>),
+    ArmReadAp,
+    ArmWriteAp,
+    ArmWriteApI,
+    ReadMem,
+    WriteMem,
+    Unknown(u8),
+}
+
+/// bit count of quick opcode, 0 means 32
+fn quick_bits(opcode: u8) -> u8 {
+    match opcode & JTAG_NUM_BITS_MASK { 0 => 32, n => n }
+}
+
+fn sequence_error(message: String) -> Error {
+    Error::JtagSequenceInvalid(message)
+}
+
+impl JtagOp {
+
+    /// `opcode` - first byte of instruction
+    pub fn opcode(&self) -> u8 {
+        match self {
+            JtagOp::End                 => JTAG_END,
+            JtagOp::Nop                 => JTAG_NOP,
+            JtagOp::EndSub              => JTAG_END_SUB,
+            JtagOp::TestLogicReset      => JTAG_TEST_LOGIC_RESET,
+            JtagOp::MoveDrScan          => JTAG_MOVE_DR_SCAN,
+            JtagOp::MoveIrScan          => JTAG_MOVE_IR_SCAN,
+            JtagOp::SetStayShift        => JTAG_SET_STAY_SHIFT,
+            JtagOp::SetExitShiftDr      => JTAG_SET_EXIT_SHIFT_DR,
+            JtagOp::SetExitShiftIr      => JTAG_SET_EXIT_SHIFT_IR,
+            JtagOp::SetExitIdle         => JTAG_SET_EXIT_IDLE,
+            JtagOp::SetInFill0          => JTAG_SET_IN_FILL_0,
+            JtagOp::SetInFill1          => JTAG_SET_IN_FILL_1,
+            JtagOp::Else                => JTAG_ELSE,
+            JtagOp::EndIf               => JTAG_END_IF,
+            JtagOp::Return              => JTAG_RETURN,
+            JtagOp::Break               => JTAG_BREAK,
+            JtagOp::Continue            => JTAG_CONTINUE,
+            JtagOp::EndRepeat           => JTAG_END_REPEAT,
+            JtagOp::SetError            => JTAG_SET_ERROR,
+            JtagOp::DebugOn             => JTAG_DEBUG_ON,
+            JtagOp::DebugOff            => JTAG_DEBUG_OFF,
+            JtagOp::Sub(sub)            => JTAG_SUB(sub.index()),
+            JtagOp::CallSub(sub)        => JTAG_CALL_SUB(sub.index()),
+            JtagOp::If(condition)       => match condition {
+                JtagCondition::VarAEq   => JTAG_IF_VARA_EQ,
+                JtagCondition::VarBEq   => JTAG_IF_VARB_EQ,
+                JtagCondition::VarANeq  => JTAG_IF_VARA_NEQ,
+                JtagCondition::VarBNeq  => JTAG_IF_VARB_NEQ,
+                JtagCondition::IterEq   => JTAG_IF_ITER_EQ,
+                JtagCondition::IterNeq  => JTAG_IF_ITER_NEQ,
+            },
+            JtagOp::LoadVar(var)        => JTAG_LOAD_VAR(var.index()),
+            JtagOp::SaveOutDp(var)      => JTAG_SAVEDP(var.index()),
+            JtagOp::RestoreDp(var)      => JTAG_RESTOREDP(var.index()),
+            JtagOp::Repeat              => JTAG_REPEAT,
+            JtagOp::Repeat8(_)          => JTAG_REPEAT8,
+            JtagOp::RepeatQ(count)      => JTAG_REPEAT_Q(*count),
+            JtagOp::RepeatDp            => JTAG_REPEAT_DP,
+            JtagOp::Push8(_)            => JTAG_PUSH8,
+            JtagOp::Push16(_)           => JTAG_PUSH16,
+            JtagOp::Push32(_)           => JTAG_PUSH32,
+            JtagOp::PushQ(value)        => JTAG_PUSH_Q(*value),
+            JtagOp::PushDp8             => JTAG_PUSH_DP_8,
+            JtagOp::PushDp16            => JTAG_PUSH_DP_16,
+            JtagOp::PushDp32            => JTAG_PUSH_DP_32,
+            JtagOp::SaveSub             => JTAG_SAVE_SUB,
+            JtagOp::SkipDp              => JTAG_SKIP_DP,
+            JtagOp::ShiftOutDpVarA      => JTAG_SHIFT_OUT_DP_VARA,
+            JtagOp::SetBusy             => JTAG_SET_BUSY,
+            JtagOp::ShiftOutVar(var, _) => JTAG_SHIFT_OUT_VAR(var.index()),
+            JtagOp::ShiftInOutVar(var, _) => JTAG_SHIFT_IN_OUT_VAR(var.index()),
+            JtagOp::ShiftOutDp(_)       => JTAG_SHIFT_OUT_DP,
+            JtagOp::ShiftInDp(_)        => JTAG_SHIFT_IN_DP,
+            JtagOp::ShiftInOutDp(_)     => JTAG_SHIFT_IN_OUT_DP,
+            JtagOp::ShiftInQ(bits)      => JTAG_SHIFT_IN_Q(*bits),
+            JtagOp::ShiftOutQ(bits, _)  => JTAG_SHIFT_OUT_Q(*bits),
+            JtagOp::ShiftInOutQ(bits, _) => JTAG_SHIFT_IN_OUT_Q(*bits),
+            JtagOp::ArmReadAp           => JTAG_ARM_READAP,
+            JtagOp::ArmWriteAp          => JTAG_ARM_WRITEAP,
+            JtagOp::ArmWriteApI         => JTAG_ARM_WRITEAP_I,
+            JtagOp::ReadMem             => JTAG_READ_MEM,
+            JtagOp::WriteMem            => JTAG_WRITE_MEM,
+            JtagOp::Unknown(opcode)     => *opcode,
+        }
+    }
+
+    /// `encode` - append instruction with in-line operands to `out`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.opcode());
+        match self {
+            JtagOp::Repeat8(count)          => out.push(*count),
+            JtagOp::Push8(value)            => out.push(*value),
+            JtagOp::Push16(value)           => out.extend_from_slice(&value.to_be_bytes()),
+            JtagOp::Push32(value)           => out.extend_from_slice(&value.to_be_bytes()),
+            JtagOp::ShiftOutVar(_, bits)    |
+            JtagOp::ShiftInOutVar(_, bits)  |
+            JtagOp::ShiftOutDp(bits)        |
+            JtagOp::ShiftInDp(bits)         |
+            JtagOp::ShiftInOutDp(bits)      => out.push(*bits),
+            JtagOp::ShiftOutQ(_, data)      |
+            JtagOp::ShiftInOutQ(_, data)    => out.extend_from_slice(data),
+            _ => {},
+        }
+    }
+
+    /// `decode` - instruction at start of `bytes` and its length
+    pub fn decode(bytes: &[u8]) -> Result<(JtagOp, usize), Error> {
+        let opcode = *bytes.first().ok_or_else(|| sequence_error("sequence ends without JTAG_END".to_string()))?;
+        let operand = |count: usize| -> Result<&[u8], Error> {
+            bytes.get(1..1 + count).ok_or_else(|| sequence_error(format!("operand of opcode {:#04X} truncated", opcode)))
+        };
+        let op = match opcode {
+            JTAG_END                => JtagOp::End,
+            JTAG_NOP                => JtagOp::Nop,
+            JTAG_END_SUB            => JtagOp::EndSub,
+            JTAG_TEST_LOGIC_RESET   => JtagOp::TestLogicReset,
+            JTAG_MOVE_DR_SCAN       => JtagOp::MoveDrScan,
+            JTAG_MOVE_IR_SCAN       => JtagOp::MoveIrScan,
+            JTAG_SET_STAY_SHIFT     => JtagOp::SetStayShift,
+            JTAG_SET_EXIT_SHIFT_DR  => JtagOp::SetExitShiftDr,
+            JTAG_SET_EXIT_SHIFT_IR  => JtagOp::SetExitShiftIr,
+            JTAG_SET_EXIT_IDLE      => JtagOp::SetExitIdle,
+            JTAG_SET_IN_FILL_0      => JtagOp::SetInFill0,
+            JTAG_SET_IN_FILL_1      => JtagOp::SetInFill1,
+            JTAG_ELSE               => JtagOp::Else,
+            JTAG_END_IF             => JtagOp::EndIf,
+            JTAG_RETURN             => JtagOp::Return,
+            JTAG_BREAK              => JtagOp::Break,
+            JTAG_CONTINUE           => JtagOp::Continue,
+            JTAG_END_REPEAT         => JtagOp::EndRepeat,
+            JTAG_SET_ERROR          => JtagOp::SetError,
+            JTAG_DEBUG_ON           => JtagOp::DebugOn,
+            JTAG_DEBUG_OFF          => JtagOp::DebugOff,
+            op if (JTAG_SUBA..=JTAG_SUBD).contains(&op)           => JtagOp::Sub(JtagSub::from_index(op - JTAG_SUBA)),
+            op if (JTAG_CALL_SUBA..=JTAG_CALL_SUBD).contains(&op) => JtagOp::CallSub(JtagSub::from_index(op - JTAG_CALL_SUBA)),
+            JTAG_IF_VARA_EQ         => JtagOp::If(JtagCondition::VarAEq),
+            JTAG_IF_VARB_EQ         => JtagOp::If(JtagCondition::VarBEq),
+            JTAG_IF_VARA_NEQ        => JtagOp::If(JtagCondition::VarANeq),
+            JTAG_IF_VARB_NEQ        => JtagOp::If(JtagCondition::VarBNeq),
+            JTAG_IF_ITER_EQ         => JtagOp::If(JtagCondition::IterEq),
+            JTAG_IF_ITER_NEQ        => JtagOp::If(JtagCondition::IterNeq),
+            JTAG_LOAD_VARA          => JtagOp::LoadVar(JtagVar::A),
+            JTAG_LOAD_VARB          => JtagOp::LoadVar(JtagVar::B),
+            JTAG_SAVE_OUT_DP_VARC   => JtagOp::SaveOutDp(JtagVar::C),
+            JTAG_SAVE_OUT_DP_VARD   => JtagOp::SaveOutDp(JtagVar::D),
+            JTAG_RESTORE_DP_VARC    => JtagOp::RestoreDp(JtagVar::C),
+            JTAG_RESTORE_DP_VARD    => JtagOp::RestoreDp(JtagVar::D),
+            JTAG_REPEAT             => JtagOp::Repeat,
+            JTAG_REPEAT8            => JtagOp::Repeat8(operand(1)?[0]),
+            JTAG_PUSH8              => JtagOp::Push8(operand(1)?[0]),
+            JTAG_PUSH16             => { let v = operand(2)?; JtagOp::Push16(u16::from_be_bytes([v[0], v[1]])) },
+            JTAG_PUSH32             => { let v = operand(4)?; JtagOp::Push32(u32::from_be_bytes([v[0], v[1], v[2], v[3]])) },
+            JTAG_PUSH_DP_8          => JtagOp::PushDp8,
+            JTAG_PUSH_DP_16         => JtagOp::PushDp16,
+            JTAG_PUSH_DP_32         => JtagOp::PushDp32,
+            JTAG_SAVE_SUB           => JtagOp::SaveSub,
+            JTAG_SKIP_DP            => JtagOp::SkipDp,
+            JTAG_SHIFT_OUT_DP_VARA  => JtagOp::ShiftOutDpVarA,
+            JTAG_SET_BUSY           => JtagOp::SetBusy,
+            op if (JTAG_SHIFT_OUT_VARA..=JTAG_SHIFT_OUT_VARD).contains(&op)       => JtagOp::ShiftOutVar(JtagVar::from_index(op - JTAG_SHIFT_OUT_VARA), operand(1)?[0]),
+            op if (JTAG_SHIFT_IN_OUT_VARA..=JTAG_SHIFT_IN_OUT_VARD).contains(&op) => JtagOp::ShiftInOutVar(JtagVar::from_index(op - JTAG_SHIFT_IN_OUT_VARA), operand(1)?[0]),
+            JTAG_SHIFT_OUT_DP       => JtagOp::ShiftOutDp(operand(1)?[0]),
+            JTAG_SHIFT_IN_DP        => JtagOp::ShiftInDp(operand(1)?[0]),
+            JTAG_SHIFT_IN_OUT_DP    => JtagOp::ShiftInOutDp(operand(1)?[0]),
+            JTAG_ARM_READAP         => JtagOp::ArmReadAp,
+            JTAG_ARM_WRITEAP        => JtagOp::ArmWriteAp,
+            JTAG_ARM_WRITEAP_I      => JtagOp::ArmWriteApI,
+            JTAG_READ_MEM           => JtagOp::ReadMem,
+            JTAG_WRITE_MEM          => JtagOp::WriteMem,
+            op => match op >> 5 {
+                3 => JtagOp::ShiftInQ(quick_bits(op)),
+                4 => { let bits = quick_bits(op); JtagOp::ShiftOutQ(bits, operand(BITS_TO_BYTES(bits) as usize)?.to_vec()) },
+                5 => { let bits = quick_bits(op); JtagOp::ShiftInOutQ(bits, operand(BITS_TO_BYTES(bits) as usize)?.to_vec()) },
+                6 if op == JTAG_REPEAT_DP => JtagOp::RepeatDp,
+                6 => JtagOp::RepeatQ(quick_bits(op)),
+                7 => JtagOp::PushQ(op & JTAG_NUM_BITS_MASK),
+                _ => JtagOp::Unknown(op),
+            },
+        };
+        let length = op.length();
+        Ok((op, length))
+    }
+
+    /// `length` - encoded size of instruction in bytes
+    pub fn length(&self) -> usize {
+        let mut bytes = Vec::new();
+        self.encode(&mut bytes);
+        bytes.len()
+    }
+
+    /// `answer_bytes` - bytes added to answer, `None` when size comes from data area
+    pub fn answer_bytes(&self) -> Option<usize> {
+        match self {
+            JtagOp::ShiftInQ(bits) | JtagOp::ShiftInOutQ(bits, _) => Some(BITS_TO_BYTES(*bits) as usize),
+            JtagOp::ShiftInDp(0) | JtagOp::ShiftInOutDp(0)        => None,
+            JtagOp::ShiftInDp(bits) | JtagOp::ShiftInOutDp(bits)  => Some(BITS_TO_BYTES(*bits) as usize),
+            JtagOp::ReadMem | JtagOp::ArmReadAp                   => None,
+            _ => Some(0),
+        }
+    }
+
+    /// `uses_pushed_value` - operand taken from `JTAG_PUSH..` before
+    fn uses_pushed_value(&self) -> bool {
+        matches!(self, JtagOp::If(_) | JtagOp::LoadVar(_) | JtagOp::Repeat | JtagOp::SetError | JtagOp::SkipDp)
+    }
+
+    /// `check` - operand ranges of instruction
+    pub fn check(&self) -> Result<(), String> {
+        let bits_ok = |bits: u8| (1..=32).contains(&bits);
+        match self {
+            JtagOp::ShiftInQ(bits) if !bits_ok(*bits) =>
+                Err(format!("SHIFT_IN_Q of {} bits, allowed 1..32", bits)),
+            JtagOp::ShiftOutQ(bits, data) | JtagOp::ShiftInOutQ(bits, data) if !bits_ok(*bits) || data.len() != BITS_TO_BYTES(*bits) as usize =>
+                Err(format!("{} of {} bits with {} data bytes, allowed 1..32 bits with whole bytes of data", self.mnemonic(), bits, data.len())),
+            JtagOp::ShiftOutVar(_, bits) | JtagOp::ShiftInOutVar(_, bits) if !bits_ok(*bits) =>
+                Err(format!("{} of {} bits, allowed 1..32", self.mnemonic(), bits)),
+            JtagOp::ShiftOutDp(0) =>
+                Err("SHIFT_OUT_DP of 0 bits".to_string()),
+            JtagOp::RepeatQ(count) if !(2..=32).contains(count) =>
+                Err(format!("REPEAT_Q count {}, allowed 2..32", count)),
+            JtagOp::Repeat8(0) =>
+                Err("REPEAT8 count 0".to_string()),
+            JtagOp::PushQ(value) if *value > JTAG_NUM_BITS_MASK =>
+                Err(format!("PUSH_Q value {}, allowed 0..31", value)),
+            JtagOp::LoadVar(var) if !matches!(var, JtagVar::A | JtagVar::B) =>
+                Err(format!("LOAD_VAR{:?}, only A and B can be loaded", var)),
+            JtagOp::SaveOutDp(var) | JtagOp::RestoreDp(var) if !matches!(var, JtagVar::C | JtagVar::D) =>
+                Err(format!("{}, data pointer is kept only in C and D", self.mnemonic())),
+            JtagOp::Unknown(opcode) =>
+                Err(format!("unknown opcode {:#04X}", opcode)),
+            _ => Ok(()),
+        }
+    }
+
+    /// `mnemonic` - name as in USBDM sources, without `JTAG_` prefix
+    pub fn mnemonic(&self) -> String {
+        match self {
+            JtagOp::End                 => "END".to_string(),
+            JtagOp::Nop                 => "NOP".to_string(),
+            JtagOp::EndSub              => "END_SUB".to_string(),
+            JtagOp::TestLogicReset      => "TEST_LOGIC_RESET".to_string(),
+            JtagOp::MoveDrScan          => "MOVE_DR_SCAN".to_string(),
+            JtagOp::MoveIrScan          => "MOVE_IR_SCAN".to_string(),
+            JtagOp::SetStayShift        => "SET_STAY_SHIFT".to_string(),
+            JtagOp::SetExitShiftDr      => "SET_EXIT_SHIFT_DR".to_string(),
+            JtagOp::SetExitShiftIr      => "SET_EXIT_SHIFT_IR".to_string(),
+            JtagOp::SetExitIdle         => "SET_EXIT_IDLE".to_string(),
+            JtagOp::SetInFill0          => "SET_IN_FILL_0".to_string(),
+            JtagOp::SetInFill1          => "SET_IN_FILL_1".to_string(),
+            JtagOp::Else                => "ELSE".to_string(),
+            JtagOp::EndIf               => "END_IF".to_string(),
+            JtagOp::Return              => "RETURN".to_string(),
+            JtagOp::Break               => "BREAK".to_string(),
+            JtagOp::Continue            => "CONTINUE".to_string(),
+            JtagOp::EndRepeat           => "END_REPEAT".to_string(),
+            JtagOp::SetError            => "SET_ERROR".to_string(),
+            JtagOp::DebugOn             => "DEBUG_ON".to_string(),
+            JtagOp::DebugOff            => "DEBUG_OFF".to_string(),
+            JtagOp::Sub(sub)            => format!("SUB{:?}", sub),
+            JtagOp::CallSub(sub)        => format!("CALL_SUB{:?}", sub),
+            JtagOp::If(condition)       => match condition {
+                JtagCondition::VarAEq   => "IF_VARA_EQ".to_string(),
+                JtagCondition::VarBEq   => "IF_VARB_EQ".to_string(),
+                JtagCondition::VarANeq  => "IF_VARA_NEQ".to_string(),
+                JtagCondition::VarBNeq  => "IF_VARB_NEQ".to_string(),
+                JtagCondition::IterEq   => "IF_ITER_EQ".to_string(),
+                JtagCondition::IterNeq  => "IF_ITER_NEQ".to_string(),
+            },
+            JtagOp::LoadVar(var)        => format!("LOAD_VAR{:?}", var),
+            JtagOp::SaveOutDp(var)      => format!("SAVE_OUT_DP_VAR{:?}", var),
+            JtagOp::RestoreDp(var)      => format!("RESTORE_DP_VAR{:?}", var),
+            JtagOp::Repeat              => "REPEAT".to_string(),
+            JtagOp::Repeat8(_)          => "REPEAT8".to_string(),
+            JtagOp::RepeatQ(_)          => "REPEAT_Q".to_string(),
+            JtagOp::RepeatDp            => "REPEAT_DP".to_string(),
+            JtagOp::Push8(_)            => "PUSH8".to_string(),
+            JtagOp::Push16(_)           => "PUSH16".to_string(),
+            JtagOp::Push32(_)           => "PUSH32".to_string(),
+            JtagOp::PushQ(_)            => "PUSH_Q".to_string(),
+            JtagOp::PushDp8             => "PUSH_DP_8".to_string(),
+            JtagOp::PushDp16            => "PUSH_DP_16".to_string(),
+            JtagOp::PushDp32            => "PUSH_DP_32".to_string(),
+            JtagOp::SaveSub             => "SAVE_SUB".to_string(),
+            JtagOp::SkipDp              => "SKIP_DP".to_string(),
+            JtagOp::ShiftOutDpVarA      => "SHIFT_OUT_DP_VARA".to_string(),
+            JtagOp::SetBusy             => "SET_BUSY".to_string(),
+            JtagOp::ShiftOutVar(var, _) => format!("SHIFT_OUT_VAR{:?}", var),
+            JtagOp::ShiftInOutVar(var, _) => format!("SHIFT_IN_OUT_VAR{:?}", var),
+            JtagOp::ShiftOutDp(_)       => "SHIFT_OUT_DP".to_string(),
+            JtagOp::ShiftInDp(_)        => "SHIFT_IN_DP".to_string(),
+            JtagOp::ShiftInOutDp(_)     => "SHIFT_IN_OUT_DP".to_string(),
+            JtagOp::ShiftInQ(_)         => "SHIFT_IN_Q".to_string(),
+            JtagOp::ShiftOutQ(_, _)     => "SHIFT_OUT_Q".to_string(),
+            JtagOp::ShiftInOutQ(_, _)   => "SHIFT_IN_OUT_Q".to_string(),
+            JtagOp::ArmReadAp           => "ARM_READAP".to_string(),
+            JtagOp::ArmWriteAp          => "ARM_WRITEAP".to_string(),
+            JtagOp::ArmWriteApI         => "ARM_WRITEAP_I".to_string(),
+            JtagOp::ReadMem             => "READ_MEM".to_string(),
+            JtagOp::WriteMem            => "WRITE_MEM".to_string(),
+            JtagOp::Unknown(_)          => "???".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for JtagOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // in-line data is MSB byte first, listed as value
+        let value = |data: &[u8]| data.iter().fold(0u64, |value, byte| (value << 8) | *byte as u64);
+        match self {
+            JtagOp::Repeat8(count) | JtagOp::RepeatQ(count)     => write!(f, "{} {}", self.mnemonic(), count),
+            JtagOp::Push8(value)                                => write!(f, "{} {:#04X}", self.mnemonic(), value),
+            JtagOp::Push16(value)                               => write!(f, "{} {:#06X}", self.mnemonic(), value),
+            JtagOp::Push32(value)                               => write!(f, "{} {:#010X}", self.mnemonic(), value),
+            JtagOp::PushQ(value)                                => write!(f, "{} {}", self.mnemonic(), value),
+            JtagOp::ShiftOutVar(_, bits) | JtagOp::ShiftInOutVar(_, bits) |
+            JtagOp::ShiftOutDp(bits) | JtagOp::ShiftInOutDp(bits) |
+            JtagOp::ShiftInQ(bits)                              => write!(f, "{} {}", self.mnemonic(), bits),
+            JtagOp::ShiftInDp(0)                                => write!(f, "{} <dp>", self.mnemonic()),
+            JtagOp::ShiftInDp(bits)                             => write!(f, "{} {}", self.mnemonic(), bits),
+            JtagOp::ShiftOutQ(bits, data) | JtagOp::ShiftInOutQ(bits, data) => write!(f, "{} {}, {:#X}", self.mnemonic(), bits, value(data)),
+            JtagOp::Unknown(opcode)                             => write!(f, "{} {:#04X}", self.mnemonic(), opcode),
+            _                                                   => write!(f, "{}", self.mnemonic()),
+        }
+    }
+}
+
+/// `JtagProgram` - validated sequence ready for `Programmer::exec_program`
+#[derive(Debug, Clone, PartialEq)]
+pub struct JtagProgram {
+    /// Instructions, `JTAG_END` and data area
+    pub sequence      : Vec<u8>,
+    /// Answer bytes returned by USBDM after status
+    pub answer_length : u8,
+    /// Firmware features needed beyond plain sequence execution
+    pub features      : Vec<FirmwareFeature>,
+}
+
+impl JtagProgram {
+
+    /// `command` - `CMD_USBDM_JTAG_EXECUTE_SEQUENCE` packet
+    pub fn command(&self) -> Vec<u8> {
+        jtag_sequence_command(self.sequence.clone(), self.answer_length)
+    }
+
+    /// `listing` - disassembly for logs
+    pub fn listing(&self) -> String {
+        disassemble(&self.sequence)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    op     : JtagOp,
+    /// Answer bytes of this step, `None` - unknown (size in data area not stated by builder)
+    answer : Option<usize>,
+}
+
+/// `JtagSequence` - builder of USBDM JTAG sequence
+///
+/// Operands are range checked, IF/ELSE/END_IF, REPEAT/END_REPEAT and SUBx/END_SUB nesting is checked
+/// and answer length is counted by `build`. First error is kept and returned from `build`.
+/// ### Usage
+///
+/// ```
+/// # use usbdm_rs::usbdm::jtag::*;
+/// # use usbdm_rs::usbdm::jtag_sequence::{JtagSequence, JtagOp};
+/// let program = JtagSequence::new()
+///     .op(JtagOp::MoveIrScan)
+///     .op(JtagOp::SetExitShiftDr)
+///     .shift_out(JTAG_CORE_COMMAND_LENGTH, JTAG_IDCODE_COMMAND as u32)
+///     .op(JtagOp::SetExitIdle)
+///     .shift_in(JTAG_IDCODE_LENGTH)
+///     .build()?;
+/// assert_eq!(program.answer_length, 4);
+/// // let id_code = prg.exec_program_idempotent(&program)?;
+/// # Ok::<(), usbdm_rs::errors::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct JtagSequence {
+    steps : Vec<Step>,
+    data  : Vec<u8>,
+    error : Option<String>,
+}
+
+impl JtagSequence {
+
+    pub fn new() -> Self {
+        JtagSequence::default()
+    }
+
+    fn step(mut self, op: JtagOp, answer: Option<usize>) -> Self {
+        if self.error.is_none() {
+            if let Err(message) = op.check() {
+                self.error = Some(message);
+            }
+        }
+        self.steps.push(Step { op, answer });
+        self
+    }
+
+    fn fail(mut self, message: String) -> Self {
+        self.error.get_or_insert(message);
+        self
+    }
+
+    /// `op` - any instruction, answer size as given by `JtagOp::answer_bytes`
+    pub fn op(self, op: JtagOp) -> Self {
+        let answer = op.answer_bytes();
+        self.step(op, answer)
+    }
+
+    /// `push` - shortest `JTAG_PUSH..` of `value`
+    pub fn push(self, value: u32) -> Self {
+        match value {
+            0..=0x1F      => self.op(JtagOp::PushQ(value as u8)),
+            0x20..=0xFF   => self.op(JtagOp::Push8(value as u8)),
+            0x100..=0xFFFF => self.op(JtagOp::Push16(value as u16)),
+            _             => self.op(JtagOp::Push32(value)),
+        }
+    }
+
+    /// `shift_out` - `bits` of `value` in-line, MSB byte first
+    pub fn shift_out(self, bits: u8, value: u32) -> Self {
+        match Self::inline_data(bits, value) {
+            Ok(data)     => self.op(JtagOp::ShiftOutQ(bits, data)),
+            Err(message) => self.fail(message),
+        }
+    }
+
+    /// `shift_in_out` - `bits` of `value` in-line, captured bits go to answer
+    pub fn shift_in_out(self, bits: u8, value: u32) -> Self {
+        match Self::inline_data(bits, value) {
+            Ok(data)     => self.op(JtagOp::ShiftInOutQ(bits, data)),
+            Err(message) => self.fail(message),
+        }
+    }
+
+    fn inline_data(bits: u8, value: u32) -> Result<Vec<u8>, String> {
+        if !(1..=32).contains(&bits) {
+            return Err(format!("shift of {} bits, allowed 1..32", bits))
+        }
+        if bits < 32 && (value >> bits) != 0 {
+            return Err(format!("value {:#X} does not fit in {} bits", value, bits))
+        }
+        let bytes = BITS_TO_BYTES(bits) as usize;
+        Ok(value.to_be_bytes()[4 - bytes..].to_vec())
+    }
+
+    /// `shift_in` - `bits` captured to answer
+    pub fn shift_in(self, bits: u8) -> Self {
+        self.op(JtagOp::ShiftInQ(bits))
+    }
+
+    /// `shift_in_dp_from_data` - `JTAG_SHIFT_IN_DP 0`, bit count is in data area and caller knows it is `bits`
+    pub fn shift_in_dp_from_data(self, bits: u8) -> Self {
+        if !(1..=32).contains(&bits) {
+            return self.fail(format!("SHIFT_IN_DP of {} bits, allowed 1..32", bits))
+        }
+        self.step(JtagOp::ShiftInDp(0), Some(BITS_TO_BYTES(bits) as usize))
+    }
+
+    /// `if_` - `JTAG_IF_..` comparing with `value`, closed by `end_if`
+    pub fn if_(self, condition: JtagCondition, value: u32) -> Self {
+        self.push(value).op(JtagOp::If(condition))
+    }
+
+    pub fn else_(self) -> Self {
+        self.op(JtagOp::Else)
+    }
+
+    pub fn end_if(self) -> Self {
+        self.op(JtagOp::EndIf)
+    }
+
+    /// `repeat` - block repeated `count` times, closed by `end_repeat`
+    ///
+    /// Counts above 32 are pushed as 16/32-bit value, as `JTAG_REPEAT_16` / `JTAG_REPEAT_32` did.
+    pub fn repeat(self, count: u32) -> Self {
+        match count {
+            0          => self.fail("REPEAT count 0".to_string()),
+            1          => self.push(1).op(JtagOp::Repeat),
+            2..=32     => self.op(JtagOp::RepeatQ(count as u8)),
+            0x21..=0xFFFF => self.op(JtagOp::Push16(count as u16)).op(JtagOp::Repeat),
+            _          => self.op(JtagOp::Push32(count)).op(JtagOp::Repeat),
+        }
+    }
+
+    pub fn end_repeat(self) -> Self {
+        self.op(JtagOp::EndRepeat)
+    }
+
+    /// `sub` - start of subroutine definition, closed by `end_sub`
+    pub fn sub(self, sub: JtagSub) -> Self {
+        self.op(JtagOp::Sub(sub))
+    }
+
+    pub fn end_sub(self) -> Self {
+        self.op(JtagOp::EndSub)
+    }
+
+    /// `call` - subroutine defined in this sequence or cached in firmware
+    pub fn call(self, sub: JtagSub) -> Self {
+        self.op(JtagOp::CallSub(sub))
+    }
+
+    /// `data` - bytes of data area after `JTAG_END`, consumed in order by `..DP` instructions and firmware subroutines
+    pub fn data(mut self, bytes: &[u8]) -> Self {
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    /// `read_mem` - `JTAG_READ_MEM` of `elements` from `address`, header goes to data area
+    pub fn read_mem(self, memory_space: u8, elements: u8, address: u32) -> Self {
+        let size = (memory_space & memory_space_t::MS_SIZE) as usize;
+        self.step(JtagOp::ReadMem, Some(elements as usize * size))
+            .data(&address.to_be_bytes())
+            .data(&[elements, memory_space])
+    }
+
+    /// `write_mem` - `JTAG_WRITE_MEM` of `elements` to `address`, header and `bytes` go to data area
+    pub fn write_mem(self, memory_space: u8, elements: u8, address: u32, bytes: &[u8]) -> Self {
+        self.op(JtagOp::WriteMem)
+            .data(&address.to_be_bytes())
+            .data(&[elements, memory_space])
+            .data(bytes)
+    }
+
+    /// `build` - validate, encode and count answer length
+    pub fn build(self) -> Result<JtagProgram, Error> {
+        if let Some(message) = self.error {
+            return Err(sequence_error(message))
+        }
+        let answer_length = check_structure(&self.steps)?;
+        if answer_length > MAX_ANSWER_LENGTH {
+            return Err(sequence_error(format!("answer of {} bytes, USBDM returns at most {}", answer_length, MAX_ANSWER_LENGTH)))
+        }
+
+        let mut sequence = Vec::new();
+        let mut features = Vec::new();
+        for step in &self.steps {
+            step.op.encode(&mut sequence);
+            if let Some(feature) = opcode_feature(step.op.opcode()) {
+                if !features.contains(&feature) {
+                    features.push(feature);
+                }
+            }
+        }
+        sequence.push(JTAG_END);
+        sequence.extend_from_slice(&self.data);
+        if sequence.len() > MAX_SEQUENCE_LENGTH {
+            return Err(sequence_error(format!("sequence of {} bytes, USBDM accepts at most {}", sequence.len(), MAX_SEQUENCE_LENGTH)))
+        }
+        Ok(JtagProgram { sequence, answer_length: answer_length as u8, features })
+    }
+}
+
+enum Block {
+    If { in_else: bool, then_answer: usize },
+    Repeat { count: Option<u32>, early_exit: bool },
+    Sub(JtagSub),
+}
+
+struct Frame {
+    block  : Block,
+    start  : usize,
+    answer : usize,
+}
+
+/// `check_structure` - nesting of blocks and answer length of whole sequence
+fn check_structure(steps: &[Step]) -> Result<usize, Error> {
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut answer: usize = 0;
+    let mut subs: [Option<usize>; 4] = [None; 4];
+    // value of last JTAG_PUSH.., None - pushed from data area
+    let mut pushed: Option<Option<u32>> = None;
+
+    for (index, step) in steps.iter().enumerate() {
+        let at = |message: String| sequence_error(format!("step {} ({}): {}", index, step.op, message));
+        let current = |stack: &mut Vec<Frame>, answer: &mut usize, bytes: usize| match stack.last_mut() {
+            Some(frame) => frame.answer += bytes,
+            None        => *answer += bytes,
+        };
+        if step.op.uses_pushed_value() && pushed.is_none() {
+            return Err(at("uses pushed value, nothing pushed before".to_string()))
+        }
+        match &step.op {
+            JtagOp::End => return Err(at("JTAG_END is added by build, use data() for data area".to_string())),
+            JtagOp::Push8(value)  => pushed = Some(Some(*value as u32)),
+            JtagOp::Push16(value) => pushed = Some(Some(*value as u32)),
+            JtagOp::Push32(value) => pushed = Some(Some(*value)),
+            JtagOp::PushQ(value)  => pushed = Some(Some(*value as u32)),
+            JtagOp::PushDp8 | JtagOp::PushDp16 | JtagOp::PushDp32 => pushed = Some(None),
+            JtagOp::If(_) => stack.push(Frame { block: Block::If { in_else: false, then_answer: 0 }, start: index, answer: 0 }),
+            JtagOp::Else => match stack.last_mut() {
+                Some(Frame { block: Block::If { in_else, then_answer }, answer, .. }) if !*in_else => {
+                    *in_else = true;
+                    *then_answer = std::mem::take(answer);
+                },
+                _ => return Err(at("ELSE without IF".to_string())),
+            },
+            JtagOp::EndIf => match stack.pop() {
+                Some(Frame { block: Block::If { in_else, then_answer }, answer: branch, .. }) => {
+                    let (then_answer, else_answer) = if in_else { (then_answer, branch) } else { (branch, 0) };
+                    if then_answer != else_answer {
+                        return Err(at(format!("IF branches return {} and {} bytes, answer length would depend on target", then_answer, else_answer)))
+                    }
+                    current(&mut stack, &mut answer, then_answer);
+                },
+                _ => return Err(at("END_IF without IF".to_string())),
+            },
+            JtagOp::Repeat | JtagOp::Repeat8(_) | JtagOp::RepeatQ(_) | JtagOp::RepeatDp => {
+                let count = match &step.op {
+                    JtagOp::Repeat8(count) => Some(*count as u32),
+                    JtagOp::RepeatQ(count) => Some(*count as u32),
+                    JtagOp::Repeat         => pushed.flatten(),
+                    _                      => None,
+                };
+                stack.push(Frame { block: Block::Repeat { count, early_exit: false }, start: index, answer: 0 });
+            },
+            JtagOp::Break | JtagOp::Continue => match stack.iter_mut().rev().find(|frame| !matches!(frame.block, Block::If { .. })) {
+                Some(Frame { block: Block::Repeat { early_exit, .. }, .. }) => *early_exit = true,
+                _ => return Err(at("outside of REPEAT".to_string())),
+            },
+            JtagOp::EndRepeat => match stack.pop() {
+                Some(Frame { block: Block::Repeat { count, early_exit }, answer: body, .. }) => {
+                    if body > 0 && early_exit {
+                        return Err(at(format!("REPEAT returning {} bytes per pass has BREAK/CONTINUE, answer length unknown", body)))
+                    }
+                    match count {
+                        Some(count)       => current(&mut stack, &mut answer, body * count as usize),
+                        None if body == 0 => {},
+                        None => return Err(at(format!("REPEAT count from data area with body returning {} bytes, answer length unknown", body))),
+                    }
+                },
+                _ => return Err(at("END_REPEAT without REPEAT".to_string())),
+            },
+            JtagOp::Sub(sub) => {
+                if !stack.is_empty() {
+                    return Err(at("subroutine defined inside block".to_string()))
+                }
+                stack.push(Frame { block: Block::Sub(*sub), start: index, answer: 0 });
+            },
+            JtagOp::EndSub => match stack.pop() {
+                Some(Frame { block: Block::Sub(sub), answer: body, .. }) => subs[sub.index() as usize] = Some(body),
+                _ => return Err(at("END_SUB without SUB".to_string())),
+            },
+            JtagOp::Return => if !stack.iter().any(|frame| matches!(frame.block, Block::Sub(_))) {
+                return Err(at("RETURN outside of subroutine".to_string()))
+            },
+            JtagOp::CallSub(sub) => {
+                if stack.iter().any(|frame| matches!(frame.block, Block::Sub(inner) if inner == *sub)) {
+                    return Err(at("recursive call".to_string()))
+                }
+                // subroutine cached in firmware (SUBA executes target instructions) returns nothing
+                let bytes = subs[sub.index() as usize].unwrap_or(0);
+                current(&mut stack, &mut answer, bytes);
+            },
+            _ => match step.answer {
+                Some(bytes) => current(&mut stack, &mut answer, bytes),
+                None => return Err(at("answer size is taken from data area, use builder method stating it".to_string())),
+            },
+        }
+    }
+    if let Some(frame) = stack.last() {
+        let name = match frame.block { Block::If { .. } => "IF", Block::Repeat { .. } => "REPEAT", Block::Sub(_) => "SUB" };
+        return Err(sequence_error(format!("{} at step {} not closed", name, frame.start)))
+    }
+    Ok(answer)
+}
+
+/// Instructions with their offset, data area after `JTAG_END`
+pub type DecodedSequence = (Vec<(usize, JtagOp)>, Vec<u8>);
+
+/// `decode` - instructions up to first `JTAG_END` outside subroutines and data area after it
+pub fn decode(bytes: &[u8]) -> Result<DecodedSequence, Error> {
+    let mut ops = Vec::new();
+    let mut offset = 0;
+    loop {
+        let (op, length) = JtagOp::decode(&bytes[offset.min(bytes.len())..])?;
+        ops.push((offset, op.clone()));
+        offset += length;
+        if op == JtagOp::End {
+            return Ok((ops, bytes[offset..].to_vec()))
+        }
+    }
+}
+
+/// `disassemble` - readable listing of sequence: offset, bytes, instruction, then data area
+///
+/// Undecodable tail is listed as is, so broken sequences can still be logged.
+pub fn disassemble(bytes: &[u8]) -> String {
+    let mut listing = String::new();
+    let mut offset = 0;
+    let mut depth: usize = 0;
+    while offset < bytes.len() {
+        let (op, length) = match JtagOp::decode(&bytes[offset..]) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                listing += &format!("{:04X}  {:<14}  ; {}\n", offset, hex_bytes(&bytes[offset..]), e);
+                return listing
+            },
+        };
+        if matches!(op, JtagOp::Else | JtagOp::EndIf | JtagOp::EndRepeat | JtagOp::EndSub) {
+            depth = depth.saturating_sub(1);
+        }
+        listing += &format!("{:04X}  {:<14}  {}{}\n", offset, hex_bytes(&bytes[offset..offset + length]), "  ".repeat(depth), op);
+        if matches!(op, JtagOp::If(_) | JtagOp::Else | JtagOp::Repeat | JtagOp::Repeat8(_) | JtagOp::RepeatQ(_) | JtagOp::RepeatDp | JtagOp::Sub(_)) {
+            depth += 1;
+        }
+        offset += length;
+        if op == JtagOp::End {
+            break
+        }
+    }
+    for chunk in bytes[offset..].chunks(8) {
+        listing += &format!("{:04X}  {:<14}  ; data\n", offset, hex_bytes(chunk));
+        offset += chunk.len();
+    }
+    listing
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_code_sequence_encoding() {
+        let program = JtagSequence::new()
+            .op(JtagOp::TestLogicReset)
+            .op(JtagOp::MoveIrScan)
+            .op(JtagOp::SetExitShiftDr)
+            .shift_out(JTAG_CORE_COMMAND_LENGTH, JTAG_IDCODE_COMMAND as u32)
+            .op(JtagOp::SetExitIdle)
+            .shift_in(32)
+            .build().unwrap();
+        assert_eq!(program.sequence, vec![
+            JTAG_TEST_LOGIC_RESET, JTAG_MOVE_IR_SCAN, JTAG_SET_EXIT_SHIFT_DR,
+            JTAG_SHIFT_OUT_Q(JTAG_CORE_COMMAND_LENGTH), JTAG_IDCODE_COMMAND,
+            JTAG_SET_EXIT_IDLE, JTAG_SHIFT_IN_Q(32), JTAG_END]);
+        assert_eq!(program.answer_length, 4);
+        assert!(program.features.is_empty());
+    }
+
+    #[test]
+    fn operand_ranges_checked() {
+        assert!(matches!(JtagSequence::new().shift_in(33).build(), Err(Error::JtagSequenceInvalid(_))));
+        assert!(matches!(JtagSequence::new().shift_out(4, 0x10).build(), Err(Error::JtagSequenceInvalid(_))));
+        assert!(matches!(JtagSequence::new().op(JtagOp::PushQ(32)).build(), Err(Error::JtagSequenceInvalid(_))));
+        assert!(matches!(JtagSequence::new().op(JtagOp::LoadVar(JtagVar::C)).build(), Err(Error::JtagSequenceInvalid(_))));
+        assert!(matches!(JtagSequence::new().op(JtagOp::ShiftOutQ(16, vec![0x12])).build(), Err(Error::JtagSequenceInvalid(_))));
+        assert_eq!(JtagSequence::new().shift_out(16, 0x1234).build().unwrap().sequence, vec![JTAG_SHIFT_OUT_Q(16), 0x12, 0x34, JTAG_END]);
+        assert!(JtagOp::ShiftOutQ(16, vec![0x12, 0x34]).to_string().ends_with(" 16, 0x1234"));
+    }
+
+    #[test]
+    fn nesting_checked() {
+        let unclosed = JtagSequence::new().repeat(4).op(JtagOp::Nop).build();
+        assert!(matches!(unclosed, Err(Error::JtagSequenceInvalid(message)) if message.contains("REPEAT at step 0 not closed")));
+        assert!(JtagSequence::new().else_().build().is_err());
+        assert!(JtagSequence::new().op(JtagOp::Break).build().is_err());
+        assert!(JtagSequence::new().end_sub().build().is_err());
+        assert!(JtagSequence::new().repeat(2).sub(JtagSub::B).end_sub().end_repeat().build().is_err());
+        assert!(JtagSequence::new().op(JtagOp::If(JtagCondition::VarAEq)).end_if().build().is_err());  // nothing pushed
+        // IF branches must return the same number of bytes
+        assert!(JtagSequence::new().if_(JtagCondition::VarAEq, 1).shift_in(8).end_if().build().is_err());
+        assert!(JtagSequence::new().if_(JtagCondition::VarAEq, 1).shift_in(8).else_().shift_in(5).end_if().build().is_ok());
+    }
+
+    #[test]
+    fn answer_length_counted() {
+        let program = JtagSequence::new()
+            .sub(JtagSub::B).shift_in(16).end_sub()
+            .repeat(3).shift_in(8).end_repeat()
+            .call(JtagSub::B)
+            .call(JtagSub::A)
+            .if_(JtagCondition::IterEq, 2).shift_in_out(4, 0x6).else_().shift_in(3).end_if()
+            .build().unwrap();
+        assert_eq!(program.answer_length, 3 + 2 + 1);
+        assert_eq!(program.features, vec![FirmwareFeature::JtagSubroutines]);
+
+        let from_data = JtagSequence::new().op(JtagOp::RepeatDp).shift_in(8).end_repeat().build();
+        assert!(matches!(from_data, Err(Error::JtagSequenceInvalid(_))));
+        let read = JtagSequence::new().read_mem(memory_space_t::MS_XWORD, 8, 0x8010).build().unwrap();
+        assert_eq!(read.answer_length, 16);
+        assert_eq!(read.sequence, vec![JTAG_READ_MEM, JTAG_END, 0x00, 0x00, 0x80, 0x10, 0x08, memory_space_t::MS_XWORD]);
+        assert_eq!(read.features, vec![FirmwareFeature::DscMemoryAccess]);
+    }
+
+    #[test]
+    fn repeat_counts_encoded_as_before() {
+        let program = JtagSequence::new().repeat(50).op(JtagOp::Nop).end_repeat().build().unwrap();
+        let mut expected = JTAG_REPEAT_16(50);
+        expected.extend([JTAG_NOP, JTAG_END_REPEAT, JTAG_END]);
+        assert_eq!(program.sequence, expected);
+        assert_eq!(JtagSequence::new().repeat(7).end_repeat().build().unwrap().sequence, vec![JTAG_REPEAT_Q(7), JTAG_END_REPEAT, JTAG_END]);
+    }
+
+    #[test]
+    fn disassembly_round_trip() {
+        let program = JtagSequence::new()
+            .repeat(50).op(JtagOp::Nop).end_repeat()
+            .op(JtagOp::MoveDrScan)
+            .op(JtagOp::ShiftOutDp(8))
+            .shift_in_dp_from_data(21)
+            .data(&[0x8B, 21])
+            .build().unwrap();
+        let (ops, data) = decode(&program.sequence).unwrap();
+        let mut encoded = Vec::new();
+        ops.iter().for_each(|(_, op)| op.encode(&mut encoded));
+        encoded.extend(&data);
+        assert_eq!(encoded, program.sequence);
+        assert_eq!(data, vec![0x8B, 21]);
+
+        let listing = program.listing();
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[0], "0000  2B 00 32        PUSH16 0x0032");
+        assert_eq!(lines[2], "0004  01                NOP");
+        assert!(lines[6].ends_with("SHIFT_IN_DP <dp>"));
+        assert_eq!(lines[8], "000C  8B 15           ; data");
+    }
+
+    #[test]
+    fn truncated_sequence_listed() {
+        let listing = disassemble(&[JTAG_NOP, JTAG_SHIFT_OUT_Q(16), 0x12]);
+        assert!(listing.lines().nth(1).unwrap().contains("truncated"));
+        assert!(decode(&[JTAG_NOP]).is_err());
+    }
+}
@@ -5,7 +5,20 @@ pub mod feedback;
 pub mod jtag;
 pub mod settings;
 pub mod usb_interface;
+pub mod transport;
+pub mod hotplug;
+pub mod session;
+pub mod retry;
+pub mod firmware;
+pub mod pipeline;
+pub mod jtag_sequence;
+pub mod jtag_interpreter;
+pub mod jtag_chain;
+pub mod virtual_dsc;
 pub mod registers;
+pub mod core_registers;
+pub mod breakpoints;
+pub mod trace;
 
 use constants::{memory_space_t, bdm_commands};
 use crate::errors::Error;
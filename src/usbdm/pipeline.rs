@@ -0,0 +1,206 @@
+use std::time::Duration;
+use crate::errors::{Error};
+use crate::usbdm::constants::{memory_space_t};
+use crate::usbdm::programmer::{Programmer};
+use crate::usbdm::transport::{SimulatedTransport, LinkTiming};
+
+/// USBDM command packet limit, length byte included
+pub const MAX_COMMAND_SIZE : usize = 254;
+/// Biggest memory block in one `JTAG_READ_MEM` / `JTAG_WRITE_MEM` sequence:
+/// write command is 4 bytes command header + 8 bytes sequence header + data, multiple of long
+pub const MAX_MEMORY_BLOCK : usize = (MAX_COMMAND_SIZE - 12) & !0x03;
+/// Commands kept in flight by `TransferConfig::pipelined`
+pub const PIPELINE_DEPTH   : usize = 4;
+
+/// `TransferConfig` - how `dsc_read_memory` / `dsc_write_memory` move memory over USB
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TransferConfig {
+    /// Bytes per memory block command, limited by `MAX_MEMORY_BLOCK`
+    pub max_memory_block : usize,
+    /// Block commands written before first answer is read, 1 - one command at a time
+    pub depth            : usize,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        TransferConfig::legacy()
+    }
+}
+
+impl TransferConfig {
+
+    /// `legacy` - 32 byte blocks one at a time, as USBDM firmware is known to take them
+    pub fn legacy() -> Self {
+        TransferConfig { max_memory_block: 0x20, depth: 1 }
+    }
+
+    /// `pipelined` - full packet blocks, `PIPELINE_DEPTH` of them in flight
+    ///
+    /// Opt-in only (`Preferences::pipelined_transfers`): not shown that USBDM firmware queues commands.
+    /// After failed pipelined read the rest is read one block at a time, failed pipelined write is not
+    /// repeated but reported as `Error::UsbdmCommandFailed` - blocks in flight may have been written.
+    pub fn pipelined() -> Self {
+        TransferConfig { max_memory_block: MAX_MEMORY_BLOCK, depth: PIPELINE_DEPTH }
+    }
+
+    /// `memory_block` - block size actually used, whole longs within USBDM packet
+    pub fn memory_block(&self) -> usize {
+        (self.max_memory_block.min(MAX_MEMORY_BLOCK) & !0x03).max(4)
+    }
+}
+
+/// `Throughput` - result of one benchmark run
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Throughput {
+    pub bytes    : usize,
+    pub commands : usize,
+    pub elapsed  : Duration,
+}
+
+impl Throughput {
+    pub fn bytes_per_second(&self) -> f32 {
+        self.bytes as f32 / self.elapsed.as_secs_f32()
+    }
+}
+
+/// `Benchmark` - read, verify and program of full device over `SimulatedTransport`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Benchmark {
+    pub read    : Throughput,
+    pub verify  : Throughput,
+    pub program : Throughput,
+}
+
+/// `benchmark` - move `size` bytes of P memory with `config` over simulated link
+///
+/// Program writes pattern, verify reads it back and compares. Time is virtual (`SimulatedTransport::elapsed`),
+/// so result is repeatable and does not depend on machine running it.
+pub fn benchmark(config: TransferConfig, timing: LinkTiming, size: usize) -> Result<Benchmark, Error> {
+    let image: Vec<u8> = (0..size).map(|i| (i as u8).wrapping_mul(7) ^ 0x5A).collect();
+
+    let measure = |run: &dyn Fn(&Programmer) -> Result<(), Error>| -> Result<Throughput, Error> {
+        let link = SimulatedTransport::new(timing);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        prog.transfer = config;
+        run(&prog)?;
+        Ok(Throughput { bytes: size, commands: link.commands(), elapsed: link.elapsed() })
+    };
+
+    let read = measure(&|prog| {
+        prog.dsc_read_memory(memory_space_t::MS_PWORD, size as u32, 0)?;
+        Ok(())
+    })?;
+    let program = measure(&|prog| prog.dsc_write_memory(memory_space_t::MS_PWORD, image.clone(), 0))?;
+    let verify = measure(&|prog| {
+        // same work as verify_target: read back and compare with image, unwritten memory differs
+        let expected: Vec<u8> = (0..size as u32).map(|i| SimulatedTransport::pattern(memory_space_t::MS_PWORD, i)).collect();
+        if prog.dsc_read_memory(memory_space_t::MS_PWORD, size as u32, 0)? != expected {
+            return Err(Error::TargetVerifyError(0, size as u32 / 2))
+        }
+        Ok(())
+    })?;
+    Ok(Benchmark { read, verify, program })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::transport::ScriptedTransport;
+
+    /// 64 KB part, full P flash of mc56f8037
+    const DEVICE_SIZE : usize = 0x10000;
+
+    #[test]
+    fn pipelined_read_matches_memory() {
+        let link = SimulatedTransport::new(LinkTiming::default());
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        prog.transfer = TransferConfig::pipelined();
+        let data = vec![0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+        prog.dsc_write_memory(memory_space_t::MS_XWORD, data.clone(), 0x100).unwrap();
+
+        let read = prog.dsc_read_memory(memory_space_t::MS_XWORD, 0x400, 0xFE).unwrap();
+        assert_eq!(read.len(), 0x400);
+        assert_eq!(&read[4..10], &data[..]);
+        assert_eq!(read[0], SimulatedTransport::pattern(memory_space_t::MS_XWORD, 0x1FC));
+        assert_eq!(read[0x3FF], SimulatedTransport::pattern(memory_space_t::MS_XWORD, 0x1FC + 0x3FF));
+    }
+
+    #[test]
+    fn overrun_falls_back_to_single_commands() {
+        let timing = LinkTiming { capacity: 1, ..LinkTiming::default() };
+        let link = SimulatedTransport::new(timing);
+        let mut prog = Programmer::from_transport(Box::new(link.clone()));
+        prog.retry_policy.retry_delay = Duration::ZERO;
+        prog.transfer = TransferConfig::pipelined();
+
+        let read = prog.dsc_read_memory(memory_space_t::MS_PWORD, 0x400, 0).unwrap();
+        let expected: Vec<u8> = (0..0x400).map(|i| SimulatedTransport::pattern(memory_space_t::MS_PWORD, i)).collect();
+        assert_eq!(read, expected);
+
+        // writes are not repeated after pipeline failure, later transfers are not pipelined
+        let commands = link.commands();
+        prog.dsc_read_memory(memory_space_t::MS_PWORD, 0x400, 0).unwrap();
+        assert_eq!(link.commands() - commands, 0x400 / MAX_MEMORY_BLOCK + 1);
+    }
+
+    #[test]
+    fn lost_answer_in_pipelined_write_escalated() {
+        let script = ScriptedTransport::new();
+        let mut prog = Programmer::from_transport(Box::new(script.clone()));
+        prog.transfer = TransferConfig::pipelined();
+        script.answer(vec![0x00]);
+        script.lose();
+        script.answer(vec![0x00]);
+
+        let result = prog.dsc_write_memory(memory_space_t::MS_XWORD, vec![0; MAX_MEMORY_BLOCK * 3], 0x8000);
+        match result {
+            Err(Error::UsbdmCommandFailed(context, _)) => assert!(context.contains("block 2 of 3")),
+            other => panic!("expected escalation, got {:?}", other),
+        }
+        assert_eq!(script.written().len(), 3);
+    }
+
+    #[test]
+    fn answers_in_flight_drained_before_fallback() {
+        let script = ScriptedTransport::new();
+        let mut prog = Programmer::from_transport(Box::new(script.clone()));
+        prog.transfer = TransferConfig::pipelined();
+        let block = |byte: u8| [vec![0x00], vec![byte; MAX_MEMORY_BLOCK]].concat();
+        script.answer(block(0x11));
+        script.answer(vec![6]);             // BDM_RC_OVERRUN on block 2
+        script.answer(block(0x33));         // block 3 already in flight, stale after failure
+        script.answer(block(0x22));         // block 2 and 3 again one by one
+        script.answer(block(0x44));
+
+        let read = prog.dsc_read_memory(memory_space_t::MS_XWORD, (MAX_MEMORY_BLOCK * 3) as u32, 0).unwrap();
+        assert_eq!(&read[..MAX_MEMORY_BLOCK], &[0x11; MAX_MEMORY_BLOCK][..]);
+        assert_eq!(&read[MAX_MEMORY_BLOCK..2 * MAX_MEMORY_BLOCK], &[0x22; MAX_MEMORY_BLOCK][..]);
+        assert_eq!(&read[2 * MAX_MEMORY_BLOCK..], &[0x44; MAX_MEMORY_BLOCK][..]);
+        assert!(script.is_finished());
+    }
+
+    #[test]
+    fn block_size_limited_by_packet() {
+        assert_eq!(TransferConfig::default(), TransferConfig::legacy());
+        assert_eq!(TransferConfig::pipelined().memory_block(), 240);
+        assert_eq!(TransferConfig { max_memory_block: 0x1000, depth: 1 }.memory_block(), 240);
+        assert_eq!(TransferConfig { max_memory_block: 0x22, depth: 1 }.memory_block(), 0x20);
+    }
+
+    #[test]
+    fn throughput_benchmark() {
+        let timing = LinkTiming::default();
+        let legacy = benchmark(TransferConfig::legacy(), timing, DEVICE_SIZE).unwrap();
+        let packed = benchmark(TransferConfig { depth: 1, ..TransferConfig::pipelined() }, timing, DEVICE_SIZE).unwrap();
+        let pipelined = benchmark(TransferConfig::pipelined(), timing, DEVICE_SIZE).unwrap();
+
+        for (name, result) in [("legacy", legacy), ("packed", packed), ("pipelined", pipelined)] {
+            println!("{:<10} read {:>8.0} B/s  verify {:>8.0} B/s  program {:>8.0} B/s  ({} read commands)", name,
+                result.read.bytes_per_second(), result.verify.bytes_per_second(), result.program.bytes_per_second(), result.read.commands);
+        }
+        assert!(packed.read.elapsed < legacy.read.elapsed / 3);
+        assert!(pipelined.read.elapsed < packed.read.elapsed);
+        assert!(pipelined.program.elapsed < legacy.program.elapsed / 5);
+        assert!(pipelined.verify.bytes_per_second() > 5.0 * legacy.verify.bytes_per_second());
+    }
+}
@@ -1,11 +1,19 @@
 #![allow(unused)]
 
 use crate::errors::{Error};
-use crate::usbdm::usb_interface::{UsbInterface};
+use crate::usbdm::transport::{UsbdmTransport, check_usbdm_return_code};
 use crate::usbdm::feedback::{FeedBack, PowerState, PowerStatus};
 use crate::usbdm::settings::{BdmSettings, TargetVddSelect, TargetType};
-use crate::usbdm::constants::{bdm_commands};
+use crate::usbdm::constants::{bdm_commands, jtag_shift};
 use crate::usbdm::bdm_info::BdmInfo;
+use crate::usbdm::hotplug::{UsbPort};
+use crate::usbdm::firmware::{FirmwareFeature, command_feature};
+use crate::usbdm::retry::{RetryPolicy, CommandKind, command_kind, command_context, is_busy, is_transient};
+use crate::usbdm::pipeline::{TransferConfig};
+use crate::usbdm::jtag_sequence::{JtagProgram};
+use crate::usbdm::jtag_chain::{ChainPosition};
+use crate::usbdm::jtag_interpreter::{TapRegister};
+use std::cell::Cell;
 use crate::usbdm::jtag::*;
 use std::{thread, time};
 use std::time::Duration;
@@ -13,11 +21,20 @@ use std::time::Duration;
 #[derive(Debug)]
 pub struct Programmer {
 
-    pub usb_device     : UsbInterface,
+    pub usb_device     : Box<dyn UsbdmTransport>,
     pub name           : String,
     pub bdm_info       : BdmInfo,
     pub feedback       : FeedBack,
     pub settings       : BdmSettings,      
+    pub retry_policy   : RetryPolicy,
+    pub transfer       : TransferConfig,
+    /// Bypass bits of other TAPs on JTAG chain, added to every scan. Set from chain scan for boundary scan,
+    /// default (DSC alone) for everything else - firmware routines of USBDM know DSC alone only
+    pub chain          : ChainPosition,
+    /// Register selected by `jtag_select_shift` and whether scan already started, for chain padding
+    pub(crate) raw_scan : Option<(TapRegister, bool)>,
+    /// Set when pipelined transfer failed on link, later transfers go one command at a time
+    pipeline_fallback  : Cell<bool>,
 }
 
 
@@ -33,27 +50,181 @@ impl Drop for Programmer{
 
 impl Programmer
 {
-    pub fn new(mut device : UsbInterface) -> Result<Self, Error> {
-        let mut prog = Self {
-            usb_device      : device,
-            name            : "?".to_string(),
-            bdm_info        : BdmInfo::default(),
-            feedback        : FeedBack::default(),
-            settings        : BdmSettings::default(), };
+    pub fn new(mut device : Box<dyn UsbdmTransport>) -> Result<Self, Error> {
+        let mut prog = Self::from_transport(device);
         prog.get_bdm_info()?;
         prog.bdm_info.check_version()?;
-        prog.bdm_info.capabilities.check_dsc_supported()?;
-        prog.name = prog.usb_device.model.clone();
+        prog.bdm_info.require(FirmwareFeature::DscTarget)?;
+        prog.name = prog.usb_device.model();
         prog.feedback = prog.get_bdm_feedback()?;
         prog.force_vdd_off()?;
         prog.bdm_info.print_version2();
         prog.bdm_info.print_capabilities();
+        println!("Firmware features: {:?}", prog.bdm_info.supported_features());
         //prog.get_bdm_string_descripton()?;
         Ok(prog)
     }
 
+    /// `from_transport` - wrap transport without talking to it, no version check and no power setup.
+    /// 
+    /// Used by tests and tools which script the whole session, use `new` for real USBDM
+    pub fn from_transport(device : Box<dyn UsbdmTransport>) -> Self {
+        Self {
+            usb_device      : device,
+            name            : "?".to_string(),
+            bdm_info        : BdmInfo::default(),
+            feedback        : FeedBack::default(),
+            settings        : BdmSettings::default(),
+            retry_policy    : RetryPolicy::default(),
+            transfer        : TransferConfig::default(),
+            chain           : ChainPosition::default(),
+            raw_scan        : None,
+            pipeline_fallback : Cell::new(false), }
+    }
+
+    /// `bdm_command` - write command to USBDM, read `rx_size` bytes of answer and check status byte
+    /// 
+    /// All bulk commands go through here, answer is returned with status byte.
+    /// Transient errors are handled by `retry_policy`, command kind is taken from command byte (see `command_kind`)
+    pub fn bdm_command(&self, command : &[u8], rx_size : usize) -> Result<Vec<u8>, Error> {
+        self.bdm_command_as(command, rx_size, command_kind(command))
+    }
+
+    /// `bdm_command_as` - `bdm_command` with kind given by caller (only caller knows what JTAG sequence does)
+    pub fn bdm_command_as(&self, command : &[u8], rx_size : usize, kind : CommandKind) -> Result<Vec<u8>, Error> {
+        if let Some(feature) = command.get(1).and_then(|cmd| command_feature(*cmd)) {
+            self.bdm_info.require(feature)?;
+        }
+        let mut attempt : u32 = 1;
+        loop {
+            let error = match self.transfer(command, rx_size) {
+                Ok(answer) => return Ok(answer),
+                Err(e)     => e,
+            };
+            if !is_transient(&error) {
+                return Err(error)
+            }
+            self.resync();
+            if kind == CommandKind::NonIdempotent {
+                let context = format!("{} failed on attempt {}, not repeated: target may have executed it", command_context(command), attempt);
+                return Err(Error::UsbdmCommandFailed(context, Box::new(error)))
+            }
+            if attempt > self.retry_policy.max_retries {
+                let context = format!("{} failed {} times", command_context(command), attempt);
+                return Err(Error::UsbdmCommandFailed(context, Box::new(error)))
+            }
+            println!("Retry {} ({}/{}) after {:?}", command_context(command), attempt, self.retry_policy.max_retries, error);
+            attempt += 1;
+            thread::sleep(self.retry_policy.retry_delay);
+        }
+    }
+
+    /// `bdm_pipeline` - several commands of the same kind with up to `transfer.depth` of them in flight
+    ///
+    /// Answers are returned in command order with status byte. USBDM executes next command while host
+    /// reads answer of previous one. Any link error (or busy answer) clears the pipe, reads out answers of
+    /// commands still in flight and switches this programmer to one command at a time: idempotent commands
+    /// are continued from the failed one through
+    /// `bdm_command_as`, non-idempotent are escalated as `Error::UsbdmCommandFailed` - commands in flight
+    /// may have been executed.
+    pub fn bdm_pipeline(&self, commands : &[(Vec<u8>, usize)], kind : CommandKind) -> Result<Vec<Vec<u8>>, Error> {
+        let depth = if self.pipeline_fallback.get() { 1 } else { self.transfer.depth.max(1) };
+        if depth == 1 || commands.len() < 2 {
+            return commands.iter().map(|(command, rx_size)| self.bdm_command_as(command, *rx_size, kind)).collect()
+        }
+        for (command, _) in commands {
+            if let Some(feature) = command.get(1).and_then(|cmd| command_feature(*cmd)) {
+                self.bdm_info.require(feature)?;
+            }
+        }
+
+        let mut answers = Vec::with_capacity(commands.len());
+        let mut in_flight = 0;
+        let error = match self.pipeline_run(commands, depth, &mut answers, &mut in_flight) {
+            Ok(())  => return Ok(answers),
+            Err(e)  => e,
+        };
+        self.resync();
+        // answers of commands written ahead still come, one by one fallback must not take them as its own
+        let rx_size = commands.iter().map(|(_, rx_size)| *rx_size).max().unwrap_or(1);
+        self.drain(in_flight, rx_size);
+        let failed = answers.len();
+        if !is_transient(&error) && !is_busy(&error) {
+            return Err(error)
+        }
+        println!("Pipelined transfer failed at block {} of {} with {:?}, continue one by one", failed + 1, commands.len(), error);
+        self.pipeline_fallback.set(true);
+        if kind == CommandKind::NonIdempotent {
+            let context = format!("{} block {} of {} failed in pipeline, not repeated: target may have executed it",
+                command_context(&commands[failed].0), failed + 1, commands.len());
+            return Err(Error::UsbdmCommandFailed(context, Box::new(error)))
+        }
+        for (command, rx_size) in &commands[failed..] {
+            answers.push(self.bdm_command_as(command, *rx_size, kind)?);
+        }
+        Ok(answers)
+    }
+
+    /// `pipeline_run` - write ahead up to `depth` commands, read answers in order into `answers`
+    ///
+    /// `in_flight` - commands written whose answer was not read yet (answer lost on link is counted)
+    fn pipeline_run(&self, commands : &[(Vec<u8>, usize)], depth : usize, answers : &mut Vec<Vec<u8>>, in_flight : &mut usize) -> Result<(), Error> {
+        let mut sent = 0;
+        while answers.len() < commands.len() {
+            while sent < commands.len() && sent - answers.len() < depth {
+                self.usb_device.write(&commands[sent].0)?;
+                sent += 1;
+                *in_flight += 1;
+            }
+            let answer = self.usb_device.read(commands[answers.len()].1)?;
+            *in_flight -= 1;
+            check_usbdm_return_code(&answer)?;
+            answers.push(answer);
+        }
+        Ok(())
+    }
+
+    /// `drain` - read and drop `in_flight` answers left by failed pipelined transfer, lost ones time out
+    fn drain(&self, in_flight : usize, rx_size : usize) {
+        for _ in 0..in_flight {
+            if let Err(e) = self.usb_device.read(rx_size) {
+                println!("USBDM answer of command in flight lost {:?}", e);
+            }
+        }
+    }
+
+    /// `transfer` - one command exchange, busy answer is polled with `CMD_USBDM_GET_COMMAND_RESPONSE`
+    fn transfer(&self, command : &[u8], rx_size : usize) -> Result<Vec<u8>, Error> {
+        self.usb_device.write(command)?;
+        let mut answer = self.usb_device.read(rx_size)?;
+        let mut polls : u32 = 0;
+        loop {
+            match check_usbdm_return_code(&answer) {
+                Ok(()) => return Ok(answer),
+                Err(e) if is_busy(&e) && polls < self.retry_policy.busy_polls && self.bdm_info.supports(FirmwareFeature::CommandResponse) => {
+                    polls += 1;
+                    thread::sleep(self.retry_policy.busy_poll_interval);
+                    self.usb_device.write(&[2, bdm_commands::CMD_USBDM_GET_COMMAND_RESPONSE])?;
+                    answer = self.usb_device.read(rx_size)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// `resync` - clear endpoint halts, USBDM drops stale answer of failed command so next answer matches next command
+    fn resync(&self) {
+        if let Err(e) = self.usb_device.clear_halt() {
+            println!("USBDM clear halt failed {:?}", e);
+        }
+    }
+
 pub fn set_vdd(&mut self, power: TargetVddSelect ) -> Result<(), Error>{
     
+    // USBDM without Vdd control - target is powered externally, nothing to switch off
+    if power == TargetVddSelect::VddOff && !self.bdm_info.supports(FirmwareFeature::VddControl) {
+        return Ok(());
+    }
 
     let current_power_status =   self.get_power_state()?;
     let expected_power = self.expected_power_status(power);
@@ -76,8 +247,7 @@ pub fn set_vdd(&mut self, power: TargetVddSelect ) -> Result<(), Error>{
     let bitter = usb_buf[1] | bit;
     usb_buf[1] = bitter;
   
-    self.usb_device.write(&usb_buf)?;                                    // write command
-    let answer = self.usb_device.read(1)?;
+    let answer = self.bdm_command(&usb_buf, 1)?;
     self.settings.target_voltage = power;
     Ok(())
   
@@ -85,6 +255,9 @@ pub fn set_vdd(&mut self, power: TargetVddSelect ) -> Result<(), Error>{
 
 pub fn force_vdd_off(&mut self ) -> Result<(), Error>{
     
+    if !self.bdm_info.supports(FirmwareFeature::VddControl) {
+        return Ok(());
+    }
     println!("force vdd_off");
     let mut usb_buf  = [0; 4];
     let command = "CMD_USBDM_SET_VDD".to_string();
@@ -99,8 +272,7 @@ pub fn force_vdd_off(&mut self ) -> Result<(), Error>{
     let bitter = usb_buf[1] | bit;
     usb_buf[1] = bitter;
   
-    self.usb_device.write(&usb_buf)?;                                    // write command
-    let answer = self.usb_device.read(1)?;
+    let answer = self.bdm_command(&usb_buf, 1)?;
     self.settings.target_voltage = TargetVddSelect::VddOff;
     Ok(())
   
@@ -181,8 +353,7 @@ pub fn set_vpp(&mut self, power: TargetVddSelect ) -> Result<(), Error>{
         let bitter = usb_buf[1] | bit;
         usb_buf[1] = bitter;
   
-        self.usb_device.write(&usb_buf)?;                                    // write command
-        let answer = self.usb_device.read(1)?;         // read status from bdm
+        let answer = self.bdm_command(&usb_buf, 1)?;         // read status from bdm
 
         self.settings.target_voltage = power;
         Ok(())
@@ -224,8 +395,7 @@ pub fn set_target_mc56f(&mut self) -> Result<(), Error>{
     let bitter = usb_buf[1] | bit;
     usb_buf[1] = bitter;
 
-    self.usb_device.write(&usb_buf)?;                                    // write command
-    let answer = self.usb_device.read(1)?;        // read status from bdm
+    let answer = self.bdm_command(&usb_buf, 1)?;        // read status from bdm
     self.settings.target_type = TargetType::MC56F80xx;
 
     Ok(())
@@ -260,12 +430,17 @@ pub fn set_settings(&mut self) -> Result<(), Error>{
     usb_buf[4] = self.settings.bdm_clock_source as u8;
     usb_buf[5] = self.settings.auto_reconnect as u8;
 
-    self.usb_device.write(&usb_buf)?;                                    // write command
-    let answer = self.usb_device.read(1)?;         // read status from bdm
+    let answer = self.bdm_command(&usb_buf, 1)?;         // read status from bdm
     Ok(())
 }
 
 
+/// `set_interface_frequency` - change JTAG clock (kHz) of opened USBDM, kept in `settings`
+pub fn set_interface_frequency(&mut self, khz : u64) -> Result<(), Error>{
+    self.settings.interface_frequency = khz;
+    self.set_speed()
+}
+
 /// `set_speed` sets the BDM communication speed.
 ///
 /// # Safety
@@ -293,33 +468,58 @@ fn set_speed(&mut self) -> Result<(), Error>{
     let hex_str = format!("{:02X}{:02X}",  usb_buf[2], usb_buf[3]);
     println!("{}", hex_str);
 
-    self.usb_device.write(&usb_buf)?;                                    // write command
-    let answer = self.usb_device.read(1)?;                  // read status from bdm
+    let answer = self.bdm_command(&usb_buf, 1)?;                  // read status from bdm
     dbg!(&answer);
     Ok(())
 }
 
-   pub fn exec_jtag_seq(&self, mut jtag_seq : Vec<u8>,  answer_lenght : u8) -> Result<(Vec<u8>), Error>{
-      
-    
-    let command = "CMD_USBDM_JTAG_EXECUTE_SEQUENCE".to_string();
-
-    let command_leght : u8 = 0x4 + jtag_seq.len() as u8;
+   /// `exec_jtag_seq` - execute JTAG sequence on USBDM, never repeated on transient error
+   pub fn exec_jtag_seq(&self, jtag_seq : Vec<u8>,  answer_lenght : u8) -> Result<(Vec<u8>), Error>{
+    self.exec_jtag_seq_as(jtag_seq, answer_lenght, CommandKind::NonIdempotent)
+   }
 
-    let mut full_command : Vec<u8> = Vec::new();
-    full_command.push(command_leght);
-    full_command.push(bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE);
-    full_command.push(answer_lenght);
-    full_command.push(jtag_seq.len() as u8);
-    full_command.append(&mut jtag_seq);
+   /// `exec_jtag_seq_idempotent` - for sequences without side effects (memory, id and status reads), repeated on transient error
+   pub fn exec_jtag_seq_idempotent(&self, jtag_seq : Vec<u8>,  answer_lenght : u8) -> Result<(Vec<u8>), Error>{
+    self.exec_jtag_seq_as(jtag_seq, answer_lenght, CommandKind::Idempotent)
+   }
 
+   fn exec_jtag_seq_as(&self, mut jtag_seq : Vec<u8>,  answer_lenght : u8, kind : CommandKind) -> Result<(Vec<u8>), Error>{
+      
+    
+    let full_command = jtag_sequence_command(jtag_seq, answer_lenght);
 
-    self.usb_device.write(&full_command.as_slice())?;   // write command
-    let mut answer: Vec<u8> = self.usb_device.read((answer_lenght + 1).into())?;         // read status from bdm 
+    let mut answer: Vec<u8> = self.bdm_command_as(&full_command, answer_lenght as usize + 1, kind)?;   // write command, read status from bdm 
     answer.remove(0);
     Ok((answer))
   } 
 
+   /// `exec_program` - sequence built by `JtagSequence`, answer length and needed firmware features come with it
+   pub fn exec_program(&self, program : &JtagProgram) -> Result<Vec<u8>, Error>{
+    self.exec_program_as(program, CommandKind::NonIdempotent)
+   }
+
+   /// `exec_program_idempotent` - `exec_program` for sequences without side effects, repeated on transient error
+   pub fn exec_program_idempotent(&self, program : &JtagProgram) -> Result<Vec<u8>, Error>{
+    self.exec_program_as(program, CommandKind::Idempotent)
+   }
+
+   /// `require_program` - firmware of this programmer can run `program`
+   pub fn require_program(&self, program : &JtagProgram) -> Result<(), Error>{
+    for feature in &program.features {
+        self.bdm_info.require(*feature)?;
+    }
+    Ok(())
+   }
+
+   fn exec_program_as(&self, program : &JtagProgram, kind : CommandKind) -> Result<Vec<u8>, Error>{
+    self.require_program(program)?;
+    let program = &self.chain.place(program)?;
+    self.exec_jtag_seq_as(program.sequence.clone(), program.answer_length, kind).map_err(|e| {
+        println!("JTAG sequence failed with {:?}:\n{}", e, program.listing());
+        e
+    })
+   }
+
  /// `jtag_reset`, single JTAG command, not sequense. Moves the TAP to \b TEST-LOGIC-RESET state
  pub fn jtag_reset(&mut self) -> Result<(), Error> {
 
@@ -328,8 +528,8 @@ fn set_speed(&mut self) -> Result<(), Error>{
     usb_buf[0] = 2;            // lenght of command
     usb_buf[1] = bdm_commands::CMD_USBDM_JTAG_GOTORESET;
 
-    self.usb_device.write(&usb_buf)?;                                    // write command
-    let answer = self.usb_device.read(1)?;                  // read status from bdm
+    let answer = self.bdm_command(&usb_buf, 1)?;                  // read status from bdm
+    self.raw_scan = None;
     Ok(())
 }
 
@@ -344,31 +544,54 @@ fn set_speed(&mut self) -> Result<(), Error>{
     usb_buf[1] = bdm_commands::CMD_USBDM_JTAG_GOTOSHIFT;
     usb_buf[2] = shift;
 
-    self.usb_device.write(&usb_buf)?;                                    // write command
-    let answer = self.usb_device.read(1)?;                  // read status from bdm
+    let answer = self.bdm_command(&usb_buf, 1)?;                  // read status from bdm
+    self.raw_scan = Some((if shift == jtag_shift::JTAG_SHIFT_IR { TapRegister::Ir } else { TapRegister::Dr }, false));
     Ok(())
 }
 
 
  /// `jtag_write`, single JTAG command, not sequense.  JTAG - write data to JTAG shift register
  /// 
- /// `shift_exit` look constants::jtag_shift
+ /// `shift_exit` look constants::jtag_shift. With `chain` set bypass bits of other TAPs are written around data
  pub fn jtag_write(&mut self, shift_exit : u8, cmd_lenght : u8, mut command : Vec<u8>) -> Result<(), Error> {
 
-    let mut full_command : Vec<u8> = Vec::new();
-    let usb_lenght : u8 = 0x4 + command.len() as u8;
+    dbg!(&command);
+    self.jtag_scan(shift_exit, cmd_lenght, Some(&command))?;
+    Ok(())
+}
 
-    full_command.push(usb_lenght); // length of command
-    full_command.push(bdm_commands::CMD_USBDM_JTAG_WRITE);
-    full_command.push(shift_exit); // jtag exit
-    full_command.push(cmd_lenght); // bitcount
-    full_command.append(&mut command); 
+ /// `jtag_read`, single JTAG command, not sequense.  JTAG - read `bit_count` bits from JTAG shift register
+ /// 
+ /// `shift_exit` look constants::jtag_shift, `JTAG_WRITE_1` in it shifts in 1's. Answer is MSB byte first, right justified
+ pub fn jtag_read(&mut self, shift_exit : u8, bit_count : u8) -> Result<Vec<u8>, Error> {
+    self.jtag_scan(shift_exit, bit_count, None)
+}
 
-    dbg!(&full_command);
-  
-    self.usb_device.write(&full_command.as_slice())?;   // write command
-    self.usb_device.read(1)?;         // read status from bdm 
-    Ok(())
+ /// `jtag_shift_command` - one `CMD_USBDM_JTAG_WRITE` (`data` given) or `CMD_USBDM_JTAG_READ`, no chain padding
+ pub(crate) fn jtag_shift_command(&mut self, shift_exit : u8, bit_count : u8, data : Option<&[u8]>) -> Result<Vec<u8>, Error> {
+
+    let mut full_command : Vec<u8> = Vec::new();
+
+    match data {
+        Some(data) => {
+            full_command.push(0x4 + data.len() as u8); // length of command
+            full_command.push(bdm_commands::CMD_USBDM_JTAG_WRITE);
+            full_command.push(shift_exit);             // jtag exit
+            full_command.push(bit_count);              // bitcount
+            full_command.extend_from_slice(data);
+            self.bdm_command(&full_command, 1)?;       // write command, read status from bdm
+            Ok(Vec::new())
+        }
+        None => {
+            full_command.push(0x4);                    // length of command
+            full_command.push(bdm_commands::CMD_USBDM_JTAG_READ);
+            full_command.push(shift_exit);             // jtag exit
+            full_command.push(bit_count);              // bitcount
+            let mut answer = self.bdm_command(&full_command, 1 + BITS_TO_BYTES(bit_count) as usize)?;
+            answer.remove(0);
+            Ok(answer)
+        }
+    }
 }
 
 
@@ -381,8 +604,7 @@ fn set_speed(&mut self) -> Result<(), Error>{
     usb_buf[2] = (control>>8) as u8;  
     usb_buf[3] = control as u8;
 
-    self.usb_device.write(&usb_buf)?;                                    // write command
-    let answer = self.usb_device.read(3)?;      // read status from bdm
+    let answer = self.bdm_command(&usb_buf, 3)?;      // read status from bdm
     Ok(())
 }
 
@@ -424,77 +646,7 @@ pub fn target_power_reset(&mut self, user_selected : TargetVddSelect) -> Result<
 }
 
 pub fn get_bdm_info(&mut self) -> Result<(), Error> {
-    self.get_bdm_version()?;
-    self.get_bdm_capabilities()?;
-    Ok(())
-}
-
-fn get_bdm_version(&mut self) -> Result<(), Error>{
-    let request_type = 64; //LIBUSB_REQUEST_TYPE_VENDOR
-    let request_type = request_type| &self.usb_device.read_ep;
-    
-    let request  = bdm_commands::CMD_USBDM_GET_VER; // command
-    let value    = 100;
-    let index    = 0;
-    let timeout  = Duration::from_millis(2500);
-    let rx_size  = 10;
-     
-    let version = self.usb_device.control_transfer(
-        request_type,
-        request,
-        value,
-        index,
-        rx_size)?;                                    
-
-    let raw_bdm_software_version = u32::from (version[1]);
-    let calculation = ((raw_bdm_software_version&0xF0)<<12) + ((raw_bdm_software_version&0x0F)<<8);
-
-    self.bdm_info.bdm_software_version = calculation;
-    self.bdm_info.bdm_hardware_version  = version[2];
-    self.bdm_info.icp_software_version  = version[3];
-    self.bdm_info.icp_hardware_version  = version[4];
-    Ok(())
-}
-
-fn get_bdm_capabilities(&mut self) -> Result<(), Error>{
-    let mut usb_buf = [0; 2];
-    usb_buf[0] = 2;  // lenght
-    usb_buf[1] = bdm_commands::CMD_USBDM_GET_CAPABILITIES;
-    let command = "CMD_USBDM_GET_CAPABILITIES".to_string();
-
-    let bit = 0x80;
-    let bitter = usb_buf[1] | bit;
-    usb_buf[1] = bitter;
-
-    self.usb_device.write(&usb_buf)?;        // write command
-    let answer: Vec<u8> = self.usb_device.read(8)?;                   //  read
-
-    if answer.len() >= 3 {
-        let capabilities: u16 = ((answer[1] as u16) << 8) | answer[2] as u16 ^ ((1<<5) | (1<<6));
-        self.bdm_info.capabilities.parse(capabilities);
-    }
-
-    if answer.len() >= 5 {
-        let mut buffer_size: u16 = ((answer[3] as u16) << 8) + answer[4] as u16;
-        let max_packet_size: u16 = 255;
-        if buffer_size > max_packet_size {
-            buffer_size = max_packet_size;
-        }
-        let jtag_header_size: u16 = 5;
-        self.bdm_info.command_buffer_size = buffer_size;
-        self.bdm_info.jtag_buffer_size = buffer_size - jtag_header_size;
-    }
-
-    if answer.len() >= 8 {
-        // Newer BDMs report extended software version
-        self.bdm_info.bdm_software_version = ((answer[5] as u32) << 16)+((answer[6] as u32) << 8)+answer[7] as u32;
-    }
-
-    // Calculate permitted read & write length in bytes
-    // Allow for JTAG header + USB header (5 bytes) & make multiple of 4
-    self.bdm_info.dsc_max_memory_read_size  = (self.bdm_info.jtag_buffer_size - JTAG_READ_MEMORY_HEADER_SIZE  - 5) & !3;
-    self.bdm_info.dsc_max_memory_write_size = (self.bdm_info.jtag_buffer_size - JTAG_WRITE_MEMORY_HEADER_SIZE - 5) & !3;
-                                        
+    self.bdm_info = BdmInfo::read_from(self.usb_device.as_ref())?;
     Ok(())
 }
 
@@ -504,6 +656,11 @@ pub fn get_string_version(&self) -> String {
     str_ver
 } 
 
+/// `usb_port` - bus and address of opened USBDM, to recognise it in hotplug events
+pub fn usb_port(&self) -> Option<UsbPort> {
+    self.usb_device.port()
+}
+
 pub fn init_usbdm_for_mc56f(&mut self) -> Result<(), Error> {
 
     self.set_settings()?;
@@ -514,5 +671,13 @@ pub fn init_usbdm_for_mc56f(&mut self) -> Result<(), Error> {
 } 
 }
 
-
-
+/// `jtag_sequence_command` - `CMD_USBDM_JTAG_EXECUTE_SEQUENCE` packet for `jtag_seq`, answer is `answer_lenght` + status byte
+pub fn jtag_sequence_command(mut jtag_seq : Vec<u8>, answer_lenght : u8) -> Vec<u8> {
+    let mut full_command : Vec<u8> = Vec::with_capacity(jtag_seq.len() + 4);
+    full_command.push(0x4 + jtag_seq.len() as u8);
+    full_command.push(bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE);
+    full_command.push(answer_lenght);
+    full_command.push(jtag_seq.len() as u8);
+    full_command.append(&mut jtag_seq);
+    full_command
+}
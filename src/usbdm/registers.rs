@@ -1,4 +1,5 @@
 use super::*;
+use crate::usbdm::jtag_sequence::{JtagSequence, JtagOp, JtagSub};
 
 const DSC_FIRST_CORE_REGISTER: u8 = 0;
 const DSC_LAST_CORE_REGISTER: u8 = 37;
@@ -41,6 +42,11 @@ pub const OCR_ISC_SINGLE_STEP : u8 = 0x05;
 pub const OCR_ISC_6           : u8 = 0x06;
 pub const OCR_ISC_7           : u8 = 0x07;
 
+// EONCE_OTXRXSR register details
+//--------------------------------------------------------------------
+pub const OTXRXSR_RDF         : u8 = 1<<0;  // ORX written by host, not read by core yet
+pub const OTXRXSR_TDF         : u8 = 1<<1;  // OTX written by core, not read by host yet
+
 //Aliases for Cached routines
 const JTAG_SUB_EXECUTE    : u8 = JTAG_SUBA;       // execute a series of target instructions (firmware implemented)
 const JTAG_SUB_MEM_READ   : u8 = JTAG_SUBB;       // read a block from target memory
@@ -48,10 +54,11 @@ const JTAG_SUB_MEM_WRITE  : u8 = JTAG_SUBC;       // write a block to target mem
 const JTAG_CALL_EXECUTE   : u8 = JTAG_CALL_SUBA;
 const JTAG_CALL_MEM_READ  : u8 = JTAG_CALL_SUBB;
 const JTAG_CALL_MEM_WRITE : u8 = JTAG_CALL_SUBC;
+const SUB_EXECUTE         : JtagSub = JtagSub::A; // JTAG_SUB_EXECUTE for `JtagSequence::call`
 
 // regNo Parameter for DSC_ReadReg() with DSC target
 // DSC Core registers
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum DscRegisters {
     // Core registers
@@ -116,6 +123,20 @@ pub enum DscRegisters {
     DscRegOb0Cntr = 56,  // Breakpoint Unit 0 Counter
 }
 
+/// All core registers in `DscRegisters` number order
+pub const CORE_REGISTERS: [DscRegisters; DSC_CORE_REGISTER_COUNT as usize] = [
+    DscRegisters::DscRegX0,   DscRegisters::DscRegY0,   DscRegisters::DscRegY1,   DscRegisters::DscRegA0,
+    DscRegisters::DscRegA1,   DscRegisters::DscRegA2,   DscRegisters::DscRegB0,   DscRegisters::DscRegB1,
+    DscRegisters::DscRegB2,   DscRegisters::DscRegC0,   DscRegisters::DscRegC1,   DscRegisters::DscRegC2,
+    DscRegisters::DscRegD0,   DscRegisters::DscRegD1,   DscRegisters::DscRegD2,   DscRegisters::DscRegOmr,
+    DscRegisters::DscRegSr,   DscRegisters::DscRegLa,   DscRegisters::DscRegLa2,  DscRegisters::DscRegLc,
+    DscRegisters::DscRegLc2,  DscRegisters::DscRegHws0, DscRegisters::DscRegHws1, DscRegisters::DscRegSp,
+    DscRegisters::DscRegN3,   DscRegisters::DscRegM01,  DscRegisters::DscRegN,    DscRegisters::DscRegR0,
+    DscRegisters::DscRegR1,   DscRegisters::DscRegR2,   DscRegisters::DscRegR3,   DscRegisters::DscRegR4,
+    DscRegisters::DscRegR5,   DscRegisters::DscRegShm01, DscRegisters::DscRegShn, DscRegisters::DscRegShr0,
+    DscRegisters::DscRegShr1, DscRegisters::DscRegPc,
+];
+
 pub struct TargetRegisterDetails {
     name: &'static str,
     width: u8,
@@ -163,9 +184,9 @@ const TARGET_REGISTER_DETAILS: [TargetRegisterDetails; DSC_CORE_REGISTER_COUNT a
 ];
 
 pub struct EonceRegisterDetails {
-    address: u8,
-    length: u8,
-    name: &'static str,
+    pub address: u8,
+    pub length: u8,
+    pub name: &'static str,
 }
 
 pub const EONCE_REGISTER_DETAILS: [EonceRegisterDetails; DSC_ONCE_REGISTER_COUNT as usize]  = [
@@ -211,9 +232,28 @@ pub fn get_register_size(reg: DscRegisters) -> Result<u8, Error> {
     Err(Error::InternalError("Unexpected error in get_register_size".to_string()))
 }
 
+/// `get_register_name` - lower case name as in `TARGET_REGISTER_DETAILS` / `EONCE_REGISTER_DETAILS`
+pub fn get_register_name(reg: DscRegisters) -> &'static str {
+    let number = reg as u8;
+    if number <= DSC_LAST_CORE_REGISTER {
+        TARGET_REGISTER_DETAILS[(number - DSC_FIRST_CORE_REGISTER) as usize].name
+    } else if number >= DSC_FIRST_ONCE_REGISTER {
+        EONCE_REGISTER_DETAILS[(number - DSC_FIRST_ONCE_REGISTER) as usize].name
+    } else {
+        "idcode"
+    }
+}
+
+/// `is_read_only` - core registers with no write sequence: LA2, LC2 and hardware stack HWS0/HWS1
+///
+/// Their read sequences give placeholder values (`$badbad`, `$abad`, `$ffbadbad`), they are shown but never written.
+pub fn is_read_only(reg: DscRegisters) -> bool {
+    matches!(reg, DscRegisters::DscRegLa2 | DscRegisters::DscRegLc2 | DscRegisters::DscRegHws0 | DscRegisters::DscRegHws1)
+}
+
 pub struct DscProgSeq {
-    instruction_count: u8,
-    sequence: &'static[u8],
+    pub instruction_count: u8,
+    pub sequence: &'static[u8],
 }
 
 // Register reads
@@ -436,24 +476,21 @@ impl Programmer
         if (reg as u8) < DSC_FIRST_CORE_REGISTER || (reg as u8) > DSC_LAST_CORE_REGISTER {
             return Err(Error::InternalError("Unexpected input value in read_core_reg".to_string())) 
         }
-        let mut sequence: Vec<u8> = vec![];
 
         // Execute target instruction to transfer register to memory-mapped EONCE reg OTX
         // Read OTX/OTX1
-        // Main
-        sequence.push(JTAG_CALL_EXECUTE);                                 // Execute target instruction: move Reg -> OTX/OTX1
-        // Read EONCE reg OTX/OTX1
-        sequence.push(JTAG_MOVE_DR_SCAN);                                  // Move to SCAN-DR (EONCE)
-        sequence.push(JTAG_SET_EXIT_SHIFT_DR);
-        sequence.push(JTAG_SHIFT_OUT_DP); sequence.push(ONCE_CMD_LENGTH); // Command for Read Register - either OTX/OTX1
-        sequence.push(JTAG_SET_EXIT_IDLE);
-        sequence.push(JTAG_SHIFT_IN_DP); sequence.push(0);                // Data size to read
-        sequence.push(JTAG_END);
-    
-        sequence.append(&mut read_core_reg_sequence(reg)?);
-
-        let answer_length = BITS_TO_BYTES(get_register_size(reg)?);
-        let answer_vec = self.exec_jtag_seq(sequence, answer_length)?;
+        let program = JtagSequence::new()
+            .call(SUB_EXECUTE)                                // Execute target instruction: move Reg -> OTX/OTX1
+            // Read EONCE reg OTX/OTX1
+            .op(JtagOp::MoveDrScan)                           // Move to SCAN-DR (EONCE)
+            .op(JtagOp::SetExitShiftDr)
+            .op(JtagOp::ShiftOutDp(ONCE_CMD_LENGTH))          // Command for Read Register - either OTX/OTX1
+            .op(JtagOp::SetExitIdle)
+            .shift_in_dp_from_data(get_register_size(reg)?)   // Data size to read
+            .data(&read_core_reg_sequence(reg)?)
+            .build()?;
+
+        let answer_vec = self.exec_program_idempotent(&program)?;
 
         if answer_vec.len() > 4 {return Err(Error::InternalError("Answer too long in read_core_reg".to_string()))}
         if answer_vec.len() == 0 {return Err(Error::InternalError("No answer in read_core_reg".to_string()))}
@@ -480,15 +517,17 @@ impl Programmer
         if (reg as u8) < DSC_FIRST_CORE_REGISTER || (reg as u8) > DSC_LAST_CORE_REGISTER {
             return Err(Error::InternalError("Unexpected input value in write_core_reg".to_string())) 
         }
+        if is_read_only(reg) {
+            return Err(Error::InternalError(format!("Core register {} is read only", get_register_name(reg))))
+        }
         
         // Execute target instructions to load register
-        let mut sequence: Vec<u8> = vec![];
-        sequence.push(JTAG_CALL_EXECUTE);  // Execute instructions routine
-        sequence.push(JTAG_END);
-
-        sequence.append(&mut write_core_reg_sequence(reg, value)?);
+        let program = JtagSequence::new()
+            .call(SUB_EXECUTE)                                // Execute instructions routine
+            .data(&write_core_reg_sequence(reg, value)?)
+            .build()?;
  
-        self.exec_jtag_seq(sequence, 0)?;
+        self.exec_program(&program)?;
 
         Ok(())
     }
@@ -518,17 +557,17 @@ impl Programmer
         let reg_index: u8 = reg as u8 - DSC_FIRST_ONCE_REGISTER;
         let command: u8 = EONCE_REGISTER_DETAILS[reg_index as usize].address | ONCE_CMD_READ;
         let length: u8 = EONCE_REGISTER_DETAILS[reg_index as usize].length;
-        let answer_length: u8 = BITS_TO_BYTES(length);
 
-        let mut sequence: Vec<u8> = vec![];
-        sequence.push(JTAG_MOVE_DR_SCAN);  // Access ONCE (DR-CHAIN)
-        sequence.push(JTAG_SET_EXIT_SHIFT_DR);
-        sequence.push(JTAG_SHIFT_OUT_Q(ONCE_CMD_LENGTH)); sequence.push(command);  // ONCE Command to Read register + RegNo
-        sequence.push(JTAG_SET_EXIT_IDLE);
-        sequence.push(JTAG_SHIFT_IN_Q(length));  // Shift-in data value
-        sequence.push(JTAG_END);
+        let program = JtagSequence::new()
+            .op(JtagOp::MoveDrScan)                           // Access ONCE (DR-CHAIN)
+            .op(JtagOp::SetExitShiftDr)
+            .shift_out(ONCE_CMD_LENGTH, command as u32)       // ONCE Command to Read register + RegNo
+            .op(JtagOp::SetExitIdle)
+            .shift_in(length)                                 // Shift-in data value
+            .build()?;
 
-        let answer_vec = self.exec_jtag_seq(sequence, answer_length)?;
+        // not repeated on error - reading OTX/ORX and trace buffer changes EOnCE state
+        let answer_vec = self.exec_program(&program)?;
 
         if answer_vec.len() > 4 {return Err(Error::InternalError("Answer too long in read_eonce_reg".to_string()))}
         if answer_vec.len() == 0 {return Err(Error::InternalError("No answer in read_eonce_reg".to_string()))}
@@ -559,19 +598,15 @@ impl Programmer
         let command: u8 = EONCE_REGISTER_DETAILS[reg_index as usize].address | ONCE_CMD_WRITE;
         let length: u8 = EONCE_REGISTER_DETAILS[reg_index as usize].length;
 
-        let mut sequence: Vec<u8> = vec![];
-        sequence.push(JTAG_MOVE_DR_SCAN);            // Write to ONCE (DR-CHAIN)
-        sequence.push(JTAG_SET_EXIT_SHIFT_DR);
-        sequence.push(JTAG_SHIFT_OUT_Q(ONCE_CMD_LENGTH)); sequence.push(command); // ONCE command - Write register+RegNo+modifier
-        sequence.push(JTAG_SET_EXIT_IDLE);
-        sequence.push(JTAG_SHIFT_OUT_Q(length));     // Shift-out data value
-        sequence.push (value as u8);  // Immediate value
-        sequence.push((value >>  8) as u8);
-        sequence.push((value >> 16) as u8);
-        sequence.push((value >> 24) as u8);
-        sequence.push(JTAG_END);
+        let program = JtagSequence::new()
+            .op(JtagOp::MoveDrScan)                           // Write to ONCE (DR-CHAIN)
+            .op(JtagOp::SetExitShiftDr)
+            .shift_out(ONCE_CMD_LENGTH, command as u32)       // ONCE command - Write register+RegNo+modifier
+            .op(JtagOp::SetExitIdle)
+            .shift_out(length, value)                         // Shift-out data value, BITS_TO_BYTES(length) bytes MSB first
+            .build()?;
 
-        self.exec_jtag_seq(sequence, 0)?;
+        self.exec_program(&program)?;
 
         Ok(())
  }
@@ -582,6 +617,7 @@ impl Programmer
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::usbdm::transport::ScriptedTransport;
 
     #[test]
     fn get_register_size_test() {
@@ -643,4 +679,33 @@ mod tests {
         assert_eq!(get_register_size(DscRegisters::DscRegOb0Cntr).unwrap(), 16);
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn read_pc_answer_decoding() {
+        let script = ScriptedTransport::new();
+        let prog = Programmer::from_transport(Box::new(script.clone()));
+        script.answer(vec![0x00, 0x00, 0x81, 0x0C]);   // 21-bit PC comes back in 3 bytes, MSB first
+
+        assert_eq!(prog.dsc_read_pc().unwrap(), 0x00810C);
+        let command = &script.written()[0];
+        assert_eq!(command[1], bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE);
+        assert_eq!(command[2], 3);                      // expected answer length
+        assert_eq!(command[4], JTAG_CALL_EXECUTE);
+    }
+
+    #[test]
+    fn write_once_reg_shifts_register_length() {
+        let script = ScriptedTransport::new();
+        let prog = Programmer::from_transport(Box::new(script.clone()));
+        script.answer(vec![0x00]);
+
+        prog.dsc_write_once_reg(DscRegisters::DscRegOpdbr, 0x1234).unwrap();
+        let command = &script.written()[0];
+        // 16-bit OPDBR goes as two in-line bytes MSB first, then sequence ends
+        let sequence = [JTAG_MOVE_DR_SCAN, JTAG_SET_EXIT_SHIFT_DR, JTAG_SHIFT_OUT_Q(ONCE_CMD_LENGTH), OPDBR_ADDRESS | ONCE_CMD_WRITE,
+            JTAG_SET_EXIT_IDLE, JTAG_SHIFT_OUT_Q(16), 0x12, 0x34, JTAG_END];
+        assert_eq!(&command[command.len() - sequence.len()..], &sequence);
+        assert!(script.is_finished());
+        assert!(prog.dsc_write_once_reg(DscRegisters::DscRegOpdbr, 0x10000).is_err());
+    }
+}
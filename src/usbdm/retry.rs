@@ -0,0 +1,166 @@
+use std::time::Duration;
+use crate::errors::{Error, USBDM_ErrorCode};
+use crate::usbdm::constants::{bdm_commands};
+
+/// `CommandKind` - may command be sent again if its answer was lost
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CommandKind {
+    /// Reads, status queries and settings - same result when repeated
+    Idempotent,
+    /// Target go, flash routine start, memory writes to peripherals... - escalated, never repeated
+    NonIdempotent,
+}
+
+/// `command_kind` - kind of raw USBDM command from its command byte
+///
+/// `CMD_USBDM_JTAG_EXECUTE_SEQUENCE` is treated as non-idempotent, sequence content is known only to the caller,
+/// reads use `Programmer::exec_jtag_seq_idempotent` to allow retry.
+pub fn command_kind(command: &[u8]) -> CommandKind {
+    match command.get(1).map(|cmd| cmd & 0x7F) {
+        Some(bdm_commands::CMD_USBDM_GET_COMMAND_RESPONSE) |
+        Some(bdm_commands::CMD_USBDM_GET_BDM_STATUS)       |
+        Some(bdm_commands::CMD_USBDM_GET_CAPABILITIES)     |
+        Some(bdm_commands::CMD_USBDM_GET_VER)              |
+        Some(bdm_commands::CMD_USBDM_SET_TARGET)           |
+        Some(bdm_commands::CMD_USBDM_SET_VDD)              |
+        Some(bdm_commands::CMD_USBDM_SET_VPP)              |
+        Some(bdm_commands::CMD_USBDM_SET_OPTIONS)          |
+        Some(bdm_commands::CMD_USBDM_SET_SPEED)            |
+        Some(bdm_commands::CMD_USBDM_CONTROL_PINS)         |
+        Some(bdm_commands::CMD_USBDM_JTAG_GOTORESET)       => CommandKind::Idempotent,
+        _                                                  => CommandKind::NonIdempotent,
+    }
+}
+
+/// `RetryPolicy` - how `Programmer::bdm_command` deals with transient errors
+///
+/// * USBDM answers `BDM_RC_BUSY` - answer is polled with `CMD_USBDM_GET_COMMAND_RESPONSE` up to `busy_polls` times
+/// * USB timeout / stall - endpoint halts are cleared (drops stale answer), idempotent command is sent again
+///   up to `max_retries` times, non-idempotent fails at once with `Error::UsbdmCommandFailed` and full context
+/// * transient USBDM codes (`is_transient`) - same as USB errors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries        : u32,
+    pub retry_delay        : Duration,
+    pub busy_polls         : u32,
+    pub busy_poll_interval : Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries        : 3,
+            retry_delay        : Duration::from_millis(20),
+            busy_polls         : 100,
+            busy_poll_interval : Duration::from_millis(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// `none` - fail on first error, as before retry policy
+    pub fn none() -> Self {
+        RetryPolicy { max_retries: 0, retry_delay: Duration::ZERO, busy_polls: 0, busy_poll_interval: Duration::ZERO }
+    }
+}
+
+/// `is_busy` - USBDM still executes command, answer comes later
+pub fn is_busy(error: &Error) -> bool {
+    matches!(error,
+        Error::USBDM_Errors(USBDM_ErrorCode::BDM_RC_BUSY) |
+        Error::USBDM_Errors(USBDM_ErrorCode::BDM_RC_FLASH_PROGRAMING_BUSY))
+}
+
+/// `is_transient` - error of link (cable, USB), not of the command itself
+pub fn is_transient(error: &Error) -> bool {
+    matches!(error,
+        Error::Usb(rusb::Error::Timeout)                    |
+        Error::Usb(rusb::Error::Pipe)                       |
+        Error::Usb(rusb::Error::Io)                         |
+        Error::Usb(rusb::Error::Overflow)                   |
+        Error::USBDM_Errors(USBDM_ErrorCode::BDM_RC_OVERRUN)      |
+        Error::USBDM_Errors(USBDM_ErrorCode::BDM_RC_USB_ERROR)    |
+        Error::USBDM_Errors(USBDM_ErrorCode::BDM_RC_ACK_TIMEOUT))
+}
+
+/// `command_context` - command name and bytes for error reports
+pub fn command_context(command: &[u8]) -> String {
+    const SHOWN_BYTES : usize = 16;
+    let name = bdm_commands::command_name(command.get(1).copied().unwrap_or(0));
+    if command.len() > SHOWN_BYTES {
+        format!("{} {:02X?}.. ({} bytes)", name, &command[..SHOWN_BYTES], command.len())
+    } else {
+        format!("{} {:02X?}", name, command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::constants::{memory_space_t};
+    use crate::usbdm::transport::ScriptedTransport;
+    use crate::usbdm::programmer::Programmer;
+
+    fn scripted_programmer() -> (ScriptedTransport, Programmer) {
+        let script = ScriptedTransport::new();
+        let mut prog = Programmer::from_transport(Box::new(script.clone()));
+        prog.retry_policy.retry_delay = Duration::ZERO;
+        prog.retry_policy.busy_poll_interval = Duration::ZERO;
+        (script, prog)
+    }
+
+    #[test]
+    fn busy_answer_polled() {
+        let (script, prog) = scripted_programmer();
+        script.answer(vec![0x03]);                                            // BDM_RC_BUSY
+        script.expect(vec![0x02, bdm_commands::CMD_USBDM_GET_COMMAND_RESPONSE], vec![0x03]);
+        script.expect(vec![0x02, bdm_commands::CMD_USBDM_GET_COMMAND_RESPONSE], [vec![0x00], vec![0x5A; 4]].concat());
+
+        assert_eq!(prog.dsc_read_memory(memory_space_t::MS_XWORD, 4, 0x8000).unwrap(), vec![0x5A; 4]);
+        assert!(script.is_finished());
+    }
+
+    #[test]
+    fn lost_read_repeated() {
+        let (script, prog) = scripted_programmer();
+        script.lose();
+        script.answer([vec![0x00], vec![0xA5; 4]].concat());
+
+        assert_eq!(prog.dsc_read_memory(memory_space_t::MS_XWORD, 4, 0x8000).unwrap(), vec![0xA5; 4]);
+        let written = script.written();
+        assert_eq!(written[0], written[1]);
+        assert_eq!(script.halts_cleared(), 1);
+    }
+
+    #[test]
+    fn retries_limited() {
+        let (script, prog) = scripted_programmer();
+        for _ in 0..=prog.retry_policy.max_retries { script.lose(); }
+
+        let result = prog.dsc_read_memory(memory_space_t::MS_XWORD, 4, 0x8000);
+        assert!(matches!(result, Err(Error::UsbdmCommandFailed(_, cause)) if matches!(*cause, Error::Usb(rusb::Error::Timeout))));
+        assert!(script.is_finished());
+    }
+
+    #[test]
+    fn lost_write_escalated() {
+        let (script, prog) = scripted_programmer();
+        script.lose();
+        script.answer(vec![0x00]);
+
+        let result = prog.dsc_write_memory(memory_space_t::MS_XWORD, vec![0x12, 0x34], 0x8000);
+        match result {
+            Err(Error::UsbdmCommandFailed(context, _)) => assert!(context.contains("CMD_USBDM_JTAG_EXECUTE_SEQUENCE")),
+            other => panic!("expected escalation, got {:?}", other),
+        }
+        assert_eq!(script.written().len(), 1);                                 // not sent again
+        assert_eq!(script.halts_cleared(), 1);
+    }
+
+    #[test]
+    fn command_kinds() {
+        assert_eq!(command_kind(&[2, bdm_commands::CMD_USBDM_GET_BDM_STATUS]), CommandKind::Idempotent);
+        assert_eq!(command_kind(&[4, bdm_commands::CMD_USBDM_SET_VDD | 0x80, 1, 1]), CommandKind::Idempotent);
+        assert_eq!(command_kind(&[5, bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE, 0, 1, 0]), CommandKind::NonIdempotent);
+    }
+}
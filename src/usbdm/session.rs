@@ -0,0 +1,457 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::errors::{Error, USBDM_ErrorCode};
+use crate::usbdm::constants::{bdm_commands};
+use crate::usbdm::transport::{UsbdmTransport};
+use crate::usbdm::hotplug::{UsbPort};
+use crate::usbdm::usb_interface::{open_preferred_usbdm};
+
+/// Path of session file to record, enables recorder without gui
+pub const RECORD_ENV : &str = "USBDM_RECORD";
+/// Path of session file to replay instead of real USBDM
+pub const REPLAY_ENV : &str = "USBDM_REPLAY";
+
+const SESSION_HEADER : &str = "# usbdm_rs USB session";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    Out,
+    In,
+    Control,
+}
+
+/// Setup of vendor control IN transfer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ControlSetup {
+    pub request_type : u8,
+    pub request      : u8,
+    pub value        : u16,
+    pub index        : u16,
+}
+
+/// `SessionRecord` - one line of session file
+///
+/// ```text
+///      12 OUT  ep=01 : 02 84 # CMD_USBDM_GET_BDM_STATUS
+///      13 IN   ep=82 : 00 40 00 # CMD_USBDM_GET_BDM_STATUS -> BDM_RC_OK
+///      15 CTRL ep=00 req_type=C2 req=0C value=0064 index=0000 : 00 4C 4C ... # CMD_USBDM_GET_VER
+///     517 IN   ep=82 ! Usb(Timeout)
+/// ```
+/// Time is ms from session start, text after `#` is decoding for humans and ignored on replay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionRecord {
+    pub time_ms   : u64,
+    pub direction : Direction,
+    pub endpoint  : u8,
+    pub setup     : Option<ControlSetup>,
+    /// Bytes moved, or text of error returned by transport
+    pub data      : Result<Vec<u8>, String>,
+}
+
+impl SessionRecord {
+
+    pub fn to_line(&self, comment: &str) -> String {
+        let mut line = String::new();
+        let direction = match self.direction {
+            Direction::Out     => "OUT ",
+            Direction::In      => "IN  ",
+            Direction::Control => "CTRL",
+        };
+        write!(line, "{:>8} {} ep={:02X}", self.time_ms, direction, self.endpoint).ok();
+        if let Some(setup) = self.setup {
+            write!(line, " req_type={:02X} req={:02X} value={:04X} index={:04X}", setup.request_type, setup.request, setup.value, setup.index).ok();
+        }
+        match &self.data {
+            Ok(bytes) => {
+                line.push_str(" :");
+                for byte in bytes { write!(line, " {:02X}", byte).ok(); }
+            }
+            Err(e) => { write!(line, " ! {}", e).ok(); }
+        }
+        if !comment.is_empty() {
+            write!(line, " # {}", comment).ok();
+        }
+        line
+    }
+
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let bad_line = || Error::FileParserError(format!("Bad session line: {}", line));
+        let line = line.split('#').next().unwrap_or("");
+
+        let (head, data) = if let Some((head, bytes)) = line.split_once(" : ") {
+            (head, Ok(parse_hex_bytes(bytes).ok_or_else(bad_line)?))
+        } else if let Some((head, error)) = line.split_once(" ! ") {
+            (head, Err(error.trim().to_string()))
+        } else if let Some(head) = line.trim_end().strip_suffix(" :") {
+            (head, Ok(Vec::new()))
+        } else {
+            return Err(bad_line())
+        };
+
+        let mut tokens = head.split_whitespace();
+        let time_ms = tokens.next().and_then(|t| t.parse::<u64>().ok()).ok_or_else(bad_line)?;
+        let direction = match tokens.next() {
+            Some("OUT")  => Direction::Out,
+            Some("IN")   => Direction::In,
+            Some("CTRL") => Direction::Control,
+            _            => return Err(bad_line()),
+        };
+
+        let mut field = |name: &str| -> Result<u16, Error> {
+            tokens.next()
+                .and_then(|t| t.strip_prefix(name))
+                .and_then(|t| t.strip_prefix('='))
+                .and_then(|t| u16::from_str_radix(t, 16).ok())
+                .ok_or_else(bad_line)
+        };
+        let endpoint = field("ep")? as u8;
+        let setup = match direction {
+            Direction::Control => Some(ControlSetup {
+                request_type : field("req_type")? as u8,
+                request      : field("req")? as u8,
+                value        : field("value")?,
+                index        : field("index")?, }),
+            _ => None,
+        };
+
+        Ok(SessionRecord { time_ms, direction, endpoint, setup, data })
+    }
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    text.split_whitespace().map(|byte| u8::from_str_radix(byte, 16).ok()).collect()
+}
+
+#[derive(Debug)]
+struct SessionLog {
+    file         : File,
+    started      : Instant,
+    last_command : u8,
+}
+
+impl SessionLog {
+    fn append(&mut self, direction: Direction, endpoint: u8, setup: Option<ControlSetup>, data: Result<Vec<u8>, String>) {
+        let comment = match (direction, &data) {
+            (Direction::Out, Ok(bytes)) => {
+                self.last_command = bytes.get(1).copied().unwrap_or(0);
+                bdm_commands::command_name(self.last_command).to_string()
+            }
+            (Direction::In, Ok(bytes)) => match bytes.first() {
+                Some(status) => format!("{} -> {:?}", bdm_commands::command_name(self.last_command), USBDM_ErrorCode::from(status & !0xC0)),
+                None         => String::new(),
+            }
+            (Direction::Control, _) => bdm_commands::command_name(setup.map(|s| s.request).unwrap_or(0)).to_string(),
+            _ => String::new(),
+        };
+        let record = SessionRecord { time_ms: self.started.elapsed().as_millis() as u64, direction, endpoint, setup, data };
+        // line at once, so file is complete up to the failure even if the program crashes
+        if let Err(e) = writeln!(self.file, "{}", record.to_line(&comment)) {
+            println!("Session record write error {:?}", e);
+        }
+    }
+}
+
+/// `RecordingTransport` - passes everything to real transport and logs it to session file
+///
+/// File starts with self-describing header (model, serial, start time, line format),
+/// see `SessionRecord` for line layout. Play it back with `ReplayTransport`.
+#[derive(Debug)]
+pub struct RecordingTransport {
+    device : Box<dyn UsbdmTransport>,
+    log    : Mutex<SessionLog>,
+}
+
+impl RecordingTransport {
+    pub fn create(device: Box<dyn UsbdmTransport>, path: &Path) -> Result<Self, Error> {
+        let mut file = File::create(path)?;
+        let started_unix = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+        writeln!(file, "{}", SESSION_HEADER)?;
+        writeln!(file, "# started_unix : {}", started_unix)?;
+        writeln!(file, "# model        : {}", device.model())?;
+        writeln!(file, "# serial       : {}", device.serial_number())?;
+        writeln!(file, "# read_ep      : {:02X}", device.read_ep())?;
+        writeln!(file, "# write_ep     : {:02X}", device.write_ep())?;
+        writeln!(file, "# line format  : <ms> OUT|IN|CTRL ep=<hex> [req_type= req= value= index=] : <hex bytes> | ! <error>  # decoded")?;
+        println!("Recording USB session to {}", path.display());
+        Ok(RecordingTransport { device, log: Mutex::new(SessionLog { file, started: Instant::now(), last_command: 0 }) })
+    }
+}
+
+fn recorded<T: Clone>(result: &Result<T, Error>) -> Result<T, String> {
+    match result {
+        Ok(value) => Ok(value.clone()),
+        Err(e)    => Err(format!("{:?}", e)),
+    }
+}
+
+impl UsbdmTransport for RecordingTransport {
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        let result = self.device.write(data);
+        let logged = recorded(&result).map(|_| data.to_vec());
+        self.log.lock().unwrap().append(Direction::Out, self.device.write_ep(), None, logged);
+        result
+    }
+
+    fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error> {
+        let result = self.device.read(rx_size);
+        self.log.lock().unwrap().append(Direction::In, self.device.read_ep(), None, recorded(&result));
+        result
+    }
+
+    fn control_transfer(&self, request_type: u8, request: u8, value: u16, index: u16, rx_size: usize) -> Result<Vec<u8>, Error> {
+        let result = self.device.control_transfer(request_type, request, value, index, rx_size);
+        let setup = ControlSetup { request_type, request, value, index };
+        self.log.lock().unwrap().append(Direction::Control, 0, Some(setup), recorded(&result));
+        result
+    }
+
+    fn read_ep(&self) -> u8 {
+        self.device.read_ep()
+    }
+
+    fn write_ep(&self) -> u8 {
+        self.device.write_ep()
+    }
+
+    fn clear_halt(&self) -> Result<(), Error> {
+        let result = self.device.clear_halt();
+        let mut log = self.log.lock().unwrap();
+        let time_ms = log.started.elapsed().as_millis();
+        writeln!(log.file, "# {:>6} clear_halt {:?}", time_ms, result).ok();
+        result
+    }
+
+    fn model(&self) -> String {
+        self.device.model()
+    }
+
+    fn serial_number(&self) -> String {
+        self.device.serial_number()
+    }
+
+    fn port(&self) -> Option<UsbPort> {
+        self.device.port()
+    }
+}
+
+/// `ReplayTransport` - plays session file back instead of USBDM
+///
+/// Host must send the same commands in the same order as in recorded session, otherwise
+/// replay stops with error naming the session line where it diverged. Timing is not reproduced.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    model    : String,
+    serial   : String,
+    read_ep  : u8,
+    write_ep : u8,
+    records  : Mutex<VecDeque<(usize, SessionRecord)>>,
+}
+
+impl ReplayTransport {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::from_session(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_session(session: &str) -> Result<Self, Error> {
+        let mut replay = ReplayTransport {
+            model    : "USBDM replay".to_string(),
+            serial   : "REPLAY".to_string(),
+            read_ep  : 0x82,
+            write_ep : 0x01,
+            records  : Mutex::new(VecDeque::new()),
+        };
+        let mut records = VecDeque::new();
+        for (n, line) in session.lines().enumerate() {
+            let line_number = n + 1;
+            if let Some(header) = line.strip_prefix('#') {
+                if let Some((key, value)) = header.split_once(':') {
+                    let value = value.trim();
+                    match key.trim() {
+                        "model"    => replay.model = value.to_string(),
+                        "serial"   => replay.serial = value.to_string(),
+                        "read_ep"  => replay.read_ep = u8::from_str_radix(value, 16).unwrap_or(replay.read_ep),
+                        "write_ep" => replay.write_ep = u8::from_str_radix(value, 16).unwrap_or(replay.write_ep),
+                        _          => {}
+                    }
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = SessionRecord::parse(line)
+                .map_err(|_e| Error::FileParserError(format!("Session line {}: {}", line_number, line)))?;
+            records.push_back((line_number, record));
+        }
+        replay.records = Mutex::new(records);
+        Ok(replay)
+    }
+
+    /// All recorded exchanges replayed
+    pub fn is_finished(&self) -> bool {
+        self.records.lock().unwrap().is_empty()
+    }
+
+    fn next_record(&self, direction: Direction, what: &str) -> Result<(usize, SessionRecord), Error> {
+        match self.records.lock().unwrap().pop_front() {
+            Some((line, record)) if record.direction == direction => Ok((line, record)),
+            Some((line, record)) => Err(Error::InternalError(
+                format!("Replay diverged at session line {}: recorded {:?}, host did {}", line, record.direction, what))),
+            None => Err(Error::InternalError(format!("Replay session ended, host did {}", what))),
+        }
+    }
+}
+
+/// `replayed_error` - recorded error text back to error, usb errors which host code reacts to are restored
+fn replayed_error(text: String) -> Error {
+    if text.contains("Timeout") {
+        Error::Usb(rusb::Error::Timeout)
+    } else if text.contains("Pipe") {
+        Error::Usb(rusb::Error::Pipe)
+    } else if text.contains("NoDevice") {
+        Error::Usb(rusb::Error::NoDevice)
+    } else {
+        Error::InternalError(format!("Recorded error: {}", text))
+    }
+}
+
+impl UsbdmTransport for ReplayTransport {
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        let what = format!("write {:02X?}", data);
+        let (line, record) = self.next_record(Direction::Out, &what)?;
+        match record.data {
+            Ok(bytes) if bytes == data => Ok(()),
+            Ok(bytes) => Err(Error::InternalError(
+                format!("Replay diverged at session line {}: recorded {:02X?}, host did {}", line, bytes, what))),
+            Err(e) => Err(replayed_error(e)),
+        }
+    }
+
+    fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error> {
+        let (_line, record) = self.next_record(Direction::In, "read")?;
+        let mut bytes = record.data.map_err(replayed_error)?;
+        bytes.resize(rx_size, 0);
+        Ok(bytes)
+    }
+
+    fn control_transfer(&self, _request_type: u8, request: u8, _value: u16, _index: u16, rx_size: usize) -> Result<Vec<u8>, Error> {
+        let what = format!("control request {}", request);
+        let (line, record) = self.next_record(Direction::Control, &what)?;
+        if record.setup.map(|s| s.request) != Some(request) {
+            return Err(Error::InternalError(format!("Replay diverged at session line {}: recorded {:?}, host did {}", line, record.setup, what)))
+        }
+        let mut bytes = record.data.map_err(replayed_error)?;
+        bytes.resize(rx_size, 0);
+        Ok(bytes)
+    }
+
+    fn read_ep(&self) -> u8 {
+        self.read_ep
+    }
+
+    fn write_ep(&self) -> u8 {
+        self.write_ep
+    }
+
+    fn model(&self) -> String {
+        self.model.clone()
+    }
+
+    fn serial_number(&self) -> String {
+        self.serial.clone()
+    }
+}
+
+/// `session_file_path` - new session file in working directory, named by start time
+pub fn session_file_path() -> PathBuf {
+    let started_unix = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+    let mut path = env::current_dir().expect("Current directory env err.");
+    path.push(format!("usbdm_session_{}.log", started_unix));
+    path
+}
+
+/// `open_usbdm_transport` - preferred USBDM, recorded if `record` is set or `USBDM_RECORD` names a file
+///
+/// With `USBDM_REPLAY=<session file>` no hardware is opened, recorded session answers instead.
+pub fn open_usbdm_transport(record: bool) -> Result<Box<dyn UsbdmTransport>, Error> {
+    if let Ok(replay_path) = env::var(REPLAY_ENV) {
+        if !replay_path.is_empty() {
+            println!("Replay USB session from {}", replay_path);
+            return Ok(Box::new(ReplayTransport::load(Path::new(&replay_path))?))
+        }
+    }
+
+    let device = Box::new(open_preferred_usbdm()?);
+    let record_path = match env::var(RECORD_ENV) {
+        Ok(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ if record                  => Some(session_file_path()),
+        _                            => None,
+    };
+    match record_path {
+        Some(path) => Ok(Box::new(RecordingTransport::create(device, &path)?)),
+        None       => Ok(device),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::transport::ScriptedTransport;
+    use crate::usbdm::constants::{memory_space_t};
+    use crate::usbdm::jtag::*;
+    use crate::usbdm::programmer::Programmer;
+
+    fn session_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("usbdm_session_{}_{}.log", name, std::process::id()))
+    }
+
+    #[test]
+    fn record_line_round_trip() {
+        let records = vec![
+            SessionRecord { time_ms: 12, direction: Direction::Out, endpoint: 0x01, setup: None, data: Ok(vec![0x02, 0x84]) },
+            SessionRecord { time_ms: 15, direction: Direction::Control, endpoint: 0x00,
+                setup: Some(ControlSetup { request_type: 0xC2, request: 12, value: 100, index: 0 }), data: Ok(vec![0x00, 0x4C]) },
+            SessionRecord { time_ms: 517, direction: Direction::In, endpoint: 0x82, setup: None, data: Err("Usb(Timeout)".to_string()) },
+        ];
+        for record in records {
+            let line = record.to_line("CMD_USBDM_GET_VER");
+            assert_eq!(SessionRecord::parse(&line).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn recorded_session_replays() {
+        let path = session_path("replay");
+        let script = ScriptedTransport::new();
+        script.answer(vec![0x00, 0x02, 0x21, 0x10, 0x04]);
+        script.answer([vec![0x00], vec![0xAA; 0x08]].concat());
+
+        let recorder = RecordingTransport::create(Box::new(script), &path).unwrap();
+        let prog = Programmer::from_transport(Box::new(recorder));
+        let id_code = read_core_id_code(true, &prog).unwrap();
+        let memory = prog.dsc_read_memory(memory_space_t::MS_XWORD, 0x08, 0x8000).unwrap();
+        drop(prog);
+
+        let session = fs::read_to_string(&path).unwrap();
+        assert!(session.contains("CMD_USBDM_JTAG_EXECUTE_SEQUENCE"));
+
+        let replay = ReplayTransport::load(&path).unwrap();
+        assert_eq!(replay.serial_number(), "SCRIPTED");
+        let prog = Programmer::from_transport(Box::new(replay));
+        assert_eq!(read_core_id_code(true, &prog).unwrap(), id_code);
+        assert_eq!(prog.dsc_read_memory(memory_space_t::MS_XWORD, 0x08, 0x8000).unwrap(), memory);
+
+        // host asks something else than recorded - stop at that line
+        let replay = ReplayTransport::load(&path).unwrap();
+        let prog = Programmer::from_transport(Box::new(replay));
+        let diverged = prog.dsc_read_memory(memory_space_t::MS_XWORD, 0x08, 0x8000);
+        assert!(matches!(diverged, Err(Error::InternalError(e)) if e.contains("session line 8")));
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use crate::errors::{Error};
+use crate::usbdm::Programmer;
+use crate::usbdm::registers::*;
+
+// EONCE_OTBCR trace buffer control register details
+//--------------------------------------------------------------------
+pub const OTBCR_TEN         : u32 = 1<<0;   // trace buffer captures
+/// capture mode, bits 2..1 (`TraceMode` codes)
+pub const OTBCR_TMODE_SHIFT : u32 = 1;
+pub const OTBCR_TMODE_MASK  : u32 = 0x03;
+pub const OTBCR_TBH         : u32 = 1<<3;   // core halts when buffer gets full
+
+// EONCE_OTBPR trace buffer pointer details
+//--------------------------------------------------------------------
+pub const OTBPR_POINTER     : u32 = 0x0F;   // entry written next, each OTB read steps it back
+pub const OTBPR_WRAPPED     : u32 = 1<<4;   // buffer was filled, all entries hold addresses
+
+/// Trace buffer entries, source and target address of 8 changes of flow
+pub const TRACE_ENTRIES     : usize = 16;
+
+/// `TraceMode` - which changes of flow trace buffer captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceMode {
+    /// every taken branch, jump, call, return and interrupt
+    ChangeOfFlow,
+    /// subroutine calls and interrupts only, keeps older history of how core got into handler
+    CallsAndInterrupts,
+}
+
+impl TraceMode {
+    fn code(self) -> u32 {
+        match self {
+            TraceMode::ChangeOfFlow       => 0,
+            TraceMode::CallsAndInterrupts => 1,
+        }
+    }
+}
+
+/// `Branch` - change of flow from P:`source` to P:`target`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Branch {
+    pub source : u32,
+    pub target : u32,
+}
+
+/// `Trace` - changes of flow read from trace buffer, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub branches : Vec<Branch>,
+}
+
+impl Trace {
+
+    /// `from_entries` - trace of OTB entries as read, newest first: target, source, target, ...
+    ///
+    /// Source without its target (capture stopped between them) is dropped.
+    pub fn from_entries(entries: &[u32]) -> Self {
+        let pairs = entries.len() / 2 * 2;
+        let branches = entries[..pairs].chunks_exact(2).rev()
+            .map(|pair| Branch { source: pair[1], target: pair[0] })
+            .collect();
+        Trace { branches }
+    }
+
+    /// `listing` - one line per branch, oldest first; `symbol` names P address, e.g. `isr_timer+$4`
+    pub fn listing(&self, symbol: &dyn Fn(u32) -> Option<String>) -> Vec<String> {
+        let describe = |address: u32| match symbol(address) {
+            Some(name) => format!("P:${:06X} <{}>", address, name),
+            None       => format!("P:${:06X}", address),
+        };
+        self.branches.iter().enumerate()
+            .map(|(index, branch)| format!("{:2}  {} -> {}", index + 1, describe(branch.source), describe(branch.target)))
+            .collect()
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.branches.is_empty() {
+            return write!(f, "no changes of flow captured")
+        }
+        write!(f, "{}", self.listing(&|_| None).join("\n"))
+    }
+}
+
+impl Programmer
+{
+    /// `dsc_start_trace` - clears trace buffer and starts capture of `mode` changes of flow
+    ///
+    /// `note` Assumes Core TAP is active & in RUN-TEST/IDLE
+    pub fn dsc_start_trace(&self, mode: TraceMode, halt_when_full: bool) -> Result<(), Error> {
+        self.dsc_write_once_reg(DscRegisters::DscRegOtbcr, 0)?;
+        self.dsc_write_once_reg(DscRegisters::DscRegOtbpr, 0)?;
+        let mut control = OTBCR_TEN | (mode.code() << OTBCR_TMODE_SHIFT);
+        if halt_when_full {
+            control |= OTBCR_TBH;
+        }
+        self.dsc_write_once_reg(DscRegisters::DscRegOtbcr, control)
+    }
+
+    /// `dsc_stop_trace` - stops capture, captured entries stay for `dsc_read_trace`
+    pub fn dsc_stop_trace(&self) -> Result<(), Error> {
+        let control = self.dsc_read_once_reg(DscRegisters::DscRegOtbcr)?;
+        self.dsc_write_once_reg(DscRegisters::DscRegOtbcr, control & !OTBCR_TEN)
+    }
+
+    /// `dsc_read_trace` - stops capture and reads out trace buffer
+    ///
+    /// `note` Reading empties buffer, call `dsc_start_trace` to capture again
+    pub fn dsc_read_trace(&self) -> Result<Trace, Error> {
+        self.dsc_stop_trace()?;
+        let pointer = self.dsc_read_once_reg(DscRegisters::DscRegOtbpr)?;
+        let count = match pointer & OTBPR_WRAPPED {
+            0 => (pointer & OTBPR_POINTER) as usize,
+            _ => TRACE_ENTRIES,
+        };
+        let entries = (0..count).map(|_| self.dsc_read_once_reg(DscRegisters::DscRegOtb)).collect::<Result<Vec<u32>, Error>>()?;
+        Ok(Trace::from_entries(&entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbdm::jtag::*;
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+    use crate::usbdm::virtual_dsc::DscTap;
+
+    #[test]
+    fn branches_from_entries() {
+        let trace = Trace::from_entries(&[0x0400, 0x0123, 0x0120, 0x0088, 0x0055]);
+        assert_eq!(trace.branches, [Branch { source: 0x0088, target: 0x0120 }, Branch { source: 0x0123, target: 0x0400 }]);
+        let symbol = |address: u32| (address >= 0x0400).then(|| format!("isr_timer+${:X}", address - 0x0400));
+        assert_eq!(trace.listing(&symbol), [
+            " 1  P:$000088 -> P:$000120",
+            " 2  P:$000123 -> P:$000400 <isr_timer+$0>",
+        ]);
+        assert_eq!(Trace::default().to_string(), "no changes of flow captured");
+    }
+
+    #[test]
+    fn captured_and_read_out() {
+        let link = InterpreterTransport::new(DscTap::new());
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+
+        prog.dsc_start_trace(TraceMode::CallsAndInterrupts, true).unwrap();
+        assert_eq!(link.with_tap(|tap| tap.once_register(0x0E)), OTBCR_TEN | (1 << OTBCR_TMODE_SHIFT) | OTBCR_TBH);
+
+        // core captured 9 changes of flow, oldest one is overwritten
+        link.with_tap(|tap| tap.trace.extend((0..18).map(|address| 0x0100 + address)));
+        let trace = prog.dsc_read_trace().unwrap();
+        assert_eq!(trace.branches.len(), 8);
+        assert_eq!(trace.branches[0], Branch { source: 0x0102, target: 0x0103 });
+        assert_eq!(trace.branches[7], Branch { source: 0x0110, target: 0x0111 });
+        assert_eq!(link.with_tap(|tap| tap.once_register(0x0E)) & OTBCR_TEN, 0);
+        assert!(prog.dsc_read_trace().unwrap().branches.is_empty());
+    }
+}
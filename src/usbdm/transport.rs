@@ -0,0 +1,396 @@
+use crate::errors::{Error, USBDM_ErrorCode};
+use crate::usbdm::hotplug::{UsbPort};
+use crate::usbdm::constants::{bdm_commands, memory_space_t};
+use crate::usbdm::jtag::{JTAG_READ_MEM, JTAG_WRITE_MEM};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// `UsbdmTransport` - the three raw operations `Programmer` needs from the USBDM link
+///
+/// `UsbInterface` is the rusb implementation, anything else (scripted fake, recorded session,
+/// network relay) only has to move the same bytes. Transports do not interpret the answer,
+/// status byte is checked by `Programmer::bdm_command`.
+pub trait UsbdmTransport: fmt::Debug + Send {
+    /// `write` - bulk write of one command packet
+    fn write(&self, data: &[u8]) -> Result<(), Error>;
+
+    /// `read` - bulk read of `rx_size` bytes answer, first byte is USBDM status
+    fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error>;
+
+    /// `control_transfer` - vendor control IN transfer (used for `CMD_USBDM_GET_VER`)
+    fn control_transfer(&self, request_type: u8, request: u8, value: u16, index: u16, rx_size: usize) -> Result<Vec<u8>, Error>;
+
+    /// Address of bulk IN endpoint, needed to build control request type
+    fn read_ep(&self) -> u8;
+
+    /// Address of bulk OUT endpoint
+    fn write_ep(&self) -> u8;
+
+    /// `clear_halt` - clear halt on both bulk endpoints after stall or timeout
+    fn clear_halt(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// USB product string
+    fn model(&self) -> String;
+
+    /// USB serial number string
+    fn serial_number(&self) -> String;
+
+    /// Bus and address of real USB device, `None` for transports without one
+    fn port(&self) -> Option<UsbPort> {
+        None
+    }
+}
+
+/// `check_usbdm_return_code` - first byte of every bulk answer is USBDM return code (upper two bits are flags)
+pub fn check_usbdm_return_code(answer: &[u8]) -> Result<(), Error> {
+    let return_code = match answer.first() {
+        Some(code) => code & !0xC0,
+        None       => return Err(Error::InternalError("Empty answer from USBDM".to_string())),
+    };
+    let return_from_bdm = USBDM_ErrorCode::from(return_code);
+    if return_from_bdm != USBDM_ErrorCode::BDM_RC_OK {
+        return Err(Error::USBDM_Errors(return_from_bdm))
+    }
+    Ok(())
+}
+
+/// One expected exchange of `ScriptedTransport`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptStep {
+    /// Bulk command written by host, `None` accepts any bytes
+    Command(Option<Vec<u8>>, Vec<u8>),
+    /// Control transfer with given request, answer returned as is
+    Control(u8, Vec<u8>),
+    /// Any command accepted, answer never comes (read times out)
+    Lost,
+}
+
+#[derive(Debug, Default)]
+struct ScriptState {
+    steps     : VecDeque<ScriptStep>,
+    /// answers of written commands in order, `None` for lost one
+    pending   : VecDeque<Option<Vec<u8>>>,
+    written   : Vec<Vec<u8>>,
+    halts_cleared : usize,
+}
+
+/// `ScriptedTransport` - fake USBDM answering from a prepared script
+///
+/// Every `write` is compared with the next scripted command and the next `read` returns its answer.
+/// Answers queue up, so several commands may be written before reading (pipelined transfers).
+/// Clones share the same script, so test can keep one handle and give the other to `Programmer`.
+///
+/// ### Usage
+///
+/// ```ignore
+/// let script = ScriptedTransport::new();
+/// script.expect(vec![0x02, 0x84], vec![0x00, 0x00, 0x00]);   // CMD_USBDM_GET_BDM_STATUS
+/// let prog = Programmer::from_transport(Box::new(script.clone()));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedTransport {
+    state : Arc<Mutex<ScriptState>>,
+}
+
+impl ScriptedTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect exactly `command`, answer with `answer` (answer includes status byte)
+    pub fn expect(&self, command: Vec<u8>, answer: Vec<u8>) -> &Self {
+        self.push(ScriptStep::Command(Some(command), answer))
+    }
+
+    /// Accept any command, answer with `answer`
+    pub fn answer(&self, answer: Vec<u8>) -> &Self {
+        self.push(ScriptStep::Command(None, answer))
+    }
+
+    /// Accept any command and lose its answer
+    pub fn lose(&self) -> &Self {
+        self.push(ScriptStep::Lost)
+    }
+
+    /// Expect control transfer with `request`, answer with `answer`
+    pub fn expect_control(&self, request: u8, answer: Vec<u8>) -> &Self {
+        self.push(ScriptStep::Control(request, answer))
+    }
+
+    pub fn push(&self, step: ScriptStep) -> &Self {
+        self.state.lock().unwrap().steps.push_back(step);
+        self
+    }
+
+    /// All commands written so far
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().written.clone()
+    }
+
+    /// Number of `clear_halt` calls
+    pub fn halts_cleared(&self) -> usize {
+        self.state.lock().unwrap().halts_cleared
+    }
+
+    /// Script fully consumed and no answer left unread
+    pub fn is_finished(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.steps.is_empty() && state.pending.is_empty()
+    }
+}
+
+impl UsbdmTransport for ScriptedTransport {
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.written.push(data.to_vec());
+        match state.steps.pop_front() {
+            Some(ScriptStep::Command(expected, answer)) => {
+                if let Some(expected) = expected {
+                    if expected != data {
+                        return Err(Error::InternalError(format!("Script expected {:02X?}, got {:02X?}", expected, data)))
+                    }
+                }
+                state.pending.push_back(Some(answer));
+                Ok(())
+            }
+            Some(ScriptStep::Lost) => {
+                state.pending.push_back(None);
+                Ok(())
+            }
+            Some(step) => Err(Error::InternalError(format!("Script expected {:?}, got write {:02X?}", step, data))),
+            None       => Err(Error::InternalError(format!("Script finished, got write {:02X?}", data))),
+        }
+    }
+
+    fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error> {
+        let mut state = self.state.lock().unwrap();
+        match state.pending.pop_front() {
+            Some(Some(mut answer)) => {
+                answer.resize(rx_size, 0);
+                Ok(answer)
+            }
+            _ => Err(Error::Usb(rusb::Error::Timeout)),
+        }
+    }
+
+    fn control_transfer(&self, _request_type: u8, request: u8, _value: u16, _index: u16, rx_size: usize) -> Result<Vec<u8>, Error> {
+        let mut state = self.state.lock().unwrap();
+        match state.steps.pop_front() {
+            Some(ScriptStep::Control(expected, mut answer)) if expected == request => {
+                answer.resize(rx_size, 0);
+                Ok(answer)
+            }
+            Some(step) => Err(Error::InternalError(format!("Script expected {:?}, got control request {}", step, request))),
+            None       => Err(Error::InternalError(format!("Script finished, got control request {}", request))),
+        }
+    }
+
+    fn read_ep(&self) -> u8 {
+        0x82
+    }
+
+    fn write_ep(&self) -> u8 {
+        0x01
+    }
+
+    fn clear_halt(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        // as on USBDM, answers of commands already taken still come after halt is cleared
+        state.halts_cleared += 1;
+        Ok(())
+    }
+
+    fn model(&self) -> String {
+        "USBDM scripted".to_string()
+    }
+
+    fn serial_number(&self) -> String {
+        "SCRIPTED".to_string()
+    }
+}
+
+/// `LinkTiming` - cost model of `SimulatedTransport`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LinkTiming {
+    /// Host side cost of one bulk transfer (submit, schedule, completion)
+    pub transfer_latency : Duration,
+    /// Time per byte on the bus
+    pub byte_time        : Duration,
+    /// USBDM and JTAG time per memory element read or written on target
+    pub element_time     : Duration,
+    /// Commands USBDM accepts before previous are answered, more gives `BDM_RC_OVERRUN`
+    pub capacity         : usize,
+}
+
+impl Default for LinkTiming {
+    /// Full speed USB and JMxx USBDM at default JTAG clock
+    fn default() -> Self {
+        LinkTiming {
+            transfer_latency : Duration::from_micros(500),
+            byte_time        : Duration::from_micros(1),
+            element_time     : Duration::from_micros(8),
+            capacity         : 8,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SimulatedState {
+    /// virtual time spent by host so far
+    now      : Duration,
+    /// USBDM finishes its queued commands at
+    busy     : Duration,
+    /// answers with time they are ready
+    pending  : VecDeque<(Duration, Vec<u8>)>,
+    memory   : HashMap<(u8, u32), u8>,
+    commands : usize,
+}
+
+/// `SimulatedTransport` - USBDM with DSC memory behind a link with timing, no real time passes
+///
+/// Executes `JTAG_READ_MEM` / `JTAG_WRITE_MEM` sequences on memory model (unwritten memory reads as
+/// pattern of address), other commands are answered OK. Host and USBDM time are counted on virtual clock,
+/// USBDM executes commands one after other while host may write next ones, so pipelining and
+/// bigger blocks show up in `elapsed` as on real link. Used for transfer throughput benchmark.
+#[derive(Debug, Clone)]
+pub struct SimulatedTransport {
+    timing : LinkTiming,
+    state  : Arc<Mutex<SimulatedState>>,
+}
+
+impl SimulatedTransport {
+    pub fn new(timing: LinkTiming) -> Self {
+        SimulatedTransport { timing, state: Arc::new(Mutex::new(SimulatedState::default())) }
+    }
+
+    /// `pattern` - content of unwritten memory
+    pub fn pattern(memory_space: u8, byte_address: u32) -> u8 {
+        (byte_address as u8) ^ ((byte_address >> 8) as u8) ^ (memory_space & memory_space_t::MS_SPACE)
+    }
+
+    /// Virtual time spent on link
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    /// Bulk commands received
+    pub fn commands(&self) -> usize {
+        self.state.lock().unwrap().commands
+    }
+
+    fn execute(&self, state: &mut SimulatedState, command: &[u8]) -> (Vec<u8>, u32) {
+        if command.get(1).map(|cmd| cmd & 0x7F) != Some(bdm_commands::CMD_USBDM_JTAG_EXECUTE_SEQUENCE) || command.len() < 12 {
+            return (vec![0x00], 0)
+        }
+        let sequence = &command[4..];
+        let address = u32::from_be_bytes([sequence[2], sequence[3], sequence[4], sequence[5]]);
+        let elements = sequence[6] as u32;
+        let memory_space = sequence[7];
+        let element_size = (memory_space & memory_space_t::MS_SIZE) as u32;
+        // word and long addresses are DSC word addresses
+        let byte_address = if element_size == 1 { address } else { address * 2 };
+        let space = memory_space & memory_space_t::MS_SPACE;
+
+        match sequence[0] {
+            JTAG_READ_MEM => {
+                let mut answer = vec![0x00];
+                for offset in 0..(elements * element_size) {
+                    let location = (space, byte_address + offset);
+                    answer.push(*state.memory.get(&location).unwrap_or(&Self::pattern(memory_space, byte_address + offset)));
+                }
+                (answer, elements)
+            }
+            JTAG_WRITE_MEM => {
+                for (offset, byte) in sequence[8..].iter().enumerate() {
+                    state.memory.insert((space, byte_address + offset as u32), *byte);
+                }
+                (vec![0x00], elements)
+            }
+            _ => (vec![0x00], 0),
+        }
+    }
+}
+
+impl UsbdmTransport for SimulatedTransport {
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.commands += 1;
+        state.now += self.timing.transfer_latency + self.timing.byte_time * data.len() as u32;
+
+        if state.pending.len() >= self.timing.capacity {
+            let ready = state.now;
+            state.pending.push_back((ready, vec![6]));      // BDM_RC_OVERRUN
+            return Ok(())
+        }
+        let (answer, elements) = self.execute(&mut state, data);
+        let start = state.now.max(state.busy);
+        state.busy = start + self.timing.element_time * elements;
+        let ready = state.busy;
+        state.pending.push_back((ready, answer));
+        Ok(())
+    }
+
+    fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error> {
+        let mut state = self.state.lock().unwrap();
+        let (ready, mut answer) = state.pending.pop_front().ok_or(Error::Usb(rusb::Error::Timeout))?;
+        state.now = state.now.max(ready) + self.timing.transfer_latency + self.timing.byte_time * rx_size as u32;
+        answer.resize(rx_size, 0);
+        Ok(answer)
+    }
+
+    fn control_transfer(&self, _request_type: u8, _request: u8, _value: u16, _index: u16, rx_size: usize) -> Result<Vec<u8>, Error> {
+        Ok(vec![0; rx_size])
+    }
+
+    fn read_ep(&self) -> u8 {
+        0x82
+    }
+
+    fn write_ep(&self) -> u8 {
+        0x01
+    }
+
+    fn clear_halt(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn model(&self) -> String {
+        "USBDM simulated".to_string()
+    }
+
+    fn serial_number(&self) -> String {
+        "SIMULATED".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn return_code_check() {
+        assert!(check_usbdm_return_code(&[0x00, 0x12]).is_ok());
+        assert!(check_usbdm_return_code(&[0xC0]).is_ok());          // flag bits ignored
+        assert!(matches!(check_usbdm_return_code(&[0x01]), Err(Error::USBDM_Errors(_))));
+        assert!(check_usbdm_return_code(&[]).is_err());
+    }
+
+    #[test]
+    fn script_order_and_mismatch() {
+        let script = ScriptedTransport::new();
+        script.expect(vec![0x02, 0x84], vec![0x00, 0x40, 0x00]);
+        script.expect_control(12, vec![0x00, 0x4C]);
+
+        assert!(script.control_transfer(0xC2, 12, 100, 0, 4).is_err()); // out of order: bulk command first
+        let script = ScriptedTransport::new();
+        script.expect(vec![0x02, 0x84], vec![0x00, 0x40, 0x00]);
+        script.write(&[0x02, 0x84]).unwrap();
+        assert_eq!(script.read(3).unwrap(), vec![0x00, 0x40, 0x00]);
+        assert!(script.is_finished());
+        assert!(script.write(&[0x02, 0x84]).is_err());
+    }
+}
@@ -5,11 +5,15 @@ use std::time::Duration;
 use crate::errors::{Error, USBDM_ErrorCode};
 use crate::usbdm::constants::{bdm_commands};
 use crate::usbdm::feedback::{FeedBack};
+use crate::usbdm::transport::{UsbdmTransport};
+use crate::usbdm::hotplug::{UsbPort};
+use crate::usbdm::bdm_info::{BdmInfo};
+use crate::preferences::{Preferences};
 use packed_struct::prelude::*;
 use std::fmt;
 
 const USB_TIMEOUT: u64 = 500;
-const USBDM_VID: u16 = 0x16D0;
+pub const USBDM_VID: u16 = 0x16D0;
 
 
 pub fn match_vid<T: rusb::UsbContext>(device:&rusb::Device<T>) -> bool 
@@ -46,6 +50,103 @@ pub fn find_usbdm() -> Result<rusb::Device<rusb::GlobalContext>, Error>
 }
 
 
+/// `UsbdmProbe` - one USBDM found on the bus, as shown in probe list
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsbdmProbe
+{
+    pub model         : String,
+    pub serial_number : String,
+    pub bus           : u8,
+    pub address       : u8,
+    /// `None` if probe can't be opened (in use by other program, driver problem)
+    pub bdm_info      : Option<BdmInfo>,
+}
+
+impl fmt::Display for UsbdmProbe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.bdm_info {
+            Some(info) => write!(f, "{} [{}]{}", self.model, self.serial_number, info.version_in_string()),
+            None       => write!(f, "USBDM busy (bus {} address {})", self.bus, self.address),
+        }
+    }
+}
+
+impl UsbdmProbe {
+    /// `selectable` - probe could be opened and has serial to remember in preferences
+    pub fn selectable(&self) -> bool {
+        self.bdm_info.is_some() && !self.serial_number.is_empty()
+    }
+}
+
+/// `list_usbdm` - all connected USBDM with model, serial and firmware version
+///
+/// Each probe is opened for a moment to read version and capabilities, target power is not touched.
+/// Probe already claimed (by us or other program) is listed without info.
+pub fn list_usbdm() -> Result<Vec<UsbdmProbe>, Error>
+{
+    let mut probes = Vec::new();
+    for device in rusb::DeviceList::new()?.iter().filter(match_vid) {
+        let bus = device.bus_number();
+        let address = device.address();
+        match UsbInterface::new(device) {
+            Ok(usb_int) => {
+                probes.push(UsbdmProbe {
+                    model         : usb_int.model.clone(),
+                    serial_number : usb_int.serial_number.clone(),
+                    bus,
+                    address,
+                    bdm_info      : BdmInfo::read_from(&usb_int).ok(),
+                });
+            }
+            Err(_e) => {
+                probes.push(UsbdmProbe {
+                    model         : "?".to_string(),
+                    serial_number : "".to_string(),
+                    bus,
+                    address,
+                    bdm_info      : None,
+                });
+            }
+        }
+    }
+    Ok(probes)
+}
+
+/// `open_usbdm_by_serial` - open USBDM with given serial number, `ProbeNotConnected` if it is not there
+pub fn open_usbdm_by_serial(serial_number: &str) -> Result<UsbInterface, Error>
+{
+    for device in rusb::DeviceList::new()?.iter().filter(match_vid) {
+        if let Ok(usb_int) = UsbInterface::new(device) {
+            if usb_int.serial_number == serial_number {
+                return Ok(usb_int)
+            }
+        }
+    }
+    Err(Error::ProbeNotConnected(serial_number.to_string()))
+}
+
+/// `open_preferred_usbdm` - open probe remembered in preferences (or `USBDM_SERIAL` env var),
+/// first found USBDM if nothing is remembered.
+///
+/// Scripts and GUI use the same choice, so with several probes on the bus both talk to the same one.
+pub fn open_preferred_usbdm() -> Result<UsbInterface, Error>
+{
+    match preferred_probe_serial() {
+        Some(serial) => open_usbdm_by_serial(&serial),
+        None         => UsbInterface::new(find_usbdm()?),
+    }
+}
+
+/// `preferred_probe_serial` - `USBDM_SERIAL` env var overrides saved preferences
+pub fn preferred_probe_serial() -> Option<String>
+{
+    if let Ok(serial) = std::env::var("USBDM_SERIAL") {
+        if !serial.is_empty() { return Some(serial) }
+    }
+    Preferences::load().preferred_probe
+}
+
+
 #[derive(Debug)]
 pub struct UsbInterface
 {
@@ -56,6 +157,7 @@ pub struct UsbInterface
     pub model: String,
     serial_number: String,
     interface_n  : u8,
+    port         : UsbPort,
 
 }
 
@@ -88,7 +190,8 @@ impl  UsbInterface
             serial_number: handle.read_serial_number_string_ascii(&device_descriptor)?,
             //handle: Arc::new(RwLock::new(handle)),
             handle: handle,
-            interface_n : number,})
+            interface_n : number,
+            port        : UsbPort::of_device(&device),})
     }
 
     /// print USBDM model, serial number, EP
@@ -99,38 +202,55 @@ impl  UsbInterface
         println!("Write EP: {}",&self.write_ep);
     }
 
+}
+
+impl UsbdmTransport for UsbInterface
+{
     /// `write` - write_bulk to usbdm. param - data u8 slice.
-    pub fn write(&self, data: &[u8]) -> Result<(), Error> {
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
         self.handle.write_bulk(self.write_ep, data, Duration::from_millis(USB_TIMEOUT))?;
         Ok(())
     }
 
     /// `read` - read_bulk from usbdm. param - rx_size
-    pub fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error> {
+    fn read(&self, rx_size: usize) -> Result<Vec<u8>, Error> {
         let mut buff: Vec<u8> = vec![0; rx_size];
         self.handle.read_bulk(self.read_ep, buff.as_mut_slice(), Duration::from_millis(USB_TIMEOUT))?;
-        let mut answer = buff.to_vec();
-        self.check_usbm_return_code(&answer)?;
-        Ok(answer)
-    }
-
-    fn check_usbm_return_code(&self, return_byte : &Vec<u8>) -> Result<(), Error> {
-        let mut return_code = return_byte[0];
-        return_code &= !0xC0;
-        let return_from_bdm = USBDM_ErrorCode::from(return_code);
-        if return_from_bdm != USBDM_ErrorCode::BDM_RC_OK {     
-            return Err(Error::USBDM_Errors(return_from_bdm))
-        } else {
-            Ok (())
-        }
+        Ok(buff)
     }
 
-    pub fn control_transfer(&self, request_type: u8, request: u8, value: u16, index: u16, rx_size: usize) -> Result<Vec<u8>, Error> {
+    fn control_transfer(&self, request_type: u8, request: u8, value: u16, index: u16, rx_size: usize) -> Result<Vec<u8>, Error> {
         let mut buff: Vec<u8> = vec![0; rx_size];
         self.handle.read_control(request_type, request, value, index, buff.as_mut_slice(), Duration::from_millis(USB_TIMEOUT))?;
         let control_answer = buff.to_vec();
         Ok(control_answer) 
     }
+
+    fn read_ep(&self) -> u8 {
+        self.read_ep
+    }
+
+    fn write_ep(&self) -> u8 {
+        self.write_ep
+    }
+
+    fn clear_halt(&self) -> Result<(), Error> {
+        self.handle.clear_halt(self.read_ep)?;
+        self.handle.clear_halt(self.write_ep)?;
+        Ok(())
+    }
+
+    fn model(&self) -> String {
+        self.model.clone()
+    }
+
+    fn serial_number(&self) -> String {
+        self.serial_number.clone()
+    }
+
+    fn port(&self) -> Option<UsbPort> {
+        Some(self.port)
+    }
 }
 
 
@@ -0,0 +1,487 @@
+use std::collections::{HashMap, VecDeque};
+use crate::usbdm::constants::memory_space_t;
+use crate::usbdm::jtag::*;
+use crate::usbdm::jtag_interpreter::{VirtualTap, TapRegister, BDM_RC_TARGET_BUSY};
+use crate::usbdm::registers::*;
+use crate::usbdm::breakpoints::{OBCR_BS1_SHIFT, OBCR_BS2_SHIFT, OBCR_BS_MASK, OBCR_DEBUG, OBCR_EN1, OBCR_EN2, OSR_BKPT};
+use crate::usbdm::trace::{OTBPR_POINTER, OTBPR_WRAPPED, TRACE_ENTRIES};
+
+pub const VIRTUAL_MASTER_ID : u32 = 0x01F2_801D;
+pub const VIRTUAL_CORE_ID   : u32 = 0x0221_1004;
+
+/// `DscTap` - virtual MC56F80xx JTAG port for `JtagInterpreter`
+///
+/// Master TAP (8-bit IR, IDCODE, TLM, BYPASS) until TLM selects core TAP (4-bit IR, IDCODE,
+/// ENABLE_ONCE, DEBUG_REQUEST). Core IR capture gives OnCE status of `mode`. ONCE data register
+/// takes 8-bit command first, then read or write of addressed EOnCE register of length from
+/// `EONCE_REGISTER_DETAILS`. Target instructions are recorded in `executed`, without `core` they are not
+/// interpreted, so core register reads come from whatever test put in `once_registers` (OTX/OTX1).
+/// ONCE exit (go) leaves debug mode, with `core` stepping and program breakpoints halt again at once, see `resume`.
+/// Running firmware talks over OTX1/ORX1: `console_output` words come out one by one as host reads them,
+/// host writes to ORX/ORX1 are taken by core at once into `console_input`.
+/// Trace buffer holds last `TRACE_ENTRIES` of `trace`, OTB reads take them newest first.
+/// Memory is byte map, word and long addresses are DSC word addresses; unwritten memory reads 0xFF.
+#[derive(Debug)]
+pub struct DscTap {
+    pub master_id      : u32,
+    pub core_id        : u32,
+    pub mode           : OnceStatus,
+    pub core_selected  : bool,
+    pub once_registers : HashMap<u8, u32>,
+    pub executed       : Vec<Vec<u8>>,
+    pub memory         : HashMap<(u8, u32), u8>,
+    /// register file running register transfer instructions, `None` - instructions only recorded
+    pub core           : Option<DscCore>,
+    /// words firmware sends, next one goes to OTX1 when status poll finds it empty
+    pub console_output : VecDeque<u32>,
+    pub console_input  : Vec<u32>,
+    /// change of flow addresses captured by core, source then target, oldest first
+    pub trace          : Vec<u32>,
+    ir                 : u8,
+    tlm                : u8,
+    once_command       : Option<u8>,
+    captured           : u64,
+    /// length of register selected at capture, later bits shifted in come out after it
+    length             : usize,
+    shifted            : Vec<bool>,
+}
+
+impl DscTap {
+    pub fn new() -> Self {
+        Self {
+            master_id      : VIRTUAL_MASTER_ID,
+            core_id        : VIRTUAL_CORE_ID,
+            mode           : OnceStatus::ExecuteMode,
+            core_selected  : false,
+            once_registers : HashMap::new(),
+            executed       : Vec::new(),
+            memory         : HashMap::new(),
+            core           : None,
+            console_output : VecDeque::new(),
+            console_input  : Vec::new(),
+            trace          : Vec::new(),
+            ir             : JTAG_IDCODE_COMMAND,
+            tlm            : TLM_MASTER_SELECT_MASK,
+            once_command   : None,
+            captured       : 0,
+            length         : 1,
+            shifted        : Vec::new(),
+        }
+    }
+
+    /// `once_register` - EOnCE register value, 0 if never written
+    pub fn once_register(&self, address: u8) -> u32 {
+        *self.once_registers.get(&address).unwrap_or(&0)
+    }
+
+    fn status_bits(&self) -> u64 {
+        match self.mode {
+            OnceStatus::ExecuteMode        => 0x01,
+            OnceStatus::StopMode           => 0x05,
+            OnceStatus::ExternalAccessMode => 0x09,
+            OnceStatus::DebugMode          => 0x0D,
+            OnceStatus::UnknownMode        => 0x00,
+        }
+    }
+
+    /// register content at update - last `length` bits shifted in, first of them is LSB
+    fn shifted_value(&self) -> u64 {
+        let start = self.shifted.len().saturating_sub(self.length);
+        self.shifted[start..].iter().rev().fold(0, |value, bit| (value << 1) | *bit as u64)
+    }
+
+    fn register_length(address: u8) -> usize {
+        EONCE_REGISTER_DETAILS.iter().find(|details| details.address == address).map_or(32, |details| details.length as usize)
+    }
+
+    fn byte_address(memory_space: u8, address: u32) -> (u8, u32) {
+        let element_size = memory_space & memory_space_t::MS_SIZE;
+        let byte_address = if element_size == memory_space_t::MS_BYTE { address } else { address * 2 };
+        (memory_space & memory_space_t::MS_SPACE, byte_address)
+    }
+
+    fn once_update(&mut self, value: u64) {
+        match self.once_command.take() {
+            None => {
+                let command = value as u8;
+                if command & 0x1F != ONCE_CMD_NOREG {
+                    self.once_command = Some(command);
+                } else if command & ONCE_CMD_EXIT != 0 {
+                    self.resume();
+                }
+            },
+            Some(command) => {
+                let address = command & 0x1F;
+                if command & ONCE_CMD_READ == 0 {
+                    self.once_registers.insert(address, value as u32);
+                    if address == ORX_ADDRESS || address == ORX1_ADDRESS {
+                        self.console_input.push(value as u32);
+                    }
+                    if address == Self::once_address(DscRegisters::DscRegOtbpr) && value == 0 {
+                        self.trace.clear();
+                    }
+                }
+                if command & ONCE_CMD_EXIT != 0 {
+                    self.resume();
+                }
+            },
+        }
+    }
+
+    /// `channel_read` - OTXRXSR / OTX read by host: status shows next firmware word, reading it frees OTX
+    fn channel_read(&mut self, address: u8) {
+        let status_address = Self::once_address(DscRegisters::DscRegOtxrxsr);
+        let status = self.once_register(status_address);
+        if address == status_address && status & OTXRXSR_TDF as u32 == 0 {
+            if let Some(word) = self.console_output.pop_front() {
+                self.once_registers.insert(OTX1_ADDRESS, word);
+                self.once_registers.insert(status_address, status | OTXRXSR_TDF as u32);
+            }
+        } else if address == OTX_ADDRESS || address == OTX1_ADDRESS {
+            self.once_registers.insert(status_address, status & !(OTXRXSR_TDF as u32));
+        }
+    }
+
+    /// `trace_read` - OTBPR read gives pointer of kept entries, OTB read takes newest entry
+    fn trace_read(&mut self, address: u8) {
+        if address == Self::once_address(DscRegisters::DscRegOtbpr) {
+            let kept = self.trace.len().saturating_sub(TRACE_ENTRIES);
+            self.trace.drain(..kept);
+            let wrapped = if self.trace.len() == TRACE_ENTRIES { OTBPR_WRAPPED } else { 0 };
+            self.once_registers.insert(address, (self.trace.len() as u32 & OTBPR_POINTER) | wrapped);
+        } else if address == Self::once_address(DscRegisters::DscRegOtb) {
+            let entry = self.trace.pop().unwrap_or(0);
+            self.once_registers.insert(address, entry);
+        }
+    }
+
+    fn once_address(reg: DscRegisters) -> u8 {
+        EONCE_REGISTER_DETAILS[reg as usize - DscRegisters::DscRegOcr as usize].address
+    }
+
+    fn once_value(&self, reg: DscRegisters) -> u32 {
+        self.once_register(Self::once_address(reg))
+    }
+
+    /// `resume` - core leaves debug mode; with `core` it runs to its next stop at once: after OSCNTR + 1
+    /// one-word instructions when OCR selects instruction step, else at enabled program comparator of
+    /// breakpoint unit (OSR breakpoint flag set). With neither core keeps running.
+    fn resume(&mut self) {
+        self.mode = OnceStatus::ExecuteMode;
+        let ocr = self.once_value(DscRegisters::DscRegOcr);
+        let control = self.once_value(DscRegisters::DscRegOb0cr);
+        let comparators = [
+            (OBCR_EN1, OBCR_BS1_SHIFT, self.once_value(DscRegisters::DscRegOb0ar1)),
+            (OBCR_EN2, OBCR_BS2_SHIFT, self.once_value(DscRegisters::DscRegOb0ar2)),
+        ];
+        let steps = self.once_value(DscRegisters::DscRegOscntr) + 1;
+        let osr = self.once_value(DscRegisters::DscRegOsr) | OSR_BKPT;
+        let Some(core) = self.core.as_mut() else { return };
+
+        if ocr & OCR_ISC_7 as u32 == OCR_ISC_SINGLE_STEP as u32 {
+            let pc = core.get(DscRegisters::DscRegPc);
+            core.set(DscRegisters::DscRegPc, pc + steps);
+            self.mode = OnceStatus::DebugMode;
+        } else if control & OBCR_DEBUG != 0 {
+            let program = comparators.iter().find(|(enable, shift, _)| control & enable != 0 && (control >> shift) & OBCR_BS_MASK == 0);
+            if let Some((_, _, address)) = program {
+                core.set(DscRegisters::DscRegPc, *address);
+                self.once_registers.insert(Self::once_address(DscRegisters::DscRegOsr), osr);
+                self.mode = OnceStatus::DebugMode;
+            }
+        }
+    }
+}
+
+/// `DscCore` - core registers of `DscTap`, by `DscRegisters` number
+///
+/// Runs only instructions of `TARGET_READ_REG_SEQUENCE` / `TARGET_WRITE_REG_SEQUENCE` (moves through R4, A and
+/// OTX/OTX1, `tfr` between accumulators, swap of shadows), others are ignored. Values are kept to register width.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DscCore {
+    pub registers : [u32; CORE_REGISTERS.len()],
+}
+
+/// sources of `move.w reg,X:>>otx1`
+const MOVE_TO_OTX1 : [([u8; 2], DscRegisters); 10] = [
+    ([0xD4, 0x7C], DscRegisters::DscRegX0), ([0xD5, 0x7C], DscRegisters::DscRegY0), ([0xD7, 0x7C], DscRegisters::DscRegY1),
+    ([0xD6, 0xFC], DscRegisters::DscRegA0), ([0xD0, 0x7C], DscRegisters::DscRegA1), ([0xD4, 0xFC], DscRegisters::DscRegA2),
+    ([0xD7, 0xFC], DscRegisters::DscRegB0), ([0xD1, 0x7C], DscRegisters::DscRegB1), ([0xD5, 0xFC], DscRegisters::DscRegB2),
+    ([0xDC, 0x7C], DscRegisters::DscRegR4),
+];
+/// destinations of `move.w R4,reg` (`moveu.w`, `tfra`)
+const MOVE_FROM_R4 : [([u8; 2], DscRegisters); 23] = [
+    ([0x84, 0x0C], DscRegisters::DscRegX0),  ([0x85, 0x0C], DscRegisters::DscRegY0),  ([0x87, 0x0C], DscRegisters::DscRegY1),
+    ([0x86, 0x8C], DscRegisters::DscRegA0),  ([0x80, 0x8C], DscRegisters::DscRegA1),  ([0x84, 0x8C], DscRegisters::DscRegA2),
+    ([0x87, 0x8C], DscRegisters::DscRegB0),  ([0x81, 0x8C], DscRegisters::DscRegB1),  ([0x85, 0x8C], DscRegisters::DscRegB2),
+    ([0x8C, 0x8C], DscRegisters::DscRegOmr), ([0x8D, 0x8C], DscRegisters::DscRegSr),  ([0x8F, 0x8C], DscRegisters::DscRegLa),
+    ([0x8E, 0x8C], DscRegisters::DscRegLc),  ([0x81, 0xAB], DscRegisters::DscRegSp),  ([0x89, 0x8C], DscRegisters::DscRegN3),
+    ([0x8A, 0x8C], DscRegisters::DscRegM01), ([0x81, 0xAA], DscRegisters::DscRegN),   ([0x81, 0xA0], DscRegisters::DscRegR0),
+    ([0x81, 0xA1], DscRegisters::DscRegR1),  ([0x81, 0xA2], DscRegisters::DscRegR2),  ([0x81, 0xA3], DscRegisters::DscRegR3),
+    ([0x81, 0xA9], DscRegisters::DscRegR5),  ([0xE7, 0x17], DscRegisters::DscRegPc),
+];
+/// `move.w reg,A0` / `moveu.w reg,R4` / `move.l PC,R4` - source and scratch register
+const MOVE_TO_SCRATCH : [([u8; 2], DscRegisters, DscRegisters); 8] = [
+    ([0x86, 0x9C], DscRegisters::DscRegOmr, DscRegisters::DscRegA0), ([0x86, 0x9D], DscRegisters::DscRegSr,  DscRegisters::DscRegA0),
+    ([0x86, 0x99], DscRegisters::DscRegN3,  DscRegisters::DscRegA0), ([0x86, 0x9A], DscRegisters::DscRegM01, DscRegisters::DscRegA0),
+    ([0x8C, 0x1F], DscRegisters::DscRegLa,  DscRegisters::DscRegR4), ([0x8C, 0x1E], DscRegisters::DscRegLc,  DscRegisters::DscRegR4),
+    ([0x81, 0xBC], DscRegisters::DscRegSp,  DscRegisters::DscRegR4), ([0xE7, 0x16], DscRegisters::DscRegPc,  DscRegisters::DscRegR4),
+];
+const SHADOWED : [(DscRegisters, DscRegisters); 4] = [
+    (DscRegisters::DscRegM01, DscRegisters::DscRegShm01), (DscRegisters::DscRegN, DscRegisters::DscRegShn),
+    (DscRegisters::DscRegR0, DscRegisters::DscRegShr0), (DscRegisters::DscRegR1, DscRegisters::DscRegShr1),
+];
+/// A, B, C, D - LSP, MSP, extension
+const ACCUMULATORS : [[DscRegisters; 3]; 4] = [
+    [DscRegisters::DscRegA0, DscRegisters::DscRegA1, DscRegisters::DscRegA2],
+    [DscRegisters::DscRegB0, DscRegisters::DscRegB1, DscRegisters::DscRegB2],
+    [DscRegisters::DscRegC0, DscRegisters::DscRegC1, DscRegisters::DscRegC2],
+    [DscRegisters::DscRegD0, DscRegisters::DscRegD1, DscRegisters::DscRegD2],
+];
+
+impl Default for DscCore {
+    fn default() -> Self {
+        DscCore { registers: [0; CORE_REGISTERS.len()] }
+    }
+}
+
+impl DscCore {
+
+    pub fn get(&self, reg: DscRegisters) -> u32 {
+        self.registers[reg as usize]
+    }
+
+    /// `set` - `value` cut to register width
+    pub fn set(&mut self, reg: DscRegisters, value: u32) {
+        let width = get_register_size(reg).unwrap_or(32) as u32;
+        self.registers[reg as usize] = if width >= 32 { value } else { value & ((1 << width) - 1) };
+    }
+
+    /// `tfr` - whole accumulator `from` to `to`
+    fn transfer(&mut self, from: usize, to: usize) {
+        for (source, destination) in ACCUMULATORS[from].iter().zip(ACCUMULATORS[to].iter()) {
+            self.set(*destination, self.get(*source));
+        }
+    }
+
+    fn execute(&mut self, instruction: &[u8], once_registers: &mut HashMap<u8, u32>) {
+        let find = |code: &[u8]| MOVE_FROM_R4.iter().find(|(bytes, _)| bytes == code).map(|(_, reg)| *reg);
+        match instruction {
+            [0xE7, 0x7F, high, low, 0xFF, 0xFF] => {
+                if let Some((_, reg)) = MOVE_TO_OTX1.iter().find(|(bytes, _)| bytes == &[*high, *low]) {
+                    once_registers.insert(OTX1_ADDRESS, self.get(*reg) & 0xFFFF);
+                }
+            },
+            // move.l Rn/N,X:>>otx
+            [0xE3, 0x7F, source @ 0xD8..=0xDE, 0x7D, 0xFF, 0xFF] => {
+                let reg = match source {
+                    0xDE => DscRegisters::DscRegN,
+                    _    => CORE_REGISTERS[DscRegisters::DscRegR0 as usize + (source - 0xD8) as usize],
+                };
+                once_registers.insert(OTX_ADDRESS, self.get(reg));
+            },
+            [0x7C, 0x20] => self.transfer(2, 0),
+            [0x7C, 0x30] => self.transfer(3, 0),
+            [0x7D, 0x00] => self.transfer(0, 2),
+            [0x7D, 0x80] => self.transfer(0, 3),
+            // move.l #imm,R4 - low word first
+            [0xE4, 0x1C, middle, low, high, upper] =>
+                self.set(DscRegisters::DscRegR4, u32::from_be_bytes([*high, *upper, *middle, *low])),
+            [0x87, 0x4C, high, low] => self.set(DscRegisters::DscRegR4, u16::from_be_bytes([*high, *low]) as u32),
+            [0xE7, 0x06] => {
+                for (register, shadow) in SHADOWED {
+                    self.registers.swap(register as usize, shadow as usize);
+                }
+            },
+            code => {
+                if let Some(reg) = find(code) {
+                    self.set(reg, self.get(DscRegisters::DscRegR4));
+                } else if let Some((_, source, scratch)) = MOVE_TO_SCRATCH.iter().find(|(bytes, _, _)| bytes == code) {
+                    self.set(*scratch, self.get(*source));
+                }
+            },
+        }
+    }
+}
+
+impl Default for DscTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualTap for DscTap {
+    fn test_logic_reset(&mut self) {
+        self.core_selected = false;
+        self.tlm = TLM_MASTER_SELECT_MASK;
+        self.ir = JTAG_IDCODE_COMMAND;
+        self.once_command = None;
+    }
+
+    fn capture(&mut self, register: TapRegister) {
+        self.shifted.clear();
+        (self.captured, self.length) = match (register, self.core_selected, self.ir) {
+            (TapRegister::Ir, true, _)                             => (self.status_bits(), JTAG_CORE_COMMAND_LENGTH as usize),
+            (TapRegister::Ir, false, _)                            => (0x01, JTAG_MASTER_COMMAND_LENGTH as usize),
+            (TapRegister::Dr, false, JTAG_IDCODE_COMMAND)          => (self.master_id as u64, JTAG_IDCODE_LENGTH as usize),
+            (TapRegister::Dr, false, JTAG_TLM_SELECT_COMMAND)      => (self.tlm as u64, TLM_REGISTER_LENGTH as usize),
+            (TapRegister::Dr, true, JTAG_IDCODE_COMMAND)           => (self.core_id as u64, JTAG_IDCODE_LENGTH as usize),
+            (TapRegister::Dr, true, CORE_ENABLE_ONCE_COMMAND)      => match self.once_command {
+                None          => (0, ONCE_CMD_LENGTH as usize),
+                Some(command) if command & ONCE_CMD_READ != 0 => {
+                    self.channel_read(command & 0x1F);
+                    self.trace_read(command & 0x1F);
+                    (self.once_register(command & 0x1F) as u64, Self::register_length(command & 0x1F))
+                },
+                Some(command) => (0, Self::register_length(command & 0x1F)),
+            },
+            // BYPASS
+            _ => (0, 1),
+        };
+    }
+
+    fn shift(&mut self, _register: TapRegister, tdi: bool) -> bool {
+        let count = self.shifted.len();
+        let tdo = if count < self.length { (self.captured >> count) & 1 != 0 } else { self.shifted[count - self.length] };
+        self.shifted.push(tdi);
+        tdo
+    }
+
+    fn update(&mut self, register: TapRegister) {
+        let value = self.shifted_value();
+        match register {
+            TapRegister::Ir => {
+                self.ir = value as u8;
+                self.once_command = None;
+                if self.core_selected && self.ir == CORE_DEBUG_REQUEST_COMMAND {
+                    self.mode = OnceStatus::DebugMode;
+                }
+            },
+            TapRegister::Dr => match (self.core_selected, self.ir) {
+                (false, JTAG_TLM_SELECT_COMMAND) => {
+                    self.tlm = value as u8;
+                    if self.tlm & TLM_SLAVE_SELECT_MASK != 0 {
+                        self.core_selected = true;
+                        self.ir = JTAG_IDCODE_COMMAND;
+                    }
+                },
+                (true, CORE_ENABLE_ONCE_COMMAND) => self.once_update(value),
+                _ => {},
+            },
+        }
+    }
+
+    fn execute(&mut self, instruction: &[u8]) -> Result<(), u8> {
+        if self.mode != OnceStatus::DebugMode {
+            return Err(BDM_RC_TARGET_BUSY)
+        }
+        self.executed.push(instruction.to_vec());
+        if let Some(core) = self.core.as_mut() {
+            core.execute(instruction, &mut self.once_registers);
+        }
+        Ok(())
+    }
+
+    fn read_memory(&mut self, memory_space: u8, address: u32, elements: u8) -> Result<Vec<u8>, u8> {
+        if self.mode != OnceStatus::DebugMode {
+            return Err(BDM_RC_TARGET_BUSY)
+        }
+        let (space, byte_address) = Self::byte_address(memory_space, address);
+        let size = (memory_space & memory_space_t::MS_SIZE) as u32 * elements as u32;
+        Ok((0..size).map(|offset| *self.memory.get(&(space, byte_address + offset)).unwrap_or(&0xFF)).collect())
+    }
+
+    fn write_memory(&mut self, memory_space: u8, address: u32, data: &[u8]) -> Result<(), u8> {
+        if self.mode != OnceStatus::DebugMode {
+            return Err(BDM_RC_TARGET_BUSY)
+        }
+        let (space, byte_address) = Self::byte_address(memory_space, address);
+        for (offset, byte) in data.iter().enumerate() {
+            self.memory.insert((space, byte_address + offset as u32), *byte);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::Error;
+    use crate::usbdm::Programmer;
+    use crate::usbdm::jtag_interpreter::InterpreterTransport;
+
+    fn virtual_programmer() -> (InterpreterTransport<DscTap>, Programmer) {
+        let link = InterpreterTransport::new(DscTap::new());
+        let prog = Programmer::from_transport(Box::new(link.clone()));
+        (link, prog)
+    }
+
+    fn halted_programmer() -> (InterpreterTransport<DscTap>, Programmer) {
+        let (link, prog) = virtual_programmer();
+        enableCoreTAP(&prog).unwrap();
+        prog.dsc_target_halt().unwrap();
+        (link, prog)
+    }
+
+    #[test]
+    fn connect_sequence() {
+        let (link, prog) = virtual_programmer();
+        assert_eq!(read_master_id_code_DSC_JTAG_ID(true, &prog).unwrap(), VIRTUAL_MASTER_ID.to_be_bytes().to_vec());
+        enableCoreTAP(&prog).unwrap();
+        assert!(link.with_tap(|tap| tap.core_selected));
+        assert_eq!(read_core_id_code(false, &prog).unwrap(), VIRTUAL_CORE_ID.to_be_bytes().to_vec());
+
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+        assert_eq!(prog.dsc_target_halt().unwrap(), OnceStatus::DebugMode);
+        prog.dsc_target_go().unwrap();
+        assert_eq!(enableONCE(&prog).unwrap(), OnceStatus::ExecuteMode);
+
+        // reset of TAP gives master back
+        read_master_id_code_DSC_JTAG_ID(true, &prog).unwrap();
+        assert!(!link.with_tap(|tap| tap.core_selected));
+    }
+
+    #[test]
+    fn once_registers_round_trip() {
+        let (link, prog) = halted_programmer();
+        // value wider than 8-bit OCR is refused before anything is sent
+        assert!(prog.dsc_write_once_reg(DscRegisters::DscRegOcr, 0x1A5).is_err());
+        prog.dsc_write_once_reg(DscRegisters::DscRegOcr, 0xA5).unwrap();
+        assert_eq!(link.with_tap(|tap| tap.once_register(0x01)), 0xA5);
+        assert_eq!(prog.dsc_read_once_reg(DscRegisters::DscRegOcr).unwrap(), 0xA5);
+
+        link.with_tap(|tap| tap.once_registers.insert(OTX_ADDRESS, 0xDEAD_BEEF));
+        assert_eq!(prog.dsc_read_once_reg(DscRegisters::DscRegOtx).unwrap(), 0xDEAD_BEEF);
+    }
+
+    #[test]
+    fn core_register_transfers() {
+        let (link, prog) = halted_programmer();
+        link.with_tap(|tap| tap.once_registers.insert(OTX_ADDRESS, 0x0000_810C));
+        assert_eq!(prog.dsc_read_pc().unwrap(), 0x810C);
+        // move.l PC,R4 then move.l R4,X:>>otx
+        assert_eq!(link.with_tap(|tap| tap.executed.clone()),
+            vec![vec![0xE7, 0x16], vec![0xE3, 0x7F, 0xDC, 0x7D, 0xFF, 0xFF]]);
+
+        link.with_tap(|tap| tap.executed.clear());
+        prog.dsc_write_pc(0x1234).unwrap();
+        // move.l #$1234,R4 then move.l R4,PC
+        assert_eq!(link.with_tap(|tap| tap.executed.clone()),
+            vec![vec![0xE4, 0x1C, 0x12, 0x34, 0x00, 0x00], vec![0xE7, 0x17]]);
+    }
+
+    #[test]
+    fn memory_through_firmware_sequences() {
+        let (link, mut prog) = halted_programmer();
+        prog.transfer.max_memory_block = 0x20;
+        let data: Vec<u8> = (0..0x50).collect();
+        prog.dsc_write_memory(memory_space_t::MS_XWORD, data.clone(), 0x8000).unwrap();
+        assert_eq!(link.with_tap(|tap| tap.memory[&(memory_space_t::MS_DATA, 0x1_0002)]), 0x02);
+        assert_eq!(prog.dsc_read_memory(memory_space_t::MS_XWORD, 0x50, 0x8000).unwrap(), data);
+        assert_eq!(prog.dsc_read_memory(memory_space_t::MS_PWORD, 4, 0x8000).unwrap(), vec![0xFF; 4]);
+
+        // running core does not take memory access
+        prog.dsc_target_go().unwrap();
+        assert!(matches!(prog.dsc_read_memory(memory_space_t::MS_XWORD, 2, 0x8000), Err(Error::USBDM_Errors(_))));
+    }
+}